@@ -0,0 +1,36 @@
+use std::path::{Path, PathBuf};
+
+use crate::interpreter::Interpreter;
+
+// Runs every benchmark script found, each in a fresh interpreter; the
+// scripts report their timings through `bench()`
+pub fn start(paths: &[PathBuf]) -> ! {
+    let files = super::find_scripts(paths, "_bench");
+    if files.is_empty() {
+        eprintln!("No benchmark files found");
+        std::process::exit(super::EXIT_NO_INPUT);
+    }
+    let mut failed = 0;
+    for file in &files {
+        println!("== {} ==", file.display());
+        if let Err(error) = run_file(file) {
+            eprintln!("{}", error);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        eprintln!("{} of {} benchmark files failed", failed, files.len());
+        std::process::exit(super::EXIT_FAILURE);
+    }
+    std::process::exit(0);
+}
+
+fn run_file(file: &Path) -> Result<(), String> {
+    let exprs = super::load(file)?;
+    let base_dir = file.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+    let mut interpreter = Interpreter::builder().base_path(base_dir).build();
+    interpreter.interpret(exprs).map(|_| ()).map_err(|error| {
+        let source = std::fs::read_to_string(file).unwrap_or_default();
+        super::error_report(&error, &source)
+    })
+}
@@ -0,0 +1,70 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// Ends an executable that carries a script: the script's length as 8
+// little-endian bytes, then this
+const TRAILER_MAGIC: &[u8; 8] = b"ALPHAEXE";
+
+// Bundles the script with its modules. With --self-contained the bundle is
+// appended to a copy of this interpreter, giving an executable that runs it.
+pub fn start(script: &Path, output: Option<&Path>, self_contained: bool) -> ! {
+    let bundled = super::bundle::bundle(script);
+    let stem = script.file_stem().unwrap_or_default().to_string_lossy();
+    let result = if self_contained {
+        let output = output.map_or_else(
+            || PathBuf::from(format!("{}{}", stem, std::env::consts::EXE_SUFFIX)),
+            Path::to_path_buf,
+        );
+        write_executable(&output, &bundled).map(|_| output)
+    } else {
+        let output = output.map_or_else(|| PathBuf::from(format!("{}.bundle.la", stem)), Path::to_path_buf);
+        fs::write(&output, &bundled).map(|_| output)
+    };
+    match result {
+        Ok(output) => {
+            println!("built {}", output.display());
+            std::process::exit(0);
+        }
+        Err(error) => {
+            eprintln!("Failed to build {}: {}", script.display(), error);
+            std::process::exit(super::EXIT_SOFTWARE);
+        }
+    }
+}
+
+fn write_executable(output: &Path, script: &str) -> std::io::Result<()> {
+    // Copying keeps the permissions that make the output executable
+    fs::copy(std::env::current_exe()?, output)?;
+    let mut file = OpenOptions::new().append(true).open(output)?;
+    file.write_all(script.as_bytes())?;
+    file.write_all(&(script.len() as u64).to_le_bytes())?;
+    file.write_all(TRAILER_MAGIC)?;
+    Ok(())
+}
+
+// Length of the script at the end of the file, if there is one
+fn trailer(file: &mut File, length: u64) -> std::io::Result<Option<u64>> {
+    if length < 16 {
+        return Ok(None);
+    }
+    let mut trailer = [0; 16];
+    file.seek(SeekFrom::Start(length - 16))?;
+    file.read_exact(&mut trailer)?;
+    if &trailer[8..] != TRAILER_MAGIC {
+        return Ok(None);
+    }
+    let script_length = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+    Ok((script_length <= length - 16).then_some(script_length))
+}
+
+// The script this executable was built with by --self-contained, if any
+pub fn embedded_script() -> Option<String> {
+    let mut file = File::open(std::env::current_exe().ok()?).ok()?;
+    let length = file.metadata().ok()?.len();
+    let script_length = trailer(&mut file, length).ok()??;
+    let mut script = vec![0; script_length as usize];
+    file.seek(SeekFrom::Start(length - 16 - script_length)).ok()?;
+    file.read_exact(&mut script).ok()?;
+    String::from_utf8(script).ok()
+}
@@ -0,0 +1,180 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::watch::{module_dir, resolve_import};
+use crate::interpreter::{data, stdlib};
+use crate::tokenizer::{Token, TokenType, Tokenizer};
+
+pub fn start(script: &Path, output: Option<&Path>) -> ! {
+    let bundled = bundle(script);
+    match output {
+        Some(output) => {
+            if let Err(error) = fs::write(output, &bundled) {
+                eprintln!("Failed to write {}: {}", output.display(), error);
+                std::process::exit(super::EXIT_SOFTWARE);
+            }
+        }
+        None => print!("{}", bundled),
+    }
+    std::process::exit(0);
+}
+
+// The script and every module it imports as one source. Modules come first,
+// each once and after the modules it imports itself, with their import
+// statements taken out; the source is otherwise kept as written. Data files
+// are not bundled: their imports stay and read them when the bundle runs. Exits when
+// a file cannot be read or tokenized.
+pub fn bundle(script: &Path) -> String {
+    let mut bundler = Bundler { seen: HashSet::new(), out: String::new() };
+    bundler.out.push_str(&format!("// Bundled from {} by alpha bundle\n", script.display()));
+    bundler.add(script);
+    bundler.out
+}
+
+struct Bundler {
+    seen: HashSet<PathBuf>,
+    out: String,
+}
+
+impl Bundler {
+    fn add(&mut self, file: &Path) {
+        let key = fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+        if !self.seen.insert(key) {
+            return;
+        }
+        let source = stdlib::read_module(file).unwrap_or_else(|error| {
+            eprintln!("Failed to read file {}: {}", file.display(), error);
+            std::process::exit(super::EXIT_NO_INPUT);
+        });
+        let tokens = tokens(&source, file);
+        // Imports resolve next to the file making them
+        let importer_dir = module_dir(file);
+        let mut imports = Vec::new();
+        for i in 0..tokens.len() {
+            let Some((path_index, renamed)) = module_statement(&tokens, i) else {
+                continue;
+            };
+            let Some(path) = tokens.get(path_index).and_then(|next| next.literal.clone()) else {
+                continue;
+            };
+            if data::format(&path).is_some() {
+                continue;
+            }
+            let Some(module) = resolve_import(&importer_dir, &path) else {
+                eprintln!("{}: could not find module {}", file.display(), path);
+                std::process::exit(super::EXIT_NO_INPUT);
+            };
+            self.add(&module);
+            // In one source every module's names are already global; only
+            // those exported under another name need defining
+            let definitions: Vec<String> = renamed
+                .iter()
+                .map(|(name, exported)| format!("var {} = {};", exported, name))
+                .collect();
+            imports.push((&tokens[i], definitions.join(" ")));
+            let mut end = path_index;
+            if tokens.get(end + 1).is_some_and(|t| t.token_type == TokenType::Semicolon) {
+                end += 1;
+            }
+            imports.extend(tokens[i + 1..=end].iter().map(|token| (token, String::new())));
+        }
+        self.out.push_str(&format!("\n// ---- {} ----\n", file.display()));
+        self.out.push_str(&without(&source, &imports));
+        if !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+    }
+}
+
+// For an import or `export ... from` statement starting at the token, the
+// index of its path and the names it exports under another name
+fn module_statement(tokens: &[Token], start: usize) -> Option<(usize, Vec<(String, String)>)> {
+    let token = &tokens[start];
+    if token.token_type == TokenType::Import {
+        return Some((start + 1, Vec::new()));
+    }
+    if token.token_type != TokenType::IDENTIfIER || token.lexeme != "export" {
+        return None;
+    }
+    let mut renamed = Vec::new();
+    let mut i = start + 1;
+    match tokens.get(i)?.token_type {
+        TokenType::Star => i += 1,
+        TokenType::LeftBrace => {
+            i += 1;
+            while tokens.get(i)?.token_type == TokenType::IDENTIfIER {
+                let name = &tokens[i].lexeme;
+                i += 1;
+                if tokens.get(i)?.lexeme == "as" {
+                    renamed.push((name.clone(), tokens.get(i + 1)?.lexeme.clone()));
+                    i += 2;
+                }
+                if tokens.get(i)?.token_type == TokenType::Comma {
+                    i += 1;
+                }
+            }
+            if tokens.get(i)?.token_type != TokenType::RightBrace {
+                return None;
+            }
+            i += 1;
+        }
+        _ => return None,
+    }
+    (tokens.get(i)?.lexeme == "from").then_some((i + 1, renamed))
+}
+
+fn tokens(source: &str, file: &Path) -> Vec<Token> {
+    let mut tokenizer = Tokenizer::new();
+    // The tokenizer reports its own errors
+    if tokenizer.tokenize(source).is_err() || !tokenizer.errors.is_empty() {
+        eprintln!("{}: syntax error", file.display());
+        std::process::exit(super::EXIT_DATA_ERROR);
+    }
+    tokenizer.into_tokens()
+}
+
+// The source with the given tokens cut out, each replaced by its text if it
+// has any, leaving lines in place and keeping the indentation of what
+// follows them
+fn without(source: &str, tokens: &[(&Token, String)]) -> String {
+    let mut lines: Vec<Vec<(char, bool)>> = source
+        .split('\n')
+        .map(|line| line.chars().map(|c| (c, true)).collect())
+        .collect();
+    let mut replacements: HashMap<(usize, usize), &str> = HashMap::new();
+    for (token, replacement) in tokens {
+        let line = &mut lines[token.line - 1];
+        let start = (token.column - 1).min(line.len());
+        let end = (start + token.lexeme.chars().count()).min(line.len());
+        for (_, kept) in &mut line[start..end] {
+            *kept = false;
+        }
+        if !replacement.is_empty() {
+            replacements.insert((token.line - 1, start), replacement);
+        }
+    }
+    lines
+        .iter()
+        .enumerate()
+        .map(|(number, line)| {
+            let indent: String = line.iter().map(|(c, _)| *c).take_while(|c| c.is_whitespace()).collect();
+            let mut text = String::new();
+            for (column, (c, kept)) in line.iter().enumerate() {
+                if let Some(replacement) = replacements.get(&(number, column)) {
+                    text.push_str(replacement);
+                }
+                if *kept {
+                    text.push(*c);
+                }
+            }
+            if line.iter().all(|(_, kept)| *kept) {
+                text.trim_end().to_string()
+            } else {
+                let rest = text.trim();
+                if rest.is_empty() { String::new() } else { format!("{}{}", indent, rest) }
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
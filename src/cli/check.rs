@@ -0,0 +1,78 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use super::watch::{collect_imports, module_dir, resolve_import};
+use super::DiagnosticsFormat;
+use crate::interpreter::{data, stdlib};
+use crate::parser::Expr;
+
+// Tokenizes and parses the program and every module reachable through its
+// imports without running any of it, and parses the data files it imports. Exits 65 on the first syntax error and
+// 66 when an imported module cannot be read.
+pub fn start(source: &str, name: &str, base_dir: &Path) -> ! {
+    let mut imports = Vec::new();
+    for statement in syntax_tree(source, name) {
+        collect_imports(&statement, &mut imports);
+    }
+    let mut imports: Vec<_> = imports.into_iter().map(|import| (base_dir.to_path_buf(), import)).collect();
+    let mut seen = HashSet::new();
+    while let Some((importer_dir, import)) = imports.pop() {
+        let Some(module) = resolve_import(&importer_dir, &import) else {
+            report(name, "InvalidImport", &format!("could not find module {}", import));
+            std::process::exit(super::EXIT_NO_INPUT);
+        };
+        if !seen.insert(module.clone()) {
+            continue;
+        }
+        let source = read_module(&module);
+        if let Some(format) = data::format(&import) {
+            if let Err(error) = data::parse(format, source) {
+                report(&module.display().to_string(), "InvalidImport", &error);
+                std::process::exit(super::EXIT_DATA_ERROR);
+            }
+            continue;
+        }
+        let mut nested = Vec::new();
+        for statement in syntax_tree(&source, &module.display().to_string()) {
+            collect_imports(&statement, &mut nested);
+        }
+        let module_dir = module_dir(&module);
+        imports.extend(nested.into_iter().map(|import| (module_dir.clone(), import)));
+    }
+    std::process::exit(0);
+}
+
+fn read_module(module: &Path) -> String {
+    stdlib::read_module(module).unwrap_or_else(|error| {
+        eprintln!("Failed to read module {}: {}", module.display(), error);
+        std::process::exit(super::EXIT_NO_INPUT);
+    })
+}
+
+fn report(file: &str, code: &str, message: &str) {
+    match super::diagnostics() {
+        DiagnosticsFormat::Human => eprintln!("{}: {}", file, message),
+        DiagnosticsFormat::Json => eprintln!("{}", super::record("error", code, file, None, None, message)),
+    }
+}
+
+fn syntax_tree(source: &str, name: &str) -> Vec<Expr> {
+    let mut tokenizer = super::tokenizer();
+    if tokenizer.tokenize(source).is_err() || !tokenizer.errors.is_empty() {
+        match super::diagnostics() {
+            DiagnosticsFormat::Human => eprintln!("{}: syntax error", name),
+            DiagnosticsFormat::Json => super::report_tokenizer_errors(&tokenizer, name),
+        }
+        std::process::exit(super::EXIT_DATA_ERROR);
+    }
+    match super::parse(tokenizer.into_tokens()) {
+        Ok(statements) => statements.into_iter().map(|(expr, _)| expr).collect(),
+        Err(error) => {
+            match super::diagnostics() {
+                DiagnosticsFormat::Human => eprintln!("{}: {}", name, super::error_report(&error, source)),
+                DiagnosticsFormat::Json => eprintln!("{}", super::error_record(&error, name)),
+            }
+            std::process::exit(super::EXIT_DATA_ERROR);
+        }
+    }
+}
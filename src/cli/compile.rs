@@ -0,0 +1,47 @@
+use std::fs;
+use std::path::Path;
+
+use crate::parser::Expr;
+
+// A compiled script starts with this, then the version of alpha that wrote it
+const MAGIC: &[u8] = b"ALC\0";
+
+// Writes the parsed script to a file `alpha run` loads without tokenizing or
+// parsing it again. Imported modules are still read from their source when
+// the script runs.
+pub fn start(script: &Path, output: Option<&Path>) -> ! {
+    let exprs = super::load(script).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(super::EXIT_DATA_ERROR);
+    });
+    let output = output.map_or_else(|| script.with_extension("alc"), Path::to_path_buf);
+    let mut bytes = MAGIC.to_vec();
+    let version = env!("CARGO_PKG_VERSION");
+    bytes.push(version.len() as u8);
+    bytes.extend_from_slice(version.as_bytes());
+    bytes.extend(rmp_serde::to_vec(&exprs).expect("the syntax tree serializes"));
+    if let Err(error) = fs::write(&output, bytes) {
+        eprintln!("Failed to write {}: {}", output.display(), error);
+        std::process::exit(super::EXIT_SOFTWARE);
+    }
+    std::process::exit(0);
+}
+
+pub fn is_compiled(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+// The syntax tree of a compiled script. Only files written by this version
+// of alpha are accepted, since the tree changes between versions.
+pub fn decode(bytes: &[u8]) -> Result<Vec<(Expr, usize)>, String> {
+    let rest = &bytes[MAGIC.len()..];
+    let length = *rest.first().ok_or("truncated file")? as usize;
+    let version = rest.get(1..1 + length).ok_or("truncated file")?;
+    if version != env!("CARGO_PKG_VERSION").as_bytes() {
+        return Err(format!(
+            "compiled by alpha {}, recompile it with this version",
+            String::from_utf8_lossy(version)
+        ));
+    }
+    rmp_serde::from_slice(&rest[1 + length..]).map_err(|error| error.to_string())
+}
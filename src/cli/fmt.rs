@@ -0,0 +1,55 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::parser::{printer, Expr};
+
+// Rewrites each file as the parser's printer prints it, or with --check
+// only lists the files that would change and exits with 1 when there are
+// any. The printer works from the syntax tree, which has no comments, so a
+// file with comments is left alone rather than losing them.
+pub fn start(files: &[PathBuf], check: bool) -> ! {
+    let mut status = 0;
+    for file in files {
+        status = status.max(format_file(file, check));
+    }
+    std::process::exit(status);
+}
+
+fn format_file(file: &Path, check: bool) -> i32 {
+    let source = match fs::read_to_string(file) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("Failed to read file {}: {}", file.display(), error);
+            return super::EXIT_NO_INPUT;
+        }
+    };
+    let mut tokenizer = super::tokenizer();
+    // The tokenizer reports its own errors
+    if tokenizer.tokenize(&source).is_err() || !tokenizer.errors.is_empty() {
+        return super::EXIT_DATA_ERROR;
+    }
+    if tokenizer.comments > 0 {
+        eprintln!("{}: has comments, which formatting would drop; left as it is", file.display());
+        return super::EXIT_DATA_ERROR;
+    }
+    let statements: Vec<Expr> = match super::parse(tokenizer.into_tokens()) {
+        Ok(statements) => statements.into_iter().map(|(expr, _)| expr).collect(),
+        Err(error) => {
+            eprintln!("{}: {}", file.display(), super::error_report(&error, &source));
+            return super::EXIT_DATA_ERROR;
+        }
+    };
+    let formatted = printer::print_program(&statements);
+    if formatted == source {
+        return 0;
+    }
+    if check {
+        println!("{}", file.display());
+        return super::EXIT_FAILURE;
+    }
+    if let Err(error) = fs::write(file, formatted) {
+        eprintln!("Failed to write {}: {}", file.display(), error);
+        return super::EXIT_SOFTWARE;
+    }
+    0
+}
@@ -0,0 +1,173 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::interpreter::enviroment::PACKAGES_DIR;
+
+// Records the exact commit of every installed package, in the project directory
+pub const LOCKFILE: &str = "alpha.lock";
+
+#[derive(Serialize, Deserialize, Default)]
+struct Lockfile {
+    #[serde(default, rename = "package")]
+    packages: Vec<LockedPackage>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct LockedPackage {
+    name: String,
+    source: String,
+    commit: String,
+}
+
+// `alpha install SOURCE...` clones each package into alpha_modules/ and
+// locks the commit it got. Without sources, installs what the lockfile
+// records at the recorded commits, then any dependency of the project
+// manifest that is not locked yet. Inside a project both the packages and
+// the lockfile live next to alpha.toml.
+pub fn start(sources: &[String]) -> ! {
+    let manifest = super::manifest::find();
+    if let Some((dir, _)) = &manifest {
+        if let Err(error) = std::env::set_current_dir(dir) {
+            fail(&dir.display().to_string(), &error.to_string());
+        }
+    }
+    let mut lockfile = read_lockfile();
+    let mut wanted: Vec<(Option<&str>, &str)> = Vec::new();
+    if sources.is_empty() {
+        for package in &lockfile.packages {
+            if let Err(error) = restore(package) {
+                fail(&package.name, &error);
+            }
+        }
+        if let Some((_, manifest)) = &manifest {
+            for (name, source) in &manifest.dependencies {
+                if !lockfile.packages.iter().any(|locked| &locked.name == name) {
+                    wanted.push((Some(name), source));
+                }
+            }
+        }
+    } else {
+        wanted.extend(sources.iter().map(|source| (None, source.as_str())));
+    }
+    if wanted.is_empty() {
+        std::process::exit(0);
+    }
+    for (name, source) in wanted {
+        let package = install(source, name).unwrap_or_else(|error| fail(source, &error));
+        println!("installed {} at {}", package.name, &package.commit[..12.min(package.commit.len())]);
+        lockfile.packages.retain(|locked| locked.name != package.name);
+        lockfile.packages.push(package);
+    }
+    lockfile.packages.sort_by(|a, b| a.name.cmp(&b.name));
+    write_lockfile(&lockfile);
+    std::process::exit(0);
+}
+
+fn fail(what: &str, error: &str) -> ! {
+    eprintln!("Failed to install {}: {}", what, error);
+    std::process::exit(super::EXIT_FAILURE);
+}
+
+// SOURCE is a git URL or path, or owner/repo for a GitHub repository,
+// optionally followed by #REF to pick a branch, tag or commit. The package
+// is named after the repository unless the manifest names it.
+fn install(spec: &str, name: Option<&str>) -> Result<LockedPackage, String> {
+    let (source, reference) = match spec.rsplit_once('#') {
+        Some((source, reference)) => (source, Some(reference)),
+        None => (spec, None),
+    };
+    let url = repository_url(source)?;
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => package_name(&url)?,
+    };
+    let dir = package_dir(&name);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).map_err(|e| e.to_string())?;
+    }
+    git(None, &["clone", "--quiet", &url, &dir.to_string_lossy()])?;
+    if let Some(reference) = reference {
+        git(Some(&dir), &["checkout", "--quiet", reference])?;
+    }
+    let commit = git(Some(&dir), &["rev-parse", "HEAD"])?;
+    Ok(LockedPackage { name, source: url, commit })
+}
+
+fn restore(package: &LockedPackage) -> Result<(), String> {
+    let dir = package_dir(&package.name);
+    if !dir.exists() {
+        git(None, &["clone", "--quiet", &package.source, &dir.to_string_lossy()])?;
+    } else if git(Some(&dir), &["rev-parse", "HEAD"])? == package.commit {
+        return Ok(());
+    } else {
+        git(Some(&dir), &["fetch", "--quiet", "origin"])?;
+    }
+    git(Some(&dir), &["checkout", "--quiet", &package.commit])?;
+    println!("installed {} at {}", package.name, &package.commit[..12.min(package.commit.len())]);
+    Ok(())
+}
+
+fn repository_url(source: &str) -> Result<String, String> {
+    let is_url = source.contains("://") || source.starts_with("git@") || source.ends_with(".git");
+    if is_url || Path::new(source).exists() {
+        return Ok(source.to_string());
+    }
+    match source.split_once('/') {
+        Some((owner, repo)) if !owner.is_empty() && !repo.is_empty() && !repo.contains('/') => {
+            Ok(format!("https://github.com/{}/{}.git", owner, repo))
+        }
+        _ => Err("expected a git URL or owner/repo".to_string()),
+    }
+}
+
+// The last part of the repository path, which is also the name to import it by
+fn package_name(url: &str) -> Result<String, String> {
+    let last = url.trim_end_matches('/').rsplit(['/', ':']).next().unwrap_or("");
+    let name = last.strip_suffix(".git").unwrap_or(last);
+    if name.is_empty() || name.starts_with('.') {
+        return Err(format!("cannot tell the package name from {}", url));
+    }
+    Ok(name.to_string())
+}
+
+fn package_dir(name: &str) -> PathBuf {
+    Path::new(PACKAGES_DIR).join(name)
+}
+
+// Runs git, giving back its trimmed output
+fn git(dir: Option<&Path>, args: &[&str]) -> Result<String, String> {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.arg("-C").arg(dir);
+    }
+    let output = command
+        .args(args)
+        .output()
+        .map_err(|error| format!("cannot run git: {}", error))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn read_lockfile() -> Lockfile {
+    match fs::read_to_string(LOCKFILE) {
+        Ok(text) => toml::from_str(&text).unwrap_or_else(|error| {
+            eprintln!("{} is not valid: {}", LOCKFILE, error);
+            std::process::exit(super::EXIT_DATA_ERROR);
+        }),
+        Err(_) => Lockfile::default(),
+    }
+}
+
+fn write_lockfile(lockfile: &Lockfile) {
+    let text = toml::to_string(lockfile).expect("the lockfile serializes");
+    let text = format!("# Written by `alpha install`; commit it with the project\n\n{}", text);
+    if let Err(error) = fs::write(LOCKFILE, text) {
+        eprintln!("Failed to write {}: {}", LOCKFILE, error);
+        std::process::exit(super::EXIT_SOFTWARE);
+    }
+}
@@ -0,0 +1,510 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::watch::{collect_imports, module_dir, parse, resolve_import};
+use super::DiagnosticsFormat;
+use crate::interpreter::{data, enviroment::Environment, stdlib, value::Value};
+use crate::parser::Expr;
+use crate::tokenizer::Token;
+
+// Checks each file without running it and prints what it finds as
+// `file:line:column: message`, or as JSON records with --diagnostics=json
+pub fn start(files: &[PathBuf]) -> ! {
+    let natives = natives();
+    let mut status = 0;
+    for file in files {
+        status = status.max(lint_file(file, &natives));
+    }
+    std::process::exit(status);
+}
+
+fn natives() -> Environment {
+    let mut natives = Environment::new(PathBuf::new());
+    natives.register_native_functions();
+    natives
+}
+
+// What `alpha run --strict` finds before running a script: what lint
+// reports, and parameters that are never used
+pub fn strict_diagnostics(statements: &[Expr], base_dir: &Path) -> Vec<Diagnostic> {
+    let mut linter = Linter::new();
+    linter.strict = true;
+    linter.statements(statements);
+    linter.check_names(statements, base_dir, &natives());
+    let mut diagnostics = linter.diagnostics;
+    diagnostics.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+    diagnostics
+}
+
+fn lint_file(file: &Path, natives: &Environment) -> i32 {
+    let source = match fs::read_to_string(file) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("Failed to read file {}: {}", file.display(), error);
+            return super::EXIT_NO_INPUT;
+        }
+    };
+    let name = file.display().to_string();
+    let mut tokenizer = super::tokenizer();
+    if tokenizer.tokenize(&source).is_err() || !tokenizer.errors.is_empty() {
+        super::report_tokenizer_errors(&tokenizer, &name);
+        return super::EXIT_DATA_ERROR;
+    }
+    let statements: Vec<Expr> = match super::parse(tokenizer.into_tokens()) {
+        Ok(statements) => statements.into_iter().map(|(expr, _)| expr).collect(),
+        Err(error) => {
+            match super::diagnostics() {
+                DiagnosticsFormat::Human => eprintln!("{}: {}", name, super::error_report(&error, &source)),
+                DiagnosticsFormat::Json => eprintln!("{}", super::error_record(&error, &name)),
+            }
+            return super::EXIT_DATA_ERROR;
+        }
+    };
+
+    let mut linter = Linter::new();
+    linter.statements(&statements);
+    let base_dir = file.parent().unwrap_or_else(|| Path::new(""));
+    linter.check_names(&statements, base_dir, natives);
+
+    let mut diagnostics = linter.diagnostics;
+    diagnostics.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+    for diagnostic in &diagnostics {
+        match super::diagnostics() {
+            DiagnosticsFormat::Human => {
+                println!("{}:{}:{}: {}", name, diagnostic.line, diagnostic.column, diagnostic.message)
+            }
+            DiagnosticsFormat::Json => println!("{}", diagnostic.record("warning", &name)),
+        }
+    }
+    if diagnostics.is_empty() {
+        0
+    } else {
+        super::EXIT_FAILURE
+    }
+}
+
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Diagnostic {
+    // The finding as a --diagnostics=json record
+    pub fn record(&self, severity: &str, file: &str) -> String {
+        super::record(severity, "Lint", file, Some(self.line), Some(self.column), &self.message)
+    }
+}
+
+struct Declaration {
+    name: String,
+    // None for names without a token of their own, like catch parameters
+    token: Option<Token>,
+    // Only local variables are reported when unused: globals may be read by
+    // the scripts importing this one, and parameters are often required
+    check_unused: bool,
+    used: bool,
+}
+
+struct Linter {
+    scopes: Vec<Vec<Declaration>>,
+    // Every name declared anywhere in the file, class members included
+    declared: HashSet<String>,
+    // Callees of plain calls with their argument counts, and variables
+    // read, checked once the whole file has been seen
+    calls: Vec<(Token, usize)>,
+    reads: Vec<Token>,
+    // Parameter counts of the functions declared in the file; None for
+    // names declared more than once in other ways
+    arities: HashMap<String, Option<usize>>,
+    // Also reports unused parameters
+    strict: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter {
+    fn new() -> Self {
+        Linter {
+            scopes: vec![Vec::new()],
+            declared: HashSet::new(),
+            calls: Vec::new(),
+            reads: Vec::new(),
+            arities: HashMap::new(),
+            strict: false,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn report(&mut self, token: &Token, message: String) {
+        self.diagnostics.push(Diagnostic {
+            line: token.line,
+            column: token.column,
+            message,
+        });
+    }
+
+    fn statements(&mut self, statements: &[Expr]) {
+        let mut returned = false;
+        for statement in statements {
+            // A `;` after a statement is a statement of its own
+            if *statement == Expr::Nil {
+                continue;
+            }
+            if returned {
+                if let Some(token) = statement.first_token() {
+                    self.report(token, "unreachable code after return or throw".to_string());
+                }
+                returned = false;
+            }
+            self.expr(statement);
+            if always_returns(statement) {
+                returned = true;
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary(left, _, right) | Expr::Logical(left, _, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Grouping(expr) | Expr::Unary(_, expr) | Expr::Await(expr) => self.expr(expr),
+            Expr::Literal(..) | Expr::Nil | Expr::Import(_, None) | Expr::Export(_, None) => {}
+            Expr::Import(_, Some(alias)) => {
+                let check_unused = self.scopes.len() > 1;
+                self.declare(&alias.lexeme, Some(alias), check_unused);
+            }
+            // Exported names are for other modules to use
+            Expr::Export(_, Some(names)) => {
+                for (_, exported) in names {
+                    self.declare(&exported.lexeme, Some(exported), false);
+                }
+            }
+            Expr::Array(elements) => {
+                for element in elements {
+                    self.expr(element);
+                }
+            }
+            Expr::Dictionary(entries) => {
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+            }
+            Expr::Variable(name) => {
+                self.read(&name.lexeme);
+                self.reads.push(name.clone());
+            }
+            Expr::Assign(name, value) => {
+                self.reads.push(name.clone());
+                self.expr(value);
+            }
+            Expr::Return(_, value) | Expr::Throw(_, value) => self.expr(value),
+            Expr::Defer(_, deferred) => self.expr(deferred),
+            Expr::Let(name, initializer) => {
+                self.expr(initializer);
+                let check_unused = self.scopes.len() > 1;
+                self.declare(&name.lexeme, Some(name), check_unused);
+            }
+            Expr::Block(statements) => {
+                self.scopes.push(Vec::new());
+                self.statements(statements);
+                self.end_scope();
+            }
+            Expr::Function(name, params, body) | Expr::AsyncFunction(name, params, body) => {
+                let previous = self.arities.get(&name.lexeme).copied();
+                self.declare(&name.lexeme, Some(name), false);
+                let arity = match previous {
+                    None => Some(params.len()),
+                    Some(arity) => arity.filter(|&arity| arity == params.len()),
+                };
+                self.arities.insert(name.lexeme.clone(), arity);
+                self.function(params, body);
+            }
+            Expr::Class(name, members) => {
+                self.declare(&name.lexeme, Some(name), false);
+                // Methods and constants live on the class rather than in the enclosing scope
+                for member in members {
+                    match member {
+                        Expr::Function(method, params, body) => {
+                            self.declared.insert(method.lexeme.clone());
+                            self.function(params, body);
+                        }
+                        Expr::Let(constant, value) => {
+                            self.declared.insert(constant.lexeme.clone());
+                            self.expr(value);
+                        }
+                        other => self.expr(other),
+                    }
+                }
+            }
+            Expr::Call(owner, callee, arguments) => {
+                match owner {
+                    // The callee names a method of the owner
+                    Some(owner) => self.expr(owner),
+                    None => match &**callee {
+                        Expr::Variable(name) => {
+                            self.calls.push((name.clone(), arguments.len()));
+                            self.read(&name.lexeme);
+                        }
+                        callee => self.expr(callee),
+                    },
+                }
+                for argument in arguments {
+                    self.expr(argument);
+                }
+            }
+            Expr::If(condition, then_branch, else_branch) => {
+                self.condition(condition);
+                self.branch(then_branch);
+                self.branch(else_branch);
+            }
+            Expr::While(condition, body) => {
+                self.condition(condition);
+                self.branch(body);
+            }
+            Expr::For(initializer, condition, increment, body) => {
+                // Variables of the initializer belong to the loop
+                self.scopes.push(Vec::new());
+                self.expr(initializer);
+                self.condition(condition);
+                self.expr(increment);
+                self.branch(body);
+                self.end_scope();
+            }
+            Expr::ForAwait(name, iterable, body) => {
+                self.expr(iterable);
+                self.scopes.push(Vec::new());
+                self.declare(&name.lexeme, Some(name), false);
+                self.expr(body);
+                self.end_scope();
+            }
+            // The property of `a.b` and the index of `a[b]` look alike, so
+            // both count as reads, though of a name that need not be declared
+            Expr::Get(object, property) => {
+                self.expr(object);
+                self.property(property);
+            }
+            Expr::Set(object, property, value) => {
+                self.expr(object);
+                self.property(property);
+                self.expr(value);
+            }
+            Expr::TryCatch(try_catch) => {
+                self.expr(&try_catch.try_block);
+                self.scopes.push(Vec::new());
+                self.declare(&try_catch.catch_param, None, false);
+                self.expr(&try_catch.catch_block);
+                self.end_scope();
+            }
+        }
+    }
+
+    fn property(&mut self, property: &Expr) {
+        match property {
+            Expr::Variable(name) => self.read(&name.lexeme),
+            // A method call, a.b(arguments)
+            Expr::Call(None, method, arguments) if matches!(**method, Expr::Variable(_)) => {
+                self.property(method);
+                for argument in arguments {
+                    self.expr(argument);
+                }
+            }
+            property => self.expr(property),
+        }
+    }
+
+    // The body of a function runs in the scope holding its parameters.
+    // Parameters starting with `_` are meant to go unused.
+    fn function(&mut self, params: &[Token], body: &Expr) {
+        self.scopes.push(Vec::new());
+        for param in params {
+            let check_unused = self.strict && !param.lexeme.starts_with('_');
+            self.declare(&param.lexeme, Some(param), check_unused);
+        }
+        match body {
+            Expr::Block(statements) => self.statements(statements),
+            body => self.expr(body),
+        }
+        self.end_scope();
+    }
+
+    // A branch or loop body without braces is still a scope
+    fn branch(&mut self, body: &Expr) {
+        match body {
+            Expr::Block(_) => self.expr(body),
+            body => {
+                self.scopes.push(Vec::new());
+                self.expr(body);
+                self.end_scope();
+            }
+        }
+    }
+
+    fn condition(&mut self, condition: &Expr) {
+        if let Some(token) = assignment(condition) {
+            self.report(token, "assignment in condition, did you mean '=='?".to_string());
+        }
+        self.expr(condition);
+    }
+
+    fn declare(&mut self, name: &str, token: Option<&Token>, check_unused: bool) {
+        if let Some(token) = token {
+            let (_, outer) = self.scopes.split_last().unwrap();
+            let shadowed = outer
+                .iter()
+                .rev()
+                .find_map(|scope| scope.iter().find(|declaration| declaration.name == name));
+            if let Some(Declaration { token: Some(original), .. }) = shadowed {
+                let message = format!("'{}' shadows the declaration on line {}", name, original.line);
+                self.report(token, message);
+            }
+        }
+        self.declared.insert(name.to_string());
+        self.scopes.last_mut().unwrap().push(Declaration {
+            name: name.to_string(),
+            token: token.cloned(),
+            check_unused,
+            used: false,
+        });
+    }
+
+    fn read(&mut self, name: &str) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(declaration) = scope.iter_mut().rev().find(|d| d.name == name) {
+                declaration.used = true;
+                return;
+            }
+        }
+    }
+
+    fn end_scope(&mut self) {
+        for declaration in self.scopes.pop().unwrap() {
+            if let (true, false, Some(token)) =
+                (declaration.check_unused, declaration.used, &declaration.token)
+            {
+                self.report(token, format!("'{}' is never used", declaration.name));
+            }
+        }
+    }
+
+    // Calls and reads of names that are not declared in the file, in a
+    // module it imports or among the natives, and calls with another number
+    // of arguments than the function takes. Functions resolve where they are
+    // called, not where they are written, so any declaration in the file
+    // counts.
+    fn check_names(&mut self, statements: &[Expr], base_dir: &Path, natives: &Environment) {
+        let Some(module_names) = module_names(statements, base_dir) else {
+            // An import we cannot follow could define anything
+            return;
+        };
+        let known = |name: &str| {
+            self.declared.contains(name) || module_names.contains(name) || natives.get(name).is_some()
+        };
+        let mut found = Vec::new();
+        let mut report = |token: &Token, message: String| {
+            found.push(Diagnostic { line: token.line, column: token.column, message });
+        };
+        for name in &self.reads {
+            // Methods see their instance as `this`
+            if !known(&name.lexeme) && name.lexeme != "this" {
+                report(name, format!("use of undefined variable '{}'", name.lexeme));
+            }
+        }
+        for (name, count) in &self.calls {
+            if !known(&name.lexeme) {
+                report(name, format!("call to undefined function '{}'", name.lexeme));
+                continue;
+            }
+            let expected = match self.arities.get(&name.lexeme) {
+                Some(Some(arity)) if arity != count => Some(arguments(*arity)),
+                Some(_) => None,
+                None if module_names.contains(&name.lexeme) => None,
+                None => match natives.get(&name.lexeme) {
+                    Some(Value::NativeFunction(native)) if !native.accepts(*count) => {
+                        Some(match native.max_arity() {
+                            max if max == native.arity() => arguments(max),
+                            usize::MAX => format!("at least {}", arguments(native.arity())),
+                            max => format!("{} to {} arguments", native.arity(), max),
+                        })
+                    }
+                    _ => None,
+                },
+            };
+            if let Some(expected) = expected {
+                report(name, format!("'{}' takes {} but is given {}", name.lexeme, expected, count));
+            }
+        }
+        self.diagnostics.extend(found);
+    }
+}
+
+fn arguments(count: usize) -> String {
+    match count {
+        1 => "1 argument".to_string(),
+        count => format!("{} arguments", count),
+    }
+}
+
+// Global names of every module reachable through imports, or None when an
+// import cannot be found
+fn module_names(statements: &[Expr], base_dir: &Path) -> Option<HashSet<String>> {
+    let mut imports = Vec::new();
+    for statement in statements {
+        collect_imports(statement, &mut imports);
+    }
+    let mut imports: Vec<_> = imports.into_iter().map(|import| (base_dir.to_path_buf(), import)).collect();
+    let mut seen = HashSet::new();
+    let mut names = HashSet::new();
+    while let Some((importer_dir, import)) = imports.pop() {
+        let module = resolve_import(&importer_dir, &import)?;
+        // Data files declare nothing; their name is declared by the import
+        if data::format(&import).is_some() || !seen.insert(module.clone()) {
+            continue;
+        }
+        let source = stdlib::read_module(&module).ok()?;
+        let module_dir = module_dir(&module);
+        let mut nested = Vec::new();
+        for statement in parse(&source) {
+            match &statement {
+                Expr::Let(name, _)
+                | Expr::Function(name, ..)
+                | Expr::AsyncFunction(name, ..)
+                | Expr::Class(name, _) => {
+                    names.insert(name.lexeme.clone());
+                }
+                Expr::Export(_, Some(exported)) => {
+                    names.extend(exported.iter().map(|(_, name)| name.lexeme.clone()));
+                }
+                _ => {}
+            }
+            collect_imports(&statement, &mut nested);
+        }
+        imports.extend(nested.into_iter().map(|import| (module_dir.clone(), import)));
+    }
+    Some(names)
+}
+
+fn always_returns(statement: &Expr) -> bool {
+    match statement {
+        Expr::Return(..) | Expr::Throw(..) => true,
+        Expr::Block(statements) => statements.iter().any(always_returns),
+        Expr::If(_, then_branch, else_branch) => {
+            always_returns(then_branch) && always_returns(else_branch)
+        }
+        _ => false,
+    }
+}
+
+// An assignment made by the condition itself rather than inside a call
+fn assignment(condition: &Expr) -> Option<&Token> {
+    match condition {
+        Expr::Assign(name, _) => Some(name),
+        Expr::Set(object, ..) => object.first_token(),
+        Expr::Grouping(expr) | Expr::Unary(_, expr) => assignment(expr),
+        Expr::Logical(left, _, right) => assignment(left).or_else(|| assignment(right)),
+        _ => None,
+    }
+}
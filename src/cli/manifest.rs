@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+pub use crate::interpreter::enviroment::MANIFEST;
+
+// alpha.toml at the root of a project:
+//
+//     [package]
+//     name = "app"
+//     entry = "src/main.la"
+//
+//     [dependencies]
+//     json = "someone/json#v1.2"
+//
+//     [run]
+//     args = ["--port", "8080"]
+//     trace = true            # or the name of a function
+//     strict = true
+//     sandbox = ["net"]       # or true to deny every capability
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default)]
+    pub package: Package,
+    // Package name to the source `alpha install` takes
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    pub run: RunOptions,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Package {
+    pub name: Option<String>,
+    #[serde(default = "default_entry")]
+    pub entry: PathBuf,
+}
+
+impl Default for Package {
+    fn default() -> Self {
+        Package { name: None, entry: default_entry() }
+    }
+}
+
+fn default_entry() -> PathBuf {
+    PathBuf::from("main.la")
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RunOptions {
+    // Passed to the script before any given on the command line
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub trace: Option<Trace>,
+    #[serde(default)]
+    pub strict: bool,
+    pub sandbox: Option<Sandbox>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Trace {
+    All(bool),
+    Function(String),
+}
+
+impl Trace {
+    // The value --trace would have been given
+    pub fn flag(&self) -> Option<String> {
+        match self {
+            Trace::All(true) => Some(String::new()),
+            Trace::All(false) => None,
+            Trace::Function(name) => Some(name.clone()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Sandbox {
+    All(bool),
+    Only(Vec<super::Capability>),
+}
+
+impl Sandbox {
+    // The value --sandbox would have been given
+    pub fn flag(self) -> Option<Vec<super::Capability>> {
+        match self {
+            Sandbox::All(true) => Some(Vec::new()),
+            Sandbox::All(false) => None,
+            Sandbox::Only(capabilities) => Some(capabilities),
+        }
+    }
+}
+
+// The manifest of the project the working directory is in, looked for in
+// the directory and its parents, together with the directory holding it.
+// Exits 65 if the manifest is not valid.
+pub fn find() -> Option<(PathBuf, Manifest)> {
+    let cwd = std::env::current_dir().ok()?;
+    let dir = cwd.ancestors().find(|dir| dir.join(MANIFEST).is_file())?;
+    Some((dir.to_path_buf(), load(dir)))
+}
+
+fn load(dir: &Path) -> Manifest {
+    let path = dir.join(MANIFEST);
+    let text = fs::read_to_string(&path).unwrap_or_else(|error| {
+        eprintln!("Failed to read {}: {}", path.display(), error);
+        std::process::exit(super::EXIT_NO_INPUT);
+    });
+    toml::from_str(&text).unwrap_or_else(|error| {
+        eprintln!("{} is not valid: {}", path.display(), error);
+        std::process::exit(super::EXIT_DATA_ERROR);
+    })
+}
@@ -0,0 +1,398 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use crate::error::{InterpreterError, SourceLocation};
+use crate::interpreter::enviroment::PACKAGES_DIR;
+use crate::interpreter::options::InterpreterOptions;
+use crate::interpreter::{json, stdlib};
+use crate::parser::Expr;
+use crate::tokenizer::{Token, Tokenizer};
+
+pub mod bench;
+pub mod build;
+pub mod bundle;
+pub mod check;
+pub mod compile;
+pub mod fmt;
+pub mod install;
+pub mod lint;
+pub mod manifest;
+pub mod repl;
+pub mod test;
+pub mod watch;
+
+// Exit statuses, following the BSD sysexits convention, except for the
+// plain failure of a check or test
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 64;
+pub const EXIT_DATA_ERROR: i32 = 65;
+pub const EXIT_NO_INPUT: i32 = 66;
+pub const EXIT_SOFTWARE: i32 = 70;
+
+#[derive(Parser)]
+#[command(
+    name = "alpha",
+    version,
+    about = "The alpha scripting language",
+    args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // `alpha script.la` is short for `alpha run script.la`
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run a script (the default when no command is given)
+    Run(RunArgs),
+    /// Start an interactive session
+    Repl,
+    /// Check scripts for likely mistakes without running them
+    Lint(LintArgs),
+    /// Rewrite scripts in the standard layout, or list those not in it with --check
+    Fmt(FmtArgs),
+    /// Run the benchmark scripts (*_bench.la or *_bench.alpha) found under PATHs
+    Bench(BenchArgs),
+    /// Run the test_* functions of the test scripts (*_test.la or *_test.alpha) found under PATHs
+    Test(TestArgs),
+    /// Install packages into alpha_modules/, or those in alpha.lock when none are given
+    Install(InstallArgs),
+    /// Write a script and the modules it imports as a single file
+    Bundle(BundleArgs),
+    /// Save a parsed script (.alc) that `alpha run` starts without parsing it again
+    Compile(CompileArgs),
+    /// Bundle a script for distribution, as an executable with --self-contained
+    Build(BuildArgs),
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    /// Run PROGRAM instead of a script file; every argument is then passed to it
+    #[arg(short = 'e', long = "eval", value_name = "PROGRAM")]
+    pub eval: Option<String>,
+
+    /// Only check that the program and the modules it imports parse
+    #[arg(long, conflicts_with_all = ["tokenize", "parse"])]
+    pub check: bool,
+
+    /// Print the tokens instead of running the program
+    #[arg(long, conflicts_with = "parse")]
+    pub tokenize: bool,
+
+    /// Print the syntax tree instead of running the program
+    #[arg(long)]
+    pub parse: bool,
+
+    /// Before running, look for undefined variables, unused parameters and
+    /// calls with the wrong number of arguments, and don't run if any are found;
+    /// while running, make + and == fail on values of different types
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Log every evaluated expression to stderr, or only those evaluated
+    /// inside calls to FUNCTION
+    #[arg(long, value_name = "FUNCTION", num_args = 0..=1, require_equals = true, default_missing_value = "")]
+    pub trace: Option<String>,
+
+    /// Run an imported module only once a name it declares is first used,
+    /// rather than when it is imported
+    #[arg(long)]
+    pub lazy_imports: bool,
+
+    /// When an error ends the script, open a prompt for looking at the stack
+    /// and the variables where it happened before exiting
+    #[arg(long, conflicts_with = "watch")]
+    pub debug_on_error: bool,
+
+    /// Keep the script from using the file system, the network, exiting the
+    /// process, starting programs or loading plugins, or only from the
+    /// CAPABILITIES given
+    #[arg(long, value_name = "CAPABILITIES", value_enum, value_delimiter = ',', num_args = 0..=1, require_equals = true)]
+    pub sandbox: Option<Vec<Capability>>,
+
+    /// Run the script again whenever it or a module it imports changes
+    #[arg(short, long, conflicts_with = "eval")]
+    pub watch: bool,
+
+    /// Print errors for people to read, or as one JSON object per line
+    #[arg(long, value_name = "FORMAT", value_enum, default_value_t = DiagnosticsFormat::Human)]
+    pub diagnostics: DiagnosticsFormat,
+
+    /// Script file followed by the arguments passed to it
+    #[arg(value_name = "FILE [ARGS]", trailing_var_arg = true)]
+    pub args: Vec<String>,
+}
+
+// What --sandbox can take away from a script
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Capability {
+    Fs,
+    Net,
+    Exit,
+    Subprocess,
+    Plugin,
+}
+
+// The interpreter options for --sandbox: everything is allowed without it,
+// nothing when it lists no capabilities
+pub fn sandbox_options(sandbox: Option<&[Capability]>) -> InterpreterOptions {
+    let Some(denied) = sandbox else {
+        return InterpreterOptions::default();
+    };
+    if denied.is_empty() {
+        return InterpreterOptions::sandboxed();
+    }
+    InterpreterOptions {
+        filesystem: !denied.contains(&Capability::Fs),
+        network: !denied.contains(&Capability::Net),
+        process_exit: !denied.contains(&Capability::Exit),
+        subprocess: !denied.contains(&Capability::Subprocess),
+        plugins: !denied.contains(&Capability::Plugin),
+        ..InterpreterOptions::default()
+    }
+}
+
+#[derive(Args)]
+pub struct LintArgs {
+    /// Scripts to check
+    #[arg(value_name = "FILE", required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Print findings and errors for people to read, or as one JSON object per line
+    #[arg(long, value_name = "FORMAT", value_enum, default_value_t = DiagnosticsFormat::Human)]
+    pub diagnostics: DiagnosticsFormat,
+}
+
+#[derive(Args)]
+pub struct FmtArgs {
+    /// Scripts to format
+    #[arg(value_name = "FILE", required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Change nothing, print the files that need formatting and exit with 1 if any do
+    #[arg(long)]
+    pub check: bool,
+}
+
+#[derive(Args)]
+pub struct BenchArgs {
+    /// Benchmark files, or directories to search for them
+    #[arg(value_name = "PATH", default_value = ".")]
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct TestArgs {
+    /// Test files, or directories to search for them
+    #[arg(value_name = "PATH", default_value = ".")]
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct InstallArgs {
+    /// Git URL or path, or owner/repo on GitHub, optionally followed by #REF
+    #[arg(value_name = "SOURCE")]
+    pub sources: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct BundleArgs {
+    /// Script to bundle
+    #[arg(value_name = "FILE")]
+    pub script: PathBuf,
+
+    /// Where to write the bundle instead of standard output
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct CompileArgs {
+    /// Script to compile
+    #[arg(value_name = "FILE")]
+    pub script: PathBuf,
+
+    /// Where to write the compiled script, by default next to it with the .alc extension
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct BuildArgs {
+    /// Script to build, by default the entry point of the project's alpha.toml
+    #[arg(value_name = "FILE")]
+    pub script: Option<PathBuf>,
+
+    /// Produce an executable holding the interpreter and the script
+    #[arg(long)]
+    pub self_contained: bool,
+
+    /// Where to write the result, by default in the working directory named after the script
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+// Scripts named `<name><suffix>.la` or `<name><suffix>.alpha` under the given
+// paths, in order. Files named on the command line are taken as they are;
+// hidden directories and build output are not searched.
+pub fn find_scripts(paths: &[PathBuf], suffix: &str) -> Vec<PathBuf> {
+    let mut scripts = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            search_dir(path, suffix, &mut scripts);
+        } else {
+            scripts.push(path.clone());
+        }
+    }
+    scripts
+}
+
+fn search_dir(dir: &Path, suffix: &str, scripts: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|e| e.path())).collect();
+    entries.sort();
+    for entry in entries {
+        let name = entry.file_name().and_then(|name| name.to_str()).unwrap_or("");
+        if entry.is_dir() {
+            if !name.starts_with('.') && name != "target" && name != PACKAGES_DIR {
+                search_dir(&entry, suffix, scripts);
+            }
+        } else if [".la", ".alpha"]
+            .iter()
+            .any(|extension| name.strip_suffix(extension).is_some_and(|stem| stem.ends_with(suffix)))
+        {
+            scripts.push(entry);
+        }
+    }
+}
+
+// How errors and findings are printed. A JSON record is one line:
+//
+//     {"severity":"error","code":"DivisionByZero","file":"main.la","line":3,"column":19,"message":"Division by zero"}
+//
+// line and column are null when not known.
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum DiagnosticsFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+static DIAGNOSTICS: OnceLock<DiagnosticsFormat> = OnceLock::new();
+
+pub fn set_diagnostics(format: DiagnosticsFormat) {
+    let _ = DIAGNOSTICS.set(format);
+}
+
+pub fn diagnostics() -> DiagnosticsFormat {
+    DIAGNOSTICS.get().copied().unwrap_or_default()
+}
+
+// A tokenizer printing its errors as it finds them, unless they are
+// wanted as JSON; report_tokenizer_errors prints them then
+pub fn tokenizer() -> Tokenizer {
+    let mut tokenizer = Tokenizer::new();
+    tokenizer.print_errors = diagnostics() == DiagnosticsFormat::Human;
+    tokenizer
+}
+
+pub fn report_tokenizer_errors(tokenizer: &Tokenizer, file: &str) {
+    if diagnostics() != DiagnosticsFormat::Json {
+        return;
+    }
+    for error in &tokenizer.errors {
+        match error.downcast_ref::<InterpreterError>() {
+            Some(error) => eprintln!("{}", error_record(error, file)),
+            None => eprintln!("{}", record("error", "SyntaxError", file, None, None, &error.to_string())),
+        }
+    }
+}
+
+// Prints an error of the source read from file as chosen by --diagnostics
+pub fn report_error(error: &InterpreterError, source: &str, file: &str) {
+    match diagnostics() {
+        DiagnosticsFormat::Human => eprintln!("{}", error_report(error, source)),
+        DiagnosticsFormat::Json => eprintln!("{}", error_record(error, file)),
+    }
+}
+
+// The JSON record of an error. An error located in a module names that
+// module rather than file.
+pub fn error_record(error: &InterpreterError, file: &str) -> String {
+    let location = error.location();
+    let file = match location.and_then(|location| location.file.as_ref()) {
+        Some(module) => module.display().to_string(),
+        None => file.to_string(),
+    };
+    let line = location.map(|location| location.line).or_else(|| error.line()).filter(|line| *line > 0);
+    let column = location.map(|location| location.column);
+    record("error", error.type_name(), &file, line, column, &error.message())
+}
+
+pub fn record(
+    severity: &str,
+    code: &str,
+    file: &str,
+    line: Option<usize>,
+    column: Option<usize>,
+    message: &str,
+) -> String {
+    let number = |n: Option<usize>| n.map_or_else(|| "null".to_string(), |n| n.to_string());
+    format!(
+        "{{\"severity\":{},\"code\":{},\"file\":{},\"line\":{},\"column\":{},\"message\":{}}}",
+        json::quote(severity),
+        json::quote(code),
+        json::quote(file),
+        number(line),
+        number(column),
+        json::quote(message)
+    )
+}
+
+// Reads and parses a script for the runners, which keep going after a bad file
+pub fn load(path: &Path) -> Result<Vec<(Expr, usize)>, String> {
+    let source = fs::read_to_string(path)
+        .map_err(|error| format!("Failed to read file {}: {}", path.display(), error))?;
+    let mut tokenizer = Tokenizer::new();
+    // The tokenizer reports its own errors
+    if tokenizer.tokenize(&source).is_err() || !tokenizer.errors.is_empty() {
+        return Err(format!("{}: syntax error", path.display()));
+    }
+    parse(tokenizer.into_tokens()).map_err(|error| format!("{}: {}", path.display(), error_report(&error, &source)))
+}
+
+// Parses tokens, locating a parse error at the token parsing stopped at
+pub fn parse(tokens: Vec<Token>) -> Result<Vec<(Expr, usize)>, InterpreterError> {
+    let mut parser = crate::parser::Parser::new(tokens);
+    parser
+        .parse()
+        .map_err(|error| error.located_at(SourceLocation::at(&parser.current_token())))
+}
+
+// The error as printed, followed by the line of the source it happened on
+// when known. An error located in a module shows that module's line.
+pub fn error_report(error: &InterpreterError, source: &str) -> String {
+    let Some(location) = error.location() else {
+        return error.to_string();
+    };
+    let snippet = match &location.file {
+        Some(file) => stdlib::read_module(file).ok().and_then(|source| location.snippet(&source)).map(|snippet| {
+            format!("  --> {}:{}:{}\n{}", file.display(), location.line, location.column, snippet)
+        }),
+        None => location.snippet(source),
+    };
+    match snippet {
+        Some(snippet) => format!("{}\n{}", error, snippet),
+        None => error.to_string(),
+    }
+}
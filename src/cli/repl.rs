@@ -0,0 +1,150 @@
+use std::io::{self, BufRead, Write};
+
+use crate::interpreter::{inspect::inspect, value::Value, Failure, Interpreter};
+use crate::parser::Expr;
+use crate::tokenizer::Tokenizer;
+
+// Runs statements typed on stdin in one interpreter, so definitions carry
+// over between inputs. Input continues over several lines while brackets
+// are left open.
+pub fn start() {
+    let mut interpreter = Interpreter::new();
+    read_eval(&mut interpreter, "> ", |_| false);
+}
+
+// The prompt --debug-on-error opens after an uncaught error: what is typed
+// runs in the scope the error happened in, and a few commands starting with
+// a colon show the stack and the variables there
+pub fn post_mortem(interpreter: &mut Interpreter, failure: Failure) {
+    interpreter.set_environment(std::sync::Arc::clone(&failure.environment));
+    eprintln!("{}", stack_trace(&failure));
+    eprintln!("Expressions typed here run where the error happened. {}", DEBUG_HELP);
+    read_eval(interpreter, "debug> ", |command| {
+        match command {
+            ":where" => eprintln!("{}", stack_trace(&failure)),
+            ":locals" => eprintln!("{}", locals(&failure)),
+            ":help" => eprintln!("{}", DEBUG_HELP),
+            _ => return false,
+        }
+        true
+    });
+}
+
+const DEBUG_HELP: &str = ":where shows the stack, :locals the variables in scope; :quit or end of input exits.";
+
+fn stack_trace(failure: &Failure) -> String {
+    let mut text = "Stack trace, innermost call first:".to_string();
+    for frame in &failure.frames {
+        let function = frame.function.as_deref().unwrap_or("<script>");
+        text.push_str(&format!("\n  at {} (line {})", function, frame.line));
+    }
+    text
+}
+
+// The variables of the failing scope and those enclosing it, leaving out the
+// global scope with its natives. A name shadowed by an inner scope is shown
+// once.
+fn locals(failure: &Failure) -> String {
+    let mut shown: Vec<String> = Vec::new();
+    let mut lines = Vec::new();
+    let mut scope = Some(std::sync::Arc::clone(&failure.environment));
+    while let Some(environment) = scope {
+        let environment = environment.lock().unwrap();
+        let Some(enclosing) = environment.get_enclosing() else {
+            break;
+        };
+        let mut values: Vec<_> = environment
+            .get_values()
+            .into_iter()
+            .filter(|(name, value)| !shown.contains(name) && !matches!(value, Value::NativeFunction(_)))
+            .collect();
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+        for (name, value) in values {
+            lines.push(format!("{} = {}", name, inspect(&value, 2, 80)));
+            shown.push(name);
+        }
+        scope = Some(enclosing);
+    }
+    if lines.is_empty() {
+        return "No variables outside the global scope".to_string();
+    }
+    lines.join("\n")
+}
+
+// Reads statements from stdin until its end or :quit, evaluating each one
+// the command handler does not take
+fn read_eval(interpreter: &mut Interpreter, prompt: &str, mut command: impl FnMut(&str) -> bool) {
+    let stdin = io::stdin();
+    let mut source = String::new();
+    loop {
+        print!("{}", if source.is_empty() { prompt } else { "... " });
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => {
+                println!();
+                return;
+            }
+            Ok(_) => source.push_str(&line),
+        }
+        if open_brackets(&source) > 0 {
+            continue;
+        }
+        let mut input = std::mem::take(&mut source);
+        let trimmed = input.trim_end();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if trimmed.trim() == ":quit" {
+            return;
+        }
+        if command(trimmed.trim()) {
+            continue;
+        }
+        // A lone expression does not need its semicolon
+        if !trimmed.ends_with(';') && !trimmed.ends_with('}') {
+            input = format!("{};", trimmed);
+        }
+        evaluate(interpreter, &input);
+    }
+}
+
+fn evaluate(interpreter: &mut Interpreter, input: &str) {
+    let mut tokenizer = Tokenizer::new();
+    // The tokenizer reports its own errors
+    if tokenizer.tokenize(input).is_err() || !tokenizer.errors.is_empty() {
+        return;
+    }
+    let exprs = match super::parse(tokenizer.into_tokens()) {
+        Ok(exprs) => exprs,
+        Err(error) => {
+            eprintln!("{}", super::error_report(&error, input));
+            return;
+        }
+    };
+    // Without the `;` statements the last value is the one worth showing
+    let exprs = exprs.into_iter().filter(|(expr, _)| *expr != Expr::Nil).collect();
+    match interpreter.interpret(exprs) {
+        Ok(Value::Nil) => {}
+        Ok(value) => println!("{}", value),
+        Err(error) => eprintln!("{}", super::error_report(&error, input)),
+    }
+}
+
+fn open_brackets(source: &str) -> i32 {
+    let mut depth = 0;
+    let mut in_string = false;
+    for line in source.lines() {
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => in_string = !in_string,
+                '/' if !in_string && chars.peek() == Some(&'/') => break,
+                '(' | '[' | '{' if !in_string => depth += 1,
+                ')' | ']' | '}' if !in_string => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+    depth
+}
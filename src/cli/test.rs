@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::error::{InterpreterError, RuntimeErrorKind};
+use crate::interpreter::Interpreter;
+use crate::parser::Expr;
+
+// Runs the `test_*` functions of every test script found. Each test gets a
+// fresh interpreter that runs the script's top level first, so tests cannot
+// see each other's state.
+pub fn start(paths: &[PathBuf]) -> ! {
+    let files = super::find_scripts(paths, "_test");
+    if files.is_empty() {
+        eprintln!("No test files found");
+        std::process::exit(super::EXIT_NO_INPUT);
+    }
+    let started = Instant::now();
+    let (mut passed, mut failed) = (0, 0);
+    for file in &files {
+        println!("{}", file.display());
+        let exprs = match super::load(file) {
+            Ok(exprs) => exprs,
+            Err(error) => {
+                println!("  FAIL  (loading the file)\n        {}", error);
+                failed += 1;
+                continue;
+            }
+        };
+        for name in test_names(&exprs) {
+            let started = Instant::now();
+            let result = run_test(file, &exprs, &name);
+            let elapsed = started.elapsed().as_secs_f64() * 1000.0;
+            match result {
+                Ok(()) => {
+                    println!("  ok    {} ({:.1} ms)", name, elapsed);
+                    passed += 1;
+                }
+                Err(error) => {
+                    println!("  FAIL  {} ({:.1} ms)\n        {}", name, elapsed, error);
+                    failed += 1;
+                }
+            }
+        }
+    }
+    println!(
+        "\n{} passed, {} failed in {:.1} ms",
+        passed,
+        failed,
+        started.elapsed().as_secs_f64() * 1000.0
+    );
+    std::process::exit(if failed > 0 { super::EXIT_FAILURE } else { 0 });
+}
+
+fn test_names(exprs: &[(Expr, usize)]) -> Vec<String> {
+    exprs
+        .iter()
+        .filter_map(|(expr, _)| match expr {
+            Expr::Function(name, ..) | Expr::AsyncFunction(name, ..) => Some(&name.lexeme),
+            _ => None,
+        })
+        .filter(|name| name.starts_with("test_"))
+        .cloned()
+        .collect()
+}
+
+fn run_test(file: &Path, exprs: &[(Expr, usize)], name: &str) -> Result<(), InterpreterError> {
+    let base_dir = file.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+    let mut interpreter = Interpreter::builder().base_path(base_dir).build();
+    match interpreter.interpret(exprs.to_vec()) {
+        // A top-level return only ends the setup
+        Ok(_) | Err(InterpreterError::RuntimeError(RuntimeErrorKind::Return(_))) => {}
+        Err(error) => return Err(error),
+    }
+    let environment = interpreter.environment();
+    let test = environment.lock().unwrap().get(name);
+    let Some(test) = test else {
+        return Ok(());
+    };
+    let _runtime_guard = interpreter.runtime.enter();
+    Interpreter::call_detached(interpreter.runtime.clone(), environment, test, Vec::new()).map(|_| ())
+}
@@ -0,0 +1,152 @@
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus};
+use std::time::{Duration, SystemTime};
+use std::{fs, thread};
+
+use crate::interpreter::{data, enviroment::module_candidates, remote, stdlib};
+use crate::parser::{Expr, Parser};
+use crate::tokenizer::Tokenizer;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Runs the script in a child process and starts it again whenever the script
+// or a module it imports changes. A fresh process per run means nothing from
+// the previous run (background tasks, exit(), globals) leaks into the next.
+pub fn start(script: &Path, run_args: Vec<String>) -> ! {
+    let exe = std::env::current_exe().unwrap_or_else(|error| {
+        eprintln!("Cannot find the alpha executable: {}", error);
+        std::process::exit(super::EXIT_SOFTWARE);
+    });
+    loop {
+        let files = watched_files(script);
+        let stamps = modified_times(&files);
+        let mut child = spawn(&exe, &run_args);
+        let changed = loop {
+            thread::sleep(POLL_INTERVAL);
+            if let Some(running) = &mut child {
+                if let Ok(Some(status)) = running.try_wait() {
+                    eprintln!("[watch] {}, waiting for changes", describe(status));
+                    child = None;
+                }
+            }
+            let now = modified_times(&files);
+            if let Some(index) = (0..files.len()).find(|&i| now[i] != stamps[i]) {
+                break &files[index];
+            }
+        };
+        if let Some(mut running) = child {
+            let _ = running.kill();
+            let _ = running.wait();
+        }
+        eprintln!("\n---------- {} changed, restarting ----------\n", changed.display());
+    }
+}
+
+fn spawn(exe: &Path, run_args: &[String]) -> Option<Child> {
+    match Command::new(exe).arg("run").args(run_args).spawn() {
+        Ok(child) => Some(child),
+        Err(error) => {
+            eprintln!("[watch] could not start the script: {}", error);
+            None
+        }
+    }
+}
+
+fn describe(status: ExitStatus) -> String {
+    match status.code() {
+        Some(code) => format!("exited with status {}", code),
+        None => "terminated by a signal".to_string(),
+    }
+}
+
+fn modified_times(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|file| fs::metadata(file).and_then(|meta| meta.modified()).ok())
+        .collect()
+}
+
+// The script and every module reachable through imports with a literal
+// path. Each module is looked up next to the file importing it, like the
+// interpreter does.
+fn watched_files(script: &Path) -> Vec<PathBuf> {
+    let mut files = vec![script.to_path_buf()];
+    let mut next = 0;
+    while next < files.len() {
+        let mut imports = Vec::new();
+        // Data files are watched but import nothing themselves
+        let is_data = data::format(&files[next].to_string_lossy()).is_some();
+        let source = if is_data { None } else { stdlib::read_module(&files[next]).ok() };
+        if let Some(source) = source {
+            for statement in parse(&source) {
+                collect_imports(&statement, &mut imports);
+            }
+        }
+        let importer_dir = module_dir(&files[next]);
+        for import in imports {
+            if let Some(module) = resolve_import(&importer_dir, &import) {
+                if !files.contains(&module) {
+                    files.push(module);
+                }
+            }
+        }
+        next += 1;
+    }
+    files
+}
+
+// Modules imported by URL resolve to their cached copy, downloaded if needed
+pub(super) fn resolve_import(base_dir: &Path, import: &str) -> Option<PathBuf> {
+    if let Some(module) = stdlib::resolve(import) {
+        return Some(module);
+    }
+    if remote::is_remote(import) {
+        return remote::fetch(import).map_err(|error| eprintln!("{}", error)).ok();
+    }
+    module_candidates(base_dir, import).into_iter().find(|path| path.is_file())
+}
+
+// The directory a module's own imports resolve against
+pub(super) fn module_dir(module: &Path) -> PathBuf {
+    module.parent().unwrap_or_else(|| Path::new("")).to_path_buf()
+}
+
+// A file that does not parse is still watched, just without its imports
+pub(super) fn parse(source: &str) -> Vec<Expr> {
+    let mut tokenizer = Tokenizer::new();
+    if tokenizer.tokenize(source).is_err() || !tokenizer.errors.is_empty() {
+        return Vec::new();
+    }
+    match Parser::new(tokenizer.into_tokens()).parse() {
+        Ok(statements) => statements.into_iter().map(|(expr, _)| expr).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+pub(super) fn collect_imports(expr: &Expr, imports: &mut Vec<String>) {
+    match expr {
+        Expr::Import(path, _) | Expr::Export(path, _) => {
+            if let Expr::Literal(_, path) = &**path {
+                imports.push(path.clone());
+            }
+        }
+        Expr::Block(statements) | Expr::Class(_, statements) => {
+            for statement in statements {
+                collect_imports(statement, imports);
+            }
+        }
+        Expr::Function(_, _, body) | Expr::AsyncFunction(_, _, body) => collect_imports(body, imports),
+        Expr::While(_, body)
+        | Expr::For(_, _, _, body)
+        | Expr::ForAwait(_, _, body) => collect_imports(body, imports),
+        Expr::If(_, then_branch, else_branch) => {
+            collect_imports(then_branch, imports);
+            collect_imports(else_branch, imports);
+        }
+        Expr::TryCatch(try_catch) => {
+            collect_imports(&try_catch.try_block, imports);
+            collect_imports(&try_catch.catch_block, imports);
+        }
+        _ => {}
+    }
+}
@@ -1,11 +1,14 @@
 use std::error::Error;
 use std::fmt;
+use std::path::PathBuf;
 
-use crate::{interpreter::value::Value, tokenizer::TokenType};
+use crate::{interpreter::value::Value, tokenizer::{Token, TokenType}};
 #[derive(Debug, Clone)]
 pub enum TokenizerErrorKind {
     UnexpectedCharacter(char, usize),
     UnterminatedString(usize),
+    UnterminatedRegex(usize),
+    InvalidRegex(String, usize),
     InvalidNumber(usize),
     InvalidIdentifier(usize),
     InvalidToken(usize),
@@ -20,6 +23,12 @@ impl fmt::Display for TokenizerErrorKind {
             TokenizerErrorKind::UnterminatedString(line) => {
                 write!(f, "[line {}] Error: Unterminated string.", line)
             }
+            TokenizerErrorKind::UnterminatedRegex(line) => {
+                write!(f, "[line {}] Error: Unterminated regex.", line)
+            }
+            TokenizerErrorKind::InvalidRegex(message, line) => {
+                write!(f, "[line {}] Error: {}", line, message)
+            }
             TokenizerErrorKind::InvalidNumber(line) => {
                 write!(f, "[line {}] Error: Invalid number.", line)
             }
@@ -92,6 +101,9 @@ impl fmt::Display for ParserErrorKind {
 #[derive(Debug, Clone)]
 pub enum RuntimeErrorKind {
     PromiseRejected(usize),
+    PromiseCancelled(usize),
+    // awaitTimeout ran out of time: the seconds it waited
+    Timeout(usize, f64),
     InvalidAwait(usize),
     InvalidTailCall(usize),
     InvalidNumber(usize),
@@ -100,8 +112,15 @@ pub enum RuntimeErrorKind {
     InvalidUnaryOperator(usize),
     OperandsMustBeNumbersOrStrings(usize),
     OperandsMustBeNumber(usize),
+    // == or != between values of two types in strict mode
+    MismatchedTypes(usize, String, String),
+    // expectType given a value of another type: the expected and actual types
+    UnexpectedType(usize, String, String),
+    // The arity of the native called with too few or too many arguments
     InvalidParametsCount(usize),
-    UndefinedVariable(usize, String),
+    // The name and, when one is close enough, a visible name it may be a
+    // typo of
+    UndefinedVariable(usize, String, Option<String>),
     UnknownBinaryOperator(usize),
     DivisionByZero(usize),
     UnknownError(usize),
@@ -111,18 +130,25 @@ pub enum RuntimeErrorKind {
     InvalidCondition(usize),
     InvalidLogicalOperator(usize),
     InvalidReturnValue(usize),
+    // The index of the argument a native was given the wrong type of
     InvalidArgumentType(usize),
     RuntimeError(usize, String),
     InvalidImport(usize, String),
     InvalidClassMethod(usize),
     InvalidDictionaryKey(usize),
-    AssertionFailed,
+    // What was checked and how it came out
+    AssertionFailed(String),
     InvalidSet(usize),
     InvalidGet(usize),
+    // A member whose name starts with _ used from outside the class's
+    // methods: the class and the member
+    PrivateMember(usize, String, String),
     NetworkError(usize),
     IoError(String),
     InvalidCall(usize),
     Return(Value),
+    // A value raised by `throw`
+    Thrown(usize, Value),
 }
 impl fmt::Display for RuntimeErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -130,6 +156,12 @@ impl fmt::Display for RuntimeErrorKind {
             RuntimeErrorKind::PromiseRejected(line) => {
                 write!(f, "[line {}] Error: Promise rejected.", line)
             }
+            RuntimeErrorKind::PromiseCancelled(line) => {
+                write!(f, "[line {}] Error: Promise cancelled.", line)
+            }
+            RuntimeErrorKind::Timeout(line, seconds) => {
+                write!(f, "[line {}] Error: Timed out after {} seconds.", line, seconds)
+            }
             RuntimeErrorKind::InvalidAwait(line) => {
                 write!(f, "[line {}] Error: Can only use 'await' inside async functions.", line)
             }
@@ -145,9 +177,18 @@ impl fmt::Display for RuntimeErrorKind {
             RuntimeErrorKind::OperandsMustBeNumbersOrStrings(line) => {
                 write!(f, "[line {}] Error: Operands must be two numbers or two strings.", line)
             }
-            RuntimeErrorKind::UndefinedVariable(line, name) => {
+            RuntimeErrorKind::MismatchedTypes(line, left, right) => {
+                write!(f, "[line {}] Error: Cannot compare {} with {}; use === to compare values of any type.", line, left, right)
+            }
+            RuntimeErrorKind::UnexpectedType(line, expected, actual) => {
+                write!(f, "[line {}] Error: Expected a value of type {} but got {}.", line, expected, actual)
+            }
+            RuntimeErrorKind::UndefinedVariable(line, name, None) => {
                 write!(f, "[line {}] Error: Undefined variable {}.", line, name)
             }
+            RuntimeErrorKind::UndefinedVariable(line, name, Some(suggestion)) => {
+                write!(f, "[line {}] Error: Undefined variable {}. Did you mean {}?", line, name, suggestion)
+            }
             RuntimeErrorKind::UnknownBinaryOperator(line) => {
                 write!(f, "[line {}] Error: Unknown binary operator.", line)
             }
@@ -181,11 +222,11 @@ impl fmt::Display for RuntimeErrorKind {
             RuntimeErrorKind::InvalidReturnValue(line) => {
                 write!(f, "[line {}] Error: Invalid return value.", line)
             }
-            RuntimeErrorKind::InvalidParametsCount(line) => {
-                write!(f, "[line {}] Error: Invalid argument count.", line)
+            RuntimeErrorKind::InvalidParametsCount(_) => {
+                write!(f, "Error: Invalid argument count.")
             }
-            RuntimeErrorKind::InvalidArgumentType(line) => {
-                write!(f, "[line {}] Error: Invalid argument type.", line)
+            RuntimeErrorKind::InvalidArgumentType(_) => {
+                write!(f, "Error: Invalid argument type.")
             }
             RuntimeErrorKind::RuntimeError(line, message) => {
                 write!(f, "[line {}] Error: {}", line, message)
@@ -211,18 +252,113 @@ impl fmt::Display for RuntimeErrorKind {
             RuntimeErrorKind::InvalidGet(line) => {
                 write!(f, "[line {}] Error: Invalid get.", line)
             }
+            RuntimeErrorKind::PrivateMember(line, class, member) => {
+                write!(f, "[line {}] Error: {} is private to {} and can only be used by its methods.", line, member, class)
+            }
             RuntimeErrorKind::InvalidDictionaryKey(line) => {
                 write!(f, "[line {}] Error: Invalid dictionary key.", line)
             }
             RuntimeErrorKind::InvalidTailCall(line) => {
                 write!(f, "[line {}] Error: Invalid Tail Call.", line)
             }
-            RuntimeErrorKind::AssertionFailed => {
-                write!(f, "Assertion failed.")
+            RuntimeErrorKind::AssertionFailed(message) => {
+                write!(f, "Assertion failed: {}", message)
             }
             RuntimeErrorKind::NetworkError(line) => {
                 write!(f, "[line {}] Error: Network error.", line)
             }
+            RuntimeErrorKind::Thrown(line, value) => {
+                write!(f, "[line {}] Error: Uncaught {}", line, value)
+            }
+        }
+    }
+}
+
+impl RuntimeErrorKind {
+    // The category of the error, as the `type` of a caught error
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            RuntimeErrorKind::PromiseRejected(_) => "PromiseRejected",
+            RuntimeErrorKind::PromiseCancelled(_) => "PromiseCancelled",
+            RuntimeErrorKind::Timeout(..) => "Timeout",
+            RuntimeErrorKind::InvalidAwait(_) => "InvalidAwait",
+            RuntimeErrorKind::InvalidTailCall(_) => "InvalidTailCall",
+            RuntimeErrorKind::InvalidNumber(_) => "InvalidNumber",
+            RuntimeErrorKind::InvalidLiteral(_) => "InvalidLiteral",
+            RuntimeErrorKind::InvalidBinaryOperator(_) => "InvalidBinaryOperator",
+            RuntimeErrorKind::InvalidUnaryOperator(_) => "InvalidUnaryOperator",
+            RuntimeErrorKind::OperandsMustBeNumbersOrStrings(_) => "OperandsMustBeNumbersOrStrings",
+            RuntimeErrorKind::OperandsMustBeNumber(_) => "OperandsMustBeNumber",
+            RuntimeErrorKind::MismatchedTypes(..) => "MismatchedTypes",
+            RuntimeErrorKind::UnexpectedType(..) => "UnexpectedType",
+            RuntimeErrorKind::InvalidParametsCount(_) => "InvalidArgumentCount",
+            RuntimeErrorKind::UndefinedVariable(..) => "UndefinedVariable",
+            RuntimeErrorKind::UnknownBinaryOperator(_) => "UnknownBinaryOperator",
+            RuntimeErrorKind::DivisionByZero(_) => "DivisionByZero",
+            RuntimeErrorKind::UnknownError(_) => "UnknownError",
+            RuntimeErrorKind::UnknownExpression(_) => "UnknownExpression",
+            RuntimeErrorKind::UndefinedFunction(_) => "UndefinedFunction",
+            RuntimeErrorKind::ExpextedArgument(..) => "InvalidArgumentCount",
+            RuntimeErrorKind::InvalidCondition(_) => "InvalidCondition",
+            RuntimeErrorKind::InvalidLogicalOperator(_) => "InvalidLogicalOperator",
+            RuntimeErrorKind::InvalidReturnValue(_) => "InvalidReturnValue",
+            RuntimeErrorKind::InvalidArgumentType(_) => "InvalidArgumentType",
+            RuntimeErrorKind::RuntimeError(..) => "RuntimeError",
+            RuntimeErrorKind::InvalidImport(..) => "InvalidImport",
+            RuntimeErrorKind::InvalidClassMethod(_) => "InvalidClassMethod",
+            RuntimeErrorKind::InvalidDictionaryKey(_) => "InvalidDictionaryKey",
+            RuntimeErrorKind::AssertionFailed(_) => "AssertionFailed",
+            RuntimeErrorKind::InvalidSet(_) => "InvalidSet",
+            RuntimeErrorKind::InvalidGet(_) => "InvalidGet",
+            RuntimeErrorKind::PrivateMember(..) => "PrivateMember",
+            RuntimeErrorKind::NetworkError(_) => "NetworkError",
+            RuntimeErrorKind::IoError(_) => "IoError",
+            RuntimeErrorKind::InvalidCall(_) => "InvalidCall",
+            RuntimeErrorKind::Return(_) => "Return",
+            RuntimeErrorKind::Thrown(..) => "Thrown",
+        }
+    }
+
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            RuntimeErrorKind::PromiseRejected(line)
+            | RuntimeErrorKind::PromiseCancelled(line)
+            | RuntimeErrorKind::Timeout(line, _)
+            | RuntimeErrorKind::InvalidAwait(line)
+            | RuntimeErrorKind::InvalidTailCall(line)
+            | RuntimeErrorKind::InvalidNumber(line)
+            | RuntimeErrorKind::InvalidLiteral(line)
+            | RuntimeErrorKind::InvalidBinaryOperator(line)
+            | RuntimeErrorKind::InvalidUnaryOperator(line)
+            | RuntimeErrorKind::OperandsMustBeNumbersOrStrings(line)
+            | RuntimeErrorKind::OperandsMustBeNumber(line)
+            | RuntimeErrorKind::MismatchedTypes(line, ..)
+            | RuntimeErrorKind::UnexpectedType(line, ..)
+            | RuntimeErrorKind::UndefinedVariable(line, ..)
+            | RuntimeErrorKind::UnknownBinaryOperator(line)
+            | RuntimeErrorKind::DivisionByZero(line)
+            | RuntimeErrorKind::UnknownError(line)
+            | RuntimeErrorKind::UnknownExpression(line)
+            | RuntimeErrorKind::UndefinedFunction(line)
+            | RuntimeErrorKind::ExpextedArgument(line, ..)
+            | RuntimeErrorKind::InvalidCondition(line)
+            | RuntimeErrorKind::InvalidLogicalOperator(line)
+            | RuntimeErrorKind::InvalidReturnValue(line)
+            | RuntimeErrorKind::RuntimeError(line, _)
+            | RuntimeErrorKind::InvalidImport(line, _)
+            | RuntimeErrorKind::InvalidClassMethod(line)
+            | RuntimeErrorKind::InvalidDictionaryKey(line)
+            | RuntimeErrorKind::InvalidSet(line)
+            | RuntimeErrorKind::InvalidGet(line)
+            | RuntimeErrorKind::PrivateMember(line, ..)
+            | RuntimeErrorKind::NetworkError(line)
+            | RuntimeErrorKind::InvalidCall(line)
+            | RuntimeErrorKind::Thrown(line, _) => Some(*line),
+            RuntimeErrorKind::InvalidParametsCount(_)
+            | RuntimeErrorKind::InvalidArgumentType(_)
+            | RuntimeErrorKind::AssertionFailed(_)
+            | RuntimeErrorKind::IoError(_)
+            | RuntimeErrorKind::Return(_) => None,
         }
     }
 }
@@ -234,12 +370,57 @@ pub enum UnknownErrorKind {
 }
 
 
+// Where in the source an error happened: the token it is shown under, in
+// the given file or, when None, in the program itself
+#[derive(Debug, Clone)]
+pub struct SourceLocation {
+    pub file: Option<PathBuf>,
+    pub line: usize,
+    pub column: usize,
+    pub lexeme: String,
+}
+
+impl SourceLocation {
+    pub fn at(token: &Token) -> Self {
+        SourceLocation {
+            file: None,
+            line: token.line,
+            column: token.column,
+            lexeme: token.lexeme.clone(),
+        }
+    }
+
+    // The line of the source with a caret under the token:
+    //
+    //        3 | var total = count / 0;
+    //          |                   ^
+    //
+    // None when the token is not found there, as for a function declared in
+    // another file than the source given
+    pub fn snippet(&self, source: &str) -> Option<String> {
+        let text = source.lines().nth(self.line.checked_sub(1)?)?;
+        let before: Vec<char> = text.chars().take(self.column.checked_sub(1)?).collect();
+        let rest: String = text.chars().skip(before.len()).collect();
+        if before.len() + 1 != self.column || !rest.starts_with(&self.lexeme) {
+            return None;
+        }
+        // Tabs are kept so the caret lines up however they are shown
+        let padding: String = before.iter().map(|&c| if c == '\t' { '\t' } else { ' ' }).collect();
+        let caret = "^".repeat(self.lexeme.chars().count().max(1));
+        let gutter = self.line.to_string().len().max(4);
+        Some(format!("{:>gutter$} | {}\n{:>gutter$} | {}{}", self.line, text, "", padding, caret))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum InterpreterError {
     TokenizerError(TokenizerErrorKind),
     ParserError(ParserErrorKind),
     RuntimeError(RuntimeErrorKind),
     UnknownError(UnknownErrorKind),
+    // An error with the place it happened at, once it leaves the
+    // interpreter running the source
+    Located(Box<InterpreterError>, SourceLocation),
 }
 
 impl Error for InterpreterError {}
@@ -259,6 +440,20 @@ impl fmt::Display for InterpreterError {
             InterpreterError::UnknownError(kind) => {
                 write!(f, "UnknownError: {:?}", kind)
             }
+            // The line shown is the one located, which the error's own may
+            // not be: natives report line 0
+            InterpreterError::Located(error, location) => {
+                let text = error.to_string();
+                let rest = match text.strip_prefix("[line ") {
+                    Some(rest) => rest.split_once("] ").map_or(rest, |(_, rest)| rest),
+                    None => &text,
+                };
+                if rest.starts_with("Error") {
+                    write!(f, "[line {}] {}", location.line, rest)
+                } else {
+                    write!(f, "[line {}] Error: {}", location.line, rest)
+                }
+            }
         }
     }
 }
@@ -276,6 +471,93 @@ impl InterpreterError {
     pub fn unknown_error(kind: UnknownErrorKind) -> Self {
         InterpreterError::UnknownError(kind)
     }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            InterpreterError::TokenizerError(_) | InterpreterError::ParserError(_) => "SyntaxError",
+            InterpreterError::RuntimeError(kind) => kind.type_name(),
+            InterpreterError::UnknownError(_) => "UnknownError",
+            InterpreterError::Located(error, _) => error.type_name(),
+        }
+    }
+
+    // The line the error was reported at. Natives report line 0, meaning
+    // the line of the call.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            InterpreterError::TokenizerError(
+                TokenizerErrorKind::UnexpectedCharacter(_, line)
+                | TokenizerErrorKind::UnterminatedString(line)
+                | TokenizerErrorKind::UnterminatedRegex(line)
+                | TokenizerErrorKind::InvalidRegex(_, line)
+                | TokenizerErrorKind::InvalidNumber(line)
+                | TokenizerErrorKind::InvalidIdentifier(line)
+                | TokenizerErrorKind::InvalidToken(line)
+                | TokenizerErrorKind::UnknownError(line),
+            ) => Some(*line),
+            InterpreterError::ParserError(
+                ParserErrorKind::ExpectedSemilicon(line)
+                | ParserErrorKind::UnexpectedToken(line, _)
+                | ParserErrorKind::InvalidAssignmentTarget(line)
+                | ParserErrorKind::InvalidParametsCount(line)
+                | ParserErrorKind::InvalidExpression(line)
+                | ParserErrorKind::InvalidStatement(line)
+                | ParserErrorKind::UnknownError(line)
+                | ParserErrorKind::ExpectedUnary(line)
+                | ParserErrorKind::UnexpectedEof(line)
+                | ParserErrorKind::InvalidImport(line)
+                | ParserErrorKind::ExpectExpression(_, line),
+            ) => Some(*line),
+            InterpreterError::RuntimeError(kind) => kind.line(),
+            InterpreterError::UnknownError(_) => None,
+            InterpreterError::Located(_, location) => Some(location.line),
+        }
+    }
+
+    pub fn location(&self) -> Option<&SourceLocation> {
+        match self {
+            InterpreterError::Located(_, location) => Some(location),
+            _ => None,
+        }
+    }
+
+    // The error as raised, without where it happened
+    pub fn without_location(self) -> InterpreterError {
+        match self {
+            InterpreterError::Located(error, _) => *error,
+            error => error,
+        }
+    }
+
+    // Attaches where the error happened, unless it already knows. Returns
+    // unwind calls rather than fail, so they are never located.
+    pub fn located_at(self, location: SourceLocation) -> InterpreterError {
+        match self {
+            InterpreterError::Located(..) | InterpreterError::RuntimeError(RuntimeErrorKind::Return(_)) => self,
+            error => InterpreterError::Located(Box::new(error), location),
+        }
+    }
+
+    // Names the file of a location found while running it
+    pub fn in_file(self, file: PathBuf) -> InterpreterError {
+        match self {
+            InterpreterError::Located(error, mut location) => {
+                location.file.get_or_insert(file);
+                InterpreterError::Located(error, location)
+            }
+            error => error,
+        }
+    }
+
+    // The description alone, without the "[line N] Error: " it is shown with
+    pub fn message(&self) -> String {
+        let text = self.to_string();
+        let without_line = match text.strip_prefix("[line ") {
+            Some(rest) => rest.split_once("] ").map_or(rest, |(_, rest)| rest),
+            None => &text,
+        };
+        without_line.strip_prefix("Error: ").unwrap_or(without_line).to_string()
+    }
 }
 
 pub type InterpreterResult<T> = Result<T, InterpreterError>;
\ No newline at end of file
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use super::value::Value;
+
+// Command line parsing for parseArgs. The spec is a dictionary:
+//
+//   name         program name shown in the usage text
+//   description  line shown under the usage line
+//   flags        dictionary of flag name to help text; a flag is true when given
+//   options      dictionary of option name to dict{"help", "default"}; an
+//                option takes a value, converted to a number when its default
+//                is one
+//   positionals  array of argument names, all required
+//   rest         name of an array collecting the arguments past the positionals
+//
+// Names of one character are written -n, longer ones --name; options also
+// take --name=value. -h and --help set "help" instead of failing on missing
+// arguments, and "--" ends the options.
+pub struct ArgSpec {
+    name: String,
+    description: Option<String>,
+    flags: Vec<(String, String)>,
+    options: Vec<ArgOption>,
+    positionals: Vec<String>,
+    rest: Option<String>,
+}
+
+struct ArgOption {
+    name: String,
+    help: String,
+    default: Value,
+}
+
+impl ArgSpec {
+    pub fn from_value(spec: &Value) -> Result<ArgSpec, String> {
+        let Value::Dictionary(spec) = spec else {
+            return Err("the spec must be a dictionary".to_string());
+        };
+        let text = |key: &str| match spec.get(key) {
+            None | Some(Value::Nil) => Ok(None),
+            Some(Value::String(text)) => Ok(Some(text.to_string())),
+            Some(_) => Err(format!("\"{}\" must be a string", key)),
+        };
+        let name = text("name")?.unwrap_or_else(|| "script".to_string());
+        let description = text("description")?;
+        let rest = text("rest")?;
+
+        let mut flags = Vec::new();
+        for (flag, help) in entries(spec, "flags")? {
+            match help {
+                Value::String(help) => flags.push((flag, help.to_string())),
+                _ => return Err(format!("the help of flag {} must be a string", flag)),
+            }
+        }
+        let mut options = Vec::new();
+        for (option, details) in entries(spec, "options")? {
+            let Value::Dictionary(details) = details else {
+                return Err(format!("option {} must be a dictionary", option));
+            };
+            let help = match details.get("help") {
+                None => String::new(),
+                Some(Value::String(help)) => help.to_string(),
+                Some(_) => return Err(format!("the help of option {} must be a string", option)),
+            };
+            let default = details.get("default").cloned().unwrap_or(Value::Nil);
+            options.push(ArgOption { name: option, help, default });
+        }
+        let positionals = match spec.get("positionals") {
+            None | Some(Value::Nil) => Vec::new(),
+            Some(Value::Array(names)) => names
+                .iter()
+                .map(|name| match name {
+                    Value::String(name) => Ok(name.to_string()),
+                    _ => Err("\"positionals\" must hold strings".to_string()),
+                })
+                .collect::<Result<_, _>>()?,
+            Some(_) => return Err("\"positionals\" must be an array".to_string()),
+        };
+        Ok(ArgSpec { name, description, flags, options, positionals, rest })
+    }
+
+    // The values the arguments give, with "help", "usage" and "error" added;
+    // "error" is nil unless the arguments do not match the spec
+    pub fn parse(&self, args: &[String]) -> HashMap<String, Value> {
+        let mut values = HashMap::new();
+        for (flag, _) in &self.flags {
+            values.insert(flag.clone(), Value::Boolean(false));
+        }
+        for option in &self.options {
+            values.insert(option.name.clone(), option.default.clone());
+        }
+        values.insert("help".to_string(), Value::Boolean(false));
+        let error = self.read(args, &mut values).err();
+        values.insert("usage".to_string(), Value::String(self.usage().into()));
+        values.insert("error".to_string(), error.map_or(Value::Nil, |s| Value::String(s.into())));
+        values
+    }
+
+    fn read(&self, args: &[String], values: &mut HashMap<String, Value>) -> Result<(), String> {
+        let mut positionals = Vec::new();
+        let mut args = args.iter();
+        let mut options_ended = false;
+        while let Some(arg) = args.next() {
+            if options_ended || arg == "-" || !arg.starts_with('-') {
+                positionals.push(arg.clone());
+                continue;
+            }
+            if arg == "--" {
+                options_ended = true;
+                continue;
+            }
+            if arg == "-h" || arg == "--help" {
+                values.insert("help".to_string(), Value::Boolean(true));
+                continue;
+            }
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            if let Some((flag, _)) = self.flags.iter().find(|(flag, _)| switch(flag) == name) {
+                if inline.is_some() {
+                    return Err(format!("Flag {} does not take a value", name));
+                }
+                values.insert(flag.clone(), Value::Boolean(true));
+            } else if let Some(option) = self.options.iter().find(|option| switch(&option.name) == name) {
+                let Some(value) = inline.or_else(|| args.next().cloned()) else {
+                    return Err(format!("Option {} needs a value", name));
+                };
+                let value = match option.default {
+                    Value::Number(_) => match value.trim().parse() {
+                        Ok(number) => Value::Number(number),
+                        Err(_) => return Err(format!("Option {} expects a number, not {}", name, value)),
+                    },
+                    _ => Value::String(value.into()),
+                };
+                values.insert(option.name.clone(), value);
+            } else {
+                return Err(format!("Unknown option {}", name));
+            }
+        }
+        if values.get("help") == Some(&Value::Boolean(true)) {
+            return Ok(());
+        }
+        if positionals.len() < self.positionals.len() {
+            return Err(format!("Missing argument {}", self.positionals[positionals.len()]));
+        }
+        let rest = positionals.split_off(self.positionals.len());
+        for (name, value) in self.positionals.iter().zip(positionals) {
+            values.insert(name.clone(), Value::String(value.into()));
+        }
+        match &self.rest {
+            Some(name) => {
+                values.insert(name.clone(), Value::Array(rest.into_iter().map(|s| Value::String(s.into())).collect()));
+            }
+            None if !rest.is_empty() => return Err(format!("Unexpected argument {}", rest[0])),
+            None => {}
+        }
+        Ok(())
+    }
+
+    pub fn usage(&self) -> String {
+        let mut usage = format!("Usage: {}", self.name);
+        if !self.flags.is_empty() || !self.options.is_empty() {
+            usage.push_str(" [options]");
+        }
+        for positional in &self.positionals {
+            usage.push_str(&format!(" <{}>", positional));
+        }
+        if let Some(rest) = &self.rest {
+            usage.push_str(&format!(" [{}...]", rest));
+        }
+        usage.push('\n');
+        if let Some(description) = &self.description {
+            usage.push_str(&format!("\n{}\n", description));
+        }
+
+        let mut lines = Vec::new();
+        for (flag, help) in &self.flags {
+            lines.push((switch(flag), help.clone()));
+        }
+        for option in &self.options {
+            let help = match &option.default {
+                Value::Nil => option.help.clone(),
+                default => format!("{} (default: {})", option.help, default).trim_start().to_string(),
+            };
+            lines.push((format!("{} <value>", switch(&option.name)), help));
+        }
+        lines.push(("-h, --help".to_string(), "Show this help".to_string()));
+        let width = lines.iter().map(|(switch, _)| switch.chars().count()).max().unwrap_or(0);
+        usage.push_str("\nOptions:\n");
+        for (switch, help) in lines {
+            usage.push_str(format!("  {:width$}  {}", switch, help, width = width).trim_end());
+            usage.push('\n');
+        }
+        usage
+    }
+}
+
+// The entries of a dictionary in the spec, sorted so the usage text keeps
+// the same order from run to run
+fn entries(spec: &HashMap<String, Value>, key: &str) -> Result<Vec<(String, Value)>, String> {
+    match spec.get(key) {
+        None | Some(Value::Nil) => Ok(Vec::new()),
+        Some(Value::Dictionary(entries)) => {
+            let mut entries: Vec<_> = entries.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            Ok(entries)
+        }
+        Some(_) => Err(format!("\"{}\" must be a dictionary", key)),
+    }
+}
+
+fn switch(name: &str) -> String {
+    if name.chars().count() == 1 { format!("-{}", name) } else { format!("--{}", name) }
+}
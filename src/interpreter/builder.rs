@@ -0,0 +1,106 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use tokio::runtime::Handle;
+
+use super::{
+    enviroment::Environment, io::ScriptIo, native::NativeFunction, options::InterpreterOptions,
+    value::Value, Interpreter,
+};
+
+// Sets up an interpreter before it runs anything:
+//
+//     let mut interpreter = Interpreter::builder()
+//         .runtime(Handle::current())
+//         .base_path("scripts")
+//         .options(InterpreterOptions::sandboxed())
+//         .max_call_depth(1000)
+//         .native(NativeFunction::new("version", 0, |_| Ok(Value::String(VERSION.into()))))
+//         .build();
+#[derive(Default)]
+pub struct InterpreterBuilder {
+    runtime: Option<Handle>,
+    base_path: PathBuf,
+    options: InterpreterOptions,
+    io: Option<ScriptIo>,
+    natives: Vec<NativeFunction>,
+    globals: Vec<(String, Value)>,
+}
+
+impl InterpreterBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Runs async work on the host's runtime, which must be multi-threaded.
+    // Without one the runtime the caller is on is used if it is
+    // multi-threaded, or else one is started. Hosts running scripts from
+    // async code call interpret_async.
+    pub fn runtime(mut self, runtime: Handle) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    // The directory imports are resolved from
+    pub fn base_path(mut self, base_path: impl Into<PathBuf>) -> Self {
+        self.base_path = base_path.into();
+        self
+    }
+
+    pub fn options(mut self, options: InterpreterOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    // Calls nested deeper than this fail instead of running out of stack
+    pub fn max_call_depth(mut self, depth: usize) -> Self {
+        self.options.max_call_depth = Some(depth);
+        self
+    }
+
+    // + and == fail on mismatched types rather than converting them
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.options.strict = strict;
+        self
+    }
+
+    pub fn io(mut self, io: ScriptIo) -> Self {
+        self.io = Some(io);
+        self
+    }
+
+    // A host function scripts can call, replacing a built-in one of the same name
+    pub fn native(mut self, native: NativeFunction) -> Self {
+        self.natives.push(native);
+        self
+    }
+
+    // A variable defined before the script runs, such as a handle made with
+    // Value::from_extern for the host's natives to be given back
+    pub fn global(mut self, name: &str, value: Value) -> Self {
+        self.globals.push((name.to_string(), value));
+        self
+    }
+
+    pub fn build(self) -> Interpreter {
+        let mut environment = Environment::new(self.base_path);
+        environment.options = self.options;
+        if let Some(io) = self.io {
+            environment.io = Arc::new(io);
+        }
+        environment.register_native_functions();
+        for native in self.natives {
+            environment.define_native_function(native);
+        }
+        for (name, value) in self.globals {
+            environment.define(&name, value);
+        }
+        let (runtime, owned_runtime) = match self.runtime {
+            Some(runtime) => (runtime, None),
+            None => super::shared_runtime(),
+        };
+        let mut interpreter = Interpreter::child(runtime, Arc::new(Mutex::new(environment)));
+        interpreter.owned_runtime = owned_runtime;
+        interpreter
+    }
+}
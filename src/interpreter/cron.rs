@@ -0,0 +1,120 @@
+// Cron expressions for `schedule`: five fields separated by spaces,
+//
+//   minute (0-59)  hour (0-23)  day of month (1-31)  month (1-12)  day of week (0-7, 0 and 7 Sunday)
+//
+// each `*`, a number, a range `a-b` or a comma separated list of those, any
+// of them followed by a step such as `*/15`. Times are UTC. As in cron, when
+// both day fields are restricted a day matching either one counts.
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    pub fn parse(expression: &str) -> Result<Schedule, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("Cron expression needs 5 fields, got {}", fields.len()));
+        };
+        let mut weekdays = parse_field(weekday, 0, 7)?;
+        // 7 is another name for Sunday
+        if weekdays & 1 << 7 != 0 {
+            weekdays |= 1;
+        }
+        Ok(Schedule {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays,
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+        })
+    }
+
+    // The first matching minute after `after`, both in seconds since the Unix
+    // epoch; None when nothing matches within five years (say, February 30)
+    pub fn next_after(&self, after: u64) -> Option<u64> {
+        let start = after / 60 + 1;
+        let first_day = start / MINUTES_PER_DAY;
+        for day in first_day..first_day + 5 * 366 {
+            if !self.matches_day(day) {
+                continue;
+            }
+            let from = if day == first_day { start % MINUTES_PER_DAY } else { 0 };
+            for minute_of_day in from..MINUTES_PER_DAY {
+                let (hour, minute) = (minute_of_day / 60, minute_of_day % 60);
+                if self.hours & 1 << hour != 0 && self.minutes & 1 << minute != 0 {
+                    return Some((day * MINUTES_PER_DAY + minute_of_day) * 60);
+                }
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, days_since_epoch: u64) -> bool {
+        let (month, day) = month_and_day(days_since_epoch);
+        // 1 January 1970 was a Thursday
+        let weekday = (days_since_epoch + 4) % 7;
+        let day_matches = self.days & 1 << day != 0;
+        let weekday_matches = self.weekdays & 1 << weekday != 0;
+        let either = match (self.any_day, self.any_weekday) {
+            (false, false) => day_matches || weekday_matches,
+            _ => day_matches && weekday_matches,
+        };
+        self.months & 1 << month != 0 && either
+    }
+}
+
+const MINUTES_PER_DAY: u64 = 24 * 60;
+
+// Bit n set for each value n the field allows
+fn parse_field(field: &str, min: u64, max: u64) -> Result<u64, String> {
+    let invalid = || format!("Invalid cron field '{}'", field);
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u64>().ok().filter(|step| *step > 0).ok_or_else(invalid)?),
+            None => (part, 1),
+        };
+        let (low, high) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((low, high)) => (
+                    low.parse().map_err(|_| invalid())?,
+                    high.parse().map_err(|_| invalid())?,
+                ),
+                None => {
+                    let value = range.parse().map_err(|_| invalid())?;
+                    // `5/10` runs from 5 to the end of the field, like `5-max/10`
+                    (value, if step > 1 { max } else { value })
+                }
+            },
+        };
+        if low < min || high > max || low > high {
+            return Err(invalid());
+        }
+        for value in (low..=high).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+// Month (1-12) and day of the month of a day counted from 1 January 1970
+fn month_and_day(days_since_epoch: u64) -> (u64, u64) {
+    // Days into the current 400 year cycle, counted from 1 March so that
+    // leap days fall at the end of each year
+    let day_of_era = (days_since_epoch + 719468) % 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    (month, day)
+}
@@ -0,0 +1,30 @@
+use std::path::Path;
+
+use super::{json, value::Value};
+
+// Files imported as a value instead of run as alpha code, told apart by
+// their extension: `import "config.json" as config`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataFormat {
+    // Parsed into dictionaries, arrays and plain values
+    Json,
+    // The whole file as a string
+    Text,
+}
+
+pub fn format(import_path: &str) -> Option<DataFormat> {
+    // The query and fragment of a URL are not part of its file name
+    let path = import_path.split(['?', '#']).next().unwrap_or(import_path);
+    match Path::new(path).extension()?.to_str()? {
+        "json" => Some(DataFormat::Json),
+        "txt" => Some(DataFormat::Text),
+        _ => None,
+    }
+}
+
+pub fn parse(format: DataFormat, text: String) -> Result<Value, String> {
+    match format {
+        DataFormat::Json => json::parse(&text),
+        DataFormat::Text => Ok(Value::String(text.into())),
+    }
+}
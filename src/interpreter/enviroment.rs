@@ -1,19 +1,60 @@
-use std::{collections::HashMap, future::Future, path::{Path, PathBuf}, pin::Pin, sync::{Arc, Mutex}};
+use std::{cell::RefCell, collections::{HashMap, HashSet}, path::{Path, PathBuf}, sync::{Arc, Mutex}};
 
 use rustc_hash::FxHashMap;
 
 use crate::{
-    error::{InterpreterError, InterpreterResult,RuntimeErrorKind},
-    parser::Parser, tokenizer::Tokenizer,
+    error::{InterpreterError, InterpreterResult, SourceLocation},
+    parser::{Expr, Parser}, tokenizer::Tokenizer,
 };
 
-use super::{native::NativeFunction, value::{self, Value}, Interpreter};
+use super::{data, io::ScriptIo, native::NativeFunction, native_functions, remote, stdlib, options::InterpreterOptions, value::Value, Interpreter};
+
+// Directory `alpha install` puts packages in
+pub const PACKAGES_DIR: &str = "alpha_modules";
+// Marks the root of a project
+pub const MANIFEST: &str = "alpha.toml";
+// Directories of modules shared between projects, separated like PATH
+pub const SEARCH_PATH_VAR: &str = "ALPHA_PATH";
+
+// Where an import may find its module, in order: relative to the importing
+// script, then among the packages installed next to it, at the root of its
+// project or in the working directory, then in the directories listed in
+// ALPHA_PATH. A package imported by its bare name loads the package's main.la.
+pub fn module_candidates(base_path: &Path, import_path: &str) -> Vec<PathBuf> {
+    let path = Path::new(import_path);
+    if path.is_absolute() {
+        return vec![path.to_path_buf()];
+    }
+    let mut candidates = vec![base_path.join(path)];
+    let mut search_dirs = vec![base_path.join(PACKAGES_DIR)];
+    search_dirs.extend(project_root(base_path).map(|root| root.join(PACKAGES_DIR)));
+    search_dirs.push(PathBuf::from(PACKAGES_DIR));
+    if let Some(search_path) = native_functions::env_var(SEARCH_PATH_VAR) {
+        search_dirs.extend(std::env::split_paths(&search_path).filter(|dir| !dir.as_os_str().is_empty()));
+    }
+    for dir in search_dirs {
+        candidates.push(dir.join(path));
+        if path.extension().is_none() {
+            candidates.push(dir.join(path).join("main.la"));
+        }
+    }
+    candidates
+}
+
+// The nearest directory holding alpha.toml, starting from the given one
+fn project_root(dir: &Path) -> Option<PathBuf> {
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let dir = std::fs::canonicalize(dir).ok()?;
+    dir.ancestors().find(|dir| dir.join(MANIFEST).is_file()).map(Path::to_path_buf)
+}
 
 #[derive(Clone, Debug)]
 pub struct Module {
     pub name: String,
     pub environment: Arc<Mutex<Environment>>,
     pub path: String,
+    // Set while a lazily imported module has not run yet
+    pub deferred: Option<Arc<DeferredModule>>,
 }
 
 #[derive(Clone, Debug)]
@@ -29,6 +70,128 @@ pub struct Environment {
     pub depth: usize,
     // Cache frequently accessed values
     pub base_path: PathBuf,
+    // Passed on to enclosed environments and imported modules
+    pub options: InterpreterOptions,
+    pub io: Arc<ScriptIo>,
+    module_cache: ModuleCache,
+}
+
+thread_local! {
+    // Ended scopes ready for reuse, kept per thread so taking one needs no lock
+    static POOL: RefCell<Vec<Arc<Mutex<Environment>>>> = const { RefCell::new(Vec::new()) };
+}
+
+const POOL_SIZE: usize = 64;
+const POOLED_CAPACITY: usize = 32;
+
+// Every module a program has imported, by canonical path, so a module
+// imported from several places runs once and they all share its globals
+pub type ModuleCache = Arc<Mutex<FxHashMap<PathBuf, ModuleState>>>;
+
+#[derive(Clone, Debug)]
+pub enum ModuleState {
+    // Still running its top level, so importing it again is a cycle
+    Running,
+    Loaded(Arc<Mutex<Environment>>),
+    // Imported with lazy_imports and not used yet
+    Deferred(Arc<DeferredModule>),
+}
+
+// A module parsed but not run. Its top level runs, once for every script
+// importing it, when one of them first uses a name it declares.
+#[derive(Debug)]
+pub struct DeferredModule {
+    path: PathBuf,
+    key: PathBuf,
+    environment: Arc<Mutex<Environment>>,
+    // The functions, classes and variables its top level declares; None
+    // when it imports other modules, whose names it passes on and which are
+    // not known before it runs
+    names: Option<HashSet<String>>,
+    // Taken when it runs
+    statements: Mutex<Option<Vec<(Expr, usize)>>>,
+}
+
+impl DeferredModule {
+    fn declares(&self, name: &str) -> bool {
+        match &self.names {
+            Some(names) => names.contains(name),
+            None => true,
+        }
+    }
+
+    fn pending(&self) -> bool {
+        self.statements.lock().unwrap().is_some()
+    }
+
+    // Runs the module's top level unless that already happened
+    pub fn load(&self) -> InterpreterResult<()> {
+        let Some(statements) = self.statements.lock().unwrap().take() else {
+            return Ok(());
+        };
+        let module_cache = Arc::clone(&self.environment.lock().unwrap().module_cache);
+        execute_module(&self.path, self.key.clone(), &self.environment, &module_cache, statements)
+    }
+}
+
+// The value of a name as a script reading it sees it: lazily imported
+// modules that may declare it run first, and modules they import in turn
+// when it is passed on from there
+pub fn get_loading(environment: &Arc<Mutex<Environment>>, name: &str) -> InterpreterResult<Option<Value>> {
+    let mut value = environment.lock().unwrap().get(name);
+    while value.is_none() {
+        let Some(deferred) = environment.lock().unwrap().deferred_module(name) else {
+            break;
+        };
+        deferred.load()?;
+        value = environment.lock().unwrap().get(name);
+    }
+    Ok(value)
+}
+
+// The names the top level of a module declares, or None if it imports other
+// modules
+fn declared_names(statements: &[(Expr, usize)]) -> Option<HashSet<String>> {
+    let mut names = HashSet::new();
+    for (statement, _) in statements {
+        match statement {
+            Expr::Let(name, _) | Expr::Function(name, ..) | Expr::AsyncFunction(name, ..) | Expr::Class(name, _) => {
+                names.insert(name.lexeme.clone());
+            }
+            Expr::Import(_, None) | Expr::Export(..) => return None,
+            Expr::Import(_, Some(alias)) => {
+                names.insert(alias.lexeme.clone());
+            }
+            _ => {}
+        }
+    }
+    Some(names)
+}
+
+// Runs a module's top level in its environment, caching its globals
+fn execute_module(
+    full_path: &Path,
+    key: PathBuf,
+    module_env: &Arc<Mutex<Environment>>,
+    module_cache: &ModuleCache,
+    statements: Vec<(Expr, usize)>,
+) -> InterpreterResult<()> {
+    let (runtime, owned_runtime) = super::shared_runtime();
+    let mut interpreter = Interpreter::child(runtime, Arc::clone(module_env));
+    interpreter.owned_runtime = owned_runtime;
+    module_cache.lock().unwrap().insert(key.clone(), ModuleState::Running);
+    let result = interpreter.interpret(statements);
+    let mut module_cache = module_cache.lock().unwrap();
+    match result {
+        Ok(_) => {
+            module_cache.insert(key, ModuleState::Loaded(Arc::clone(module_env)));
+            Ok(())
+        }
+        Err(error) => {
+            module_cache.remove(&key);
+            Err(error.in_file(full_path.to_path_buf()))
+        }
+    }
 }
 
 
@@ -40,32 +203,104 @@ impl Environment {
             modules: FxHashMap::default(),
             enclosing: None,
             depth: 0,
-            base_path
+            base_path,
+            options: InterpreterOptions::default(),
+            io: Arc::new(ScriptIo::default()),
+            module_cache: ModuleCache::default(),
         }
     }
 
     pub fn new_with_enclosing(enclosing: Option<Arc<Mutex<Environment>>>) -> Arc<Mutex<Self>> {
-        let depth = enclosing.as_ref().map_or(0, |e| e.lock().unwrap().depth + 1);
-        Arc::new(Mutex::new(Self {
-            natives: FxHashMap::default(),
-            modules: FxHashMap::default(),
-            values: FxHashMap::default(),
-            enclosing,
-            depth,
-            base_path: PathBuf::from(".".to_string())
-        }))
+        let Some(enclosing) = enclosing else {
+            return Arc::new(Mutex::new(Environment::new(PathBuf::new())));
+        };
+        let outer = enclosing.lock().unwrap();
+        let environment = match POOL.with(|pool| pool.borrow_mut().pop()) {
+            Some(environment) => {
+                let mut scope = environment.lock().unwrap();
+                scope.depth = outer.depth + 1;
+                scope.base_path.clone_from(&outer.base_path);
+                scope.options = outer.options;
+                scope.io = Arc::clone(&outer.io);
+                scope.module_cache = Arc::clone(&outer.module_cache);
+                drop(scope);
+                environment
+            }
+            None => Arc::new(Mutex::new(Self {
+                natives: FxHashMap::default(),
+                modules: FxHashMap::default(),
+                values: FxHashMap::default(),
+                enclosing: None,
+                depth: outer.depth + 1,
+                base_path: outer.base_path.clone(),
+                options: outer.options,
+                io: Arc::clone(&outer.io),
+                module_cache: Arc::clone(&outer.module_cache),
+            })),
+        };
+        drop(outer);
+        environment.lock().unwrap().enclosing = Some(enclosing);
+        environment
     }
 
-    pub fn define_native(
-        &mut self,
-        name: &str,
-        arity: usize,
-        func: fn(&Vec<Value>) -> InterpreterResult<Value>,
-    ) {
-        self.natives
-            .insert(name.to_string(), NativeFunction::new(name, arity, func));
+    // Takes back a scope that has ended for new_with_enclosing to reuse,
+    // saving the allocations of a fresh one in loops and calls. Scopes still
+    // held elsewhere, by an instance or a callback started in them, are
+    // left alone.
+    pub fn recycle(mut environment: Arc<Mutex<Environment>>) {
+        let Some(scope) = Arc::get_mut(&mut environment) else {
+            return;
+        };
+        let scope = scope.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner());
+        scope.enclosing = None;
+        scope.modules.clear();
+        scope.natives.clear();
+        // A scope that grew large is not worth keeping that size
+        if scope.values.capacity() > POOLED_CAPACITY {
+            scope.values = FxHashMap::default();
+        } else {
+            scope.values.clear();
+        }
+        POOL.with(|pool| {
+            let mut pool = pool.borrow_mut();
+            if pool.len() < POOL_SIZE {
+                pool.push(environment);
+            }
+        });
     }
-    
+
+    pub fn define_native_function(&mut self, native: NativeFunction) {
+        self.natives.insert(native.name.clone(), native);
+    }
+
+    pub fn define_native<F>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(&Vec<Value>) -> InterpreterResult<Value> + Send + Sync + 'static,
+    {
+        self.define_native_function(NativeFunction::new(name, arity, func));
+    }
+
+    pub fn define_native_optional<F>(&mut self, name: &str, arity: usize, max_arity: usize, func: F)
+    where
+        F: Fn(&Vec<Value>) -> InterpreterResult<Value> + Send + Sync + 'static,
+    {
+        self.define_native_function(NativeFunction::with_optional(name, arity, max_arity, func));
+    }
+
+    pub fn define_native_with_interpreter<F>(&mut self, name: &str, arity: usize, max_arity: usize, func: F)
+    where
+        F: Fn(&mut Interpreter, &Vec<Value>) -> InterpreterResult<Value> + Send + Sync + 'static,
+    {
+        self.define_native_function(NativeFunction::with_interpreter(name, arity, max_arity, func));
+    }
+
+    pub fn define_native_variadic<F>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(&Vec<Value>) -> InterpreterResult<Value> + Send + Sync + 'static,
+    {
+        self.define_native_optional(name, arity, usize::MAX, func);
+    }
+
     pub fn define_class(&mut self, name: String, methods: HashMap<String, Value>) {
         self.values.insert(name.clone(), Value::Class(name, methods));
     }
@@ -77,6 +312,14 @@ impl Environment {
         self.enclosing.clone()
     }
 
+    // A sibling scope starting with copies of this one's variables, for the
+    // next iteration of a loop
+    pub fn next_iteration(&self) -> Arc<Mutex<Environment>> {
+        let next = Environment::new_with_enclosing(self.enclosing.clone());
+        next.lock().unwrap().values.clone_from(&self.values);
+        next
+    }
+
     pub fn define(&mut self, name: &str, value: Value) {
         self.values.insert(name.to_string(), value);
     }
@@ -89,10 +332,8 @@ impl Environment {
         }else if let Some(enclosing) = &self.enclosing {
             let enclosing_lock = enclosing.lock().unwrap();
             enclosing_lock.get(name)
-        }else if let Some(value) = &self.get_from_module(name) {
-            Some(value.clone())
-        }  else {
-            None
+        }else {
+            self.get_from_module(name)
         }
     }
 
@@ -106,53 +347,133 @@ impl Environment {
         } 
         else {
             Err(InterpreterError::runtime_error(
-                crate::error::RuntimeErrorKind::UndefinedVariable(0, name.to_string())
+                crate::error::RuntimeErrorKind::UndefinedVariable(0, name.to_string(), None)
             ))
         }
     }
     
     pub fn resolve_module_path(&self, import_path: &str) -> InterpreterResult<PathBuf> {
-        let path = Path::new(import_path);
-        
-        // If the path is absolute, use it directly
-        if path.is_absolute() {
-            return Ok(path.to_path_buf());
-        }
-
-        // Try to resolve relative to the current module's base path
-        let resolved_path = self.base_path.join(path);
-        
-        // Check if the file exists
-        if resolved_path.exists() {
-            Ok(resolved_path)
-        } else {
-            // You might want to add additional search paths here
-            Err(InterpreterError::runtime_error(
-                crate::error::RuntimeErrorKind::RuntimeError(0,
-                    format!("Could not find module: {}", import_path)
-                )
-            ))
+        if let Some(path) = stdlib::resolve(import_path) {
+            return Ok(path);
+        }
+        if remote::is_remote(import_path) {
+            if !self.options.network {
+                return Err(InterpreterError::runtime_error(crate::error::RuntimeErrorKind::RuntimeError(
+                    0,
+                    format!("Cannot import {}: network access is disabled by the sandbox", import_path),
+                )));
+            }
+            return remote::fetch(import_path).map_err(|error| {
+                InterpreterError::runtime_error(crate::error::RuntimeErrorKind::RuntimeError(0, error))
+            });
         }
+        module_candidates(&self.base_path, import_path)
+            .into_iter()
+            .find(|path| path.is_file())
+            .ok_or_else(|| {
+                InterpreterError::runtime_error(crate::error::RuntimeErrorKind::RuntimeError(
+                    0,
+                    format!("Could not find module: {}", import_path),
+                ))
+            })
     }
 
-    pub fn import_module(&mut self, path: &str) -> InterpreterResult<()> {
+    // Imports the module, giving back the name it is known by
+    pub fn import_module(&mut self, path: &str) -> InterpreterResult<String> {
         let full_path = self.resolve_module_path(path)?;
+        let remote_name = remote::is_remote(path).then(|| remote::module_name(path));
         // Normalize path and get module name
         let path = std::path::Path::new(path);
-        let module_name = full_path
-            .file_stem()
-            .and_then(|s| s.to_str())
+        // A package imported by name is known by that name, not by its main.la
+        let named_by = if path.extension().is_none() { path } else { full_path.as_path() };
+        let module_name = remote_name
+            .or_else(|| named_by.file_stem().and_then(|s| s.to_str()))
+            .filter(|name| !name.is_empty())
             .ok_or_else(|| InterpreterError::runtime_error(
                 crate::error::RuntimeErrorKind::RuntimeError(0,"Invalid module path".to_string())
             ))?;
 
         // Check if module is already imported
         if self.modules.contains_key(module_name) {
-            return Ok(());
+            return Ok(module_name.to_string());
         }
 
+        let key = std::fs::canonicalize(&full_path).unwrap_or_else(|_| full_path.clone());
+        let cached = self.module_cache.lock().unwrap().get(&key).cloned();
+        let state = match cached {
+            Some(state) => state,
+            None => self.run_module(&full_path, key)?,
+        };
+        let (module_env, deferred) = match state {
+            ModuleState::Loaded(module_env) => (module_env, None),
+            ModuleState::Running => {
+                return Err(InterpreterError::runtime_error(
+                    crate::error::RuntimeErrorKind::InvalidImport(
+                        0,
+                        format!("{} (circular import)", path.display()),
+                    ),
+                ))
+            }
+            ModuleState::Deferred(deferred) if self.options.lazy_imports => {
+                (Arc::clone(&deferred.environment), Some(deferred))
+            }
+            // Imported without lazy_imports, so it runs now
+            ModuleState::Deferred(deferred) => {
+                deferred.load()?;
+                (Arc::clone(&deferred.environment), None)
+            }
+        };
+        // Store module
+        let module = Module {
+            name: module_name.to_string(),
+            environment: module_env,
+            path: path.to_str().unwrap().to_string(),
+            deferred,
+        };
+        self.modules.insert(module_name.to_string(), module);
+        Ok(module_name.to_string())
+    }
+
+    // A name an imported module declares or passes on, running the module
+    // first if it was imported lazily
+    pub fn module_value(&self, module: &str, name: &str) -> InterpreterResult<Option<Value>> {
+        let Some(module) = self.modules.get(module) else {
+            return Ok(None);
+        };
+        if let Some(deferred) = &module.deferred {
+            deferred.load()?;
+        }
+        get_loading(&module.environment, name)
+    }
+
+    // The value of a data file imported with `as`, parsed by its extension
+    pub fn import_data(&self, path: &str) -> InterpreterResult<Value> {
+        let Some(format) = data::format(path) else {
+            return Err(InterpreterError::runtime_error(crate::error::RuntimeErrorKind::InvalidImport(
+                0,
+                format!("{} (only .json and .txt files are imported with as)", path),
+            )));
+        };
+        let full_path = self.resolve_module_path(path)?;
+        let text = stdlib::read_module(&full_path).map_err(|_| {
+            InterpreterError::runtime_error(crate::error::RuntimeErrorKind::RuntimeError(
+                0,
+                format!("Could not read data file: {}", full_path.display()),
+            ))
+        })?;
+        data::parse(format, text).map_err(|error| {
+            InterpreterError::runtime_error(crate::error::RuntimeErrorKind::RuntimeError(
+                0,
+                format!("{}: {}", full_path.display(), error),
+            ))
+        })
+    }
+
+    // Runs a module file for the first time, caching its globals. With
+    // lazy_imports it is only parsed, and runs when first used.
+    fn run_module(&self, full_path: &Path, key: PathBuf) -> InterpreterResult<ModuleState> {
         // Read file content
-        let content = std::fs::read_to_string(&full_path).map_err(|_| {
+        let content = stdlib::read_module(full_path).map_err(|_| {
             InterpreterError::runtime_error(
                 crate::error::RuntimeErrorKind::RuntimeError(0,
                     format!("Could not read module file: {}", full_path.display())
@@ -162,27 +483,95 @@ impl Environment {
         // Parse and execute module code
         let mut tokenizer = Tokenizer::new();
         tokenizer.tokenize(&content)?;
-        let tokens: Vec<crate::tokenizer::Token> = tokenizer.get_tokens();
-        let expresions = Parser::new(tokens).parse()?;
-        // Create interpreter for module
-        let mut interpreter = Interpreter::new();
-        interpreter.interpret(expresions)?;
+        let tokens: Vec<crate::tokenizer::Token> = tokenizer.into_tokens();
+        let mut parser = Parser::new(tokens);
+        let expresions = parser.parse().map_err(|error| {
+            error.located_at(SourceLocation::at(&parser.current_token())).in_file(full_path.to_path_buf())
+        })?;
+        // Create interpreter for module. Its own imports resolve next to it.
+        let module_dir = full_path.parent().unwrap_or_else(|| Path::new(""));
+        let interpreter = Interpreter::builder().base_path(module_dir).options(self.options).build();
         let module_env = interpreter.environment.clone();
-        // Store module
-        let module = Module {
-            name: module_name.to_string(),
-            environment: module_env,
-            path: path.to_str().unwrap().to_string(),
-        };
-        interpreter.runtime.shutdown_background();
-        self.modules.insert(module_name.to_string(), module);
-        Ok(())
+        {
+            let mut env = module_env.lock().unwrap();
+            env.io = Arc::clone(&self.io);
+            env.module_cache = Arc::clone(&self.module_cache);
+        }
+        if self.options.lazy_imports {
+            let deferred = Arc::new(DeferredModule {
+                path: full_path.to_path_buf(),
+                key: key.clone(),
+                environment: Arc::clone(&module_env),
+                names: declared_names(&expresions),
+                statements: Mutex::new(Some(expresions)),
+            });
+            self.module_cache.lock().unwrap().insert(key, ModuleState::Deferred(Arc::clone(&deferred)));
+            return Ok(ModuleState::Deferred(deferred));
+        }
+        execute_module(full_path, key, &module_env, &self.module_cache, expresions)?;
+        Ok(ModuleState::Loaded(module_env))
     }
 
     pub fn get_module(&self, name: &str) -> Option<&Module> {
         self.modules.get(name)
     }
 
+    pub fn get_modules(&self) -> Vec<Module> {
+        self.modules.values().cloned().collect()
+    }
+
+    pub fn add_module(&mut self, module: Module) {
+        self.modules.insert(module.name.clone(), module);
+    }
+
+    // The lazily imported module that has yet to run and may declare the
+    // name, looked for where get looks in modules
+    pub fn deferred_module(&self, name: &str) -> Option<Arc<DeferredModule>> {
+        if let Some(enclosing) = &self.enclosing {
+            return enclosing.lock().unwrap().deferred_module(name);
+        }
+        let pending = self
+            .modules
+            .values()
+            .filter_map(|module| module.deferred.as_ref())
+            .find(|deferred| deferred.pending() && deferred.declares(name))
+            .cloned();
+        // Modules that have run may have imported others lazily in turn
+        pending.or_else(|| {
+            self.modules
+                .values()
+                .find_map(|module| module.environment.lock().unwrap().deferred_module(name))
+        })
+    }
+
+    // The visible name closest to one that is undefined, for "did you mean"
+    // in the error. Looks where get looks, and only suggests a name a few
+    // edits away.
+    pub fn suggest(&self, name: &str) -> Option<String> {
+        let mut names = HashSet::new();
+        self.visible_names(&mut names);
+        let limit = (name.chars().count() / 3).max(1);
+        names
+            .into_iter()
+            .map(|candidate| (edit_distance(name, &candidate), candidate))
+            .filter(|(distance, _)| *distance <= limit)
+            .min()
+            .map(|(_, candidate)| candidate)
+    }
+
+    fn visible_names(&self, names: &mut HashSet<String>) {
+        names.extend(self.values.keys().cloned());
+        names.extend(self.natives.keys().cloned());
+        match &self.enclosing {
+            Some(enclosing) => enclosing.lock().unwrap().visible_names(names),
+            None => {
+                for module in self.modules.values() {
+                    names.extend(module.environment.lock().unwrap().values.keys().cloned());
+                }
+            }
+        }
+    }
+
     pub fn get_from_module(&self, var_name: &str) -> Option<Value> {
         for module in self.modules.values() {
             if let Some(value) = module.environment.lock().unwrap().get(var_name) {
@@ -192,3 +581,28 @@ impl Environment {
         None
     }
 }
+
+// Edits between two names, counted in characters: insertions, deletions,
+// substitutions and swaps of neighbouring characters, the usual typos
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = distances[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = substitution.min(distances[i - 1][j] + 1).min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
@@ -0,0 +1,178 @@
+use std::path::Path;
+
+// Glob patterns for `glob` and `globMatch`, matched a path segment at a time:
+//
+//   *        any run of characters within a segment
+//   ?        any one character
+//   [abc]    one of the characters, with ranges like [a-z] and [!abc] for the rest
+//   **       a whole segment matching any number of directories, none included
+//   {a,b}    either alternative, for instance *.{la,alpha}
+//
+// As in shells, a wildcard does not match a name starting with '.' unless the
+// pattern segment starts with '.' too.
+
+pub fn matches(pattern: &str, path: &str) -> bool {
+    let path: Vec<&str> = path.split('/').collect();
+    expand_braces(pattern)
+        .iter()
+        .any(|pattern| match_segments(&pattern.split('/').collect::<Vec<_>>(), &path))
+}
+
+// Paths matching the pattern, relative ones to the working directory, sorted.
+// Directories that cannot be read are passed over.
+pub fn glob(pattern: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    for pattern in expand_braces(pattern) {
+        let segments: Vec<&str> = pattern.split('/').collect();
+        match segments.split_first() {
+            Some((&"", rest)) if !rest.is_empty() => walk(Path::new("/"), "/", rest, &mut found),
+            _ => walk(Path::new("."), "", &segments, &mut found),
+        }
+    }
+    found.sort();
+    found.dedup();
+    found
+}
+
+fn walk(dir: &Path, shown: &str, segments: &[&str], found: &mut Vec<String>) {
+    let Some((&segment, rest)) = segments.split_first() else {
+        if !shown.is_empty() {
+            found.push(shown.to_string());
+        }
+        return;
+    };
+    if segment == "**" {
+        walk(dir, shown, rest, found);
+        for (name, path) in entries(dir) {
+            if !name.starts_with('.') && path.is_dir() {
+                walk(&path, &join(shown, &name), segments, found);
+            }
+        }
+    } else if !segment.contains(['*', '?', '[']) {
+        let path = dir.join(segment);
+        if (rest.is_empty() && path.exists()) || path.is_dir() {
+            walk(&path, &join(shown, segment), rest, found);
+        }
+    } else {
+        for (name, path) in entries(dir) {
+            if match_segment(segment, &name) && (rest.is_empty() || path.is_dir()) {
+                walk(&path, &join(shown, &name), rest, found);
+            }
+        }
+    }
+}
+
+fn entries(dir: &Path) -> Vec<(String, std::path::PathBuf)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| (entry.file_name().to_string_lossy().into_owned(), entry.path()))
+        .collect()
+}
+
+fn join(shown: &str, name: &str) -> String {
+    match shown {
+        "" => name.to_string(),
+        "/" => format!("/{}", name),
+        _ => format!("{}/{}", shown, name),
+    }
+}
+
+fn match_segments(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len())
+            .take_while(|&skipped| skipped == 0 || !path[skipped - 1].starts_with('.'))
+            .any(|skipped| match_segments(rest, &path[skipped..])),
+        Some((segment, rest)) => match path.split_first() {
+            Some((name, path)) => match_segment(segment, name) && match_segments(rest, path),
+            None => false,
+        },
+    }
+}
+
+fn match_segment(pattern: &str, name: &str) -> bool {
+    if name.starts_with('.') && !pattern.starts_with('.') {
+        return false;
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    match_chars(&pattern, &name)
+}
+
+fn match_chars(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skipped| match_chars(&pattern[1..], &name[skipped..])),
+        Some('?') => !name.is_empty() && match_chars(&pattern[1..], &name[1..]),
+        Some('[') => match (name.first(), match_class(&pattern[1..])) {
+            (Some(&c), Some((matches, length))) => {
+                matches(c) && match_chars(&pattern[1 + length..], &name[1..])
+            }
+            // Without its closing ']' the bracket is an ordinary character
+            (Some('['), None) => match_chars(&pattern[1..], &name[1..]),
+            _ => false,
+        },
+        Some(&c) => name.first() == Some(&c) && match_chars(&pattern[1..], &name[1..]),
+    }
+}
+
+// The test for a [...] class whose contents start the slice, and how many
+// characters it takes including the closing ']'
+fn match_class(pattern: &[char]) -> Option<(impl Fn(char) -> bool + '_, usize)> {
+    let negated = matches!(pattern.first(), Some('!' | '^'));
+    let start = negated as usize;
+    // A ']' right after the opening bracket is part of the class
+    let end = start + 1 + pattern.get(start + 1..)?.iter().position(|&c| c == ']')?;
+    let members = &pattern[start..end];
+    let test = move |c: char| {
+        let mut i = 0;
+        let mut found = false;
+        while i < members.len() {
+            if i + 2 < members.len() && members[i + 1] == '-' {
+                found |= (members[i]..=members[i + 2]).contains(&c);
+                i += 3;
+            } else {
+                found |= members[i] == c;
+                i += 1;
+            }
+        }
+        found != negated
+    };
+    Some((test, end + 1))
+}
+
+// Every pattern the {a,b} alternatives stand for
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let Some(open) = pattern.find('{') else {
+        return vec![pattern.to_string()];
+    };
+    let mut depth = 0;
+    let mut alternatives = Vec::new();
+    let mut start = open + 1;
+    for (i, c) in pattern[open..].char_indices().map(|(i, c)| (open + i, c)) {
+        match c {
+            '{' => depth += 1,
+            ',' if depth == 1 => {
+                alternatives.push(&pattern[start..i]);
+                start = i + 1;
+            }
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    alternatives.push(&pattern[start..i]);
+                    let (prefix, suffix) = (&pattern[..open], &pattern[i + 1..]);
+                    return alternatives
+                        .iter()
+                        .flat_map(|alternative| expand_braces(&format!("{}{}{}", prefix, alternative, suffix)))
+                        .collect();
+                }
+            }
+            _ => {}
+        }
+    }
+    // An unclosed brace is taken literally
+    vec![pattern.to_string()]
+}
@@ -0,0 +1,590 @@
+use std::collections::HashMap;
+
+use super::value::Value;
+
+// Parses HTML the way browsers tolerate it into the element trees xmlParse
+// gives, under a dict{"name": "#document"} root. Tag and attribute names are
+// lowercased, void elements such as <br> have no children, an element left
+// open is closed by its parent's end tag, and <p>, <li>, <td> and the like
+// are closed by the tags that may not nest in them. Nothing makes the text
+// fail to parse.
+pub fn parse(text: &str) -> Value {
+    let mut parser = HtmlParser {
+        chars: text.chars().collect(),
+        position: 0,
+        open: vec![Open { name: "#document".to_string(), attributes: HashMap::new(), children: Vec::new() }],
+        text: String::new(),
+    };
+    parser.run();
+    parser.close_to(1);
+    let root = parser.open.pop().expect("document");
+    element(root.name, root.attributes, root.children)
+}
+
+const VOID: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr",
+];
+// Elements whose content is text up to their end tag, markup included
+const RAW_TEXT: &[&str] = &["script", "style", "textarea", "title"];
+// Elements that end an open <p>
+const BLOCK: &[&str] = &[
+    "address", "article", "aside", "blockquote", "div", "dl", "fieldset", "footer", "form", "h1", "h2", "h3",
+    "h4", "h5", "h6", "header", "hr", "main", "nav", "ol", "p", "pre", "section", "table", "ul",
+];
+
+// Whether opening `new` ends the open element `open`
+fn closed_by(open: &str, new: &str) -> bool {
+    match open {
+        "p" => BLOCK.contains(&new),
+        "li" => new == "li",
+        "dt" | "dd" => matches!(new, "dt" | "dd"),
+        "option" => matches!(new, "option" | "optgroup"),
+        "td" | "th" => matches!(new, "td" | "th" | "tr"),
+        "tr" => new == "tr",
+        _ => false,
+    }
+}
+
+struct Open {
+    name: String,
+    attributes: HashMap<String, Value>,
+    children: Vec<Value>,
+}
+
+struct HtmlParser {
+    chars: Vec<char>,
+    position: usize,
+    // Elements not closed yet, the document first
+    open: Vec<Open>,
+    text: String,
+}
+
+impl HtmlParser {
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.position + offset).copied()
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        text.chars()
+            .enumerate()
+            .all(|(i, c)| self.peek_at(i).is_some_and(|found| found.eq_ignore_ascii_case(&c)))
+    }
+
+    // Moves past the terminator, or to the end when there is none
+    fn skip_past(&mut self, terminator: &str) {
+        while self.position < self.chars.len() && !self.starts_with(terminator) {
+            self.position += 1;
+        }
+        self.position = (self.position + terminator.chars().count()).min(self.chars.len());
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek_at(0).is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    fn run(&mut self) {
+        while let Some(c) = self.peek_at(0) {
+            if self.starts_with("<!--") {
+                self.skip_past("-->");
+            } else if self.starts_with("<!") || self.starts_with("<?") {
+                self.skip_past(">");
+            } else if self.starts_with("</") && self.peek_at(2).is_some_and(|c| c.is_ascii_alphabetic()) {
+                self.position += 2;
+                let name = self.name();
+                self.skip_past(">");
+                self.end_tag(&name);
+            } else if c == '<' && self.peek_at(1).is_some_and(|c| c.is_ascii_alphabetic()) {
+                self.start_tag();
+            } else if c == '&' {
+                self.position += 1;
+                let decoded = self.entity();
+                self.text.push_str(&decoded);
+            } else {
+                self.text.push(c);
+                self.position += 1;
+            }
+        }
+        self.flush_text();
+    }
+
+    fn name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.peek_at(0).filter(|c| !c.is_whitespace() && !matches!(c, '/' | '>' | '=')) {
+            name.push(c.to_ascii_lowercase());
+            self.position += 1;
+        }
+        name
+    }
+
+    fn start_tag(&mut self) {
+        self.position += 1;
+        let name = self.name();
+        let mut attributes = HashMap::new();
+        let mut self_closing = false;
+        loop {
+            self.skip_whitespace();
+            match self.peek_at(0) {
+                None => break,
+                Some('>') => {
+                    self.position += 1;
+                    break;
+                }
+                Some('/') => {
+                    self.position += 1;
+                    self_closing = self.peek_at(0) == Some('>');
+                }
+                Some(_) => {
+                    let key = self.name();
+                    self.skip_whitespace();
+                    let value = if self.peek_at(0) == Some('=') {
+                        self.position += 1;
+                        self.skip_whitespace();
+                        self.attribute_value()
+                    } else {
+                        String::new()
+                    };
+                    if !key.is_empty() {
+                        attributes.entry(key).or_insert_with(|| Value::String(value.into()));
+                    }
+                }
+            }
+        }
+        self.flush_text();
+        while self.open.len() > 1 && closed_by(&self.open[self.open.len() - 1].name, &name) {
+            self.close_to(self.open.len() - 1);
+        }
+        self.open.push(Open { name: name.clone(), attributes, children: Vec::new() });
+        if VOID.contains(&name.as_str()) || self_closing {
+            self.close_to(self.open.len() - 1);
+        } else if RAW_TEXT.contains(&name.as_str()) {
+            let end = format!("</{}", name);
+            let mut content = String::new();
+            while self.position < self.chars.len() && !self.starts_with(&end) {
+                content.push(self.chars[self.position]);
+                self.position += 1;
+            }
+            self.skip_past(">");
+            // Entities still count in the text of <title> and <textarea>
+            if matches!(name.as_str(), "title" | "textarea") {
+                content = decode_entities(&content);
+            }
+            if !content.is_empty() {
+                self.open.last_mut().expect("open element").children.push(Value::String(content.into()));
+            }
+            self.close_to(self.open.len() - 1);
+        }
+    }
+
+    fn attribute_value(&mut self) -> String {
+        let quote = self.peek_at(0).filter(|c| matches!(c, '"' | '\''));
+        if quote.is_some() {
+            self.position += 1;
+        }
+        let mut value = String::new();
+        while let Some(c) = self.peek_at(0) {
+            let ends = match quote {
+                Some(quote) => c == quote,
+                None => c.is_whitespace() || c == '>',
+            };
+            if ends {
+                break;
+            }
+            value.push(c);
+            self.position += 1;
+        }
+        if quote.is_some() && self.peek_at(0).is_some() {
+            self.position += 1;
+        }
+        decode_entities(&value)
+    }
+
+    // The text of an entity reference after its '&', which is kept as it is
+    // when it names nothing known
+    fn entity(&mut self) -> String {
+        let length = (0..32)
+            .map_while(|i| self.peek_at(i))
+            .position(|c| c == ';' || !(c.is_ascii_alphanumeric() || c == '#'));
+        if let Some(length) = length.filter(|&length| self.peek_at(length) == Some(';')) {
+            let reference: String = self.chars[self.position..self.position + length].iter().collect();
+            if let Some(decoded) = entity(&reference) {
+                self.position += length + 1;
+                return decoded.to_string();
+            }
+        }
+        "&".to_string()
+    }
+
+    fn end_tag(&mut self, name: &str) {
+        self.flush_text();
+        // An end tag nothing opened is ignored
+        if let Some(index) = self.open.iter().rposition(|open| open.name == name).filter(|&index| index > 0) {
+            self.close_to(index);
+        }
+    }
+
+    // Closes the open elements from the given depth up, each becoming a child
+    // of the one it was opened in
+    fn close_to(&mut self, depth: usize) {
+        while self.open.len() > depth {
+            let closed = self.open.pop().expect("open element");
+            let closed = element(closed.name, closed.attributes, closed.children);
+            self.open.last_mut().expect("document").children.push(closed);
+        }
+    }
+
+    // Adds the text gathered so far unless it is only whitespace
+    fn flush_text(&mut self) {
+        if !self.text.trim().is_empty() {
+            let text = std::mem::take(&mut self.text);
+            self.open.last_mut().expect("document").children.push(Value::String(text.into()));
+        }
+        self.text.clear();
+    }
+}
+
+fn element(name: String, attributes: HashMap<String, Value>, children: Vec<Value>) -> Value {
+    Value::Dictionary(HashMap::from([
+        ("name".to_string(), Value::String(name.into())),
+        ("attributes".to_string(), Value::Dictionary(attributes)),
+        ("children".to_string(), Value::Array(children)),
+    ]))
+}
+
+fn entity(reference: &str) -> Option<char> {
+    if let Some(number) = reference.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    Some(match reference {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "hellip" => '…',
+        "mdash" => '—',
+        "ndash" => '–',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "middot" => '·',
+        "bull" => '•',
+        "euro" => '€',
+        _ => return None,
+    })
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut parser = HtmlParser { chars: text.chars().collect(), position: 0, open: Vec::new(), text: String::new() };
+    let mut decoded = String::new();
+    while let Some(c) = parser.peek_at(0) {
+        parser.position += 1;
+        if c == '&' {
+            decoded.push_str(&parser.entity());
+        } else {
+            decoded.push(c);
+        }
+    }
+    decoded
+}
+
+// The text inside an element tree, every descendant's joined in order
+pub fn text(node: &Value) -> String {
+    let mut text = String::new();
+    gather_text(node, &mut text);
+    text
+}
+
+fn gather_text(node: &Value, text: &mut String) {
+    match node {
+        Value::String(s) => text.push_str(s),
+        Value::Dictionary(fields) => {
+            if let Some(Value::Array(children)) = fields.get("children") {
+                for child in children {
+                    gather_text(child, text);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// CSS selectors for `select`: type, `*`, `.class`, `#id`, `[attr]` and
+// `[attr=value]` (also ~=, |=, ^=, $= and *=), combined with descendant
+// (space) and child (`>`) combinators, in comma separated groups
+struct Compound {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    attributes: Vec<(String, Option<(char, String)>)>,
+}
+
+#[derive(Clone, Copy)]
+enum Combinator {
+    Descendant,
+    Child,
+}
+
+// Compounds from left to right, with the combinator before each but the first
+struct Complex {
+    compounds: Vec<Compound>,
+    combinators: Vec<Combinator>,
+}
+
+// The elements under root, itself excluded, that the selector matches, in
+// document order
+pub fn select(root: &Value, selector: &str) -> Result<Vec<Value>, String> {
+    let selectors = parse_selector(selector)?;
+    let mut found = Vec::new();
+    let Value::Dictionary(fields) = root else {
+        return Ok(found);
+    };
+    let mut ancestors = Vec::new();
+    // The document itself is no element a selector could name
+    if !matches!(fields.get("name"), Some(Value::String(name)) if name.starts_with('#')) {
+        ancestors.push(fields);
+    }
+    if let Some(Value::Array(children)) = fields.get("children") {
+        for child in children {
+            collect(child, &selectors, &mut ancestors, &mut found);
+        }
+    }
+    Ok(found)
+}
+
+fn collect<'a>(
+    node: &'a Value,
+    selectors: &[Complex],
+    ancestors: &mut Vec<&'a HashMap<String, Value>>,
+    found: &mut Vec<Value>,
+) {
+    let Value::Dictionary(fields) = node else {
+        return;
+    };
+    if selectors.iter().any(|complex| {
+        matches_complex(&complex.compounds, &complex.combinators, fields, ancestors)
+    }) {
+        found.push(node.clone());
+    }
+    if let Some(Value::Array(children)) = fields.get("children") {
+        ancestors.push(fields);
+        for child in children {
+            collect(child, selectors, ancestors, found);
+        }
+        ancestors.pop();
+    }
+}
+
+fn matches_complex(
+    compounds: &[Compound],
+    combinators: &[Combinator],
+    element: &HashMap<String, Value>,
+    ancestors: &[&HashMap<String, Value>],
+) -> bool {
+    let Some((last, compounds)) = compounds.split_last() else {
+        return true;
+    };
+    if !matches_compound(last, element) {
+        return false;
+    }
+    let Some((combinator, combinators)) = combinators.split_last() else {
+        return true;
+    };
+    match combinator {
+        Combinator::Child => match ancestors.split_last() {
+            Some((parent, ancestors)) => matches_complex(compounds, combinators, parent, ancestors),
+            None => false,
+        },
+        Combinator::Descendant => (0..ancestors.len())
+            .rev()
+            .any(|i| matches_complex(compounds, combinators, ancestors[i], &ancestors[..i])),
+    }
+}
+
+fn matches_compound(compound: &Compound, element: &HashMap<String, Value>) -> bool {
+    let attribute = |name: &str| match element.get("attributes") {
+        Some(Value::Dictionary(attributes)) => attributes.get(name).map(|value| value.to_string()),
+        _ => None,
+    };
+    let name_matches = match (&compound.tag, element.get("name")) {
+        (None, _) => true,
+        (Some(tag), Some(Value::String(name))) => tag.eq_ignore_ascii_case(name),
+        _ => false,
+    };
+    let id_matches = compound.id.is_none() || attribute("id") == compound.id;
+    let classes = attribute("class").unwrap_or_default();
+    let classes_match = compound
+        .classes
+        .iter()
+        .all(|class| classes.split_whitespace().any(|name| name == class));
+    let attributes_match = compound.attributes.iter().all(|(name, test)| {
+        let Some(value) = attribute(name) else {
+            return false;
+        };
+        match test {
+            None => true,
+            Some(('=', expected)) => value == *expected,
+            Some(('~', expected)) => value.split_whitespace().any(|word| word == expected),
+            Some(('|', expected)) => value == *expected || value.starts_with(&format!("{}-", expected)),
+            Some(('^', expected)) => !expected.is_empty() && value.starts_with(expected.as_str()),
+            Some(('$', expected)) => !expected.is_empty() && value.ends_with(expected.as_str()),
+            Some(('*', expected)) => !expected.is_empty() && value.contains(expected.as_str()),
+            Some(_) => false,
+        }
+    });
+    name_matches && id_matches && classes_match && attributes_match
+}
+
+fn parse_selector(selector: &str) -> Result<Vec<Complex>, String> {
+    let mut parser = SelectorParser { chars: selector.chars().collect(), position: 0 };
+    let mut selectors = vec![parser.complex()?];
+    while parser.peek() == Some(',') {
+        parser.position += 1;
+        selectors.push(parser.complex()?);
+    }
+    match parser.peek() {
+        None => Ok(selectors),
+        Some(c) => Err(parser.error(&format!("unexpected '{}'", c))),
+    }
+}
+
+struct SelectorParser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl SelectorParser {
+    fn error(&self, message: &str) -> String {
+        format!("Invalid selector at column {}: {}", self.position + 1, message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.position;
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+        self.position > start
+    }
+
+    fn complex(&mut self) -> Result<Complex, String> {
+        self.skip_whitespace();
+        let mut complex = Complex { compounds: vec![self.compound()?], combinators: Vec::new() };
+        loop {
+            let spaced = self.skip_whitespace();
+            let combinator = match self.peek() {
+                None | Some(',') => return Ok(complex),
+                Some('>') => {
+                    self.position += 1;
+                    self.skip_whitespace();
+                    Combinator::Child
+                }
+                Some(_) if spaced => Combinator::Descendant,
+                Some(c) => return Err(self.error(&format!("unexpected '{}'", c))),
+            };
+            complex.combinators.push(combinator);
+            complex.compounds.push(self.compound()?);
+        }
+    }
+
+    fn compound(&mut self) -> Result<Compound, String> {
+        let mut compound = Compound { tag: None, id: None, classes: Vec::new(), attributes: Vec::new() };
+        let start = self.position;
+        if self.peek() == Some('*') {
+            self.position += 1;
+        } else if self.peek().is_some_and(is_identifier_char) {
+            compound.tag = Some(self.identifier()?);
+        }
+        loop {
+            match self.peek() {
+                Some('.') => {
+                    self.position += 1;
+                    compound.classes.push(self.identifier()?);
+                }
+                Some('#') => {
+                    self.position += 1;
+                    compound.id = Some(self.identifier()?);
+                }
+                Some('[') => {
+                    self.position += 1;
+                    compound.attributes.push(self.attribute()?);
+                }
+                Some(':') => return Err(self.error("pseudo-classes are not supported")),
+                _ if self.position == start => return Err(self.error("expected a selector")),
+                _ => return Ok(compound),
+            }
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        let start = self.position;
+        while self.peek().is_some_and(is_identifier_char) {
+            self.position += 1;
+        }
+        if self.position == start {
+            return Err(self.error("expected a name"));
+        }
+        Ok(self.chars[start..self.position].iter().collect())
+    }
+
+    // After the '[', up to and including the ']'
+    fn attribute(&mut self) -> Result<(String, Option<(char, String)>), String> {
+        self.skip_whitespace();
+        let name = self.identifier()?.to_ascii_lowercase();
+        self.skip_whitespace();
+        let operator = match self.peek() {
+            Some(']') => {
+                self.position += 1;
+                return Ok((name, None));
+            }
+            Some('=') => '=',
+            Some(operator @ ('~' | '|' | '^' | '$' | '*')) if self.chars.get(self.position + 1) == Some(&'=') => {
+                self.position += 1;
+                operator
+            }
+            _ => return Err(self.error("expected ']' or an operator in attribute selector")),
+        };
+        self.position += 1;
+        self.skip_whitespace();
+        let value = match self.peek() {
+            Some(quote @ ('"' | '\'')) => {
+                self.position += 1;
+                let start = self.position;
+                while self.peek().is_some_and(|c| c != quote) {
+                    self.position += 1;
+                }
+                if self.peek().is_none() {
+                    return Err(self.error("unterminated string"));
+                }
+                self.position += 1;
+                self.chars[start..self.position - 1].iter().collect()
+            }
+            _ => self.identifier()?,
+        };
+        self.skip_whitespace();
+        if self.peek() != Some(']') {
+            return Err(self.error("expected ']'"));
+        }
+        self.position += 1;
+        Ok((name, Some((operator, value))))
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_')
+}
@@ -0,0 +1,480 @@
+use std::collections::HashMap;
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+use super::{http_pool, proxy::Proxy, value::Value};
+
+// Requests with larger bodies are answered with 413 instead of being read
+const MAX_BODY: usize = 16 * 1024 * 1024;
+
+pub enum RequestError {
+    Io(std::io::Error),
+    // Answered with this status and the connection dropped
+    Status(u16),
+}
+
+impl From<std::io::Error> for RequestError {
+    fn from(e: std::io::Error) -> Self {
+        RequestError::Io(e)
+    }
+}
+
+// Reads one request into the dictionary handed to `httpServe` handlers:
+// method, path, query, headers (lowercased names) and body. None when the
+// client closed the connection before sending anything.
+pub async fn read_request<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+) -> Result<Option<Value>, RequestError> {
+    let mut head = String::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return if head.is_empty() { Ok(None) } else { Err(RequestError::Status(400)) };
+        }
+        if line.trim_end_matches(['\r', '\n']).is_empty() {
+            break;
+        }
+        head.push_str(&line);
+    }
+    let (mut request, length) = request_head(&head).map_err(RequestError::Status)?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+    request.insert("body".to_string(), Value::String(String::from_utf8_lossy(&body).into()));
+    Ok(Some(Value::Dictionary(request)))
+}
+
+// A whole request in text, as read_request gives it: parseHttpRequest
+pub fn parse_request(text: &str) -> Result<Value, String> {
+    let (head, body) = split_head(text).ok_or("the headers do not end with a blank line")?;
+    let (mut request, length) = request_head(head).map_err(|status| match status {
+        413 => "the body is too large".to_string(),
+        _ => "invalid request line or header".to_string(),
+    })?;
+    let body = body.get(..length).unwrap_or(body);
+    request.insert("body".to_string(), Value::String(body.into()));
+    Ok(Value::Dictionary(request))
+}
+
+// A whole response in text: status, headers (lowercased names) and body, the
+// dictionary buildHttpResponse takes. parseHttpResponse
+pub fn parse_response(text: &str) -> Result<Value, String> {
+    let (head, body) = split_head(text).ok_or("the headers do not end with a blank line")?;
+    let mut lines = head.lines();
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or("invalid status line")?;
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+        .collect();
+    let body = match headers.get("transfer-encoding") {
+        Some(encoding) if encoding.eq_ignore_ascii_case("chunked") => {
+            dechunk(body.as_bytes()).map_err(|e| e.to_string())?
+        }
+        _ => body.as_bytes().to_vec(),
+    };
+    let mut response = HashMap::new();
+    response.insert("status".to_string(), Value::Number(status as f64));
+    response.insert(
+        "headers".to_string(),
+        Value::Dictionary(headers.into_iter().map(|(name, value)| (name, Value::String(value.into()))).collect()),
+    );
+    response.insert("body".to_string(), Value::String(String::from_utf8_lossy(&body).into()));
+    Ok(Value::Dictionary(response))
+}
+
+// The head of a message and the rest, split at the first blank line
+fn split_head(text: &str) -> Option<(&str, &str)> {
+    let crlf = text.find("\r\n\r\n").map(|end| (end, 4));
+    let lf = text.find("\n\n").map(|end| (end, 2));
+    let (end, length) = match (crlf, lf) {
+        (Some(crlf), Some(lf)) => crlf.min(lf),
+        (crlf, lf) => crlf.or(lf)?,
+    };
+    Some((&text[..end], &text[end + length..]))
+}
+
+// The request line and headers of a request, and the length of its body;
+// the status to answer with when they are not acceptable
+fn request_head(head: &str) -> Result<(HashMap<String, Value>, usize), u16> {
+    let mut lines = head.lines();
+    let line = lines.next().unwrap_or("");
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/") => {
+            (method.to_string(), target.to_string())
+        }
+        _ => return Err(400),
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), query.to_string()),
+        None => (target, String::new()),
+    };
+
+    let mut headers = HashMap::new();
+    for header in lines {
+        match header.split_once(':') {
+            Some((name, value)) => {
+                headers.insert(name.trim().to_lowercase(), Value::String(value.trim().into()));
+            }
+            None => return Err(400),
+        }
+    }
+
+    let length = match headers.get("content-length") {
+        Some(Value::String(length)) => length.parse::<usize>().map_err(|_| 400u16)?,
+        _ => 0,
+    };
+    if length > MAX_BODY {
+        return Err(413);
+    }
+
+    let mut request = HashMap::new();
+    request.insert("method".to_string(), Value::String(method.into()));
+    request.insert("path".to_string(), Value::String(path.into()));
+    request.insert("query".to_string(), Value::String(query.into()));
+    request.insert("headers".to_string(), Value::Dictionary(headers));
+    Ok((request, length))
+}
+
+// The parameters of a request path matching a route pattern. A :name
+// segment matches any one segment and a final * the rest of the path, given
+// as the parameter "*"; other segments match themselves. Empty segments are
+// left out on both sides, so /users/ matches /users.
+pub fn match_route(pattern: &str, path: &str) -> Option<HashMap<String, Value>> {
+    let mut params = HashMap::new();
+    let mut path_segments = path.split('/').filter(|segment| !segment.is_empty());
+    let mut pattern_segments = pattern.split('/').filter(|segment| !segment.is_empty()).peekable();
+    while let Some(expected) = pattern_segments.next() {
+        if expected == "*" && pattern_segments.peek().is_none() {
+            let rest: Vec<_> = path_segments.map(percent_decode).collect();
+            params.insert("*".to_string(), Value::String(rest.join("/").into()));
+            return Some(params);
+        }
+        let segment = path_segments.next()?;
+        match expected.strip_prefix(':') {
+            Some(name) => {
+                params.insert(name.to_string(), Value::String(percent_decode(segment).into()));
+            }
+            None if expected == segment => {}
+            None => return None,
+        }
+    }
+    path_segments.next().is_none().then_some(params)
+}
+
+// Text with its %XX escapes turned back into the bytes they stand for
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// The fields of an application/x-www-form-urlencoded body or query string.
+// A name given more than once has an array of its values, in order.
+pub fn parse_urlencoded(text: &str) -> HashMap<String, Value> {
+    let mut fields: HashMap<String, Value> = HashMap::new();
+    for pair in text.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let name = percent_decode(&name.replace('+', " "));
+        let value = Value::String(percent_decode(&value.replace('+', " ")).into());
+        match fields.remove(&name) {
+            Some(Value::Array(mut values)) => {
+                values.push(value);
+                fields.insert(name, Value::Array(values));
+            }
+            Some(first) => {
+                fields.insert(name, Value::Array(vec![first, value]));
+            }
+            None => {
+                fields.insert(name, value);
+            }
+        }
+    }
+    fields
+}
+
+// One part of a multipart/form-data body
+pub struct FormPart {
+    pub name: String,
+    // Set for uploaded files
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+// The parts of a multipart/form-data body, with the boundary taken from the
+// Content-Type header value it was sent with
+pub fn parse_multipart(body: &[u8], content_type: &str) -> Result<Vec<FormPart>, String> {
+    let boundary = content_type
+        .split(';')
+        .filter_map(|parameter| parameter.trim().split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim().trim_matches('"').to_string())
+        .ok_or("the Content-Type has no boundary")?;
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut position = find(body, &delimiter, 0).ok_or("the body has no boundary line")? + delimiter.len();
+    let mut parts = Vec::new();
+    loop {
+        if body[position..].starts_with(b"--") {
+            return Ok(parts);
+        }
+        let start = find(body, b"\r\n", position).ok_or("a part is cut off")? + 2;
+        let mut end_delimiter = b"\r\n".to_vec();
+        end_delimiter.extend_from_slice(&delimiter);
+        let end = find(body, &end_delimiter, start).ok_or("a part has no closing boundary")?;
+        parts.push(form_part(&body[start..end])?);
+        position = end + end_delimiter.len();
+    }
+}
+
+fn form_part(part: &[u8]) -> Result<FormPart, String> {
+    let head_end = find(part, b"\r\n\r\n", 0).ok_or("a part has no blank line after its headers")?;
+    let head = String::from_utf8_lossy(&part[..head_end]);
+    let mut disposition = None;
+    let mut content_type = None;
+    for header in head.split("\r\n") {
+        match header.split_once(':') {
+            Some((name, value)) if name.trim().eq_ignore_ascii_case("content-disposition") => {
+                disposition = Some(value.trim().to_string());
+            }
+            Some((name, value)) if name.trim().eq_ignore_ascii_case("content-type") => {
+                content_type = Some(value.trim().to_string());
+            }
+            _ => {}
+        }
+    }
+    let disposition = disposition.ok_or("a part has no Content-Disposition")?;
+    let parameter = |wanted: &str| {
+        disposition
+            .split(';')
+            .filter_map(|parameter| parameter.trim().split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case(wanted))
+            .map(|(_, value)| value.trim().trim_matches('"').to_string())
+    };
+    Ok(FormPart {
+        name: parameter("name").ok_or("a part has no name")?,
+        filename: parameter("filename"),
+        content_type,
+        data: part[head_end + 4..].to_vec(),
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|offset| from + offset)
+}
+
+// Turns a handler's result into response bytes. A dictionary may set status
+// (default 200), headers and body; any other value is sent as a plain text body.
+pub fn response_bytes(response: &Value) -> Vec<u8> {
+    let (status, headers, body) = match response {
+        Value::Dictionary(response) => {
+            let status = match response.get("status") {
+                Some(Value::Number(status)) => *status as u16,
+                _ => 200,
+            };
+            let body = match response.get("body") {
+                Some(Value::Nil) | None => String::new(),
+                Some(body) => body.to_string(),
+            };
+            let headers = match response.get("headers") {
+                Some(Value::Dictionary(headers)) => headers
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.to_string()))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            (status, headers, body)
+        }
+        Value::Nil => (204, Vec::new(), String::new()),
+        other => (
+            200,
+            vec![("Content-Type".to_string(), "text/plain; charset=utf-8".to_string())],
+            other.to_string(),
+        ),
+    };
+    build_response(status, &headers, body.as_bytes())
+}
+
+pub fn status_response(status: u16) -> Vec<u8> {
+    build_response(status, &[], reason(status).as_bytes())
+}
+
+fn build_response(status: u16, headers: &[(String, String)], body: &[u8]) -> Vec<u8> {
+    let mut out = format!("HTTP/1.1 {} {}\r\n", status, reason(status));
+    for (name, value) in headers {
+        // Framing is ours to decide
+        if name.eq_ignore_ascii_case("content-length") || name.eq_ignore_ascii_case("connection") {
+            continue;
+        }
+        out.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    out.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n\r\n", body.len()));
+    let mut out = out.into_bytes();
+    out.extend_from_slice(body);
+    out
+}
+
+pub fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+// Fetches the body of an http:// or https:// URL, following redirects
+// but not from https to http. Made for downloading modules: one request
+// per connection and the whole body in memory.
+pub async fn get(url: &str) -> std::io::Result<Vec<u8>> {
+    match send("GET", url, &[], None, false).await? {
+        (200, _, body) => Ok(body),
+        (status, _, _) => Err(std::io::Error::other(format!("{} answered with status {}", url, status))),
+    }
+}
+
+// Sends a request to an http:// or https:// URL and gives the status,
+// headers (lowercased names) and body of the response, following redirects:
+// 303, and 301 and 302 of a POST, continue as a GET without the body.
+// Connects through the proxy in ALPHA_HTTP_PROXY when it is set, and reuses
+// connections kept open by http_pool.
+pub async fn request(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<&[u8]>,
+) -> std::io::Result<(u16, HashMap<String, String>, Vec<u8>)> {
+    send(method, url, headers, body, true).await
+}
+
+async fn send(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<&[u8]>,
+    downgrade: bool,
+) -> std::io::Result<(u16, HashMap<String, String>, Vec<u8>)> {
+    let mut url = url.to_string();
+    let mut method = method.to_string();
+    let mut body = body;
+    let proxy = Proxy::from_env()?;
+    for _ in 0..5 {
+        let (tls, host, port, path) = split_url(&url)?;
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: alpha/{}\r\nAccept-Encoding: identity\r\n",
+            method,
+            path,
+            host,
+            env!("CARGO_PKG_VERSION")
+        );
+        if !http_pool::keep_alive() {
+            request.push_str("Connection: close\r\n");
+        }
+        for (name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if let Some(body) = body {
+            request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        request.push_str("\r\n");
+        let mut request = request.into_bytes();
+        request.extend_from_slice(body.unwrap_or_default());
+        let response = http_pool::send(tls, &host, port, proxy.as_ref(), method == "HEAD", &request).await?;
+        let http_pool::Response { status, headers, body: response_body } = response;
+        match (status, headers.get("location")) {
+            (301 | 302 | 303 | 307 | 308, Some(location)) => {
+                url = if location.contains("://") {
+                    location.clone()
+                } else {
+                    format!("{}://{}:{}{}", if tls { "https" } else { "http" }, host, port, location)
+                };
+                if tls && !downgrade && !url.starts_with("https://") {
+                    return Err(std::io::Error::other(format!("refused redirect from https to {}", url)));
+                }
+                if status == 303 || (method == "POST" && status < 303) {
+                    method = "GET".to_string();
+                    body = None;
+                }
+            }
+            (301 | 302 | 303 | 307 | 308, None) => return Err(invalid("redirect without a location")),
+            _ => return Ok((status, headers, response_body)),
+        }
+    }
+    Err(std::io::Error::other("too many redirects"))
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Whether it is https, then the host, port and path with query
+fn split_url(url: &str) -> std::io::Result<(bool, String, u16, String)> {
+    let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        (true, rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        (false, rest)
+    } else {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Unsupported URL {}", url)));
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(slash) => (&rest[..slash], &rest[slash..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| invalid("invalid port"))?),
+        None => (authority, if tls { 443 } else { 80 }),
+    };
+    Ok((tls, host.to_string(), port, path.to_string()))
+}
+
+fn dechunk(mut body: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    loop {
+        let line_end = body.windows(2).position(|w| w == b"\r\n").ok_or_else(|| invalid("invalid chunk"))?;
+        let size = String::from_utf8_lossy(&body[..line_end]);
+        let size = usize::from_str_radix(size.split(';').next().unwrap_or("").trim(), 16)
+            .map_err(|_| invalid("invalid chunk size"))?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok(data);
+        }
+        data.extend_from_slice(body.get(..size).ok_or_else(|| invalid("truncated chunk"))?);
+        body = body.get(size + 2..).unwrap_or_default();
+    }
+}
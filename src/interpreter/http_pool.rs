@@ -0,0 +1,217 @@
+use std::{collections::HashMap, io, sync::Mutex, time::{Duration, Instant}};
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+use super::{proxy::{self, Proxy}, socket::{connect_tls, Stream}};
+
+// How many idle connections the HTTP client keeps for each host and how long
+// one may sit unused before it is dropped. setHttpPool changes them; a size
+// of 0 closes every connection after its response.
+struct Settings {
+    size: usize,
+    idle_timeout: Duration,
+}
+
+static SETTINGS: Mutex<Settings> = Mutex::new(Settings { size: 4, idle_timeout: Duration::from_secs(30) });
+static IDLE: Mutex<Vec<Idle>> = Mutex::new(Vec::new());
+
+type Connection = BufReader<Box<dyn Stream>>;
+
+// Connections are only shared between requests to the same host and port,
+// over the same scheme and through the same proxy
+#[derive(Clone, PartialEq)]
+struct Key {
+    tls: bool,
+    host: String,
+    port: u16,
+    proxy: Option<Proxy>,
+}
+
+struct Idle {
+    key: Key,
+    connection: Connection,
+    since: Instant,
+}
+
+pub struct Response {
+    pub status: u16,
+    // Lowercased names
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+pub fn configure(size: usize, idle_timeout: Duration) {
+    *SETTINGS.lock().unwrap() = Settings { size, idle_timeout };
+    let mut idle = IDLE.lock().unwrap();
+    let mut kept: Vec<Idle> = Vec::new();
+    for connection in idle.drain(..) {
+        if kept.iter().filter(|other| other.key == connection.key).count() < size {
+            kept.push(connection);
+        }
+    }
+    *idle = kept;
+}
+
+// Whether connections are kept open for reuse, so requests should not ask
+// the server to close them
+pub fn keep_alive() -> bool {
+    SETTINGS.lock().unwrap().size > 0
+}
+
+// Sends a whole request to the host and reads its response, on an idle
+// connection to the host when there is one. A kept connection the server has
+// closed in the meantime is replaced by a new one before the request counts
+// as failed.
+pub async fn send(
+    tls: bool,
+    host: &str,
+    port: u16,
+    proxy: Option<&Proxy>,
+    head_only: bool,
+    request: &[u8],
+) -> io::Result<Response> {
+    let key = Key { tls, host: host.to_string(), port, proxy: proxy.cloned() };
+    let mut connection = match take_idle(&key) {
+        Some(mut connection) => match reuse(&mut connection, request).await {
+            Ok(()) => connection,
+            Err(_) => open(&key, request).await?,
+        },
+        None => open(&key, request).await?,
+    };
+    let (response, reusable) = read_response(&mut connection, head_only).await?;
+    if reusable {
+        put_idle(key, connection);
+    }
+    Ok(response)
+}
+
+fn take_idle(key: &Key) -> Option<Connection> {
+    let idle_timeout = SETTINGS.lock().unwrap().idle_timeout;
+    let mut idle = IDLE.lock().unwrap();
+    idle.retain(|connection| connection.since.elapsed() < idle_timeout);
+    let position = idle.iter().rposition(|connection| connection.key == *key)?;
+    Some(idle.remove(position).connection)
+}
+
+fn put_idle(key: Key, connection: Connection) {
+    let size = SETTINGS.lock().unwrap().size;
+    let mut idle = IDLE.lock().unwrap();
+    if idle.iter().filter(|other| other.key == key).count() < size {
+        idle.push(Idle { key, connection, since: Instant::now() });
+    }
+}
+
+async fn open(key: &Key, request: &[u8]) -> io::Result<Connection> {
+    let stream = proxy::connect(key.proxy.as_ref(), &key.host, key.port).await?;
+    let stream: Box<dyn Stream> = if key.tls {
+        Box::new(connect_tls(&key.host, stream).await?)
+    } else {
+        Box::new(stream)
+    };
+    let mut connection = BufReader::new(stream);
+    connection.get_mut().write_all(request).await?;
+    Ok(connection)
+}
+
+// Writes the request on a kept connection and waits for the first bytes of
+// the answer, which do not come when the server has closed it
+async fn reuse(connection: &mut Connection, request: &[u8]) -> io::Result<()> {
+    connection.get_mut().write_all(request).await?;
+    if connection.fill_buf().await?.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"));
+    }
+    Ok(())
+}
+
+// The response and whether the connection can carry another request, which
+// it cannot when the body runs to the end of the connection
+async fn read_response(connection: &mut Connection, head_only: bool) -> io::Result<(Response, bool)> {
+    let (version, status, headers) = loop {
+        let (version, status, headers) = read_head(connection).await?;
+        // Interim answers such as 100 Continue come before the real one
+        if !(100..200).contains(&status) {
+            break (version, status, headers);
+        }
+    };
+    // HTTP/1.1 keeps connections open unless told otherwise, 1.0 the reverse
+    let mut reusable = match headers.get("connection") {
+        Some(value) => value.eq_ignore_ascii_case("keep-alive"),
+        None => version != "HTTP/1.0",
+    };
+    let body = if head_only || status == 204 || status == 304 {
+        Vec::new()
+    } else if headers.get("transfer-encoding").is_some_and(|value| value.eq_ignore_ascii_case("chunked")) {
+        read_chunked(connection).await?
+    } else if let Some(length) = headers.get("content-length") {
+        let length = length.parse().map_err(|_| invalid("invalid content length"))?;
+        let mut body = vec![0; length];
+        connection.read_exact(&mut body).await?;
+        body
+    } else {
+        reusable = false;
+        let mut body = Vec::new();
+        match connection.read_to_end(&mut body).await {
+            Ok(_) => {}
+            // Servers often close TLS connections without saying so
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {}
+            Err(e) => return Err(e),
+        }
+        body
+    };
+    Ok((Response { status, headers, body }, reusable))
+}
+
+// The version and status from the status line, and the headers
+async fn read_head(connection: &mut Connection) -> io::Result<(String, u16, HashMap<String, String>)> {
+    let status_line = read_line(connection).await?;
+    let mut parts = status_line.split(' ');
+    let version = parts.next().unwrap_or_default().to_string();
+    let status = parts
+        .next()
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| invalid("invalid status line"))?;
+    let mut headers = HashMap::new();
+    loop {
+        let line = read_line(connection).await?;
+        if line.is_empty() {
+            return Ok((version, status, headers));
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+}
+
+async fn read_chunked(connection: &mut Connection) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line = read_line(connection).await?;
+        let size = usize::from_str_radix(line.split(';').next().unwrap_or("").trim(), 16)
+            .map_err(|_| invalid("invalid chunk size"))?;
+        if size == 0 {
+            // Trailer headers, up to the blank line ending the message
+            while !read_line(connection).await?.is_empty() {}
+            return Ok(body);
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        connection.read_exact(&mut body[start..]).await?;
+        read_line(connection).await?;
+    }
+}
+
+// A line without its line break; an error at the end of the connection
+async fn read_line(connection: &mut Connection) -> io::Result<String> {
+    let mut line = Vec::new();
+    if connection.read_until(b'\n', &mut line).await? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "incomplete response"));
+    }
+    while matches!(line.last(), Some(b'\n' | b'\r')) {
+        line.pop();
+    }
+    Ok(String::from_utf8_lossy(&line).into_owned())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
@@ -0,0 +1,101 @@
+use std::{borrow::Cow, sync::{Arc, Mutex}};
+
+use super::{enviroment::Environment, json::quote, value::{format_number, Value}};
+
+// Readable text for a value, for inspect and pprint. Arrays, dictionaries
+// and instances stay on one line while they fit in width columns and
+// otherwise put each element on a line of its own, indented two spaces per
+// level. Dictionary keys and instance fields are sorted; strings are quoted
+// so they stand apart from numbers. Containers nested deeper than max_depth
+// show as [...] and {...}, and an instance reached again inside itself as
+// <cycle>.
+pub fn inspect(value: &Value, max_depth: usize, width: usize) -> String {
+    Inspector { max_depth, width, visiting: Vec::new() }.render(value, 0, 0)
+}
+
+struct Inspector {
+    max_depth: usize,
+    width: usize,
+    // Instances being rendered, to stop at cycles through `this`
+    visiting: Vec<Arc<Mutex<Environment>>>,
+}
+
+type Items<'a> = Vec<(Option<String>, Cow<'a, Value>)>;
+
+impl Inspector {
+    fn render(&mut self, value: &Value, depth: usize, indent: usize) -> String {
+        match value {
+            Value::String(text) => quote(text),
+            Value::Number(n) => format_number(*n),
+            Value::Array(elements) => {
+                let items = elements.iter().map(|element| (None, Cow::Borrowed(element))).collect();
+                self.container("", ('[', ']'), items, depth, indent)
+            }
+            Value::Dictionary(entries) => {
+                let mut entries: Vec<_> = entries.iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                let items = entries
+                    .into_iter()
+                    .map(|(key, value)| (Some(key_text(key)), Cow::Borrowed(value)))
+                    .collect();
+                self.container("", ('{', '}'), items, depth, indent)
+            }
+            Value::Instance(class, environment) => {
+                if self.visiting.iter().any(|visiting| Arc::ptr_eq(visiting, environment)) {
+                    return "<cycle>".to_string();
+                }
+                // Fields are what the instance holds besides its methods and this
+                let values = environment.lock().unwrap().get_values();
+                let mut fields: Vec<_> = values
+                    .into_iter()
+                    .filter(|(name, value)| {
+                        name != "this" && !matches!(value, Value::Function(..) | Value::AsyncFunction(..))
+                    })
+                    .collect();
+                fields.sort_by(|a, b| a.0.cmp(&b.0));
+                let items = fields
+                    .into_iter()
+                    .map(|(name, value)| (Some(key_text(&name)), Cow::Owned(value)))
+                    .collect();
+                self.visiting.push(Arc::clone(environment));
+                let text = self.container(&format!("{} ", class), ('{', '}'), items, depth, indent);
+                self.visiting.pop();
+                text
+            }
+            other => other.to_string(),
+        }
+    }
+
+    fn container(&mut self, prefix: &str, (open, close): (char, char), items: Items, depth: usize, indent: usize) -> String {
+        if items.is_empty() {
+            return format!("{}{}{}", prefix, open, close);
+        }
+        if depth >= self.max_depth {
+            return format!("{}{}...{}", prefix, open, close);
+        }
+        let parts: Vec<String> = items
+            .iter()
+            .map(|(key, value)| {
+                let value = self.render(value, depth + 1, indent + 2);
+                match key {
+                    Some(key) => format!("{}: {}", key, value),
+                    None => value,
+                }
+            })
+            .collect();
+        let line = format!("{}{}{}{}", prefix, open, parts.join(", "), close);
+        if !line.contains('\n') && indent + line.chars().count() <= self.width {
+            return line;
+        }
+        let inner = " ".repeat(indent + 2);
+        let lines: Vec<String> = parts.iter().map(|part| format!("{}{}", inner, part)).collect();
+        format!("{}{}\n{}\n{}{}", prefix, open, lines.join(",\n"), " ".repeat(indent), close)
+    }
+}
+
+// Keys that read as names are shown bare, others quoted
+fn key_text(key: &str) -> String {
+    let is_name = key.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if is_name { key.to_string() } else { quote(key) }
+}
@@ -0,0 +1,122 @@
+use std::io::{BufRead, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use super::terminal;
+
+// The streams `print`, `input` and the other console natives use. By
+// default they are the process's own; hosts embedding the interpreter can
+// give it others to capture what scripts print or to feed them input.
+pub struct ScriptIo {
+    stdout: Mutex<Box<dyn Write + Send>>,
+    stderr: Mutex<Box<dyn Write + Send>>,
+    // None reads the process's stdin through its shared buffer, so input
+    // read elsewhere (the REPL) is not taken ahead of time
+    stdin: Option<Mutex<Box<dyn BufRead + Send>>>,
+}
+
+impl ScriptIo {
+    pub fn new(
+        stdout: impl Write + Send + 'static,
+        stderr: impl Write + Send + 'static,
+        stdin: impl BufRead + Send + 'static,
+    ) -> Self {
+        ScriptIo {
+            stdout: Mutex::new(Box::new(stdout)),
+            stderr: Mutex::new(Box::new(stderr)),
+            stdin: Some(Mutex::new(Box::new(stdin))),
+        }
+    }
+
+    pub fn write_stdout(&self, text: &str) -> std::io::Result<()> {
+        let mut stdout = self.stdout.lock().unwrap();
+        stdout.write_all(text.as_bytes())?;
+        stdout.flush()
+    }
+
+    pub fn write_stderr(&self, text: &str) -> std::io::Result<()> {
+        let mut stderr = self.stderr.lock().unwrap();
+        stderr.write_all(text.as_bytes())?;
+        stderr.flush()
+    }
+
+    // Appends the next line, line ending included; 0 at the end of input
+    pub fn read_line(&self, line: &mut String) -> std::io::Result<usize> {
+        match &self.stdin {
+            Some(stdin) => stdin.lock().unwrap().read_line(line),
+            None => std::io::stdin().read_line(line),
+        }
+    }
+
+    // Like read_line, but what is typed at a terminal is not echoed
+    pub fn read_secret(&self, line: &mut String) -> std::io::Result<usize> {
+        match &self.stdin {
+            Some(stdin) => stdin.lock().unwrap().read_line(line),
+            None => terminal::read_terminal_secret(line).unwrap_or_else(|| std::io::stdin().read_line(line)),
+        }
+    }
+
+    // Whether the script's own stdin and stdout are a terminal, for prompts
+    // that redraw themselves as keys are pressed
+    pub fn is_interactive(&self) -> bool {
+        self.stdin.is_none() && terminal::is_interactive()
+    }
+
+    pub fn read_to_string(&self, text: &mut String) -> std::io::Result<usize> {
+        match &self.stdin {
+            Some(stdin) => stdin.lock().unwrap().read_to_string(text),
+            None => std::io::stdin().read_to_string(text),
+        }
+    }
+
+    // The next key pressed, see terminal::read_key; None at the end of
+    // input. Input other than the terminal is read a key at a time as it is.
+    pub fn read_key(&self) -> std::io::Result<Option<String>> {
+        match &self.stdin {
+            Some(stdin) => terminal::read_key(&mut terminal::bytes(&mut *stdin.lock().unwrap())),
+            None => terminal::read_terminal_key()
+                .unwrap_or_else(|| terminal::read_key(&mut terminal::bytes(&mut std::io::stdin().lock()))),
+        }
+    }
+}
+
+impl Default for ScriptIo {
+    fn default() -> Self {
+        ScriptIo {
+            stdout: Mutex::new(Box::new(std::io::stdout())),
+            stderr: Mutex::new(Box::new(std::io::stderr())),
+            stdin: None,
+        }
+    }
+}
+
+impl std::fmt::Debug for ScriptIo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ScriptIo")
+    }
+}
+
+// A writer that keeps what is written, for capturing a script's output:
+//
+//     let output = SharedBuffer::default();
+//     interpreter.set_io(ScriptIo::new(output.clone(), std::io::stderr(), std::io::empty()));
+//     interpreter.interpret(exprs)?;
+//     assert_eq!(output.contents(), "hello\n");
+#[derive(Clone, Default, Debug)]
+pub struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,452 @@
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use super::value::Value;
+
+// Parses JSON text into the values scripts work with: objects become
+// dictionaries, arrays arrays, and null nil. Errors name the line and
+// column the text stops making sense at.
+pub fn parse(text: &str) -> Result<Value, String> {
+    parse_at(text, 1, 1)
+}
+
+// parse for text found at that line and column of a larger one, which its
+// errors then name
+fn parse_at(text: &str, line: usize, column: usize) -> Result<Value, String> {
+    let mut parser = JsonParser { chars: text.chars().peekable(), line, column };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.peek() {
+        None => Ok(value),
+        Some(&c) => Err(parser.error(&format!("unexpected '{}' after the value", c))),
+    }
+}
+
+// The text as a JSON string literal, quotes included
+pub fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// The value as JSON text, on one line when indent is 0 and otherwise with
+// each element on a line of its own, indented by that many spaces per level.
+// Keys are sorted so the same value always gives the same text. Only
+// dictionaries, arrays, strings, finite numbers, booleans and nil have a
+// JSON form.
+pub fn stringify(value: &Value, indent: usize) -> Result<String, String> {
+    let mut out = String::new();
+    write_value(&mut out, value, indent, 0)?;
+    Ok(out)
+}
+
+fn write_value(out: &mut String, value: &Value, indent: usize, depth: usize) -> Result<(), String> {
+    match value {
+        Value::Nil => out.push_str("null"),
+        Value::Boolean(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) if n.is_finite() => out.push_str(&n.to_string()),
+        Value::Number(n) => return Err(format!("{} has no JSON form", super::value::format_number(*n))),
+        Value::String(text) => out.push_str(&quote(text)),
+        Value::Array(elements) => {
+            let items = elements.iter().map(|element| (None, element)).collect();
+            write_items(out, ('[', ']'), items, indent, depth)?;
+        }
+        Value::Dictionary(entries) => {
+            let mut items: Vec<_> = entries.iter().map(|(key, value)| (Some(key.as_str()), value)).collect();
+            items.sort_by(|a, b| a.0.cmp(&b.0));
+            write_items(out, ('{', '}'), items, indent, depth)?;
+        }
+        other => return Err(format!("a {} has no JSON form", other.get_type())),
+    }
+    Ok(())
+}
+
+fn write_items(
+    out: &mut String,
+    (open, close): (char, char),
+    items: Vec<(Option<&str>, &Value)>,
+    indent: usize,
+    depth: usize,
+) -> Result<(), String> {
+    out.push(open);
+    if items.is_empty() {
+        out.push(close);
+        return Ok(());
+    }
+    for (i, (key, value)) in items.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        if indent > 0 {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent * (depth + 1)));
+        }
+        if let Some(key) = key {
+            out.push_str(&quote(key));
+            out.push_str(if indent > 0 { ": " } else { ":" });
+        }
+        write_value(out, value, indent, depth + 1)?;
+    }
+    if indent > 0 {
+        out.push('\n');
+        out.push_str(&" ".repeat(indent * depth));
+    }
+    out.push(close);
+    Ok(())
+}
+
+// Splits JSON read a chunk at a time into its values, for jsonStream to
+// parse one by one without holding all the text: the elements of a
+// top-level array, or else a sequence of values such as NDJSON's one per
+// line. Only the text of the value being read is kept.
+#[derive(Default)]
+pub struct JsonSplitter {
+    state: SplitState,
+    // The value read so far, and where in the text it started
+    value: Vec<u8>,
+    start: (usize, usize),
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    line: usize,
+    column: usize,
+}
+
+#[derive(Default, PartialEq)]
+enum SplitState {
+    // Nothing but whitespace read yet
+    #[default]
+    Start,
+    // In the top-level array, before an element; after a comma one must follow
+    Element { after_comma: bool },
+    // In the top-level array, after an element
+    Separator,
+    // After the array's closing bracket
+    End,
+    Sequence,
+}
+
+impl JsonSplitter {
+    pub fn new() -> Self {
+        JsonSplitter { line: 1, column: 1, ..Default::default() }
+    }
+
+    // Takes the next chunk, adding the values it completes
+    pub fn feed(&mut self, chunk: &[u8], values: &mut Vec<Value>) -> Result<(), String> {
+        for &byte in chunk {
+            // A byte ending a number or literal is read again as what follows it
+            let taken = !self.value.is_empty() && self.continue_value(byte, values)?;
+            if !taken {
+                self.between_values(byte)?;
+            }
+            if byte == b'\n' {
+                self.line += 1;
+                self.column = 1;
+            } else if byte & 0xC0 != 0x80 {
+                // Continuation bytes of a character are not columns
+                self.column += 1;
+            }
+        }
+        Ok(())
+    }
+
+    // The value the text ended on, once there is no more
+    pub fn finish(&mut self) -> Result<Option<Value>, String> {
+        if !self.value.is_empty() {
+            if self.depth > 0 || self.in_string {
+                return Err(self.error("the text ended inside a value"));
+            }
+            let mut values = Vec::new();
+            self.complete(&mut values)?;
+            if self.state == SplitState::Sequence {
+                return Ok(values.pop());
+            }
+        }
+        match self.state {
+            SplitState::Element { .. } | SplitState::Separator => Err(self.error("the array is never closed")),
+            _ => Ok(None),
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("Invalid JSON at line {} column {}: {}", self.line, self.column, message)
+    }
+
+    fn between_values(&mut self, byte: u8) -> Result<(), String> {
+        if matches!(byte, b' ' | b'\t' | b'\n' | b'\r') {
+            return Ok(());
+        }
+        match (&self.state, byte) {
+            (SplitState::Start, b'[') => self.state = SplitState::Element { after_comma: false },
+            (SplitState::Start, _) => {
+                self.state = SplitState::Sequence;
+                self.begin_value(byte);
+            }
+            (SplitState::Element { after_comma: false }, b']') | (SplitState::Separator, b']') => {
+                self.state = SplitState::End;
+            }
+            (SplitState::Element { .. }, b']' | b',') => {
+                return Err(self.error(&format!("expected a value but found '{}'", byte as char)));
+            }
+            (SplitState::Element { .. } | SplitState::Sequence, _) => self.begin_value(byte),
+            (SplitState::Separator, b',') => self.state = SplitState::Element { after_comma: true },
+            (SplitState::Separator, _) => {
+                return Err(self.error(&format!("expected ',' or ']' but found '{}'", byte as char)));
+            }
+            (SplitState::End, _) => return Err(self.error("unexpected text after the array")),
+        }
+        Ok(())
+    }
+
+    fn begin_value(&mut self, byte: u8) {
+        self.start = (self.line, self.column);
+        self.value.push(byte);
+        match byte {
+            b'"' => self.in_string = true,
+            b'{' | b'[' => self.depth = 1,
+            _ => {}
+        }
+    }
+
+    // False when the byte is not part of the value, which it ended
+    fn continue_value(&mut self, byte: u8, values: &mut Vec<Value>) -> Result<bool, String> {
+        if self.in_string {
+            self.value.push(byte);
+            if self.escaped {
+                self.escaped = false;
+            } else if byte == b'\\' {
+                self.escaped = true;
+            } else if byte == b'"' {
+                self.in_string = false;
+                if self.depth == 0 {
+                    self.complete(values)?;
+                }
+            }
+            return Ok(true);
+        }
+        if self.depth == 0 {
+            // A number or literal runs until whatever cannot be part of one
+            if matches!(byte, b' ' | b'\t' | b'\n' | b'\r' | b',' | b']' | b'}' | b'[' | b'{' | b'"') {
+                self.complete(values)?;
+                return Ok(false);
+            }
+            self.value.push(byte);
+            return Ok(true);
+        }
+        self.value.push(byte);
+        match byte {
+            b'"' => self.in_string = true,
+            b'{' | b'[' => self.depth += 1,
+            b'}' | b']' => {
+                self.depth -= 1;
+                if self.depth == 0 {
+                    self.complete(values)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn complete(&mut self, values: &mut Vec<Value>) -> Result<(), String> {
+        let text = std::mem::take(&mut self.value);
+        let (line, column) = self.start;
+        values.push(parse_at(&String::from_utf8_lossy(&text), line, column)?);
+        if self.state != SplitState::Sequence {
+            self.state = SplitState::Separator;
+        }
+        Ok(())
+    }
+}
+
+struct JsonParser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl JsonParser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("Invalid JSON at line {} column {}: {}", self.line, self.column, message)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.chars.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.chars.peek() {
+            Some(&c) if c == expected => {
+                self.next();
+                Ok(())
+            }
+            Some(&c) => Err(self.error(&format!("expected '{}' but found '{}'", expected, c))),
+            None => Err(self.error(&format!("expected '{}' but the text ended", expected))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(|s| Value::String(s.into())),
+            Some('t') => self.word("true", Value::Boolean(true)),
+            Some('f') => self.word("false", Value::Boolean(false)),
+            Some('n') => self.word("null", Value::Nil),
+            Some(c) if *c == '-' || c.is_ascii_digit() => self.number(),
+            Some(&c) => Err(self.error(&format!("unexpected '{}'", c))),
+            None => Err(self.error("expected a value but the text ended")),
+        }
+    }
+
+    fn word(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        for expected in word.chars() {
+            self.expect(expected)?;
+        }
+        Ok(value)
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut entries = HashMap::new();
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'}') {
+            self.next();
+            return Ok(Value::Dictionary(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.chars.peek() != Some(&'"') {
+                return Err(self.error("expected a string key"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.value()?;
+            entries.insert(key, value);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Value::Dictionary(entries)),
+                _ => return Err(self.error("expected ',' or '}' in object")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&']') {
+            self.next();
+            return Ok(Value::Array(elements));
+        }
+        loop {
+            elements.push(self.value()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Value::Array(elements)),
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => {
+                    let escaped = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape in string")),
+                    };
+                    string.push(escaped);
+                }
+                Some(c) if (c as u32) < 0x20 => {
+                    return Err(self.error("control character in string"));
+                }
+                Some(c) => string.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    // The character of a \u escape, joining UTF-16 surrogate pairs
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error("invalid \\u escape"));
+        }
+        if self.next() != Some('\\') || self.next() != Some('u') {
+            return Err(self.error("unpaired surrogate in \\u escape"));
+        }
+        let low = self.hex4()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Err(self.error("unpaired surrogate in \\u escape"));
+        }
+        char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+            .ok_or_else(|| self.error("invalid \\u escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.next().and_then(|c| c.to_digit(16));
+            code = code * 16 + digit.ok_or_else(|| self.error("invalid \\u escape"))?;
+        }
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let mut number = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+                break;
+            }
+            number.push(c);
+            self.next();
+        }
+        number
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| self.error(&format!("invalid number {}", number)))
+    }
+}
@@ -0,0 +1,127 @@
+use std::{collections::HashMap, fmt};
+
+use serde::{
+    de::{self, MapAccess, SeqAccess, Visitor},
+    ser::{self, SerializeMap, SerializeSeq},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use super::value::Value;
+
+// Values as MessagePack bytes for marshal and unmarshal, compact and
+// readable by other programs too. Only data goes in: numbers, strings,
+// booleans, nil, and arrays and dictionaries of them. Whole numbers are
+// written as integers, which take less room than doubles.
+pub fn marshal(value: &Value) -> Result<Vec<u8>, String> {
+    rmp_serde::to_vec(&Marshalled(value)).map_err(|e| e.to_string())
+}
+
+pub fn unmarshal(bytes: &[u8]) -> Result<Value, String> {
+    rmp_serde::from_slice::<Unmarshalled>(bytes)
+        .map(|unmarshalled| unmarshalled.0)
+        .map_err(|e| format!("Invalid marshalled data: {}", e))
+}
+
+// Numbers from here to its negative are whole and exact as doubles
+const MAX_EXACT: f64 = 9_007_199_254_740_992.0;
+
+struct Marshalled<'a>(&'a Value);
+
+impl Serialize for Marshalled<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Nil => serializer.serialize_unit(),
+            Value::Boolean(b) => serializer.serialize_bool(*b),
+            Value::Number(n) if n.fract() == 0.0 && n.abs() <= MAX_EXACT && !(*n == 0.0 && n.is_sign_negative()) => {
+                serializer.serialize_i64(*n as i64)
+            }
+            Value::Number(n) => serializer.serialize_f64(*n),
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Array(elements) => {
+                let mut seq = serializer.serialize_seq(Some(elements.len()))?;
+                for element in elements {
+                    seq.serialize_element(&Marshalled(element))?;
+                }
+                seq.end()
+            }
+            Value::Dictionary(entries) => {
+                // Sorted, so equal values always marshal to the same bytes
+                let mut keys: Vec<&String> = entries.keys().collect();
+                keys.sort();
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for key in keys {
+                    map.serialize_entry(key, &Marshalled(&entries[key]))?;
+                }
+                map.end()
+            }
+            other => Err(ser::Error::custom(format!("a {} cannot be marshalled", other.get_type()))),
+        }
+    }
+}
+
+struct Unmarshalled(Value);
+
+impl<'de> Deserialize<'de> for Unmarshalled {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor).map(Unmarshalled)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a number, string, boolean, nil, array or dictionary")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_bool<E: de::Error>(self, b: bool) -> Result<Value, E> {
+        Ok(Value::Boolean(b))
+    }
+
+    fn visit_i64<E: de::Error>(self, n: i64) -> Result<Value, E> {
+        Ok(Value::Number(n as f64))
+    }
+
+    fn visit_u64<E: de::Error>(self, n: u64) -> Result<Value, E> {
+        Ok(Value::Number(n as f64))
+    }
+
+    fn visit_f64<E: de::Error>(self, n: f64) -> Result<Value, E> {
+        Ok(Value::Number(n))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Value, E> {
+        Ok(Value::String(s.into()))
+    }
+
+    // Binary data from other programs comes back as byte numbers
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Value, E> {
+        Ok(Value::Array(bytes.iter().map(|&b| Value::Number(b as f64)).collect()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut elements = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(Unmarshalled(element)) = seq.next_element()? {
+            elements.push(element);
+        }
+        Ok(Value::Array(elements))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut entries = HashMap::with_capacity(map.size_hint().unwrap_or(0).min(4096));
+        while let Some((key, Unmarshalled(value))) = map.next_entry::<String, Unmarshalled>()? {
+            entries.insert(key, value);
+        }
+        Ok(Value::Dictionary(entries))
+    }
+}
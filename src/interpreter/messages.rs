@@ -0,0 +1,150 @@
+use std::{collections::HashMap, path::Path, sync::Mutex};
+
+use super::{json, value::{format_number, Value}};
+
+// Translated strings for loadMessages and t. A catalog is a JSON file of
+// messages by key, where a nested dictionary groups keys under a dotted name:
+//
+//   {"greeting": "Hello, {name}!",
+//    "files": {"one": "{count} file", "other": "{count} files"},
+//    "menu": {"open": "Open"}}
+//
+// t("menu.open") gives "Open" and {name} is replaced by the parameter of that
+// name. A message that is a dictionary of plural forms, named by the
+// categories zero, one, two, few, many and other or by exact counts like
+// "=0", is chosen by the "count" parameter under the rules of the catalog's
+// language.
+struct Catalog {
+    locale: String,
+    messages: HashMap<String, Value>,
+}
+
+// The catalog loaded last comes first; a key it lacks is looked up in the
+// ones loaded before, so a translation falls back to the language it was
+// made from
+static CATALOGS: Mutex<Vec<Catalog>> = Mutex::new(Vec::new());
+
+const PLURAL_FORMS: &[&str] = &["zero", "one", "two", "few", "many", "other"];
+
+// Loads the catalog at path for the locale, by default the file's name as
+// in "de.json" or "pt-BR.json". Loading a locale again replaces its messages.
+pub fn load(path: &Path, locale: Option<&str>) -> Result<String, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let messages = match json::parse(&text)? {
+        Value::Dictionary(messages) => messages,
+        other => return Err(format!("{} holds a {}, not a dictionary of messages", path.display(), other.get_type())),
+    };
+    let locale = match locale {
+        Some(locale) => locale.to_string(),
+        None => path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default(),
+    };
+    let mut catalogs = CATALOGS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    catalogs.retain(|catalog| catalog.locale != locale);
+    catalogs.insert(0, Catalog { locale: locale.clone(), messages });
+    Ok(locale)
+}
+
+// The message for key with the parameters put in, None when no catalog has it
+pub fn translate(key: &str, params: &HashMap<String, Value>) -> Option<String> {
+    let catalogs = CATALOGS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    catalogs.iter().find_map(|catalog| {
+        let template = match lookup(&catalog.messages, key)? {
+            Value::String(template) => template,
+            Value::Dictionary(forms) => match plural_form(&catalog.locale, forms, params.get("count"))? {
+                Value::String(template) => template,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(interpolate(template, params))
+    })
+}
+
+// A key is looked up whole first, so keys may contain dots themselves
+fn lookup<'a>(messages: &'a HashMap<String, Value>, key: &str) -> Option<&'a Value> {
+    if let Some(message) = messages.get(key) {
+        return Some(message);
+    }
+    let (group, rest) = key.split_once('.')?;
+    match messages.get(group)? {
+        Value::Dictionary(group) => lookup(group, rest),
+        _ => None,
+    }
+}
+
+fn plural_form<'a>(locale: &str, forms: &'a HashMap<String, Value>, count: Option<&Value>) -> Option<&'a Value> {
+    // Only a dictionary naming nothing but plural forms is one message
+    if !forms.keys().all(|form| form.starts_with('=') || PLURAL_FORMS.contains(&form.as_str())) {
+        return None;
+    }
+    let Some(&Value::Number(n)) = count else {
+        return forms.get("other");
+    };
+    forms
+        .get(&format!("={}", format_number(n)))
+        .or_else(|| forms.get(plural_category(locale, n)))
+        .or_else(|| forms.get("other"))
+}
+
+// The CLDR plural category of n in the locale's language, for the common
+// languages; any other uses one for 1 and other for the rest, like English
+fn plural_category(locale: &str, n: f64) -> &'static str {
+    let language = locale.split(['-', '_']).next().unwrap_or_default().to_lowercase();
+    let whole = n.fract() == 0.0;
+    let i = n.abs().trunc() as u64;
+    let (mod10, mod100) = (i % 10, i % 100);
+    match language.as_str() {
+        "ja" | "zh" | "ko" | "vi" | "th" | "id" | "ms" | "tr" => "other",
+        "fr" | "pt" if i <= 1 => "one",
+        "fr" | "pt" => "other",
+        "ru" | "uk" | "be" | "sr" | "hr" | "bs" if !whole => "other",
+        "ru" | "uk" | "be" | "sr" | "hr" | "bs" => match (mod10, mod100) {
+            (1, m) if m != 11 => "one",
+            (2..=4, m) if !(12..=14).contains(&m) => "few",
+            _ if language == "ru" || language == "uk" || language == "be" => "many",
+            _ => "other",
+        },
+        "pl" if !whole => "other",
+        "pl" if i == 1 => "one",
+        "pl" if (2..=4).contains(&mod10) && !(12..=14).contains(&mod100) => "few",
+        "pl" => "many",
+        "cs" | "sk" if !whole => "many",
+        "cs" | "sk" if i == 1 => "one",
+        "cs" | "sk" if (2..=4).contains(&i) => "few",
+        "cs" | "sk" => "other",
+        "ar" if !whole => "other",
+        "ar" => match (i, mod100) {
+            (0, _) => "zero",
+            (1, _) => "one",
+            (2, _) => "two",
+            (_, 3..=10) => "few",
+            (_, 11..=99) => "many",
+            _ => "other",
+        },
+        _ if whole && i == 1 => "one",
+        _ => "other",
+    }
+}
+
+// Replaces each {name} with the parameter of that name; a name without one
+// is left as written
+fn interpolate(template: &str, params: &HashMap<String, Value>) -> String {
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        text.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        match after.find('}').and_then(|close| Some((params.get(&after[..close])?, close))) {
+            Some((value, close)) => {
+                text.push_str(&value.to_string());
+                rest = &after[close + 1..];
+            }
+            None => {
+                text.push('{');
+                rest = after;
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
@@ -1,89 +1,299 @@
+use builder::InterpreterBuilder;
 use enviroment::Environment;
 use std::collections::HashMap;
 use std::future::Future;
-use std::path::PathBuf;
-use std::pin::Pin;
-use std::sync::{Arc, Mutex};
-use tokio::task::JoinHandle;
-use value::{PromiseState, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::runtime::{Handle, Runtime, RuntimeFlavor};
+use io::ScriptIo;
+use native::NativeFunction;
+use value::Value;
 
-use crate::error::{InterpreterError, InterpreterResult};
+use crate::error::{InterpreterError, InterpreterResult, SourceLocation};
 use crate::parser::{Expr, TryCatch};
-use crate::tokenizer::TokenType;
+use crate::tokenizer::{Token, TokenType};
+pub mod args;
+pub mod builder;
+pub mod cron;
+pub mod data;
 pub mod enviroment;
+pub mod glob;
+pub mod html;
+pub mod http;
+pub mod http_pool;
+pub mod inspect;
+pub mod io;
+pub mod json;
+pub mod marshal;
+pub mod messages;
 pub mod native;
 pub mod native_functions;
+pub mod options;
+pub mod pattern;
+pub mod plugin;
+pub mod process;
+pub mod prompt;
+pub mod proxy;
+pub mod remote;
+pub mod smtp;
+pub mod snapshot;
+pub mod socket;
+pub mod stdlib;
+pub mod terminal;
 pub mod value;
+pub mod xml;
+#[cfg(test)]
+mod tests;
 
 pub struct Interpreter {
     environment: Arc<Mutex<Environment>>,
     line: usize,
-    pub runtime: tokio::runtime::Runtime
+    // Names of the functions being run, innermost last
+    calls: Vec<String>,
+    max_call_depth: Option<usize>,
+    strict: bool,
+    // Where the error being raised happened, kept from the innermost
+    // expression that failed
+    error_token: Option<Token>,
+    // Where the error being raised happened, kept under --debug-on-error
+    failure: Option<Failure>,
+    // Expressions `defer` put off until the blocks they are in end, those
+    // of inner blocks after those of the blocks around them
+    deferred: Vec<Arc<Expr>>,
+    // Shared by every interpreter working for the same program
+    pub runtime: Handle,
+    // Set only on the interpreter that had to start the runtime itself
+    owned_runtime: Option<Runtime>,
 }
 
-impl Interpreter {
-    pub fn new() -> Self {
-        let path = PathBuf::new();
-        let env = Arc::new(Mutex::new(Environment::new(path)));
-        env.lock().unwrap().register_native_functions();
-        let runtime = tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .build()
-        .unwrap();
-        Interpreter {
-            environment: env,
-            line: 0,
-            runtime
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Interpreter {
+    fn drop(&mut self) {
+        // Don't wait for background tasks that may never finish
+        if let Some(runtime) = self.owned_runtime.take() {
+            runtime.shutdown_background();
         }
     }
+}
 
-    pub fn new_with_environment(env: Arc<Mutex<Environment>>) -> Self {
-        let runtime = tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .build().unwrap();
-        Interpreter {
-            environment: env,
-            line: 0,
-            runtime
+// Set by --trace: None logs every expression, a name only those evaluated
+// while a function of that name is running
+static TRACE: OnceLock<Option<String>> = OnceLock::new();
+
+pub fn enable_trace(function: Option<String>) {
+    let _ = TRACE.set(function);
+}
+
+// Set by --debug-on-error: errors keep the scope they happened in and the
+// calls they passed through
+static POST_MORTEM: AtomicBool = AtomicBool::new(false);
+
+pub fn enable_post_mortem() {
+    POST_MORTEM.store(true, Ordering::Relaxed);
+}
+
+// An error as it left the program, for looking around after the fact
+pub struct Failure {
+    // The scope of the expression that failed
+    pub environment: Arc<Mutex<Environment>>,
+    // The function running at each level of the stack, innermost first
+    pub frames: Vec<Frame>,
+    // How many calls deep the last frame was
+    depth: usize,
+}
+
+pub struct Frame {
+    // None for the top level of the script
+    pub function: Option<String>,
+    pub line: usize,
+}
+
+// Methods the interpreter calls on instances itself, public despite their
+// leading underscore
+const SPECIAL_METHODS: &[&str] = &["_construct", "_str"];
+
+// Joins the runtime the caller is running on, or starts one when there is
+// none. A current-thread runtime cannot be joined: the interpreter waits for
+// promises by blocking a thread, which would stop that runtime altogether.
+fn shared_runtime() -> (Handle, Option<Runtime>) {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => (handle, None),
+        _ => {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            (runtime.handle().clone(), Some(runtime))
         }
     }
+}
+
+// The token an error in the expression is shown under: the operator of an
+// operation, otherwise where the expression starts
+fn error_token(expr: &Expr) -> Option<&Token> {
+    match expr {
+        Expr::Binary(_, operator, _) | Expr::Logical(_, operator, _) => Some(operator),
+        _ => expr.first_token(),
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::builder().build()
+    }
 
-    pub fn new_with_base_path(base_path: PathBuf) -> Self {
-        let env = Arc::new(Mutex::new(Environment::new(base_path)));
-        env.lock().unwrap().register_native_functions();
-        let runtime = tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .build().unwrap();
+    pub fn builder() -> InterpreterBuilder {
+        InterpreterBuilder::new()
+    }
+
+    // Another interpreter on the same runtime, for running function bodies
+    fn child(runtime: Handle, environment: Arc<Mutex<Environment>>) -> Self {
+        let options = environment.lock().unwrap().options;
         Interpreter {
-            environment: env,
+            environment,
             line: 0,
-            runtime
+            calls: Vec::new(),
+            max_call_depth: options.max_call_depth,
+            strict: options.strict,
+            error_token: None,
+            failure: None,
+            deferred: Vec::new(),
+            runtime,
+            owned_runtime: None,
         }
     }
 
     pub fn interpret(&mut self, expressions: Vec<(Expr, usize)>) -> InterpreterResult<Value> {
-        let mut last_value = Value::Nil;
+        // Lets natives and module imports find the shared runtime
+        let runtime = self.runtime.clone();
+        let _runtime_guard = runtime.enter();
+        let mut result = Ok(Value::Nil);
+        // What is deferred outside any block waits for the program to end
+        let mark = self.deferred.len();
         //println!("expressions: {:#?}", expressions);
         for (expr, line) in expressions {
             self.line = line;
+            self.error_token = None;
+            self.failure = None;
             //println!("{:?}", expr);
-            match self.evaluate(&expr) {
-                Ok(value) => {
-                    last_value = value;
-                }
-                Err(e) => {
-                    return Err(e);
-                }
+            result = self.evaluate(&expr);
+            if result.is_err() {
+                break;
+            }
+        }
+        self.run_deferred(mark, result).map_err(|e| match self.error_token.take() {
+            Some(token) => e.located_at(SourceLocation::at(&token)),
+            None => e,
+        })
+    }
+
+    // interpret for async hosts, such as servers running scripts per request.
+    // The program runs on a blocking thread of the interpreter's runtime,
+    // where waiting for its promises is allowed, while the task awaiting it
+    // is free to yield; interpret itself would block that task's thread, and
+    // panic on a current-thread runtime.
+    pub async fn interpret_async(&mut self, expressions: Vec<(Expr, usize)>) -> InterpreterResult<Value> {
+        let mut worker = Interpreter::child(self.runtime.clone(), Arc::clone(&self.environment));
+        let running = self.runtime.spawn_blocking(move || {
+            let result = worker.interpret(expressions);
+            (worker, result)
+        });
+        match running.await {
+            Ok((mut worker, result)) => {
+                self.failure = worker.failure.take();
+                result
             }
+            Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+            Err(_) => Err(InterpreterError::runtime_error(
+                crate::error::RuntimeErrorKind::PromiseCancelled(0),
+            )),
         }
-        Ok(last_value)
     }
 
     pub fn evaluate(&mut self, expr: &Expr) -> InterpreterResult<Value> {
+        let result = self.evaluate_expr(expr);
+        if result.is_err() && self.error_token.is_none() {
+            self.error_token = error_token(expr).cloned();
+        }
+        if let Err(error) = &result {
+            if POST_MORTEM.load(Ordering::Relaxed) {
+                self.record_failure(expr, error);
+            }
+        }
+        if let Some(filter) = TRACE.get() {
+            self.trace(expr, &result, filter.as_deref());
+        }
+        result
+    }
+
+    // Keeps the scope of the innermost expression an error comes out of, then
+    // a frame for each call it unwinds through
+    fn record_failure(&mut self, expr: &Expr, error: &InterpreterError) {
+        if matches!(error, InterpreterError::RuntimeError(crate::error::RuntimeErrorKind::Return(_))) {
+            return;
+        }
+        let line = error_token(expr).map_or(self.line, |token| token.line);
+        let frame = Frame { function: self.calls.last().cloned(), line };
+        match &mut self.failure {
+            None => {
+                self.failure = Some(Failure {
+                    environment: Arc::clone(&self.environment),
+                    frames: vec![frame],
+                    depth: self.calls.len(),
+                })
+            }
+            Some(failure) if failure.depth > self.calls.len() => {
+                failure.frames.push(frame);
+                failure.depth = self.calls.len();
+            }
+            Some(_) => {}
+        }
+    }
+
+    // What the last error to end interpret kept under --debug-on-error
+    pub fn take_failure(&mut self) -> Option<Failure> {
+        self.failure.take()
+    }
+
+    fn trace(&self, expr: &Expr, result: &InterpreterResult<Value>, filter: Option<&str>) {
+        if *expr == Expr::Nil || filter.is_some_and(|name| !self.calls.iter().any(|call| call == name)) {
+            return;
+        }
+        let line = expr.first_token().map_or(self.line, |token| token.line);
+        let mut text = expr.to_rpn();
+        if text.chars().count() > 60 {
+            text = text.chars().take(57).collect::<String>() + "...";
+        }
+        let outcome = match result {
+            Ok(value) => value.to_string(),
+            Err(InterpreterError::RuntimeError(crate::error::RuntimeErrorKind::Return(value))) => {
+                format!("return {}", value)
+            }
+            Err(error) => format!("error: {}", error),
+        };
+        eprintln!("[trace] line {:<4} depth {:<2} {} => {}", line, self.calls.len(), text, outcome);
+    }
+
+    fn evaluate_expr(&mut self, expr: &Expr) -> InterpreterResult<Value> {
         match expr {
             Expr::Literal(token, value) => match token.token_type {
                 TokenType::Number => Ok(Value::Number(value.parse().unwrap())),
-                TokenType::STRING => Ok(Value::String(value.clone())),
+                TokenType::STRING => Ok(Value::String(value.as_str().into())),
+                // Compiled when tokenized, so this finds it in the cache
+                TokenType::Regex => {
+                    let flags = token.lexeme.rsplit_once('/').map_or("", |(_, flags)| flags);
+                    match pattern::compile(value, flags) {
+                        Ok(pattern) => Ok(Value::Regex(pattern)),
+                        Err(message) => Err(InterpreterError::runtime_error(
+                            crate::error::RuntimeErrorKind::RuntimeError(token.line, message),
+                        )),
+                    }
+                }
                 TokenType::True => Ok(Value::Boolean(true)),
                 TokenType::False => Ok(Value::Boolean(false)),
                 TokenType::Nil => Ok(Value::Nil),
@@ -92,13 +302,13 @@ impl Interpreter {
                 )),
             },
             Expr::Variable(name) => {
-                let value = self.environment.lock().unwrap().get(&name.lexeme);
-                match value {
+                match enviroment::get_loading(&self.environment, &name.lexeme)? {
                     Some(value) => Ok(value.clone()),
                     None => Err(InterpreterError::runtime_error(
                         crate::error::RuntimeErrorKind::UndefinedVariable(
                             self.line,
                             name.lexeme.clone(),
+                            self.environment.lock().unwrap().suggest(&name.lexeme),
                         ),
                     )),
                 }
@@ -117,7 +327,7 @@ impl Interpreter {
                     let value = self.evaluate(value)?;
                     match key {
                         Value::String(key) => {
-                            values.insert(key, value);
+                            values.insert(key.to_string(), value);
                         }
                         _ => {
                             return Err(InterpreterError::runtime_error(
@@ -136,6 +346,7 @@ impl Interpreter {
                     TokenType::Minus => self.subtract(left, right),
                     TokenType::Star => self.multiply(left, right),
                     TokenType::Modulo => self.modulo(left, right),
+                    TokenType::Div => self.integer_divide(left, right),
                     TokenType::Slash => self.divide(left, right),
                     TokenType::Greater => self.greater(left, right),
                     TokenType::GreaterEqual => self.greater_equal(left, right),
@@ -143,6 +354,8 @@ impl Interpreter {
                     TokenType::LessEqual => self.less_equal(left, right),
                     TokenType::EqualEqual => self.equal(left, right),
                     TokenType::BandEqual => self.not_equal(left, right),
+                    TokenType::StrictEqual => Ok(Value::Boolean(self.is_equal(&left, &right))),
+                    TokenType::StrictNotEqual => Ok(Value::Boolean(!self.is_equal(&left, &right))),
                     _ => Err(InterpreterError::runtime_error(
                         crate::error::RuntimeErrorKind::InvalidBinaryOperator(operator.line),
                     )),
@@ -167,102 +380,40 @@ impl Interpreter {
                     .assign(&name.lexeme, evaluated_value.clone())?;
                 Ok(evaluated_value)
             }
-            Expr::Set(object, name, value) => {
-                let value_name = object.lexeme.clone();
-                let object = self
-                    .environment
-                    .lock()
-                    .unwrap()
-                    .get(&value_name)
-                    .ok_or_else(|| {
-                        InterpreterError::runtime_error(
-                            crate::error::RuntimeErrorKind::UndefinedVariable(
-                                self.line,
-                                value_name.clone(),
-                            ),
-                        )
-                    })?;
+            Expr::Set(target, key, value) => {
+                // The keys from the outermost container in, as in
+                // root[k1][k2] = value
+                let mut keys = vec![key.as_ref()];
+                let mut root = target.as_ref();
+                while let Expr::Get(object, key) = root {
+                    keys.push(key);
+                    root = object;
+                }
+                keys.reverse();
+                let object = self.evaluate(root)?;
                 let value = self.evaluate(value)?;
-                let name = self.evaluate(name)?;
-                match object {
-                    Value::Instance(_, _) => match name {
-                        Value::String(name) => {
-                            self.environment
-                                .lock()
-                                .unwrap()
-                                .assign(&name, value.clone())?;
-                            return Ok(value);
-                        }
-                        _ => {
-                            return Err(InterpreterError::runtime_error(
-                                crate::error::RuntimeErrorKind::InvalidSet(self.line),
-                            ))
-                        }
-                    },
-                    Value::Array(mut values) => match name {
-                        Value::Number(index) => {
-                            if index < values.len() as f64 {
-                                let index = index as usize;
-                                values[index] = value.clone();
-                                self.environment
-                                    .lock()
-                                    .unwrap()
-                                    .assign(&value_name, Value::Array(values.clone()))?;
-                                return Ok(value);
-                            } else {
-                                return Err(InterpreterError::runtime_error(
-                                    crate::error::RuntimeErrorKind::InvalidSet(self.line),
-                                ));
-                            }
-                        }
-                        _ => {
-                            return Err(InterpreterError::runtime_error(
-                                crate::error::RuntimeErrorKind::InvalidSet(self.line),
-                            ))
-                        }
-                    },
-                    Value::Dictionary(mut values) => match name {
-                        Value::String(key) => {
-                            values.insert(key, value.clone());
-                            self.environment
-                                .lock()
-                                .unwrap()
-                                .assign(&value_name, Value::Dictionary(values.clone()))?;
-                            return Ok(value);
-                        }
-                        _ => {
-                            return Err(InterpreterError::runtime_error(
-                                crate::error::RuntimeErrorKind::InvalidSet(self.line),
-                            ))
-                        }
-                    },
-                    _ => {
-                        return Err(InterpreterError::runtime_error(
-                            crate::error::RuntimeErrorKind::InvalidSet(self.line),
-                        ))
-                    }
+                let object = self.set_member(object, &keys, value.clone())?;
+                // Arrays and dictionaries are values, so the updated one
+                // replaces the variable's; an instance was updated in place
+                if let (Expr::Variable(name), Value::Array(_) | Value::Dictionary(_)) = (root, &object) {
+                    self.environment.lock().unwrap().assign(&name.lexeme, object)?;
                 }
+                Ok(value)
             }
             Expr::Get(object, name) => {
                 let object = self.evaluate(object)?;
+                if let Value::Instance(..) = object {
+                    return self.instance_member(object, name);
+                }
+                if let (Value::Class(class, members), Expr::Variable(member)) = (&object, name.as_ref()) {
+                    return self.class_constant(class, members, &member.lexeme);
+                }
                 let name = self.evaluate(name)?;
                 match object {
-                    Value::Instance(_, _) => match name {
-                        Value::String(name) => {
-                            self.environment.lock().unwrap().get(&name).ok_or_else(|| {
-                                InterpreterError::runtime_error(
-                                    crate::error::RuntimeErrorKind::InvalidGet(self.line),
-                                )
-                            })
-                        }
-                        _ => Err(InterpreterError::runtime_error(
-                            crate::error::RuntimeErrorKind::InvalidGet(self.line),
-                        )),
-                    },
                     Value::Array(values) => match name {
                         Value::Number(index) => {
                             if index < values.len() as f64 {
-                                return Ok(values[index as usize].clone());
+                                Ok(values[index as usize].clone())
                             } else {
                                 Err(InterpreterError::runtime_error(
                                     crate::error::RuntimeErrorKind::InvalidGet(self.line),
@@ -274,7 +425,7 @@ impl Interpreter {
                         )),
                     },
                     Value::Dictionary(values) => match name {
-                        Value::String(key) => match values.get(&key) {
+                        Value::String(key) => match values.get(&*key) {
                             Some(value) => Ok(value.clone()),
                             None => Err(InterpreterError::runtime_error(
                                 crate::error::RuntimeErrorKind::InvalidGet(self.line),
@@ -306,7 +457,7 @@ impl Interpreter {
                 let function = Value::Function(
                     name.lexeme.clone(),
                     params.iter().map(|p| p.lexeme.clone()).collect(),
-                    body.clone(),
+                    Arc::clone(body),
                     // Some(environment),
                 );
                 self.environment
@@ -319,7 +470,7 @@ impl Interpreter {
                 let function = Value::AsyncFunction(
                     name.lexeme.clone(),
                     params.iter().map(|p| p.lexeme.clone()).collect(),
-                    body.clone(),
+                    Arc::clone(body),
                 );
                 self.environment
                     .lock()
@@ -334,31 +485,19 @@ impl Interpreter {
                 }
                 if let Some(owner) = owner {
                     let owner = self.evaluate(owner)?;
-                    if let Value::Instance(_, env) = owner.clone() {
-                        let previous = self.environment.clone();
-                        self.environment = env;
-                        let callee = self.evaluate(callee)?;
-                        let result = self.execute_call(Some(owner), callee, evaluated_args);
-                        self.environment = previous;
-                        return result;
-                    }
-                    Err(InterpreterError::runtime_error(
-                        crate::error::RuntimeErrorKind::InvalidCall(0),
-                    ))
+                    self.call_method(owner, callee, evaluated_args)
                 } else {
                     let callee = self.evaluate(callee)?;
                     match callee {
                         Value::Function(_, _, _) => {
-                            let result = self.execute_call(None, callee, evaluated_args);
-                            return result;
+                            self.execute_call(None, callee, evaluated_args)
                         }
                         Value::AsyncFunction(_, _, _) => {
                             let future = self.execute_async_call(None, callee, evaluated_args);
-                            return Ok(Value::create_promise(Box::pin(future)));
+                            Ok(Value::create_promise(Box::pin(future)))
                         }
-                        Value::NativeFunction(_) => {
-                            let result = self.execute_call(None, callee, evaluated_args);
-                            return result;
+                        Value::NativeFunction(_) | Value::Class(..) => {
+                            self.execute_call(None, callee, evaluated_args)
                         }
                         _ => Err(InterpreterError::runtime_error(
                             crate::error::RuntimeErrorKind::InvalidCall(0),
@@ -368,27 +507,13 @@ impl Interpreter {
                 }
             }
             Expr::Await(expr) => {
-                let expr = self.evaluate(expr)?;
-                if let Value::Promise(join_handle) = expr {
-                    let mut promise = join_handle.lock().unwrap();
-                    match &mut *promise {
-                        PromiseState::Pending(join_handle) => {
-                            let result = tokio::task::block_in_place(|| {
-                                self.runtime.block_on(join_handle)                                                        
-                            });
-                            return result;
-                        }
-                        PromiseState::Fulfilled(value) => return Ok(value.clone()),
-                        PromiseState::Rejected(_error) => {
-                            return Err(InterpreterError::runtime_error(
-                                crate::error::RuntimeErrorKind::PromiseRejected(self.line),
-                            ))
-                        }
+                match self.evaluate(expr)? {
+                    Value::Promise(promise) => {
+                        tokio::task::block_in_place(|| self.runtime.block_on(promise.awaited()))
                     }
+                    // Like JS, awaiting anything else just gives the value back
+                    value => Ok(value),
                 }
-                Err(InterpreterError::runtime_error(
-                    crate::error::RuntimeErrorKind::InvalidCall(0),
-                ))
             }
             //     let mut evaluated_args = Vec::new();
             //     for arg in arguments {
@@ -417,8 +542,8 @@ impl Interpreter {
             Expr::If(condition, then_branch, else_branch) => {
                 let condition = self.evaluate(condition)?;
                 match self.is_truthy(&condition) {
-                    true => self.evaluate(then_branch),
-                    false => self.evaluate(else_branch),
+                    true => self.evaluate_scoped(then_branch),
+                    false => self.evaluate_scoped(else_branch),
                     // _ => Err(InterpreterError::runtime_error(
                     //     crate::error::RuntimeErrorKind::InvalidCondition(self.line),
                     // )),
@@ -452,19 +577,31 @@ impl Interpreter {
                 let mut result = Value::Nil;
                 let mut _condition = self.evaluate(condition)?;
                 while self.is_truthy(&_condition) {
-                    result = self.evaluate(body)?;
+                    result = self.evaluate_scoped(body)?;
                     _condition = self.evaluate(condition)?;
                 }
                 Ok(result)
             }
             Expr::For(initializer, condition, increment, body) => {
+                let scope = Environment::new_with_enclosing(Some(Arc::clone(&self.environment)));
+                let previous = std::mem::replace(&mut self.environment, scope);
+                let result = self.execute_for(initializer, condition, increment, body);
+                Environment::recycle(std::mem::replace(&mut self.environment, previous));
+                result
+            }
+            Expr::ForAwait(name, iterable, body) => {
+                let iterable = self.evaluate(iterable)?;
                 let mut result = Value::Nil;
-                self.evaluate(initializer)?;
-                let mut _condition = self.evaluate(condition)?;
-                while self.is_truthy(&_condition) {
-                    result = self.evaluate(body)?;
-                    self.evaluate(increment)?;
-                    _condition = self.evaluate(condition)?;
+                let mut index = 0;
+                while let Some(item) = self.next_async_item(&iterable, index)? {
+                    index += 1;
+                    let environment =
+                        Environment::new_with_enclosing(Some(Arc::clone(&self.environment)));
+                    environment.lock().unwrap().define(&name.lexeme, item);
+                    let previous = std::mem::replace(&mut self.environment, environment);
+                    let value = self.evaluate(body);
+                    Environment::recycle(std::mem::replace(&mut self.environment, previous));
+                    result = value?;
                 }
                 Ok(result)
             }
@@ -474,11 +611,38 @@ impl Interpreter {
                     crate::error::RuntimeErrorKind::Return(value),
                 ))
             }
-            Expr::Import(path) => {
+            Expr::Throw(keyword, value) => {
+                let value = self.evaluate(value)?;
+                Err(InterpreterError::runtime_error(
+                    crate::error::RuntimeErrorKind::Thrown(keyword.line, value),
+                ))
+            }
+            Expr::Defer(_, deferred) => {
+                self.deferred.push(Arc::clone(deferred));
+                Ok(Value::Nil)
+            }
+            Expr::Import(path, alias) => {
                 let path = self.evaluate(path)?;
                 match path {
                     Value::String(path) => {
-                        self.environment.lock().unwrap().import_module(&path)?;
+                        let mut environment = self.environment.lock().unwrap();
+                        match alias {
+                            Some(alias) => {
+                                let value = environment.import_data(&path)?;
+                                environment.define(&alias.lexeme, value);
+                            }
+                            None if data::format(&path).is_some() => {
+                                return Err(InterpreterError::runtime_error(
+                                    crate::error::RuntimeErrorKind::InvalidImport(
+                                        self.line,
+                                        format!("{} (data files need a name: import \"{}\" as NAME)", path, path),
+                                    ),
+                                ));
+                            }
+                            None => {
+                                environment.import_module(&path)?;
+                            }
+                        }
                         Ok(Value::String(path))
                     }
                     _ => Err(InterpreterError::runtime_error(
@@ -486,6 +650,34 @@ impl Interpreter {
                     )),
                 }
             }
+            // The module is imported here, so whoever imports this one sees
+            // its names; those exported under another name are defined too
+            Expr::Export(path, names) => {
+                let path = match self.evaluate(path)? {
+                    Value::String(path) if data::format(&path).is_none() => path,
+                    path => {
+                        return Err(InterpreterError::runtime_error(
+                            crate::error::RuntimeErrorKind::InvalidImport(self.line, path.to_string()),
+                        ))
+                    }
+                };
+                let mut environment = self.environment.lock().unwrap();
+                let module = environment.import_module(&path)?;
+                for (name, exported) in names.iter().flatten() {
+                    let Some(value) = environment.module_value(&module, &name.lexeme)? else {
+                        return Err(InterpreterError::runtime_error(
+                            crate::error::RuntimeErrorKind::InvalidImport(
+                                name.line,
+                                format!("{} (it has no {})", path, name.lexeme),
+                            ),
+                        ));
+                    };
+                    if exported.lexeme != name.lexeme {
+                        environment.define(&exported.lexeme, value);
+                    }
+                }
+                Ok(Value::String(path))
+            }
             Expr::Class(name, methods) => {
                 let mut class_methods = HashMap::new();
                 for method in methods {
@@ -494,11 +686,17 @@ impl Interpreter {
                             let function = Value::Function(
                                 name.lexeme.clone(),
                                 params.iter().map(|p| p.lexeme.clone()).collect(),
-                                body.clone(),
+                                Arc::clone(body),
                                 // self.environment.lock().unwrap().get_enclosing().clone(),
                             );
                             class_methods.insert(name.lexeme.clone(), function);
                         }
+                        // Constants are read as Class.NAME, and by the
+                        // methods of instances by name
+                        Expr::Let(name, value) => {
+                            let value = self.evaluate(value)?;
+                            class_methods.insert(name.lexeme.clone(), value);
+                        }
                         _ => {
                             return Err(InterpreterError::runtime_error(
                                 crate::error::RuntimeErrorKind::InvalidClassMethod(self.line),
@@ -517,50 +715,410 @@ impl Interpreter {
         }
     }
 
+    pub fn environment(&self) -> Arc<Mutex<Environment>> {
+        Arc::clone(&self.environment)
+    }
+
+    // Runs what is interpreted next in another scope, e.g. that of a failure
+    pub fn set_environment(&mut self, environment: Arc<Mutex<Environment>>) {
+        self.environment = environment;
+    }
+
+    // Makes a host function callable from scripts under its name. Unlike the
+    // built-in natives it can capture state, e.g. a connection or a counter:
+    //
+    //     let calls = Arc::new(AtomicUsize::new(0));
+    //     let counter = Arc::clone(&calls);
+    //     interpreter.register_native(NativeFunction::new("tick", 0, move |_| {
+    //         Ok(Value::Number(counter.fetch_add(1, Ordering::SeqCst) as f64))
+    //     }));
+    //
+    // A native with the same name as a built-in one replaces it.
+    pub fn register_native(&mut self, native: NativeFunction) {
+        self.environment.lock().unwrap().define_native_function(native);
+    }
+
+    // The streams the console natives of this interpreter's scripts use
+    pub fn io(&self) -> Arc<ScriptIo> {
+        Arc::clone(&self.environment.lock().unwrap().io)
+    }
+
+    // Gives scripts other streams than the process's own. Modules imported
+    // afterwards use them too.
+    pub fn set_io(&mut self, io: ScriptIo) {
+        self.environment.lock().unwrap().io = Arc::new(io);
+    }
+
+    // The function a script defined (or imported) under this name, if any
+    pub fn get_function(&self, name: &str) -> Option<Value> {
+        match self.environment.lock().unwrap().get(name)? {
+            value @ (Value::Function(..) | Value::AsyncFunction(..) | Value::NativeFunction(_)) => {
+                Some(value)
+            }
+            _ => None,
+        }
+    }
+
+    // Calls a function of an interpreted script by name, so hosts can use
+    // scripts as plugins or hooks. An async function is awaited, so the
+    // result is always its final value.
+    pub fn call_function(&mut self, name: &str, arguments: Vec<Value>) -> InterpreterResult<Value> {
+        let function = self.get_function(name).ok_or_else(|| {
+            InterpreterError::runtime_error(crate::error::RuntimeErrorKind::UndefinedVariable(
+                self.line,
+                name.to_string(),
+                self.environment.lock().unwrap().suggest(name),
+            ))
+        })?;
+        match self.call_value(function, arguments)? {
+            Value::Promise(promise) => {
+                tokio::task::block_in_place(|| self.runtime.block_on(promise.settle()))
+            }
+            value => Ok(value),
+        }
+    }
+
+    // Calls a function value the way a call expression in the script would:
+    // async functions give back a promise instead of running right away
+    pub fn call_value(&mut self, callee: Value, arguments: Vec<Value>) -> InterpreterResult<Value> {
+        match callee {
+            Value::Function(..) | Value::NativeFunction(_) | Value::Class(..) => {
+                self.execute_call(None, callee, arguments)
+            }
+            Value::AsyncFunction(..) => {
+                let future = self.execute_async_call(None, callee, arguments);
+                Ok(Value::create_promise(Box::pin(future)))
+            }
+            _ => Err(InterpreterError::runtime_error(
+                crate::error::RuntimeErrorKind::InvalidCall(self.line),
+            )),
+        }
+    }
+
+    // object.member reads a field or method of the instance and
+    // object.method(arguments) calls a method; a member given by any other
+    // expression is looked up by the string it evaluates to
+    fn instance_member(&mut self, owner: Value, name: &Expr) -> InterpreterResult<Value> {
+        let Value::Instance(class, instance) = &owner else {
+            return Err(InterpreterError::runtime_error(
+                crate::error::RuntimeErrorKind::InvalidGet(self.line),
+            ));
+        };
+        let member = match name {
+            Expr::Call(None, callee, arguments) if matches!(callee.as_ref(), Expr::Variable(_)) => {
+                let mut evaluated_args = Vec::new();
+                for arg in arguments {
+                    evaluated_args.push(self.evaluate(arg)?);
+                }
+                return self.call_method(owner, callee, evaluated_args);
+            }
+            Expr::Variable(member) => member.lexeme.clone(),
+            name => match self.evaluate(name)? {
+                Value::String(member) => member.to_string(),
+                _ => {
+                    return Err(InterpreterError::runtime_error(
+                        crate::error::RuntimeErrorKind::InvalidGet(self.line),
+                    ))
+                }
+            },
+        };
+        self.check_access(class, instance, &member)?;
+        let value = instance.lock().unwrap().get_values().get(&member).cloned();
+        value.ok_or_else(|| {
+            InterpreterError::runtime_error(crate::error::RuntimeErrorKind::InvalidGet(self.line))
+        })
+    }
+
+    // Stores the value under the last of the keys, in the container the
+    // keys before it lead to from object, and gives back object with the
+    // change made. Each key is evaluated once.
+    fn set_member(&mut self, object: Value, keys: &[&Expr], value: Value) -> InterpreterResult<Value> {
+        let Some((key, rest)) = keys.split_first() else {
+            return Ok(value);
+        };
+        let key = match (&object, key) {
+            (Value::Instance(..), Expr::Variable(member)) => Value::String(member.lexeme.as_str().into()),
+            (_, key) => self.evaluate(key)?,
+        };
+        let value = if rest.is_empty() {
+            value
+        } else {
+            let inner = self.member_value(&object, &key)?;
+            self.set_member(inner, rest, value)?
+        };
+        let invalid = || InterpreterError::runtime_error(crate::error::RuntimeErrorKind::InvalidSet(self.line));
+        match (object, key) {
+            (Value::Instance(class, instance), Value::String(name)) => {
+                self.check_access(&class, &instance, &name)?;
+                instance.lock().unwrap().define(&name, value);
+                Ok(Value::Instance(class, instance))
+            }
+            (Value::Array(mut values), Value::Number(index)) if index < values.len() as f64 => {
+                values[index as usize] = value;
+                Ok(Value::Array(values))
+            }
+            (Value::Dictionary(mut values), Value::String(key)) => {
+                values.insert(key.to_string(), value);
+                Ok(Value::Dictionary(values))
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    // What is stored under the key, on the way to a nested set
+    fn member_value(&self, object: &Value, key: &Value) -> InterpreterResult<Value> {
+        let value = match (object, key) {
+            (Value::Instance(class, instance), Value::String(name)) => {
+                self.check_access(class, instance, name)?;
+                instance.lock().unwrap().get_values().get(&**name).cloned()
+            }
+            (Value::Array(values), Value::Number(index)) if *index < values.len() as f64 => {
+                Some(values[*index as usize].clone())
+            }
+            (Value::Dictionary(values), Value::String(key)) => values.get(&**key).cloned(),
+            _ => None,
+        };
+        value.ok_or_else(|| InterpreterError::runtime_error(crate::error::RuntimeErrorKind::InvalidGet(self.line)))
+    }
+
+    // Class.NAME gives a constant the class declares; its methods need an
+    // instance. A constant named with a leading _ is for the methods only.
+    fn class_constant(&self, class: &str, members: &HashMap<String, Value>, name: &str) -> InterpreterResult<Value> {
+        match members.get(name) {
+            Some(Value::Function(..)) | None => Err(InterpreterError::runtime_error(
+                crate::error::RuntimeErrorKind::InvalidGet(self.line),
+            )),
+            Some(_) if name.starts_with('_') => Err(InterpreterError::runtime_error(
+                crate::error::RuntimeErrorKind::PrivateMember(self.line, class.to_string(), name.to_string()),
+            )),
+            Some(value) => Ok(value.clone()),
+        }
+    }
+
+    // Calls a method of the instance, with the instance's scope enclosing
+    // the call
+    fn call_method(&mut self, owner: Value, callee: &Expr, arguments: Vec<Value>) -> InterpreterResult<Value> {
+        let Value::Instance(class, instance) = owner.clone() else {
+            return Err(InterpreterError::runtime_error(
+                crate::error::RuntimeErrorKind::InvalidCall(self.line),
+            ));
+        };
+        if let Expr::Variable(method) = callee {
+            self.check_access(&class, &instance, &method.lexeme)?;
+        }
+        let previous = std::mem::replace(&mut self.environment, instance);
+        let result = self
+            .evaluate(callee)
+            .and_then(|callee| self.execute_call(Some(owner), callee, arguments));
+        self.environment = previous;
+        result
+    }
+
+    // Members whose name starts with _ are private: only code running in one
+    // of the instance's own methods, whose scope encloses the instance's,
+    // may use them. The methods the interpreter calls itself stay public.
+    fn check_access(&self, class: &str, instance: &Arc<Mutex<Environment>>, member: &str) -> InterpreterResult<()> {
+        if !member.starts_with('_') || SPECIAL_METHODS.contains(&member) {
+            return Ok(());
+        }
+        let mut scope = Some(Arc::clone(&self.environment));
+        while let Some(current) = scope {
+            if Arc::ptr_eq(&current, instance) {
+                return Ok(());
+            }
+            scope = current.lock().unwrap().get_enclosing();
+        }
+        Err(InterpreterError::runtime_error(crate::error::RuntimeErrorKind::PrivateMember(
+            self.line,
+            class.to_string(),
+            member.to_string(),
+        )))
+    }
+
+    // The text an instance whose class defines _str() shows as when printed
+    // or joined to a string; None for other values, which keep their usual
+    // display
+    pub fn str_method_text(&mut self, value: &Value) -> InterpreterResult<Option<String>> {
+        let Value::Instance(class, environment) = value else {
+            return Ok(None);
+        };
+        let Some(method) = environment.lock().unwrap().get_values().get("_str").cloned() else {
+            return Ok(None);
+        };
+        let previous = std::mem::replace(&mut self.environment, Arc::clone(environment));
+        let result = self.execute_call(Some(value.clone()), method, Vec::new());
+        self.environment = previous;
+        match result? {
+            Value::String(text) => Ok(Some(text.to_string())),
+            other => Err(InterpreterError::runtime_error(crate::error::RuntimeErrorKind::RuntimeError(
+                self.line,
+                format!("_str() of {} must return a string, not {}", class, other.get_type()),
+            ))),
+        }
+    }
+
+    // Calls a function value from outside the script's own flow (timer and
+    // task callbacks) on a fresh interpreter sharing the given environment,
+    // awaiting the promise an async function gives back
+    pub fn call_detached(
+        runtime: Handle,
+        environment: Arc<Mutex<Environment>>,
+        callee: Value,
+        arguments: Vec<Value>,
+    ) -> InterpreterResult<Value> {
+        let mut interpreter = Interpreter::child(runtime, environment);
+        match interpreter.call_value(callee, arguments) {
+            Ok(Value::Promise(promise)) => tokio::task::block_in_place(|| {
+                interpreter.runtime.block_on(promise.awaited())
+            }),
+            result => result,
+        }
+    }
+
+    // Next value of a `for await` loop: arrays give their elements and
+    // receivers the values sent until their channel is closed, awaiting
+    // those that are promises
+    fn next_async_item(&mut self, iterable: &Value, index: usize) -> InterpreterResult<Option<Value>> {
+        match iterable {
+            Value::Array(elements) => match elements.get(index) {
+                Some(Value::Promise(promise)) => {
+                    tokio::task::block_in_place(|| self.runtime.block_on(promise.awaited())).map(Some)
+                }
+                Some(value) => Ok(Some(value.clone())),
+                None => Ok(None),
+            },
+            Value::Receiver(receiver) => {
+                let item = tokio::task::block_in_place(|| {
+                    self.runtime.block_on(async { receiver.lock().await.recv().await })
+                });
+                match item {
+                    Some(Value::Promise(promise)) => {
+                        tokio::task::block_in_place(|| self.runtime.block_on(promise.awaited())).map(Some)
+                    }
+                    item => Ok(item),
+                }
+            }
+            _ => Err(InterpreterError::runtime_error(
+                crate::error::RuntimeErrorKind::RuntimeError(
+                    self.line,
+                    format!("Cannot iterate over {} with for await", iterable.get_type()),
+                ),
+            )),
+        }
+    }
+
+    // Branches and loop bodies are scopes of their own even without braces,
+    // so `if (ready) var x = 1;` declares nothing outside the if. Only a
+    // declaration needs the scope made for it.
+    fn evaluate_scoped(&mut self, expr: &Expr) -> InterpreterResult<Value> {
+        match expr {
+            Expr::Let(..) | Expr::Function(..) | Expr::AsyncFunction(..) | Expr::Class(..) => {
+                let environment = Environment::new_with_enclosing(Some(Arc::clone(&self.environment)));
+                self.execute_block(std::slice::from_ref(expr), environment)
+            }
+            expr => self.evaluate(expr),
+        }
+    }
+
+    // Runs a for loop in the scope made for it. Variables the initializer
+    // declares belong to the loop, and each iteration has its own copy of
+    // them: work an iteration starts in the background, like a setTimeout
+    // callback, sees the values of that iteration rather than the last.
+    fn execute_for(&mut self, initializer: &Expr, condition: &Expr, increment: &Expr, body: &Expr) -> InterpreterResult<Value> {
+        let mut result = Value::Nil;
+        self.evaluate(initializer)?;
+        loop {
+            let condition = self.evaluate(condition)?;
+            if !self.is_truthy(&condition) {
+                return Ok(result);
+            }
+            result = self.evaluate_scoped(body)?;
+            let next = self.environment.lock().unwrap().next_iteration();
+            Environment::recycle(std::mem::replace(&mut self.environment, next));
+            self.evaluate(increment)?;
+        }
+    }
+
     fn execute_block(
         &mut self,
         statements: &[Expr],
         environment: Arc<Mutex<Environment>>,
     ) -> InterpreterResult<Value> {
-        let previous = self.environment.clone();
-        self.environment = environment;
-        let mut result = Value::Nil;
+        let previous = std::mem::replace(&mut self.environment, environment);
+        let mark = self.deferred.len();
+        let mut result = Ok(Value::Nil);
         for statement in statements {
-            match self.evaluate(statement) {
-                Err(InterpreterError::RuntimeError(crate::error::RuntimeErrorKind::Return(
-                    value,
-                ))) => {
-                    return Ok(value.clone());
+            // A return keeps unwinding as an error until the function call catches it
+            result = self.evaluate(statement);
+            if result.is_err() {
+                break;
+            }
+        }
+        // Still in the block's scope, so what was deferred sees its variables
+        let result = self.run_deferred(mark, result);
+        Environment::recycle(std::mem::replace(&mut self.environment, previous));
+        result
+    }
+
+    // Runs what was deferred since mark, the last first, awaiting any that
+    // gives a promise so `defer` of an async call is not left pending. Each
+    // runs whatever the others do; the first error among them is raised
+    // unless the block was failing already, a return not counting as failing.
+    fn run_deferred(&mut self, mark: usize, mut result: InterpreterResult<Value>) -> InterpreterResult<Value> {
+        while self.deferred.len() > mark {
+            let deferred = self.deferred.pop().unwrap();
+            let outcome = match self.evaluate(&deferred) {
+                Ok(Value::Promise(promise)) => {
+                    tokio::task::block_in_place(|| self.runtime.block_on(promise.awaited()))
+                }
+                outcome => outcome,
+            };
+            if let Err(error) = outcome {
+                let replaceable = match &result {
+                    Ok(_) => true,
+                    Err(InterpreterError::RuntimeError(kind)) => matches!(kind, crate::error::RuntimeErrorKind::Return(_)),
+                    Err(_) => false,
+                };
+                if replaceable {
+                    result = Err(error);
                 }
-                Err(e) => return Err(e),
-                Ok(value) => result = value.clone(),
             }
         }
-        self.environment = previous;
-        Ok(result)
+        result
     }
 
-    async fn execute_async_block(
+    // Runs a function body in the scope holding its arguments
+    fn call_body(
         &mut self,
-        statements: &[Expr],
+        name: String,
+        body: Arc<Expr>,
         environment: Arc<Mutex<Environment>>,
     ) -> InterpreterResult<Value> {
-        let previous = self.environment.clone();
-        self.environment = environment;
-        let mut result = Value::Nil;
-        for statement in statements {
-            match self.evaluate(statement) {
-                Err(InterpreterError::RuntimeError(crate::error::RuntimeErrorKind::Return(
-                    value,
-                ))) => {
-                    return Ok(value.clone());
-                }
-                Err(e) => return Err(e),
-                Ok(value) => result = value.clone(),
+        if self.max_call_depth.is_some_and(|max| self.calls.len() >= max) {
+            return Err(InterpreterError::runtime_error(
+                crate::error::RuntimeErrorKind::RuntimeError(
+                    self.line,
+                    format!("Maximum call depth of {} exceeded in {}", self.calls.len(), name),
+                ),
+            ));
+        }
+        self.calls.push(name);
+        let result = Self::function_result(match &*body {
+            Expr::Block(statements) => self.execute_block(statements, environment),
+            body => self.evaluate(body),
+        });
+        self.calls.pop();
+        result
+    }
+
+    // The value a function call gives back: a `return` anywhere in its body ends it
+    fn function_result(result: InterpreterResult<Value>) -> InterpreterResult<Value> {
+        match result {
+            Err(InterpreterError::RuntimeError(crate::error::RuntimeErrorKind::Return(value))) => {
+                Ok(value)
             }
+            result => result,
         }
-        self.environment = previous;
-        Ok(result)
     }
 
     fn execute_call(
@@ -587,16 +1145,7 @@ impl Interpreter {
                     env_lock.define(param, arg);
                 }
                 drop(env_lock);
-                match *body {
-                    Expr::Block(statements) => {
-                        let result = self.execute_block(&statements, environment)?;
-                        Ok(result)
-                    }
-                    _ => {
-                        let result = self.evaluate(&body)?;
-                        Ok(result)
-                    }
-                }
+                self.call_body(name, body, environment)
             }
             Value::AsyncFunction(name, params, body) => {
                 if arguments.len() != params.len() {
@@ -615,21 +1164,21 @@ impl Interpreter {
                     env_lock.define(param, arg);
                 }
                 drop(env_lock);
-                match *body {
-                    Expr::Block(statements) => {
-                        let result = self.execute_block(&statements, environment)?;
-                        Ok(result)
-                    }
-                    _ => {
-                        let result = self.evaluate(&body)?;
-                        Ok(result)
-                    }
-                }
+                self.call_body(name, body, environment)
             }
-            Value::NativeFunction(function) => function.call(&arguments),
+            Value::NativeFunction(function) => function.call(self, &arguments),
             Value::Class(name, methods) => {
                 let environment =
                     Environment::new_with_enclosing(Some(Arc::clone(&self.environment)));
+                // Every instance has the class's methods and constants, with
+                // or without a constructor, and the constructor can use them
+                for (name, value) in &methods {
+                    environment.lock().unwrap().define(name.as_str(), value.clone());
+                }
+                environment
+                    .lock()
+                    .unwrap()
+                    .define("this", Value::Instance(name.clone(), environment.clone()));
                 if let Some(method) = methods.get("_construct") {
                     match method {
                         Value::Function(_, params, body) => {
@@ -637,11 +1186,9 @@ impl Interpreter {
                             for (param, arg) in params.iter().zip(arguments) {
                                 environment.lock().unwrap().define(param, arg);
                             }
-                            environment
-                                .lock()
-                                .unwrap()
-                                .define("this", Value::Instance(name.clone(), environment.clone()));
-                            self.execute_block(&[*body.clone()], Arc::clone(&environment))?;
+                            Self::function_result(
+                                self.execute_block(std::slice::from_ref(body.as_ref()), Arc::clone(&environment)),
+                            )?;
                         }
                         _ => {
                             return Err(InterpreterError::runtime_error(
@@ -649,9 +1196,6 @@ impl Interpreter {
                             ))
                         }
                     }
-                    for (name, value) in methods {
-                        environment.lock().unwrap().define(name.as_str(), value);
-                    }
                 }
                 let instance = Value::Instance(name, environment);
                 Ok(instance)
@@ -667,12 +1211,14 @@ impl Interpreter {
         _owner: Option<Value>,
         callee: Value,
         arguments: Vec<Value>,
-    ) -> impl Future<Output = Result<Value, InterpreterError>> {
+    ) -> impl Future<Output = Result<Value, InterpreterError>> + Send {
         let environment = Arc::clone(&self.environment);
-        let line = self.line.clone();
+        let runtime = self.runtime.clone();
+        let line = self.line;
+        let calls = self.calls.clone();
         async move {
             match callee {
-                Value::AsyncFunction(_name, params, body) => {
+                Value::AsyncFunction(name, params, body) => {
                     if arguments.len() != params.len() {
                         return Err(InterpreterError::runtime_error(
                             crate::error::RuntimeErrorKind::ExpextedArgument(
@@ -682,25 +1228,16 @@ impl Interpreter {
                             ),
                         ));
                     }
+                    // Each call gets its own scope so concurrent calls keep their arguments apart
+                    let environment = Environment::new_with_enclosing(Some(environment));
                     let mut env_lock = environment.lock().unwrap();
                     for (param, arg) in params.iter().zip(arguments) {
                         env_lock.define(param, arg);
                     }
                     drop(env_lock);
-                    let mut interpreter =
-                        Interpreter::new_with_environment(Arc::clone(&environment));
-                    let result = match *body {
-                        Expr::Block(statements) => {
-                            let result = interpreter.execute_block(&statements, environment)?;
-                            Ok(result)
-                        }
-                        _ => {
-                            let result = interpreter.evaluate(&body)?;
-                            Ok(result)
-                        }
-                    };
-                    interpreter.runtime.shutdown_background();
-                    return result;
+                    let mut interpreter = Interpreter::child(runtime, Arc::clone(&environment));
+                    interpreter.calls = calls;
+                    interpreter.call_body(name, body, environment)
                 }
                 _ => Err(InterpreterError::runtime_error(
                     crate::error::RuntimeErrorKind::UndefinedFunction(line),
@@ -721,14 +1258,19 @@ impl Interpreter {
                 self.environment = previous_env;
                 Ok(value)
             }
+            // A return inside the try block is not an error
+            Err(InterpreterError::RuntimeError(crate::error::RuntimeErrorKind::Return(value))) => {
+                self.environment = previous_env;
+                Err(InterpreterError::runtime_error(crate::error::RuntimeErrorKind::Return(value)))
+            }
             Err(error) => {
                 // Error occurred, execute catch block
+                self.error_token = None;
+                self.failure = None;
                 let catch_env = Environment::new_with_enclosing(Some(Arc::clone(&previous_env)));
                 // Bind error to the catch parameter
-                catch_env
-                    .lock()
-                    .unwrap()
-                    .define(&try_catch.catch_param, Value::String(error.to_string()));
+                let caught = self.caught_value(error);
+                catch_env.lock().unwrap().define(&try_catch.catch_param, caught);
                 // Set catch block environment
                 self.environment = catch_env;
                 // Evaluate catch block
@@ -740,14 +1282,36 @@ impl Interpreter {
         }
     }
 
-    fn add(&self, left: Value, right: Value) -> InterpreterResult<Value> {
+    // What a catch gets: a thrown value as it was thrown, any other error as
+    // dict{"type": ..., "message": ..., "line": ...}
+    fn caught_value(&self, error: InterpreterError) -> Value {
+        let error = error.without_location();
+        if let InterpreterError::RuntimeError(crate::error::RuntimeErrorKind::Thrown(_, value)) = error {
+            return value;
+        }
+        let line = match error.line() {
+            Some(0) | None => self.line,
+            Some(line) => line,
+        };
+        Value::Dictionary(HashMap::from([
+            ("type".to_string(), Value::String(error.type_name().into())),
+            ("message".to_string(), Value::String(error.message().into())),
+            ("line".to_string(), Value::Number(line as f64)),
+        ]))
+    }
+
+    fn add(&mut self, left: Value, right: Value) -> InterpreterResult<Value> {
         match (left, right) {
             (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
-            (Value::String(a), Value::String(b)) => Ok(Value::String(a + &b)),
-            (a, b) => Ok(Value::String(a.to_string() + &b.to_string())),
-            // _ => Err(InterpreterError::runtime_error(
-            //     crate::error::RuntimeErrorKind::OperandsMustBeNumbersOrStrings(self.line),
-            // )),
+            (Value::String(a), Value::String(b)) => Ok(Value::String([&*a, &*b].concat().into())),
+            _ if self.strict => Err(InterpreterError::runtime_error(
+                crate::error::RuntimeErrorKind::OperandsMustBeNumbersOrStrings(self.line),
+            )),
+            (a, b) => {
+                let a = self.str_method_text(&a)?.unwrap_or_else(|| a.to_string());
+                let b = self.str_method_text(&b)?.unwrap_or_else(|| b.to_string());
+                Ok(Value::String((a + &b).into()))
+            }
         }
     }
 
@@ -768,7 +1332,20 @@ impl Interpreter {
             )),
         }
     }
+    // % and div round the quotient down, so the remainder has the sign of
+    // the divisor: -7 div 2 is -4 and -7 % 2 is 1, and a % n is always a
+    // valid index into n elements. a == (a div b) * b + a % b.
     fn modulo(&self, left: Value, right: Value) -> InterpreterResult<Value> {
+        let (a, b) = self.division_operands(left, right)?;
+        Ok(Value::Number(a - b * (a / b).floor()))
+    }
+
+    fn integer_divide(&self, left: Value, right: Value) -> InterpreterResult<Value> {
+        let (a, b) = self.division_operands(left, right)?;
+        Ok(Value::Number((a / b).floor()))
+    }
+
+    fn division_operands(&self, left: Value, right: Value) -> InterpreterResult<(f64, f64)> {
         match (left, right) {
             (Value::Number(a), Value::Number(b)) => {
                 if b == 0.0 {
@@ -776,7 +1353,7 @@ impl Interpreter {
                         crate::error::RuntimeErrorKind::DivisionByZero(self.line),
                     ))
                 } else {
-                    Ok(Value::Number(a % b))
+                    Ok((a, b))
                 }
             }
             _ => Err(InterpreterError::runtime_error(
@@ -839,19 +1416,43 @@ impl Interpreter {
     }
 
     fn equal(&self, left: Value, right: Value) -> InterpreterResult<Value> {
+        self.check_comparable(&left, &right)?;
         Ok(Value::Boolean(self.is_equal(&left, &right)))
     }
 
     fn not_equal(&self, left: Value, right: Value) -> InterpreterResult<Value> {
+        self.check_comparable(&left, &right)?;
         Ok(Value::Boolean(!self.is_equal(&left, &right)))
     }
 
+    // In strict mode == and != only compare values of the same type, or
+    // anything with nil; === and !== compare anything
+    fn check_comparable(&self, left: &Value, right: &Value) -> InterpreterResult<()> {
+        let (left_type, right_type) = (left.get_type(), right.get_type());
+        if !self.strict || left_type == right_type || matches!(left, Value::Nil) || matches!(right, Value::Nil) {
+            return Ok(());
+        }
+        Err(InterpreterError::runtime_error(
+            crate::error::RuntimeErrorKind::MismatchedTypes(self.line, left_type, right_type),
+        ))
+    }
+
+    // NaN equals nothing, not even NaN, and compares false with < and >
+    // like any IEEE float; isNaN tells it apart. Infinity equals itself.
     fn is_equal(&self, left: &Value, right: &Value) -> bool {
         match (left, right) {
-            (Value::Number(a), Value::Number(b)) => (a - b).abs() < f64::EPSILON,
+            (Value::Number(a), Value::Number(b)) => a == b || (a - b).abs() < f64::EPSILON,
             (Value::String(a), Value::String(b)) => a == b,
             (Value::Boolean(a), Value::Boolean(b)) => a == b,
             (Value::Nil, Value::Nil) => true,
+            (Value::Regex(_), Value::Regex(_)) | (Value::Extern(_), Value::Extern(_)) => left == right,
+            (Value::Array(a), Value::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| self.is_equal(a, b))
+            }
+            (Value::Dictionary(a), Value::Dictionary(b)) => {
+                a.len() == b.len()
+                    && a.iter().all(|(key, a)| b.get(key).is_some_and(|b| self.is_equal(a, b)))
+            }
             _ => false,
         }
     }
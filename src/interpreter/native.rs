@@ -1,34 +1,103 @@
+use std::sync::Arc;
+
 use crate::error::{InterpreterError, InterpreterResult};
 
-use super::value::Value;
+use super::{value::Value, Interpreter};
+
+// Natives are closures so hosts can register functions that capture their
+// own state (configuration, handles, counters). They may be called from any
+// thread running script code.
+pub type NativeFn = Arc<dyn Fn(&Vec<Value>) -> InterpreterResult<Value> + Send + Sync>;
+// Natives that call back into script code get the calling interpreter
+pub type InterpreterNativeFn =
+    Arc<dyn Fn(&mut Interpreter, &Vec<Value>) -> InterpreterResult<Value> + Send + Sync>;
+
+#[derive(Clone)]
+enum NativeBody {
+    Plain(NativeFn),
+    WithInterpreter(InterpreterNativeFn),
+}
+
+impl std::fmt::Debug for NativeBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NativeBody::Plain(_) => write!(f, "Plain"),
+            NativeBody::WithInterpreter(_) => write!(f, "WithInterpreter"),
+        }
+    }
+}
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub struct NativeFunction {
     pub name: String,
     arity: usize,
-    func: fn(&Vec<Value>) -> InterpreterResult<Value>,
+    // Natives with optional trailing arguments accept up to max_arity
+    max_arity: usize,
+    func: NativeBody,
 }
 
 impl NativeFunction {
-    pub fn new(
-        name: &str,
-        arity: usize,
-        func: fn(&Vec<Value>) -> InterpreterResult<Value>,
-    ) -> Self {
+    pub fn new<F>(name: &str, arity: usize, func: F) -> Self
+    where
+        F: Fn(&Vec<Value>) -> InterpreterResult<Value> + Send + Sync + 'static,
+    {
+        Self::with_optional(name, arity, arity, func)
+    }
+
+    pub fn with_optional<F>(name: &str, arity: usize, max_arity: usize, func: F) -> Self
+    where
+        F: Fn(&Vec<Value>) -> InterpreterResult<Value> + Send + Sync + 'static,
+    {
+        NativeFunction {
+            name: name.to_string(),
+            arity,
+            max_arity,
+            func: NativeBody::Plain(Arc::new(func)),
+        }
+    }
+
+    pub fn with_interpreter<F>(name: &str, arity: usize, max_arity: usize, func: F) -> Self
+    where
+        F: Fn(&mut Interpreter, &Vec<Value>) -> InterpreterResult<Value> + Send + Sync + 'static,
+    {
         NativeFunction {
             name: name.to_string(),
             arity,
-            func,
+            max_arity,
+            func: NativeBody::WithInterpreter(Arc::new(func)),
         }
     }
 
-    pub fn call(&self, args: &Vec<Value>) -> InterpreterResult<Value> {
-        if args.len() != self.arity {
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    // usize::MAX for natives taking any number of trailing arguments
+    pub fn max_arity(&self) -> usize {
+        self.max_arity
+    }
+
+    pub fn accepts(&self, count: usize) -> bool {
+        (self.arity..=self.max_arity).contains(&count)
+    }
+
+    pub fn call(&self, interpreter: &mut Interpreter, args: &Vec<Value>) -> InterpreterResult<Value> {
+        if args.len() < self.arity || args.len() > self.max_arity {
             return Err(InterpreterError::runtime_error(
                 crate::error::RuntimeErrorKind::InvalidParametsCount(self.arity),
             ));
         }
-        (self.func)(args)
+        match &self.func {
+            NativeBody::Plain(func) => func(args),
+            NativeBody::WithInterpreter(func) => func(interpreter, args),
+        }
     }
 }
 
+// The host object of type T a native was passed, for natives handling the
+// values an embedding program gave scripts through Value::from_extern
+pub fn extern_arg<T: std::any::Any + Send + Sync>(args: &[Value], index: usize) -> InterpreterResult<&T> {
+    args.get(index).and_then(Value::as_extern).ok_or_else(|| {
+        InterpreterError::runtime_error(crate::error::RuntimeErrorKind::InvalidArgumentType(index))
+    })
+}
@@ -1,19 +1,103 @@
-use std::{fmt::format, io::Write, sync::{Arc, Mutex}, time::Duration};
+use std::{collections::HashMap, fs::{File, OpenOptions}, io::{BufRead, BufReader, Read, Seek, SeekFrom, Write}, path::PathBuf, sync::{atomic::{AtomicI32, AtomicI64, Ordering}, Arc, OnceLock}, time::Duration};
 
-use rustls::{pki_types::ServerName, ClientConfig};
-use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::{TcpListener, TcpSocket, TcpStream}, stream, time::sleep};
-use tokio_rustls::TlsConnector;
+use hmac::{Hmac, Mac};
+use md5::Md5;
+use rand::{rngs::StdRng, seq::{IndexedRandom, SliceRandom}, RngExt, SeedableRng};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use tokio::{io::AsyncWriteExt, net::{TcpListener, TcpStream}, sync::{Mutex, Semaphore}, task::JoinSet, time::sleep};
 
 use crate::error::{InterpreterError, InterpreterResult, RuntimeErrorKind};
-use super::{enviroment::Environment, native::NativeFunction, value::Value};
+use super::{args::ArgSpec, cron::Schedule, enviroment::Environment, glob, html, http, http_pool, inspect, json, marshal, messages, native::NativeFunction, options, pattern, plugin, process::{self, Process, StdioOptions}, prompt, proxy::{self, Proxy}, smtp, socket::{connect_tls, with_timeout, Connection, Listener}, value::{deep_copy, deep_equals, format_number, Promise, Value, TYPE_NAMES}, xml, Interpreter};
+
+// Arguments passed after the script filename, exposed through `scriptArgs()`
+static SCRIPT_ARGS: OnceLock<Vec<String>> = OnceLock::new();
+
+pub fn set_script_args(args: Vec<String>) {
+    let _ = SCRIPT_ARGS.set(args);
+}
+
+// Variables set by `setEnv(name, value)`, read before the real environment
+// by getEnv and the interpreter itself and passed on to the programs
+// spawnProcess starts. The process environment is left alone: changing it
+// while the runtime's threads may be reading it is undefined behaviour.
+static ENV_OVERRIDES: std::sync::Mutex<Vec<(String, String)>> = std::sync::Mutex::new(Vec::new());
+
+// The variable as setEnv left it, otherwise as the process started with it
+pub fn env_var(name: &str) -> Option<String> {
+    let overrides = ENV_OVERRIDES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    match overrides.iter().find(|(key, _)| key == name) {
+        Some((_, value)) => Some(value.clone()),
+        None => std::env::var(name).ok(),
+    }
+}
+
+pub fn env_overrides() -> Vec<(String, String)> {
+    ENV_OVERRIDES.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
+
+// Status the process exits with once the script finishes, set by `exitCode(n)`
+static EXIT_CODE: AtomicI32 = AtomicI32::new(0);
+
+pub fn exit_code() -> i32 {
+    EXIT_CODE.load(Ordering::SeqCst)
+}
+
+// Generator behind random() and the other random natives, seeded from the
+// system on first use unless randomSeed(n) fixed it for reproducible runs
+static RANDOM: std::sync::Mutex<Option<StdRng>> = std::sync::Mutex::new(None);
+
+fn with_random<T>(f: impl FnOnce(&mut StdRng) -> T) -> T {
+    let mut random = RANDOM.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    f(random.get_or_insert_with(|| StdRng::from_rng(&mut rand::rng())))
+}
 
 impl Environment {
     pub fn register_native_functions(&mut self) {
         self.register_system_functions();
         self.register_io_functions();
+        self.register_file_functions();
         self.register_conversion_functions();
+        self.register_regex_functions();
+        self.register_message_functions();
+        self.register_collection_functions();
+        self.register_result_functions();
+        self.register_crypto_functions();
+        self.register_data_functions();
+        self.register_archive_functions();
+        self.register_database_functions();
         self.register_async_functions();
+        self.register_channel_functions();
+        self.register_topic_functions();
+        self.register_lock_functions();
+        self.register_process_functions();
         self.register_network_functions();
+        self.register_http_message_functions();
+        self.register_router_functions();
+        self.register_plugin_functions();
+        self.restrict_natives();
+    }
+
+    // Replaces the natives of every capability the options turn off with
+    // ones that fail with the reason
+    fn restrict_natives(&mut self) {
+        let restricted = [
+            (self.options.filesystem, "file system access", options::FILESYSTEM_NATIVES),
+            (self.options.network, "network access", options::NETWORK_NATIVES),
+            (self.options.process_exit, "exiting the process", options::PROCESS_EXIT_NATIVES),
+            (self.options.subprocess, "starting programs", options::SUBPROCESS_NATIVES),
+            (self.options.plugins, "loading plugins", options::PLUGIN_NATIVES),
+        ];
+        for (_, capability, natives) in restricted.into_iter().filter(|(allowed, _, _)| !allowed) {
+            for &name in natives {
+                self.define_native_variadic(name, 0, move |_args| {
+                    Err(InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+                        0,
+                        format!("{}() is not allowed: {} is disabled by the sandbox", name, capability),
+                    )))
+                });
+            }
+        }
     }
 
     fn register_system_functions(&mut self) {
@@ -26,36 +110,381 @@ impl Environment {
                 ))
             }
         });
+        self.define("NaN", Value::Number(f64::NAN));
+        self.define("Infinity", Value::Number(f64::INFINITY));
+        self.define_native("isNaN", 1, |args| match args[0] {
+            Value::Number(n) => Ok(Value::Boolean(n.is_nan())),
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+        // isFinite function - false for NaN and both infinities
+        self.define_native("isFinite", 1, |args| match args[0] {
+            Value::Number(n) => Ok(Value::Boolean(n.is_finite())),
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
         self.define_native("random", 0, |_args| {
-            Ok(Value::Number(rand::random::<f64>()))
+            Ok(Value::Number(with_random(|random| random.random::<f64>())))
+        });
+        // randomInt function - a whole number from min to max, both included
+        self.define_native("randomInt", 2, |args| {
+            let bound = |index: usize| match &args[index] {
+                Value::Number(n) if n.fract() == 0.0 && n.is_finite() => Ok(*n as i64),
+                _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(index))),
+            };
+            let (min, max) = (bound(0)?, bound(1)?);
+            if min > max {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+                    0,
+                    format!("randomInt: min {} is greater than max {}", min, max),
+                )));
+            }
+            Ok(Value::Number(with_random(|random| random.random_range(min..=max)) as f64))
+        });
+        // randomChoice function - nil for an empty array
+        self.define_native("randomChoice", 1, |args| match &args[0] {
+            Value::Array(elements) => {
+                Ok(with_random(|random| elements.choose(random).cloned()).unwrap_or(Value::Nil))
+            }
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+        // shuffle function - returns a shuffled copy, leaving the array as it was
+        self.define_native("shuffle", 1, |args| match &args[0] {
+            Value::Array(elements) => {
+                let mut shuffled = elements.clone();
+                with_random(|random| shuffled.shuffle(random));
+                Ok(Value::Array(shuffled))
+            }
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+        // randomSeed function - makes the random natives repeat the same
+        // sequence for the same seed
+        self.define_native("randomSeed", 1, |args| match &args[0] {
+            Value::Number(seed) if seed.is_finite() => {
+                *RANDOM.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) =
+                    Some(StdRng::seed_from_u64(*seed as i64 as u64));
+                Ok(Value::Nil)
+            }
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
         });
         self.define_native("clock", 0, |_args| {
-            Ok(Value::Number(
-                std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs_f64(),
-            ))
+            let since_epoch = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_err(|_| {
+                    InterpreterError::runtime_error(RuntimeErrorKind::IoError(
+                        "The system clock is set before 1970".to_string(),
+                    ))
+                })?;
+            Ok(Value::Number(since_epoch.as_secs_f64()))
+        });
+        // bench function - calls fn `iterations` times (100 by default) after a
+        // warmup, prints min/avg/p95 in milliseconds and returns them in a dictionary
+        self.define_native_with_interpreter("bench", 2, 3, |interpreter, args| {
+            let name = match &args[0] {
+                Value::String(name) => name.to_string(),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let callback = match &args[1] {
+                Value::Function(..) | Value::AsyncFunction(..) | Value::NativeFunction(_) => args[1].clone(),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(1),
+                )),
+            };
+            let iterations = match args.get(2) {
+                None => 100,
+                Some(Value::Number(n)) if *n >= 1.0 && n.fract() == 0.0 => *n as usize,
+                Some(_) => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(2),
+                )),
+            };
+            let mut call = || match interpreter.call_value(callback.clone(), Vec::new())? {
+                Value::Promise(promise) => tokio::task::block_in_place(|| {
+                    interpreter.runtime.block_on(promise.settle())
+                }),
+                value => Ok(value),
+            };
+            for _ in 0..(iterations / 10).clamp(1, 1000) {
+                call()?;
+            }
+            let mut timings = Vec::with_capacity(iterations);
+            for _ in 0..iterations {
+                let start = std::time::Instant::now();
+                call()?;
+                timings.push(start.elapsed().as_secs_f64() * 1000.0);
+            }
+            timings.sort_by(f64::total_cmp);
+            let min = timings[0];
+            let avg = timings.iter().sum::<f64>() / iterations as f64;
+            let p95 = timings[(iterations * 95).div_ceil(100) - 1];
+            write_stdout(interpreter, &format!(
+                "{}: min {:.3} ms, avg {:.3} ms, p95 {:.3} ms ({} iterations)\n",
+                name, min, avg, p95, iterations
+            ))?;
+            let mut result = HashMap::new();
+            result.insert("name".to_string(), Value::String(name.into()));
+            result.insert("iterations".to_string(), Value::Number(iterations as f64));
+            result.insert("min".to_string(), Value::Number(min));
+            result.insert("avg".to_string(), Value::Number(avg));
+            result.insert("p95".to_string(), Value::Number(p95));
+            Ok(Value::Dictionary(result))
         });
+        // memoize function - the function wrapped to remember its result for
+        // each list of arguments and give it back when called with the same
+        // ones again. Arguments are told apart by value as marshal writes
+        // them; a call with any marshal cannot write, like a socket, is
+        // passed through uncached. Recursive calls of a function by its own
+        // name go through the wrapper once the name holds it, as in
+        // fib = memoize(fib).
+        self.define_native("memoize", 1, |args| {
+            let function = callable_arg(args, 0)?;
+            let (name, arity, max_arity) = match &function {
+                Value::Function(name, params, _) | Value::AsyncFunction(name, params, _) => {
+                    (name.clone(), params.len(), params.len())
+                }
+                Value::NativeFunction(native) => (native.name.clone(), native.arity(), native.max_arity()),
+                _ => unreachable!("callable_arg gives only functions"),
+            };
+            let results = Arc::new(std::sync::Mutex::new(HashMap::<Vec<u8>, Value>::new()));
+            let memoized = NativeFunction::with_interpreter(&name, arity, max_arity, move |interpreter, args| {
+                let Ok(key) = marshal::marshal(&Value::Array(args.clone())) else {
+                    return interpreter.call_value(function.clone(), args.clone());
+                };
+                if let Some(result) = results.lock().unwrap().get(&key) {
+                    return Ok(result.clone());
+                }
+                // Not locked during the call, which may well call the wrapper again
+                let result = interpreter.call_value(function.clone(), args.clone())?;
+                results.lock().unwrap().insert(key, result.clone());
+                Ok(result)
+            });
+            Ok(Value::NativeFunction(memoized))
+        });
+        // deepEquals function - compares arrays, dictionaries and instances by what they hold
+        self.define_native("deepEquals", 2, |args| Ok(Value::Boolean(deep_equals(&args[0], &args[1]))));
+        // clone function - a deep copy, see deep_copy
+        self.define_native("clone", 1, |args| Ok(deep_copy(&args[0])));
         self.define_native("typeOf", 1, |args| {
-            Ok(Value::String(args[0].get_type()))
+            Ok(Value::String(args[0].get_type().into()))
         });
-        self.define_native("assert", 2, |args| {
-            if args[0] == args[1] {
-                Ok(Value::Nil)
+        // expectType function - the value when typeOf gives the type named,
+        // otherwise an UnexpectedType error
+        self.define_native("expectType", 2, |args| {
+            let expected = match &args[1] {
+                Value::String(name) if TYPE_NAMES.contains(&&**name) => name,
+                _ => return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1))),
+            };
+            let actual = args[0].get_type();
+            if actual == **expected {
+                Ok(args[0].clone())
+            } else {
+                Err(InterpreterError::runtime_error(RuntimeErrorKind::UnexpectedType(0, expected.to_string(), actual)))
+            }
+        });
+        self.define_native("isNumber", 1, |args| Ok(Value::Boolean(matches!(args[0], Value::Number(_)))));
+        self.define_native("isString", 1, |args| Ok(Value::Boolean(matches!(args[0], Value::String(_)))));
+        self.define_native("isArray", 1, |args| Ok(Value::Boolean(matches!(args[0], Value::Array(_)))));
+        self.define_native("isDict", 1, |args| Ok(Value::Boolean(matches!(args[0], Value::Dictionary(_)))));
+        self.define_native("isNil", 1, |args| Ok(Value::Boolean(matches!(args[0], Value::Nil))));
+        // isFunction function - true for anything callable like a function:
+        // script functions, async ones and natives
+        self.define_native("isFunction", 1, |args| {
+            Ok(Value::Boolean(matches!(
+                args[0],
+                Value::Function(..) | Value::AsyncFunction(..) | Value::NativeFunction(_)
+            )))
+        });
+        // The assertions fail with an AssertionFailed error showing the
+        // values involved, after the message given as their last argument
+        // if there is one. assert and assertEquals are the same check, that
+        // actual deepEquals expected.
+        for name in ["assert", "assertEquals"] {
+            self.define_native_optional(name, 2, 3, |args| {
+                check(deep_equals(&args[0], &args[1]), args.get(2), || {
+                    format!("expected {}, got {}", shown(&args[1]), shown(&args[0]))
+                })
+            });
+        }
+        // assertClose function - actual is within epsilon (by default 1e-9)
+        // of expected, for results of floating point arithmetic
+        self.define_native_optional("assertClose", 2, 4, |args| {
+            let number = |i: usize| match &args[i] {
+                Value::Number(n) => Ok(*n),
+                _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(i))),
+            };
+            let (actual, expected) = (number(0)?, number(1)?);
+            let epsilon = match args.get(2) {
+                None | Some(Value::Nil) => 1e-9,
+                Some(Value::Number(n)) if *n >= 0.0 => *n,
+                Some(_) => return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(2))),
+            };
+            check((actual - expected).abs() <= epsilon, args.get(3), || {
+                format!(
+                    "expected {} within {}, got {} (off by {})",
+                    format_number(expected),
+                    format_number(epsilon),
+                    format_number(actual),
+                    format_number((actual - expected).abs()),
+                )
+            })
+        });
+        // assertContains function - an array holds an element deepEquals to
+        // item, a string holds item as a substring, or a dictionary has item
+        // as a key
+        self.define_native_optional("assertContains", 2, 3, |args| {
+            let contains = match (&args[0], &args[1]) {
+                (Value::Array(elements), item) => elements.iter().any(|element| deep_equals(element, item)),
+                (Value::String(text), Value::String(part)) => text.contains(&**part),
+                (Value::Dictionary(entries), Value::String(key)) => entries.contains_key(&**key),
+                (Value::String(_) | Value::Dictionary(_), _) => {
+                    return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1)))
+                }
+                _ => return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+            };
+            check(contains, args.get(2), || format!("expected {} to contain {}", shown(&args[0]), shown(&args[1])))
+        });
+        // assertThrows function - calling the function, and awaiting what an
+        // async one returns, throws or fails. Gives what a catch of it would
+        // get, for checking further.
+        self.define_native_with_interpreter("assertThrows", 1, 2, |interpreter, args| {
+            let callback = callable_arg(args, 0)?;
+            let result = match interpreter.call_value(callback, Vec::new()) {
+                Ok(Value::Promise(promise)) => {
+                    tokio::task::block_in_place(|| interpreter.runtime.block_on(promise.awaited()))
+                }
+                result => result,
+            };
+            match result {
+                Err(error) => Ok(interpreter.caught_value(error)),
+                Ok(value) => {
+                    check(false, args.get(1), || format!("expected an error, got {}", shown(&value)))?;
+                    Ok(Value::Nil)
+                }
+            }
+        });
+        // fail function - fails with an AssertionFailed error carrying the
+        // message, for checks the assertions above do not cover
+        self.define_native("fail", 1, |args| {
+            let message = match &args[0] {
+                Value::String(message) => message.to_string(),
+                other => shown(other),
+            };
+            Err(InterpreterError::runtime_error(RuntimeErrorKind::AssertionFailed(message)))
+        });
+        self.define_native("getEnv", 1, |args| {
+            if let Value::String(name) = &args[0] {
+                Ok(env_var(name).map_or(Value::Nil, |value| Value::String(value.into())))
             } else {
                 Err(InterpreterError::runtime_error(
-                    crate::error::RuntimeErrorKind::AssertionFailed,
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                ))
+            }
+        });
+        // setEnv function - sets the variable for getEnv and the processes
+        // the script starts from now on, not for the interpreter's own process
+        self.define_native("setEnv", 2, |args| {
+            match (&args[0], &args[1]) {
+                (Value::String(name), Value::String(value)) => {
+                    let mut overrides = ENV_OVERRIDES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                    overrides.retain(|(key, _)| **key != **name);
+                    overrides.push((name.to_string(), value.to_string()));
+                    Ok(Value::Nil)
+                }
+                (Value::String(_), _) => Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(1),
+                )),
+                _ => Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            }
+        });
+        self.define_native("scriptArgs", 0, |_args| {
+            let args = SCRIPT_ARGS.get().cloned().unwrap_or_default();
+            Ok(Value::Array(args.into_iter().map(|s| Value::String(s.into())).collect()))
+        });
+        // parseArgs function - reads scriptArgs(), or the given array, as the
+        // spec describes; see args.rs for the spec and what comes back
+        self.define_native_optional("parseArgs", 1, 2, |args| {
+            let spec = ArgSpec::from_value(&args[0]).map_err(|message| {
+                InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+                    0,
+                    format!("Invalid parseArgs spec: {}", message),
                 ))
+            })?;
+            let arguments = match args.get(1) {
+                None => SCRIPT_ARGS.get().cloned().unwrap_or_default(),
+                Some(Value::Array(values)) => values.iter().map(|value| value.to_string()).collect(),
+                Some(_) => {
+                    return Err(InterpreterError::runtime_error(
+                        RuntimeErrorKind::InvalidArgumentType(1),
+                    ))
+                }
+            };
+            Ok(Value::Dictionary(spec.parse(&arguments)))
+        });
+        // exitCode function - sets the status used when the script ends normally
+        // (exit() ends it at once); without an argument returns the current one
+        self.define_native_optional("exitCode", 0, 1, |args| match args.first() {
+            None => Ok(Value::Number(exit_code() as f64)),
+            Some(Value::Number(code)) => {
+                EXIT_CODE.store(*code as i32, Ordering::SeqCst);
+                Ok(Value::Nil)
             }
+            Some(_) => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::InvalidArgumentType(0),
+            )),
+        });
+        self.define_native("platform", 0, |_args| {
+            Ok(Value::String(std::env::consts::OS.into()))
+        });
+        self.define_native("arch", 0, |_args| {
+            Ok(Value::String(std::env::consts::ARCH.into()))
         });
+        self.define_native("hostname", 0, |_args| match hostname::get() {
+            Ok(name) => Ok(Value::String(name.to_string_lossy().into())),
+            Err(e) => Err(InterpreterError::runtime_error(RuntimeErrorKind::IoError(
+                e.to_string(),
+            ))),
+        });
+        self.define_native("cpuCount", 0, |_args| {
+            let count = std::thread::available_parallelism().map_or(1, |n| n.get());
+            Ok(Value::Number(count as f64))
+        });
+        // homeDir function - returns nil when the home directory is unknown
+        self.define_native("homeDir", 0, |_args| {
+            Ok(match dirs::home_dir() {
+                Some(path) => Value::String(path.to_string_lossy().into()),
+                None => Value::Nil,
+            })
+        });
+        // configDir, cacheDir and dataDir functions - where the platform keeps
+        // an application's settings, caches and data, such as
+        // ~/.config/<appName> on Linux. Nothing is created; nil when the
+        // platform has no such directory.
+        for (name, base) in [
+            ("configDir", dirs::config_dir as fn() -> Option<std::path::PathBuf>),
+            ("cacheDir", dirs::cache_dir),
+            ("dataDir", dirs::data_dir),
+        ] {
+            self.define_native_optional(name, 0, 1, move |args| {
+                let app = match args.first() {
+                    None | Some(Value::Nil) => None,
+                    Some(Value::String(app)) if !app.is_empty() => Some(app.to_string()),
+                    Some(_) => return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+                };
+                Ok(match (base(), app) {
+                    (Some(path), Some(app)) => Value::String(path.join(app).to_string_lossy().into()),
+                    (Some(path), None) => Value::String(path.to_string_lossy().into()),
+                    (None, _) => Value::Nil,
+                })
+            });
+        }
     }
 
     fn register_io_functions(&mut self) {
         self.define_native("readFile", 1, |args| {
             if let Value::String(filename) = &args[0] {
-                match std::fs::read_to_string(filename) {
-                    Ok(contents) => Ok(Value::String(contents)),
+                match std::fs::read_to_string(&**filename) {
+                    Ok(contents) => Ok(Value::String(contents.into())),
                     Err(e) => Err(InterpreterError::runtime_error(
                         crate::error::RuntimeErrorKind::IoError(e.to_string())
                     ))
@@ -66,9 +495,17 @@ impl Environment {
                 ))
             }
         });
+        // readFileBytes function - a file's contents as byte numbers, for
+        // binary files that readFile would mangle
+        self.define_native("readFileBytes", 1, |args| match &args[0] {
+            Value::String(filename) => std::fs::read(&**filename).map(|bytes| bytes_value(&bytes)).map_err(|e| {
+                InterpreterError::runtime_error(RuntimeErrorKind::IoError(e.to_string()))
+            }),
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
         self.define_native("writeFile", 2, |args| {
             if let (Value::String(filename), Value::String(contents)) = (&args[0], &args[1]) {
-                match std::fs::write(filename, contents) {
+                match std::fs::write(&**filename, &**contents) {
                     Ok(_) => Ok(Value::Nil),
                     Err(e) => Err(InterpreterError::runtime_error(
                         crate::error::RuntimeErrorKind::IoError(e.to_string())
@@ -82,13 +519,10 @@ impl Environment {
         });
         self.define_native("appendFile", 2, |args| {
             if let (Value::String(filename), Value::String(contents)) = (&args[0], &args[1]) {
-                use std::fs::OpenOptions;
-                use std::io::Write;
-        
                 match OpenOptions::new()
                     .append(true)
                     .create(true)
-                    .open(filename)
+                    .open(&**filename)
                     .and_then(|mut file| file.write_all(contents.as_bytes()))
                 {
                     Ok(_) => Ok(Value::Nil),
@@ -103,17 +537,96 @@ impl Environment {
             }
         });
 
-        self.define_native("print", 1, |args| {
-            println!("{}", args[0]);
-            Ok(Value::Nil)
+        // The async file functions give promises and leave the disk work to
+        // tokio, so a server can go on with other requests while it waits
+        self.define_native("readFileAsync", 1, |args| {
+            let Value::String(filename) = args[0].clone() else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
+            };
+            let future = async move {
+                let contents = tokio::fs::read_to_string(&*filename).await.map_err(socket_error)?;
+                Ok(Value::String(contents.into()))
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        self.define_native("writeFileAsync", 2, |args| {
+            let (Value::String(filename), Value::String(contents)) = (args[0].clone(), args[1].clone()) else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
+            };
+            let future = async move {
+                tokio::fs::write(&*filename, &*contents).await.map_err(socket_error)?;
+                Ok(Value::Nil)
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // listDirAsync function - promise of the names in the directory, sorted
+        self.define_native("listDirAsync", 1, |args| {
+            let Value::String(path) = args[0].clone() else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
+            };
+            let future = async move {
+                let mut entries = tokio::fs::read_dir(&*path).await.map_err(socket_error)?;
+                let mut names = Vec::new();
+                while let Some(entry) = entries.next_entry().await.map_err(socket_error)? {
+                    names.push(entry.file_name().to_string_lossy().into_owned());
+                }
+                names.sort();
+                Ok(Value::Array(names.into_iter().map(|s| Value::String(s.into())).collect()))
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // glob function - paths matching a pattern such as "src/**/*.la",
+        // sorted; see glob.rs for the syntax
+        self.define_native("glob", 1, |args| match &args[0] {
+            Value::String(pattern) => Ok(Value::Array(
+                glob::glob(pattern).into_iter().map(|path| Value::String(path.into())).collect(),
+            )),
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+        // globMatch function - whether a path matches a glob pattern, without
+        // looking at the disk
+        self.define_native("globMatch", 2, |args| match (&args[0], &args[1]) {
+            (Value::String(pattern), Value::String(path)) => Ok(Value::Boolean(glob::matches(pattern, path))),
+            (Value::String(_), _) => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1))),
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+
+        self.define_native_with_interpreter("print", 1, usize::MAX, |interpreter, args| {
+            let text = print_text(interpreter, args)?;
+            write_stdout(interpreter, &format!("{}\n", text))
+        });
+        // printRaw function - prints without a trailing newline
+        self.define_native_with_interpreter("printRaw", 1, usize::MAX, |interpreter, args| {
+            let text = print_text(interpreter, args)?;
+            write_stdout(interpreter, &text)
+        });
+        // inspect function - the value as readable, indented text; an options
+        // dictionary may set the depth shown (default 6) and the width lines
+        // are kept within (default 80)
+        self.define_native_optional("inspect", 1, 2, |args| {
+            let (depth, width) = inspect_options(args)?;
+            Ok(Value::String(inspect::inspect(&args[0], depth, width).into()))
+        });
+        // pprint function - prints what inspect gives
+        self.define_native_with_interpreter("pprint", 1, 2, |interpreter, args| {
+            let (depth, width) = inspect_options(args)?;
+            write_stdout(interpreter, &format!("{}\n", inspect::inspect(&args[0], depth, width)))
         });
-        self.define_native("input", 0, |_args| {
+        self.define_native_with_interpreter("eprint", 1, usize::MAX, |interpreter, args| {
+            let text = print_text(interpreter, args)?;
+            write_stderr(interpreter, &text)
+        });
+        self.define_native_with_interpreter("eprintln", 1, usize::MAX, |interpreter, args| {
+            let text = print_text(interpreter, args)?;
+            write_stderr(interpreter, &format!("{}\n", text))
+        });
+        self.define_native_with_interpreter("input", 0, 0, |interpreter, _args| {
             let mut input = String::new();
-            match std::io::stdin().read_line(&mut input) {
+            match interpreter.io().read_line(&mut input) {
                 Ok(_) => {
                     // Trim the trailing newline
                     input = input.trim().to_string();
-                    Ok(Value::String(input))
+                    Ok(Value::String(input.into()))
                 }
                 Err(_) => Err(InterpreterError::runtime_error(
                     RuntimeErrorKind::RuntimeError(
@@ -123,12 +636,51 @@ impl Environment {
                 )),
             }
         });
-        self.define_native("einput", 1, |args| {
+        // readLineStdin function - next line without its line ending, nil at EOF
+        self.define_native_with_interpreter("readLineStdin", 0, 0, |interpreter, _args| {
+            let mut line = String::new();
+            match interpreter.io().read_line(&mut line) {
+                Ok(0) => Ok(Value::Nil),
+                Ok(_) => {
+                    if line.ends_with('\n') {
+                        line.pop();
+                        if line.ends_with('\r') {
+                            line.pop();
+                        }
+                    }
+                    Ok(Value::String(line.into()))
+                }
+                Err(e) => Err(InterpreterError::runtime_error(RuntimeErrorKind::IoError(
+                    e.to_string(),
+                ))),
+            }
+        });
+        self.define_native_with_interpreter("readAllStdin", 0, 0, |interpreter, _args| {
+            let mut input = String::new();
+            match interpreter.io().read_to_string(&mut input) {
+                Ok(_) => Ok(Value::String(input.into())),
+                Err(e) => Err(InterpreterError::runtime_error(RuntimeErrorKind::IoError(
+                    e.to_string(),
+                ))),
+            }
+        });
+        // readKey function - the next key pressed, without waiting for Enter:
+        // the character typed or a name such as "Up", "Enter" or "Ctrl+C"; nil
+        // at the end of input
+        self.define_native_with_interpreter("readKey", 0, 0, |interpreter, _args| {
+            match interpreter.io().read_key() {
+                Ok(key) => Ok(key.map_or(Value::Nil, |s| Value::String(s.into()))),
+                Err(e) => Err(InterpreterError::runtime_error(RuntimeErrorKind::IoError(
+                    e.to_string(),
+                ))),
+            }
+        });
+        self.define_native_with_interpreter("einput", 1, 1, |interpreter, args| {
             match &args[0] {
                 Value::String(prompt) => {
-                    print!("{}", prompt);
+                    let io = interpreter.io();
                     // Ensure the prompt is displayed before reading input
-                    if let Err(_) = std::io::stdout().flush() {
+                    if io.write_stdout(prompt).is_err() {
                         return Err(InterpreterError::runtime_error(
                             crate::error::RuntimeErrorKind::RuntimeError(
                                 0,
@@ -138,10 +690,10 @@ impl Environment {
                     }
 
                     let mut input = String::new();
-                    match std::io::stdin().read_line(&mut input) {
+                    match io.read_line(&mut input) {
                         Ok(_) => {
                             input = input.trim().to_string();
-                            Ok(Value::String(input))
+                            Ok(Value::String(input.into()))
                         }
                         Err(_) => Err(InterpreterError::runtime_error(
                             crate::error::RuntimeErrorKind::RuntimeError(
@@ -156,25 +708,222 @@ impl Environment {
                 )),
             }
         });
+
+        // promptSelect function - the option chosen from an array, with the
+        // arrow keys at a terminal and by number otherwise; nil if cancelled
+        self.define_native_with_interpreter("promptSelect", 2, 2, |interpreter, args| {
+            let (Value::String(title), Value::Array(options)) = (&args[0], &args[1]) else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
+            };
+            if options.is_empty() {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+                    0,
+                    "promptSelect needs at least one option".to_string(),
+                )));
+            }
+            let labels: Vec<String> = options.iter().map(|option| option.to_string()).collect();
+            match prompt::select(&interpreter.io(), title, &labels) {
+                Ok(chosen) => Ok(chosen.map_or(Value::Nil, |i| options[i].clone())),
+                Err(e) => Err(InterpreterError::runtime_error(RuntimeErrorKind::IoError(e.to_string()))),
+            }
+        });
+        // promptConfirm function - true or false for a yes or no answer, the
+        // default for an empty one if given; nil at the end of input
+        self.define_native_with_interpreter("promptConfirm", 1, 2, |interpreter, args| {
+            let Value::String(message) = &args[0] else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
+            };
+            let default = match args.get(1) {
+                None | Some(Value::Nil) => None,
+                Some(Value::Boolean(default)) => Some(*default),
+                Some(_) => return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1))),
+            };
+            match prompt::confirm(&interpreter.io(), message, default) {
+                Ok(answer) => Ok(answer.map_or(Value::Nil, Value::Boolean)),
+                Err(e) => Err(InterpreterError::runtime_error(RuntimeErrorKind::IoError(e.to_string()))),
+            }
+        });
+        // promptPassword function - a line typed without being shown; nil at
+        // the end of input
+        self.define_native_with_interpreter("promptPassword", 1, 1, |interpreter, args| {
+            let Value::String(message) = &args[0] else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
+            };
+            match prompt::password(&interpreter.io(), message) {
+                Ok(password) => Ok(password.map_or(Value::Nil, |s| Value::String(s.into()))),
+                Err(e) => Err(InterpreterError::runtime_error(RuntimeErrorKind::IoError(e.to_string()))),
+            }
+        });
+    }
+
+    // File handles for reading and writing files piece by piece
+    fn register_file_functions(&mut self) {
+        // openFile function - modes are "r" (default), "w", "a", "r+", "w+" and "a+"
+        self.define_native_optional("openFile", 1, 2, |args| {
+            let path = match &args[0] {
+                Value::String(path) => path,
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let mode = match args.get(1) {
+                None => "r",
+                Some(Value::String(mode)) => &**mode,
+                Some(_) => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(1),
+                )),
+            };
+            let mut options = OpenOptions::new();
+            match mode {
+                "r" => options.read(true),
+                "w" => options.write(true).create(true).truncate(true),
+                "a" => options.append(true).create(true),
+                "r+" => options.read(true).write(true),
+                "w+" => options.read(true).write(true).create(true).truncate(true),
+                "a+" => options.read(true).append(true).create(true),
+                _ => return Err(InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+                    0,
+                    format!("Invalid file mode '{}'", mode),
+                ))),
+            };
+            match options.open(&**path) {
+                Ok(file) => Ok(Value::File(Arc::new(std::sync::Mutex::new(Some(BufReader::new(file)))))),
+                Err(e) => Err(InterpreterError::runtime_error(RuntimeErrorKind::IoError(
+                    e.to_string(),
+                ))),
+            }
+        });
+        // readLine function - next line without its line ending, nil at end of file.
+        // On a socket it gives a promise of the line instead.
+        self.define_native("readLine", 1, |args| {
+            if let Value::Socket(_) | Value::TlsSocket(_) | Value::Pipe(_) = &args[0] {
+                let socket = socket_arg(args, 0)?;
+                let future = async move {
+                    let line = socket.lock().await.read_line().await.map_err(socket_error)?;
+                    Ok(line.map_or(Value::Nil, |line| {
+                        Value::String(String::from_utf8_lossy(&line).into())
+                    }))
+                };
+                return Ok(Value::create_promise(Box::pin(future)));
+            }
+            with_open_file(&args[0], |file| {
+                let mut line = String::new();
+                if file.read_line(&mut line)? == 0 {
+                    return Ok(Value::Nil);
+                }
+                if line.ends_with('\n') {
+                    line.pop();
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
+                }
+                Ok(Value::String(line.into()))
+            })
+        });
+        // readChunk function - up to n bytes as a string, nil at end of file.
+        // A character the chunk would cut in two is left for the next read,
+        // or read whole when it is all the chunk holds.
+        self.define_native("readChunk", 2, |args| {
+            let size = match args[1] {
+                Value::Number(n) if n >= 1.0 => n as u64,
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(1),
+                )),
+            };
+            with_open_file(&args[0], |file| {
+                let mut chunk = Vec::new();
+                file.by_ref().take(size).read_to_end(&mut chunk)?;
+                if chunk.is_empty() {
+                    return Ok(Value::Nil);
+                }
+                let (cut, missing) = cut_character(&chunk);
+                if cut == chunk.len() {
+                    file.by_ref().take(missing as u64).read_to_end(&mut chunk)?;
+                } else if cut > 0 {
+                    chunk.truncate(chunk.len() - cut);
+                    file.seek_relative(-(cut as i64))?;
+                }
+                Ok(Value::String(String::from_utf8_lossy(&chunk).into()))
+            })
+        });
+        self.define_native("writeChunk", 2, |args| {
+            let data = bytes_arg(args, 1)?;
+            with_open_file(&args[0], |file| {
+                // Drop the read buffer so the write lands at the logical position
+                let position = file.stream_position()?;
+                file.seek(SeekFrom::Start(position))?;
+                file.get_mut().write_all(&data)?;
+                Ok(Value::Nil)
+            })
+        });
+        // close function - closes a handle before returning, so nothing can
+        // be read from or written to it afterwards; awaiting it is harmless.
+        // A receiver is the exception, closing once reads in progress end.
+        self.define_native_with_interpreter("close", 1, 1, |interpreter, args| match &args[0] {
+            Value::File(file) => {
+                file.lock().unwrap().take();
+                Ok(Value::Nil)
+            }
+            Value::Sender(sender) => {
+                sender.lock().unwrap().take();
+                Ok(Value::Nil)
+            }
+            Value::Topic(topic) => {
+                topic.lock().unwrap().take();
+                Ok(Value::Nil)
+            }
+            Value::Receiver(receiver) => {
+                let receiver = receiver.clone();
+                let future = async move {
+                    receiver.lock().await.close();
+                    Ok(Value::Nil)
+                };
+                Ok(Value::create_promise(Box::pin(future)))
+            }
+            Value::Socket(socket) | Value::TlsSocket(socket) | Value::Pipe(socket) => {
+                let mut connection = lock_socket(interpreter, socket);
+                tokio::task::block_in_place(|| interpreter.runtime.block_on(connection.close()));
+                Ok(Value::Nil)
+            }
+            Value::Server(server) => {
+                server.close();
+                Ok(Value::Nil)
+            }
+            Value::Database(db) => {
+                db.lock().unwrap().take();
+                Ok(Value::Nil)
+            }
+            // Queries already running keep the connection until they finish
+            Value::Postgres(client) => {
+                client.lock().unwrap().take();
+                Ok(Value::Nil)
+            }
+            _ => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::InvalidArgumentType(0),
+            )),
+        });
     }
 
     fn register_conversion_functions(&mut self) {
-        self.define_native("toString", 1, |args| {
+        self.define_native_with_interpreter("toString", 1, 1, |interpreter, args| {
             let value = &args[0];
             let string_value = match value {
                 Value::Number(n) => n.to_string(),
-                Value::String(s) => s.clone(),
+                Value::String(s) => s.to_string(),
                 Value::Boolean(b) => b.to_string(),
                 Value::Nil => "nil".to_string(),
                 Value::Function(name, _, _) => format!("<fn {}>", name),
                 Value::NativeFunction(nf) => format!("<native fn {}>", nf.name),
                 Value::Class(name, _) => format!("<class {}>", name),
-                Value::Instance(name, _) => format!("<instance {}>", name),
+                Value::Instance(name, _) => match interpreter.str_method_text(value)? {
+                    Some(text) => text,
+                    None => format!("<instance {}>", name),
+                },
                 Value::Array(arr) => {
                     let mut result = "".to_string();
                     for (i, v) in arr.iter().enumerate() {
                         if i > 0 {
-                            result.push_str(",");
+                            result.push(',');
                         }
                         result.push_str(&v.to_string());
                     }
@@ -186,7 +935,7 @@ impl Environment {
                         if i > 0 {
                             result.push_str(", ");
                         }
-                        result.push_str(&k);
+                        result.push_str(k);
                         result.push_str(": ");
                         result.push_str(&v.to_string());
                     }
@@ -194,12 +943,32 @@ impl Environment {
                 }
                 Value::Socket(_) => "socket".to_string(),
                 Value::TlsSocket(_) => "tls socket".to_string(),
+                Value::Pipe(_) => "pipe".to_string(),
                 Value::Server(_) => "server".to_string(),
+                Value::File(_) => "file".to_string(),
+                Value::Sender(_) => "sender".to_string(),
+                Value::Receiver(_) => "receiver".to_string(),
+                Value::Topic(_) => "topic".to_string(),
+                Value::Semaphore(..) => "semaphore".to_string(),
+                Value::Timer(_) => "timer".to_string(),
+                Value::StringBuilder(builder) => builder.lock().unwrap().clone(),
+                Value::Process(_) => "process".to_string(),
+                Value::WaitGroup(_) => "wait group".to_string(),
+                Value::Counter(counter) => counter.load(Ordering::SeqCst).to_string(),
+                Value::Cell(cell) => cell.lock().unwrap().to_string(),
+                Value::Regex(pattern) => pattern.literal(),
+                Value::Extern(_) => "extern".to_string(),
+                Value::Database(_) => "database".to_string(),
+                Value::Postgres(_) => "postgres".to_string(),
                 Value::AsyncFunction(name, _, _) => format!("<async fn {}>", name),
                 Value::Promise(_) => "promise".to_string(),
                 // Add other value types as needed
             };
-            Ok(Value::String(string_value))
+            Ok(Value::String(string_value.into()))
+        });
+        // format function - printf-style formatting, see format_values
+        self.define_native_variadic("format", 1, |args| {
+            Ok(Value::String(format_args_string(args)?.into()))
         });
         // toNumber function - attempts to convert a value to a number
         self.define_native("toNumber", 1, |args| {
@@ -221,6 +990,91 @@ impl Environment {
                 )),
             }
         });
+        // divmod function - [a div b, a % b], the quotient rounded down and a
+        // remainder with the sign of b
+        self.define_native("divmod", 2, |args| match (&args[0], &args[1]) {
+            (Value::Number(_), Value::Number(b)) if *b == 0.0 => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::DivisionByZero(0),
+            )),
+            (Value::Number(a), Value::Number(b)) => {
+                let quotient = (a / b).floor();
+                Ok(Value::Array(vec![Value::Number(quotient), Value::Number(a - b * quotient)]))
+            }
+            (Value::Number(_), _) => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1))),
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+        // toFixed function - number as a string with a fixed count of decimals
+        self.define_native("toFixed", 2, |args| match (&args[0], &args[1]) {
+            (Value::Number(n), Value::Number(digits)) if (0.0..=100.0).contains(digits) => {
+                Ok(Value::String(format!("{:.*}", *digits as usize, n).into()))
+            }
+            (Value::Number(_), _) => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::InvalidArgumentType(1),
+            )),
+            _ => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::InvalidArgumentType(0),
+            )),
+        });
+        // parseInt function - integer in the given radix (2 to 36, default 10)
+        self.define_native_optional("parseInt", 1, 2, |args| {
+            let text = match &args[0] {
+                Value::String(text) => text.trim(),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let radix = match args.get(1) {
+                None => 10,
+                Some(Value::Number(radix)) if (2.0..=36.0).contains(radix) && radix.fract() == 0.0 => {
+                    *radix as u32
+                }
+                Some(_) => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(1),
+                )),
+            };
+            let (negative, digits) = match text.strip_prefix('-') {
+                Some(rest) => (true, rest),
+                None => (false, text.strip_prefix('+').unwrap_or(text)),
+            };
+            let digits = match radix {
+                16 => digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")).unwrap_or(digits),
+                2 => digits.strip_prefix("0b").unwrap_or(digits),
+                8 => digits.strip_prefix("0o").unwrap_or(digits),
+                _ => digits,
+            };
+            match u64::from_str_radix(digits, radix) {
+                Ok(n) if negative => Ok(Value::Number(-(n as f64))),
+                Ok(n) => Ok(Value::Number(n as f64)),
+                Err(_) => Err(InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+                    0,
+                    format!("Could not parse '{}' as a base {} integer", text, radix),
+                ))),
+            }
+        });
+        self.define_native("parseFloat", 1, |args| match &args[0] {
+            Value::String(text) => match text.trim().parse::<f64>() {
+                Ok(n) => Ok(Value::Number(n)),
+                Err(_) => Err(InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+                    0,
+                    format!("Could not parse '{}' as a number", text),
+                ))),
+            },
+            _ => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::InvalidArgumentType(0),
+            )),
+        });
+        // toHex function - a whole number in lowercase hexadecimal
+        self.define_native("toHex", 1, |args| match &args[0] {
+            // u64::MAX as f64 rounds up to 2^64, which is already too large
+            Value::Number(n) if n.fract() != 0.0 || n.abs() >= u64::MAX as f64 => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::InvalidArgumentType(0),
+            )),
+            Value::Number(n) if *n < 0.0 => Ok(Value::String(format!("-{:x}", -n as u64).into())),
+            Value::Number(n) => Ok(Value::String(format!("{:x}", *n as u64).into())),
+            _ => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::InvalidArgumentType(0),
+            )),
+        });
         // toBool function - converts value to boolean
         self.define_native("toBool", 1, |args| {
             let value = &args[0];
@@ -235,192 +1089,2890 @@ impl Environment {
             Ok(Value::Boolean(bool_value))
         });
     }
-    fn register_async_functions(&mut self){
-        self.define_native("delay", 1, |args| {
-            let duration = match args[0] {
-                Value::Number(n) => Duration::from_secs_f64(n),
-                _ => return Err(InterpreterError::runtime_error(
-                    crate::error::RuntimeErrorKind::InvalidArgumentType(0),
-                )),
+
+    // Regular expressions, as regex values from /pattern/flags literals or
+    // regex(), or as strings compiled without flags; see pattern.rs. Match
+    // positions count characters, as slice does.
+    fn register_regex_functions(&mut self) {
+        // regex function - the pattern compiled with the given flags
+        self.define_native_optional("regex", 1, 2, |args| {
+            let Value::String(source) = &args[0] else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
             };
-            let future = async move {
-                sleep(duration).await;
-                Ok(Value::Nil)
+            let flags = match args.get(1) {
+                None | Some(Value::Nil) => "",
+                Some(Value::String(flags)) => flags,
+                Some(_) => return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1))),
             };
-            Ok(Value::create_promise(Box::pin(future)))
+            pattern::compile(source, flags)
+                .map(Value::Regex)
+                .map_err(|message| InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, message)))
+        });
+        // regexTest function - whether the pattern matches anywhere in text
+        self.define_native("regexTest", 2, |args| {
+            let pattern = pattern_arg(args, 0)?;
+            Ok(Value::Boolean(pattern.regex.is_match(text_arg(args, 1)?)))
+        });
+        // regexMatch function - the first match as dict{"match", "index",
+        // "groups", "named"}, or nil; a group that took no part is nil
+        self.define_native("regexMatch", 2, |args| {
+            let pattern = pattern_arg(args, 0)?;
+            let text = text_arg(args, 1)?;
+            Ok(pattern.regex.captures(text).map_or(Value::Nil, |captures| match_value(&pattern, text, &captures)))
+        });
+        // regexMatchAll function - every match that does not overlap the one before
+        self.define_native("regexMatchAll", 2, |args| {
+            let pattern = pattern_arg(args, 0)?;
+            let text = text_arg(args, 1)?;
+            Ok(Value::Array(
+                pattern.regex.captures_iter(text).map(|captures| match_value(&pattern, text, &captures)).collect(),
+            ))
+        });
+        // regexReplace function - text with every match replaced, either by a
+        // string where $1 and ${name} stand for groups, or by what a function
+        // given the match dictionary returns
+        self.define_native_with_interpreter("regexReplace", 3, 3, |interpreter, args| {
+            let pattern = pattern_arg(args, 0)?;
+            let text = text_arg(args, 1)?;
+            if let Value::String(replacement) = &args[2] {
+                return Ok(Value::String(pattern.regex.replace_all(text, &**replacement).as_ref().into()));
+            }
+            let callback = callable_arg(args, 2)?;
+            let mut replaced = String::with_capacity(text.len());
+            let mut end = 0;
+            for captures in pattern.regex.captures_iter(text) {
+                let whole = captures.get(0).unwrap();
+                let replacement = interpreter.call_value(callback.clone(), vec![match_value(&pattern, text, &captures)])?;
+                replaced.push_str(&text[end..whole.start()]);
+                replaced.push_str(&replacement.to_string());
+                end = whole.end();
+            }
+            replaced.push_str(&text[end..]);
+            Ok(Value::String(replaced.into()))
+        });
+        // regexSplit function - the pieces of text between matches
+        self.define_native("regexSplit", 2, |args| {
+            let pattern = pattern_arg(args, 0)?;
+            Ok(Value::Array(
+                pattern.regex.split(text_arg(args, 1)?).map(|piece| Value::String(piece.into())).collect(),
+            ))
         });
     }
-    fn register_network_functions(&mut self){
-        self.define_native("listen", 1, |args| {
-            let port = match args[0] {
-                Value::Number(n) => n as u8,
-                _ => return Err(InterpreterError::runtime_error(
-                    crate::error::RuntimeErrorKind::InvalidArgumentType(0),
-                )),
+
+    // Translated strings; see messages.rs for the catalog format
+    fn register_message_functions(&mut self) {
+        // loadMessages function - loads a JSON message catalog, found beside
+        // the script or else from the working directory, for the locale given
+        // or named by the file; gives the locale
+        self.define_native_with_interpreter("loadMessages", 1, 2, |interpreter, args| {
+            let Value::String(path) = &args[0] else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
             };
-            let future = async move {
-                let listener = TcpListener::bind(format!("127.0.0.1:{}", port)).await.unwrap();
-                Ok(Value::Server(Arc::new(Mutex::new(listener))))
+            let locale = match args.get(1) {
+                None | Some(Value::Nil) => None,
+                Some(Value::String(locale)) => Some(&**locale),
+                Some(_) => return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1))),
             };
-            Ok(Value::create_promise(Box::pin(future)))
+            let path = beside_script(&globals(interpreter).lock().unwrap(), path);
+            messages::load(&path, locale)
+                .map(|locale| Value::String(locale.into()))
+                .map_err(|message| InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, message)))
         });
-        self.define_native("connect", 2, |args| {
-            let address = match &args[0] {
-                Value::String(address) => address.clone(),
-                _ => return Err(InterpreterError::runtime_error(
-                    crate::error::RuntimeErrorKind::InvalidArgumentType(0),
-                )),
-            };
-            let port = match args[1] {
-                Value::Number(n) => n as u8,
-                _ => return Err(InterpreterError::runtime_error(
-                    crate::error::RuntimeErrorKind::InvalidArgumentType(0),
-                )),
+        // t function - the message for key in the catalogs loaded, with
+        // parameters from a dictionary; the key itself when none has it
+        self.define_native_optional("t", 1, 2, |args| {
+            let Value::String(key) = &args[0] else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
             };
-            let future = async move {
-                let stream = TcpStream::connect(format!("{}:{}", address, port)).await;
-                match stream {
-                    Ok(stream) => Ok(Value::Socket(Arc::new(Mutex::new(stream)))),
-                    Err(e) => Err(InterpreterError::runtime_error(
-                        crate::error::RuntimeErrorKind::IoError(e.to_string()),
-                    ))
-                }
+            let no_params = HashMap::new();
+            let params = match args.get(1) {
+                None | Some(Value::Nil) => &no_params,
+                Some(Value::Dictionary(params)) => params,
+                Some(_) => return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1))),
             };
-            Ok(Value::create_promise(Box::pin(future)))
+            Ok(Value::String(messages::translate(key, params).unwrap_or_else(|| key.to_string()).into()))
         });
-        self.define_native("connectTLS", 2, |args| {
-            let address = match &args[0] {
-                Value::String(address) => address.clone(),
-                _ => return Err(InterpreterError::runtime_error(
-                    crate::error::RuntimeErrorKind::InvalidArgumentType(0),
+    }
+
+    // ok/err results (see Value::ok) and the helpers for using them
+    fn register_result_functions(&mut self) {
+        self.define_native("ok", 1, |args| Ok(Value::ok(args[0].clone())));
+        self.define_native("err", 1, |args| Ok(Value::err(args[0].clone())));
+        self.define_native("isOk", 1, |args| Ok(Value::Boolean(result_arg(args, 0)?.is_ok())));
+        self.define_native("isErr", 1, |args| Ok(Value::Boolean(result_arg(args, 0)?.is_err())));
+        // unwrap function - the value of an ok result; an err result throws its error
+        self.define_native("unwrap", 1, |args| {
+            result_arg(args, 0)?.map_err(|error| {
+                InterpreterError::runtime_error(RuntimeErrorKind::Thrown(0, error))
+            })
+        });
+        // unwrapOr function - the value of an ok result, or default for an err one
+        self.define_native("unwrapOr", 2, |args| Ok(result_arg(args, 0)?.unwrap_or_else(|_| args[1].clone())));
+        // mapOk function - ok(f(value)) for an ok result; an err result as it is
+        self.define_native_with_interpreter("mapOk", 2, 2, |interpreter, args| {
+            let callback = callable_arg(args, 1)?;
+            match result_arg(args, 0)? {
+                Ok(value) => Ok(Value::ok(interpreter.call_value(callback, vec![value])?)),
+                Err(_) => Ok(args[0].clone()),
+            }
+        });
+        // mapErr function - err(f(error)) for an err result; an ok result as it is
+        self.define_native_with_interpreter("mapErr", 2, 2, |interpreter, args| {
+            let callback = callable_arg(args, 1)?;
+            match result_arg(args, 0)? {
+                Ok(_) => Ok(args[0].clone()),
+                Err(error) => Ok(Value::err(interpreter.call_value(callback, vec![error])?)),
+            }
+        });
+    }
+
+    // The primitives the standard library builds on. Arrays and strings are
+    // values, so these give back new ones instead of changing their argument.
+    fn register_collection_functions(&mut self) {
+        // len function - characters of a string, elements of an array or entries of a dictionary
+        self.define_native("len", 1, |args| match &args[0] {
+            Value::String(s) => Ok(Value::Number(s.chars().count() as f64)),
+            Value::Array(elements) => Ok(Value::Number(elements.len() as f64)),
+            Value::Dictionary(entries) => Ok(Value::Number(entries.len() as f64)),
+            Value::StringBuilder(builder) => Ok(Value::Number(builder.lock().unwrap().chars().count() as f64)),
+            _ => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::InvalidArgumentType(0),
+            )),
+        });
+        // push function - the array with the value added at the end
+        self.define_native("push", 2, |args| match &args[0] {
+            Value::Array(elements) => {
+                let mut elements = elements.clone();
+                elements.push(args[1].clone());
+                Ok(Value::Array(elements))
+            }
+            _ => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::InvalidArgumentType(0),
+            )),
+        });
+        // slice function - elements or characters from start up to end (default: the end),
+        // clamped to the bounds
+        self.define_native_optional("slice", 2, 3, |args| {
+            let index = |i: usize| match args.get(i) {
+                None | Some(Value::Nil) => Ok(None),
+                Some(Value::Number(n)) => Ok(Some(n.max(0.0) as usize)),
+                Some(_) => Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(i),
                 )),
             };
-            let port = match args[1] {
-                Value::Number(n) => n as u16,
-                _ => return Err(InterpreterError::runtime_error(
-                    crate::error::RuntimeErrorKind::InvalidArgumentType(1),
+            let (start, end) = (index(1)?.unwrap_or(0), index(2)?);
+            match &args[0] {
+                Value::String(s) => {
+                    let end = end.unwrap_or(usize::MAX);
+                    Ok(Value::String(s.chars().take(end).skip(start).collect::<String>().into()))
+                }
+                Value::Array(elements) => {
+                    let end = end.unwrap_or(elements.len()).min(elements.len());
+                    Ok(Value::Array(elements.get(start.min(end)..end).unwrap_or_default().to_vec()))
+                }
+                _ => Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
                 )),
+            }
+        });
+        // keys function - the keys of a dictionary, sorted
+        self.define_native("keys", 1, |args| match &args[0] {
+            Value::Dictionary(entries) => {
+                let mut keys: Vec<&String> = entries.keys().collect();
+                keys.sort();
+                Ok(Value::Array(keys.into_iter().map(|key| Value::String(key.as_str().into())).collect()))
+            }
+            _ => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::InvalidArgumentType(0),
+            )),
+        });
+        // stringBuilder function - an empty buffer to append text to in place,
+        // instead of building a new string for every s = s + part
+        self.define_native("stringBuilder", 0, |_args| {
+            Ok(Value::StringBuilder(Arc::new(std::sync::Mutex::new(String::new()))))
+        });
+        // append function - adds the value, as print shows it, to the end of
+        // the builder and returns the builder
+        self.define_native("append", 2, |args| match &args[0] {
+            Value::StringBuilder(builder) => {
+                match &args[1] {
+                    Value::String(s) => builder.lock().unwrap().push_str(s),
+                    value => builder.lock().unwrap().push_str(&value.to_string()),
+                }
+                Ok(args[0].clone())
+            }
+            _ => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::InvalidArgumentType(0),
+            )),
+        });
+        // build function - the text appended so far; the builder keeps it
+        self.define_native("build", 1, |args| match &args[0] {
+            Value::StringBuilder(builder) => Ok(Value::String(builder.lock().unwrap().as_str().into())),
+            _ => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::InvalidArgumentType(0),
+            )),
+        });
+    }
+    fn register_crypto_functions(&mut self) {
+        self.define_native("sha256", 1, |args| {
+            let data = bytes_arg(args, 0)?;
+            Ok(Value::String(to_hex(&Sha256::digest(&data)).into()))
+        });
+        self.define_native("sha1", 1, |args| {
+            let data = bytes_arg(args, 0)?;
+            Ok(Value::String(to_hex(&Sha1::digest(&data)).into()))
+        });
+        self.define_native("md5", 1, |args| {
+            let data = bytes_arg(args, 0)?;
+            Ok(Value::String(to_hex(&Md5::digest(&data)).into()))
+        });
+        self.define_native("crc32", 1, |args| {
+            let data = bytes_arg(args, 0)?;
+            Ok(Value::String(format!("{:08x}", crc32fast::hash(&data)).into()))
+        });
+        // hmacSha256 function - the MAC of data under key, both strings or
+        // arrays of bytes, in hex or, when the third argument is "bytes", as
+        // an array of bytes to use as the key of another hmacSha256
+        self.define_native_optional("hmacSha256", 2, 3, |args| {
+            let key = bytes_arg(args, 0)?;
+            let data = bytes_arg(args, 1)?;
+            let as_bytes = match args.get(2) {
+                None | Some(Value::Nil) => false,
+                Some(Value::String(output)) if &**output == "hex" => false,
+                Some(Value::String(output)) if &**output == "bytes" => true,
+                Some(_) => return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(2))),
             };
-    
-            let future = async move {
-                // Create TLS configuration
-                let mut config = ClientConfig::builder()
-                    .with_root_certificates(rustls::RootCertStore {
-                        roots: webpki_roots::TLS_SERVER_ROOTS.into(),
-                    })
-                    .with_no_client_auth();
-    
-                let connector = TlsConnector::from(Arc::new(config));
-                
-                // Connect to TCP first
-                let stream = TcpStream::connect(format!("{}:{}", address, port)).await.unwrap();
-                // Upgrade to TLS
-                let domain = ServerName::try_from(address)
-                    .map_err(|_| std::io::Error::new(
-                        std::io::ErrorKind::InvalidInput,
-                        "Invalid domain name"
-                    )).unwrap();
-    
-                let tls_stream = connector.connect(domain, stream).await.unwrap();
-                
-                Ok(Value::TlsSocket(Arc::new(Mutex::new(tls_stream))))
-            };
-    
-            Ok(Value::create_promise(Box::pin(future)))
+            let mut mac = Hmac::<Sha256>::new_from_slice(&key).map_err(|e| {
+                InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, e.to_string()))
+            })?;
+            mac.update(&data);
+            let digest = mac.finalize().into_bytes();
+            Ok(if as_bytes { bytes_value(&digest) } else { Value::String(to_hex(&digest).into()) })
         });
-    
-        self.define_native("accept", 1, |args| {
-            let server = match &args[0] {
-                Value::Server(server) => server.clone(),
+        // fromHex function - the array of bytes a hex string such as a
+        // digest stands for
+        self.define_native("fromHex", 1, |args| {
+            let Value::String(text) = &args[0] else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
+            };
+            from_hex(text).map(|bytes| bytes_value(&bytes)).ok_or_else(|| {
+                InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, format!("Invalid hex string \"{}\"", text)))
+            })
+        });
+        self.define_native("randomBytes", 1, |args| {
+            let count = match args[0] {
+                Value::Number(n) if n >= 0.0 => n as usize,
                 _ => return Err(InterpreterError::runtime_error(
-                    crate::error::RuntimeErrorKind::InvalidArgumentType(0),
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let bytes = (0..count)
+                .map(|_| Value::Number(rand::random::<u8>() as f64))
+                .collect();
+            Ok(Value::Array(bytes))
+        });
+        self.define_native("uuid", 0, |_args| {
+            Ok(Value::String(uuid::Uuid::new_v4().to_string().into()))
+        });
+    }
+    fn register_data_functions(&mut self) {
+        self.define_native_optional("csvParse", 1, 2, |args| {
+            let text = match &args[0] {
+                Value::String(text) => text,
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let (header, delimiter) = csv_options(args.get(1))?;
+            let mut reader = csv::ReaderBuilder::new()
+                .has_headers(header)
+                .delimiter(delimiter)
+                .flexible(true)
+                .from_reader(text.as_bytes());
+            let headers = if header {
+                Some(reader.headers().map_err(csv_error)?.clone())
+            } else {
+                None
+            };
+            let mut rows = Vec::new();
+            for record in reader.records() {
+                let record = record.map_err(csv_error)?;
+                let row = match &headers {
+                    Some(headers) => Value::Dictionary(
+                        headers
+                            .iter()
+                            .zip(record.iter())
+                            .map(|(key, field)| (key.to_string(), Value::String(field.into())))
+                            .collect(),
+                    ),
+                    None => Value::Array(
+                        record.iter().map(|field| Value::String(field.into())).collect(),
+                    ),
+                };
+                rows.push(row);
+            }
+            Ok(Value::Array(rows))
+        });
+        self.define_native("jsonParse", 1, |args| match &args[0] {
+            Value::String(text) => json::parse(text).map_err(|message| {
+                InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, message))
+            }),
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+        // jsonStream function - receiver of the values of JSON read from a
+        // file or socket as it arrives, for `for await`: the elements of a
+        // top-level array, or else each value in turn as in NDJSON. Text
+        // that is not JSON ends it with a promise that rejects, which
+        // `for await` throws.
+        self.define_native_with_interpreter("jsonStream", 1, 1, |interpreter, args| {
+            let (sender, receiver) = tokio::sync::mpsc::channel(16);
+            match &args[0] {
+                Value::File(file) => {
+                    let file = Arc::clone(file);
+                    interpreter.runtime.spawn_blocking(move || stream_json_file(file, sender));
+                }
+                _ => {
+                    interpreter.runtime.spawn(stream_json(socket_arg(args, 0)?, sender));
+                }
+            }
+            Ok(Value::Receiver(Arc::new(Mutex::new(receiver))))
+        });
+        // jsonStringify function - one line, or indented by the given number
+        // of spaces per level
+        self.define_native_optional("jsonStringify", 1, 2, |args| {
+            let indent = match args.get(1) {
+                None => 0,
+                Some(Value::Number(n)) if *n >= 0.0 => *n as usize,
+                Some(_) => return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1))),
+            };
+            json::stringify(&args[0], indent).map(|s| Value::String(s.into())).map_err(|message| {
+                InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, message))
+            })
+        });
+        // marshal function - a value as compact binary bytes to write to a
+        // file or send to another process; see marshal.rs
+        self.define_native("marshal", 1, |args| {
+            marshal::marshal(&args[0]).map(|bytes| bytes_value(&bytes)).map_err(|message| {
+                InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, message))
+            })
+        });
+        // unmarshal function - the value marshal made the bytes from
+        self.define_native("unmarshal", 1, |args| {
+            marshal::unmarshal(&bytes_arg(args, 0)?).map_err(|message| {
+                InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, message))
+            })
+        });
+        // xmlParse function - the root element of an XML document as a
+        // dict{"name", "attributes", "children"} tree; see xml.rs
+        self.define_native("xmlParse", 1, |args| match &args[0] {
+            Value::String(text) => xml::parse(text).map_err(|message| {
+                InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, message))
+            }),
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+        // xmlStringify function - an element tree as XML, on one line or
+        // indented by the given number of spaces per level
+        self.define_native_optional("xmlStringify", 1, 2, |args| {
+            let indent = match args.get(1) {
+                None => 0,
+                Some(Value::Number(n)) if *n >= 0.0 => *n as usize,
+                Some(_) => return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1))),
+            };
+            xml::stringify(&args[0], indent).map(|s| Value::String(s.into())).map_err(|message| {
+                InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, message))
+            })
+        });
+        // markdownToHtml function - CommonMark as HTML, with the tables,
+        // strikethrough, task lists and footnotes of GitHub's dialect
+        self.define_native("markdownToHtml", 1, |args| match &args[0] {
+            Value::String(text) => {
+                let options = pulldown_cmark::Options::ENABLE_TABLES
+                    | pulldown_cmark::Options::ENABLE_STRIKETHROUGH
+                    | pulldown_cmark::Options::ENABLE_TASKLISTS
+                    | pulldown_cmark::Options::ENABLE_FOOTNOTES;
+                let mut html = String::new();
+                pulldown_cmark::html::push_html(&mut html, pulldown_cmark::Parser::new_ext(text, options));
+                Ok(Value::String(html.into()))
+            }
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+        // htmlParse function - the element tree of an HTML page, shaped like
+        // xmlParse's under a "#document" root; see html.rs
+        self.define_native("htmlParse", 1, |args| match &args[0] {
+            Value::String(text) => Ok(html::parse(text)),
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+        // select function - the elements under a document or element that a
+        // CSS selector such as "div.item > a[href]" matches, in document order
+        self.define_native("select", 2, |args| {
+            let Value::String(selector) = &args[1] else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1)));
+            };
+            if !matches!(args[0], Value::Dictionary(_)) {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
+            }
+            html::select(&args[0], selector).map(Value::Array).map_err(|message| {
+                InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, message))
+            })
+        });
+        // text function - all the text inside an HTML or XML element
+        self.define_native("text", 1, |args| match &args[0] {
+            Value::Dictionary(_) | Value::String(_) => Ok(Value::String(html::text(&args[0]).into())),
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+        // attr function - an attribute of an HTML or XML element, nil when it
+        // has none by that name
+        self.define_native("attr", 2, |args| match (&args[0], &args[1]) {
+            (Value::Dictionary(element), Value::String(name)) => Ok(match element.get("attributes") {
+                Some(Value::Dictionary(attributes)) => attributes.get(&**name).cloned().unwrap_or(Value::Nil),
+                _ => Value::Nil,
+            }),
+            (Value::Dictionary(_), _) => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1))),
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+        self.define_native_optional("csvStringify", 1, 2, |args| {
+            let rows = match &args[0] {
+                Value::Array(rows) => rows,
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let (_, delimiter) = csv_options(args.get(1))?;
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(delimiter)
+                .flexible(true)
+                .from_writer(Vec::new());
+            // Rows given as dictionaries share the sorted keys of the first one as header
+            let mut headers: Option<Vec<String>> = None;
+            for row in rows {
+                match row {
+                    Value::Array(fields) => {
+                        writer
+                            .write_record(fields.iter().map(|field| field.to_string()))
+                            .map_err(csv_error)?;
+                    }
+                    Value::Dictionary(fields) => {
+                        let keys = headers.get_or_insert_with(|| {
+                            let mut keys: Vec<String> = fields.keys().cloned().collect();
+                            keys.sort();
+                            keys
+                        });
+                        if writer.get_ref().is_empty() {
+                            writer.write_record(keys.iter()).map_err(csv_error)?;
+                        }
+                        writer
+                            .write_record(keys.iter().map(|key| {
+                                fields.get(key).map(|v| v.to_string()).unwrap_or_default()
+                            }))
+                            .map_err(csv_error)?;
+                    }
+                    _ => return Err(InterpreterError::runtime_error(
+                        RuntimeErrorKind::InvalidArgumentType(0),
+                    )),
+                }
+            }
+            let bytes = writer.into_inner().map_err(|e| csv_error(e.into_error().into()))?;
+            Ok(Value::String(String::from_utf8_lossy(&bytes).into()))
+        });
+    }
+    // SQLite and PostgreSQL databases. SQLite parameters are an array for `?`
+    // placeholders or a dictionary for `:name` ones, PostgreSQL parameters an
+    // array for `$1`, `$2`, ...
+    fn register_database_functions(&mut self) {
+        // sqliteOpen function - opens or creates a database file, ":memory:" for a private one
+        self.define_native("sqliteOpen", 1, |args| {
+            let path = match &args[0] {
+                Value::String(path) => path.to_string(),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let db = rusqlite::Connection::open(path).map_err(sqlite_error)?;
+            Ok(Value::Database(Arc::new(std::sync::Mutex::new(Some(db)))))
+        });
+        // query function - rows as an array of dictionaries keyed by column name
+        self.define_native_optional("query", 2, 3, |args| {
+            with_database(args, |db, sql, params| {
+                let mut statement = db.prepare(sql)?;
+                let columns: Vec<String> = statement.column_names().iter().map(|name| name.to_string()).collect();
+                let mut rows = match params {
+                    SqlParams::Positional(values) => statement.query(rusqlite::params_from_iter(values))?,
+                    SqlParams::Named(values) => statement.query(named_params(&values).as_slice())?,
+                };
+                let mut result = Vec::new();
+                while let Some(row) = rows.next()? {
+                    let mut record = HashMap::new();
+                    for (i, column) in columns.iter().enumerate() {
+                        record.insert(column.clone(), from_sql(row.get_ref(i)?));
+                    }
+                    result.push(Value::Dictionary(record));
+                }
+                Ok(Value::Array(result))
+            })
+        });
+        // execute function - runs a statement, returning the number of rows it changed
+        self.define_native_optional("execute", 2, 3, |args| {
+            with_database(args, |db, sql, params| {
+                let mut statement = db.prepare(sql)?;
+                let changed = match params {
+                    SqlParams::Positional(values) => statement.execute(rusqlite::params_from_iter(values))?,
+                    SqlParams::Named(values) => statement.execute(named_params(&values).as_slice())?,
+                };
+                Ok(Value::Number(changed as f64))
+            })
+        });
+        // pgConnect function - connects with a libpq style connection string, without TLS
+        self.define_native("pgConnect", 1, |args| {
+            let dsn = match &args[0] {
+                Value::String(dsn) => dsn.to_string(),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
                 )),
             };
             let future = async move {
-                let (socket, _) = server.lock().unwrap().accept().await.unwrap();
-                Ok(Value::Socket(Arc::new(Mutex::new(socket))))
+                let (client, connection) = tokio_postgres::connect(&dsn, tokio_postgres::NoTls)
+                    .await
+                    .map_err(postgres_error)?;
+                // Drives the socket until the client is dropped
+                tokio::spawn(connection);
+                Ok(Value::Postgres(Arc::new(std::sync::Mutex::new(Some(Arc::new(client))))))
             };
             Ok(Value::create_promise(Box::pin(future)))
         });
-        self.define_native("write", 2, |args| {
-            match &args[0] {
-                Value::Socket(socket) => {
-                    let socket = socket.clone();
-                    let message = match &args[1] {
-                        Value::String(message) => {
-                            // Convert escape sequences to actual bytes
-                            message.replace("\\r\\n", "\r\n")
-                                   .replace("\\n", "\n")
-                                   .replace("\\r", "\r")
-                        },
-                        _ => return Err(InterpreterError::runtime_error(
-                            crate::error::RuntimeErrorKind::InvalidArgumentType(1),
+        // pgQuery function - promise of the rows as an array of dictionaries
+        self.define_native_optional("pgQuery", 2, 3, |args| {
+            let (client, sql, params) = postgres_args(args)?;
+            let future = async move {
+                let statement = client.prepare(&sql).await.map_err(postgres_error)?;
+                let params = postgres_params(&params, statement.params())?;
+                let rows = client
+                    .query(&statement, &postgres_refs(&params))
+                    .await
+                    .map_err(postgres_error)?;
+                let mut result = Vec::new();
+                for row in rows {
+                    let mut record = HashMap::new();
+                    for (i, column) in row.columns().iter().enumerate() {
+                        record.insert(column.name().to_string(), from_postgres(&row, i)?);
+                    }
+                    result.push(Value::Dictionary(record));
+                }
+                Ok(Value::Array(result))
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // pgExecute function - promise of the number of rows the statement changed
+        self.define_native_optional("pgExecute", 2, 3, |args| {
+            let (client, sql, params) = postgres_args(args)?;
+            let future = async move {
+                let statement = client.prepare(&sql).await.map_err(postgres_error)?;
+                let params = postgres_params(&params, statement.params())?;
+                let changed = client
+                    .execute(&statement, &postgres_refs(&params))
+                    .await
+                    .map_err(postgres_error)?;
+                Ok(Value::Number(changed as f64))
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+    }
+
+    fn register_archive_functions(&mut self) {
+        // zipCreate function - files is an array of paths, or a dictionary of
+        // archive names to paths; directories are added recursively
+        self.define_native("zipCreate", 2, |args| {
+            let path = match &args[0] {
+                Value::String(path) => path,
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let entries: Vec<(String, String)> = match &args[1] {
+                Value::Array(paths) => paths
+                    .iter()
+                    .map(|value| match value {
+                        Value::String(source) => {
+                            let name = source.trim_start_matches("./").trim_start_matches('/');
+                            Ok((name.to_string(), source.to_string()))
+                        }
+                        _ => Err(InterpreterError::runtime_error(
+                            RuntimeErrorKind::InvalidArgumentType(1),
                         )),
-                    };
-    
-                    let future = async move {
-                        let mut socket = socket.lock().unwrap();
-                        socket.write_all(message.as_bytes()).await.unwrap();
-                        Ok(Value::Nil)
-                    };
-                    Ok(Value::create_promise(Box::pin(future)))
-                },
-                Value::TlsSocket(socket) => {
-                    let socket = socket.clone();
-                    let message = match &args[1] {
-                        Value::String(message) => {
-                            // Convert escape sequences to actual bytes
-                            message.replace("\\r\\n", "\r\n")
-                                   .replace("\\n", "\n")
-                                   .replace("\\r", "\r")
-                        },
-                        _ => return Err(InterpreterError::runtime_error(
-                            crate::error::RuntimeErrorKind::InvalidArgumentType(1),
+                    })
+                    .collect::<InterpreterResult<_>>()?,
+                Value::Dictionary(files) => files
+                    .iter()
+                    .map(|(name, value)| match value {
+                        Value::String(source) => Ok((name.clone(), source.to_string())),
+                        _ => Err(InterpreterError::runtime_error(
+                            RuntimeErrorKind::InvalidArgumentType(1),
                         )),
-                    };
-    
-                    let future = async move {
-                        let mut socket = socket.lock().unwrap();
-                        let bytes = message.as_bytes();
-                        println!("Writing {:?} bytes", bytes);
-                        socket.write_all(message.as_bytes()).await.unwrap();
-                        Ok(Value::Nil)
-                    };
-                    Ok(Value::create_promise(Box::pin(future)))
-                },
-                _ => Err(InterpreterError::runtime_error(
-                    crate::error::RuntimeErrorKind::InvalidArgumentType(0),
+                    })
+                    .collect::<InterpreterResult<_>>()?,
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(1),
                 )),
+            };
+            let file = File::create(&**path).map_err(zip_error)?;
+            let mut writer = zip::ZipWriter::new(file);
+            for (name, source) in entries {
+                zip_add_path(&mut writer, std::path::Path::new(&source), &name)?;
             }
+            writer.finish().map_err(zip_error)?;
+            Ok(Value::Nil)
         });
-        self.define_native("read", 1, |args| {
-            match &args[0] {
-                Value::Socket(socket) => {
-                    let socket = socket.clone();
-                    let future = async move {
-                        let mut buffer = [0; 1024];
-                        let mut socket = socket.lock().unwrap();
-                        let n = socket.read(&mut buffer).await.unwrap();
-                        let message = String::from_utf8_lossy(&buffer[..n]).to_string();
-                        Ok(Value::String(message))
-                    };
-                    Ok(Value::create_promise(Box::pin(future)))
-                },
-                Value::TlsSocket(socket) => {
-                    let socket = socket.clone();
-                    let future = async move {
-                        let mut buffer = [0; 1024];
-                        let mut socket = socket.lock().unwrap();
-                        let n = socket.read(&mut buffer).await.unwrap();
-                        let message = String::from_utf8_lossy(&buffer[..n]).to_string();
-                        Ok(Value::String(message))
+        self.define_native("zipList", 1, |args| {
+            let path = match &args[0] {
+                Value::String(path) => path,
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let file = File::open(&**path).map_err(zip_error)?;
+            let archive = zip::ZipArchive::new(file).map_err(zip_error)?;
+            Ok(Value::Array(
+                archive.file_names().map(|name| Value::String(name.into())).collect(),
+            ))
+        });
+        self.define_native("zipExtract", 2, |args| {
+            let (path, dest) = match (&args[0], &args[1]) {
+                (Value::String(path), Value::String(dest)) => (path, dest),
+                (Value::String(_), _) => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(1),
+                )),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let file = File::open(&**path).map_err(zip_error)?;
+            let mut archive = zip::ZipArchive::new(file).map_err(zip_error)?;
+            archive.extract(&**dest).map_err(zip_error)?;
+            Ok(Value::Nil)
+        });
+    }
+
+    fn register_async_functions(&mut self){
+        // delay function - promise settled after the given number of seconds;
+        // a time already past (a negative number) settles it at once
+        self.define_native("delay", 1, |args| {
+            let duration = match args[0] {
+                Value::Number(n) => seconds_duration(if n < 0.0 { 0.0 } else { n }),
+                _ => None,
+            };
+            let Some(duration) = duration else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
+            };
+            let future = async move {
+                sleep(duration).await;
+                Ok(Value::Nil)
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // spawn function - starts a promise, or a call of an async function with
+        // the remaining arguments, in the background; the returned promise can
+        // be awaited later or ignored
+        self.define_native_with_interpreter("spawn", 1, usize::MAX, |interpreter, args| {
+            spawn_task(interpreter, args, 0)
+        });
+        // waitGroup function - a group of tasks: add starts one in it, wait
+        // settles them all and cancel stops those still running
+        self.define_native("waitGroup", 0, |_args| {
+            Ok(Value::WaitGroup(Arc::new(std::sync::Mutex::new(Vec::new()))))
+        });
+        // add function - starts a promise, or a call of an async function
+        // with the remaining arguments, like spawn, as part of the group
+        self.define_native_with_interpreter("add", 2, usize::MAX, |interpreter, args| {
+            let Value::WaitGroup(group) = &args[0] else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
+            };
+            let task = spawn_task(interpreter, args, 1)?;
+            if let Value::Promise(promise) = &task {
+                group.lock().unwrap().push(promise.clone());
+            }
+            Ok(task)
+        });
+        // setTimeout function - calls fn once after ms milliseconds
+        self.define_native_with_interpreter("setTimeout", 2, 2, |interpreter, args| {
+            let (callback, period) = timer_args(args)?;
+            let environment = interpreter.environment();
+            let runtime = interpreter.runtime.clone();
+            let task = interpreter.runtime.spawn(async move {
+                sleep(period).await;
+                report_callback_error(Interpreter::call_detached(
+                    runtime,
+                    environment,
+                    callback,
+                    Vec::new(),
+                ));
+            });
+            Ok(Value::Timer(Arc::new(task.abort_handle())))
+        });
+        // setInterval function - calls fn every ms milliseconds until cleared
+        self.define_native_with_interpreter("setInterval", 2, 2, |interpreter, args| {
+            let (callback, period) = timer_args(args)?;
+            if period.is_zero() {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1)));
+            }
+            let environment = interpreter.environment();
+            let runtime = interpreter.runtime.clone();
+            let task = interpreter.runtime.spawn(async move {
+                let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+                loop {
+                    ticks.tick().await;
+                    report_callback_error(Interpreter::call_detached(
+                        runtime.clone(),
+                        environment.clone(),
+                        callback.clone(),
+                        Vec::new(),
+                    ));
+                }
+            });
+            Ok(Value::Timer(Arc::new(task.abort_handle())))
+        });
+        // every function - calls fn every given number of seconds, the first
+        // time one period from now; a call still running when the next is
+        // due delays it rather than running twice at once
+        self.define_native_with_interpreter("every", 2, 2, |interpreter, args| {
+            let period = match args[0] {
+                Value::Number(n) => seconds_duration(n).filter(|period| !period.is_zero()),
+                _ => None,
+            };
+            let Some(period) = period else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
+            };
+            let callback = callback_arg(args, 1)?;
+            let environment = interpreter.environment();
+            let runtime = interpreter.runtime.clone();
+            let task = interpreter.runtime.spawn(async move {
+                let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+                ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                loop {
+                    ticks.tick().await;
+                    report_callback_error(Interpreter::call_detached(
+                        runtime.clone(),
+                        environment.clone(),
+                        callback.clone(),
+                        Vec::new(),
+                    ));
+                }
+            });
+            Ok(Value::Timer(Arc::new(task.abort_handle())))
+        });
+        // schedule function - calls fn at each minute matching a cron
+        // expression such as "*/5 * * * *" (UTC, see cron::Schedule)
+        self.define_native_with_interpreter("schedule", 2, 2, |interpreter, args| {
+            let schedule = match &args[0] {
+                Value::String(expression) => Schedule::parse(expression).map_err(|message| {
+                    InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, message))
+                })?,
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let callback = callback_arg(args, 1)?;
+            let environment = interpreter.environment();
+            let runtime = interpreter.runtime.clone();
+            let task = interpreter.runtime.spawn(async move {
+                loop {
+                    let now = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap_or_default();
+                    let Some(next) = schedule.next_after(now.as_secs()) else {
+                        break;
                     };
-                    Ok(Value::create_promise(Box::pin(future)))
+                    sleep(Duration::from_secs(next) - now).await;
+                    report_callback_error(Interpreter::call_detached(
+                        runtime.clone(),
+                        environment.clone(),
+                        callback.clone(),
+                        Vec::new(),
+                    ));
+                }
+            });
+            Ok(Value::Timer(Arc::new(task.abort_handle())))
+        });
+        self.define_native("clearTimeout", 1, |args| match &args[0] {
+            Value::Timer(timer) => {
+                timer.abort();
+                Ok(Value::Nil)
+            }
+            _ => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::InvalidArgumentType(0),
+            )),
+        });
+        // cancel function - abandons a pending promise or the running tasks of
+        // a wait group, or stops a timer like clearTimeout; true if it had not
+        // settled or finished yet
+        self.define_native("cancel", 1, |args| match &args[0] {
+            Value::Promise(promise) => Ok(Value::Boolean(promise.cancel())),
+            Value::Timer(timer) => {
+                let pending = !timer.is_finished();
+                timer.abort();
+                Ok(Value::Boolean(pending))
+            }
+            Value::WaitGroup(group) => {
+                let cancelled = group.lock().unwrap().iter().filter(|task| task.cancel()).count();
+                Ok(Value::Boolean(cancelled > 0))
+            }
+            _ => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::InvalidArgumentType(0),
+            )),
+        });
+        // awaitTimeout function - the promise's result, or a Timeout error once
+        // the seconds pass, cancelling the promise so whatever it was waiting
+        // on is dropped
+        self.define_native("awaitTimeout", 2, |args| {
+            let (seconds, limit) = match args[1] {
+                Value::Number(n) => match seconds_duration(n) {
+                    Some(limit) => (n, limit),
+                    None => return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1))),
                 },
-                _ => Err(InterpreterError::runtime_error(
-                    crate::error::RuntimeErrorKind::InvalidArgumentType(0),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(1),
+                )),
+            };
+            let promise = match &args[0] {
+                Value::Promise(promise) => promise.clone(),
+                // A plain value counts as already settled
+                value => return Ok(value.clone()),
+            };
+            let future = async move {
+                // Settled in a task of its own, as an async function's body
+                // keeps the thread running it until its next await
+                let task = promise.clone();
+                let settled = tokio::spawn(async move { task.settle().await });
+                match tokio::time::timeout(limit, settled).await {
+                    Ok(joined) => joined.map_err(task_error)?,
+                    Err(_) => {
+                        promise.cancel();
+                        Err(InterpreterError::runtime_error(RuntimeErrorKind::Timeout(0, seconds)))
+                    }
+                }
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // awaitAll function - settles every promise concurrently and fulfills
+        // with their results in order, or rejects with the first failure
+        self.define_native("awaitAll", 1, |args| {
+            let values = match &args[0] {
+                Value::Array(values) => values.clone(),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let future = async move {
+                let mut results = vec![Value::Nil; values.len()];
+                let mut tasks = JoinSet::new();
+                for (index, value) in values.into_iter().enumerate() {
+                    match value {
+                        Value::Promise(promise) => {
+                            tasks.spawn(async move { (index, promise.settle().await) });
+                        }
+                        value => results[index] = value,
+                    }
+                }
+                while let Some(joined) = tasks.join_next().await {
+                    match joined.map_err(task_error)? {
+                        (index, Ok(value)) => results[index] = value,
+                        (_, Err(error)) => {
+                            // Let the other promises settle on their own
+                            tasks.detach_all();
+                            return Err(error);
+                        }
+                    }
+                }
+                Ok(Value::Array(results))
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // awaitAny function - settles with whichever promise settles first; the
+        // rest keep running but their results are ignored
+        self.define_native("awaitAny", 1, |args| {
+            let values = match &args[0] {
+                Value::Array(values) if !values.is_empty() => values.clone(),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
                 )),
+            };
+            let future = async move {
+                // A plain value counts as already settled
+                if let Some(value) = values.iter().find(|value| !matches!(value, Value::Promise(_))) {
+                    return Ok(value.clone());
+                }
+                let mut tasks = JoinSet::new();
+                for value in values {
+                    if let Value::Promise(promise) = value {
+                        tasks.spawn(async move { promise.settle().await });
+                    }
+                }
+                let first = tasks.join_next().await.expect("at least one promise");
+                tasks.detach_all();
+                first.map_err(task_error)?
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // retry function - calls fn until it returns without an error or the
+        // attempts run out, rejecting with the last error then. Options are
+        // dict{"attempts": 3, "delay": 0.1, "backoff": 2}: the wait in seconds
+        // before the second attempt, multiplied by backoff for each one after,
+        // and shortened by a random part of up to half so retries spread out.
+        self.define_native_with_interpreter("retry", 1, 2, |interpreter, args| {
+            let callback = callback_arg(args, 0)?;
+            let (attempts, delay, backoff) = retry_options(args.get(1))?;
+            let environment = interpreter.environment();
+            let runtime = interpreter.runtime.clone();
+            let future = async move {
+                let mut wait = delay;
+                let mut attempt = 1;
+                loop {
+                    let result = Interpreter::call_detached(
+                        runtime.clone(),
+                        environment.clone(),
+                        callback.clone(),
+                        Vec::new(),
+                    );
+                    match result {
+                        Err(_) if attempt < attempts => {
+                            let jitter = with_random(|random| random.random_range(0.5..=1.0));
+                            // Backoff can grow the wait past what a Duration holds
+                            sleep(Duration::try_from_secs_f64(wait * jitter).unwrap_or(Duration::MAX)).await;
+                            wait *= backoff;
+                            attempt += 1;
+                        }
+                        result => return result,
+                    }
+                }
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+    }
+    // Channels pass values between tasks: channel(capacity) gives back
+    // [sender, receiver]
+    fn register_channel_functions(&mut self) {
+        self.define_native("channel", 1, |args| {
+            let capacity = match args[0] {
+                Value::Number(n) if n >= 1.0 => n as usize,
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let (sender, receiver) = tokio::sync::mpsc::channel(capacity);
+            Ok(Value::Array(vec![
+                Value::Sender(Arc::new(std::sync::Mutex::new(Some(sender)))),
+                Value::Receiver(Arc::new(Mutex::new(receiver))),
+            ]))
+        });
+        // send function - waits while the channel is full
+        self.define_native("send", 2, |args| {
+            let sender = match &args[0] {
+                Value::Sender(sender) => sender.lock().unwrap().clone(),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let value = args[1].clone();
+            let future = async move {
+                let closed = || InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+                    0,
+                    "Channel is closed".to_string(),
+                ));
+                let sender = sender.ok_or_else(closed)?;
+                sender.send(value).await.map_err(|_| closed())?;
+                Ok(Value::Nil)
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // interval function - receiver that gets 1, 2, 3, ... every given number
+        // of seconds, for use with `for await`
+        self.define_native_with_interpreter("interval", 1, 1, |interpreter, args| {
+            let period = match args[0] {
+                Value::Number(n) => seconds_duration(n).filter(|period| !period.is_zero()),
+                _ => None,
+            };
+            let Some(period) = period else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
+            };
+            let (sender, receiver) = tokio::sync::mpsc::channel(1);
+            interpreter.runtime.spawn(async move {
+                let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+                for tick in 1.. {
+                    ticks.tick().await;
+                    // Stops once nothing holds the receiver any more
+                    if sender.send(Value::Number(tick as f64)).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            Ok(Value::Receiver(Arc::new(Mutex::new(receiver))))
+        });
+        // lines function - receiver that gets each line read from a socket
+        self.define_native_with_interpreter("lines", 1, 1, |interpreter, args| {
+            let (sender, receiver) = tokio::sync::mpsc::channel(16);
+            interpreter.runtime.spawn(send_lines(socket_arg(args, 0)?, sender));
+            Ok(Value::Receiver(Arc::new(Mutex::new(receiver))))
+        });
+        // receive function - next value, or nil once the channel is closed and empty
+        self.define_native("receive", 1, |args| {
+            let receiver = match &args[0] {
+                Value::Receiver(receiver) => receiver.clone(),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let future = async move {
+                Ok(receiver.lock().await.recv().await.unwrap_or(Value::Nil))
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+    }
+
+    // Topics fan values out to every subscriber: broadcast(capacity?) makes
+    // one, publish hands a value to each current subscription and subscribe
+    // gives a receiver of the values published from then on
+    fn register_topic_functions(&mut self) {
+        self.define_native_optional("broadcast", 0, 1, |args| {
+            let capacity = match args.first() {
+                None | Some(Value::Nil) => 64,
+                Some(Value::Number(n)) if *n >= 1.0 => *n as usize,
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let (sender, _) = tokio::sync::broadcast::channel(capacity);
+            Ok(Value::Topic(Arc::new(std::sync::Mutex::new(Some(sender)))))
+        });
+        // publish function - how many subscriptions the value went to; it
+        // never waits, a subscriber more than capacity values behind skips
+        // the oldest ones
+        self.define_native("publish", 2, |args| {
+            let Value::Topic(topic) = &args[0] else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
+            };
+            match topic.lock().unwrap().as_ref() {
+                Some(sender) => Ok(Value::Number(sender.send(args[1].clone()).unwrap_or(0) as f64)),
+                None => Err(InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+                    0,
+                    "Topic is closed".to_string(),
+                ))),
             }
         });
+        // subscribe function - receiver for `receive` and `for await`, ending
+        // once the topic is closed
+        self.define_native_with_interpreter("subscribe", 1, 1, |interpreter, args| {
+            let Value::Topic(topic) = &args[0] else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
+            };
+            let (sender, receiver) = tokio::sync::mpsc::channel(1);
+            // A closed topic gives a receiver that ends right away
+            if let Some(subscription) = topic.lock().unwrap().as_ref().map(|topic| topic.subscribe()) {
+                interpreter.runtime.spawn(forward_topic(subscription, sender));
+            }
+            Ok(Value::Receiver(Arc::new(Mutex::new(receiver))))
+        });
     }
-}
\ No newline at end of file
+
+    // Locks for tasks sharing state: acquire waits for a free permit and
+    // release hands it back
+    fn register_lock_functions(&mut self) {
+        self.define_native("mutex", 0, |_args| {
+            Ok(Value::Semaphore(Arc::new(Semaphore::new(1)), 1))
+        });
+        self.define_native("semaphore", 1, |args| match args[0] {
+            Value::Number(n) if n >= 1.0 => {
+                let permits = n as usize;
+                Ok(Value::Semaphore(Arc::new(Semaphore::new(permits)), permits))
+            }
+            _ => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::InvalidArgumentType(0),
+            )),
+        });
+        // taskPool function - a semaphore whose permits bound how many run()
+        // tasks are in flight at once
+        self.define_native("taskPool", 1, |args| match args[0] {
+            Value::Number(n) if n >= 1.0 => {
+                let permits = n as usize;
+                Ok(Value::Semaphore(Arc::new(Semaphore::new(permits)), permits))
+            }
+            _ => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::InvalidArgumentType(0),
+            )),
+        });
+        // run function - queues a promise, or a call of an async function with
+        // the remaining arguments, to start once the pool has a free permit
+        self.define_native_with_interpreter("run", 2, usize::MAX, |interpreter, args| {
+            let pool = match &args[0] {
+                Value::Semaphore(pool, _) => pool.clone(),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let task = match &args[1] {
+                Value::Promise(promise) if args.len() == 2 => promise.clone(),
+                Value::AsyncFunction(..) => {
+                    match interpreter.call_value(args[1].clone(), args[2..].to_vec())? {
+                        Value::Promise(promise) => promise,
+                        value => return Ok(value),
+                    }
+                }
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(1),
+                )),
+            };
+            let queued = Value::create_promise(Box::pin(async move {
+                let _permit = pool.acquire_owned().await.map_err(|_| {
+                    InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+                        0,
+                        "Task pool is closed".to_string(),
+                    ))
+                })?;
+                task.settle().await
+            }));
+            if let Value::Promise(promise) = &queued {
+                let promise = promise.clone();
+                interpreter.runtime.spawn(async move {
+                    let _ = promise.settle().await;
+                });
+            }
+            Ok(queued)
+        });
+        self.define_native("acquire", 1, |args| {
+            let semaphore = match &args[0] {
+                Value::Semaphore(semaphore, _) => semaphore.clone(),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let future = async move {
+                // The permit stays taken until release() adds it back
+                semaphore.acquire().await.map(|permit| permit.forget()).ok();
+                Ok(Value::Nil)
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        self.define_native("release", 1, |args| match &args[0] {
+            Value::Semaphore(semaphore, permits) => {
+                if semaphore.available_permits() >= *permits {
+                    return Err(InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+                        0,
+                        "Released more times than acquired".to_string(),
+                    )));
+                }
+                semaphore.add_permits(1);
+                Ok(Value::Nil)
+            }
+            _ => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::InvalidArgumentType(0),
+            )),
+        });
+        // Shared state for tasks: atomicCounter(initial?) counts in whole
+        // numbers and sharedCell(initial) holds any value. get, set, increment
+        // and compareAndSwap each happen at once, so no task sees another's
+        // change half done.
+        self.define_native_optional("atomicCounter", 0, 1, |args| {
+            let initial = match args.first() {
+                None => 0,
+                Some(_) => counter_number(args, 0)?,
+            };
+            Ok(Value::Counter(Arc::new(AtomicI64::new(initial))))
+        });
+        self.define_native("sharedCell", 1, |args| {
+            Ok(Value::Cell(Arc::new(std::sync::Mutex::new(args[0].clone()))))
+        });
+        self.define_native("get", 1, |args| match &args[0] {
+            Value::Counter(counter) => Ok(Value::Number(counter.load(Ordering::SeqCst) as f64)),
+            Value::Cell(cell) => Ok(cell.lock().unwrap().clone()),
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+        self.define_native("set", 2, |args| match &args[0] {
+            Value::Counter(counter) => {
+                counter.store(counter_number(args, 1)?, Ordering::SeqCst);
+                Ok(Value::Nil)
+            }
+            Value::Cell(cell) => {
+                *cell.lock().unwrap() = args[1].clone();
+                Ok(Value::Nil)
+            }
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+        // increment function - adds by (1 if left out, negative to count
+        // down) and gives back the new value; a cell must hold a number
+        self.define_native_optional("increment", 1, 2, |args| match &args[0] {
+            Value::Counter(counter) => {
+                let by = if args.len() > 1 { counter_number(args, 1)? } else { 1 };
+                Ok(Value::Number(counter.fetch_add(by, Ordering::SeqCst).wrapping_add(by) as f64))
+            }
+            Value::Cell(cell) => {
+                let by = match args.get(1) {
+                    None => 1.0,
+                    Some(Value::Number(by)) => *by,
+                    Some(_) => return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1))),
+                };
+                let mut value = cell.lock().unwrap();
+                let Value::Number(n) = *value else {
+                    return Err(InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+                        0,
+                        format!("Cannot increment a cell holding a {}", value.get_type()),
+                    )));
+                };
+                *value = Value::Number(n + by);
+                Ok(value.clone())
+            }
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+        // compareAndSwap function - sets the new value only if the current
+        // one equals expected (deeply, for a cell); true if it did
+        self.define_native("compareAndSwap", 3, |args| match &args[0] {
+            Value::Counter(counter) => {
+                let (expected, new) = (counter_number(args, 1)?, counter_number(args, 2)?);
+                let swapped = counter.compare_exchange(expected, new, Ordering::SeqCst, Ordering::SeqCst);
+                Ok(Value::Boolean(swapped.is_ok()))
+            }
+            Value::Cell(cell) => {
+                let mut value = cell.lock().unwrap();
+                let swapped = deep_equals(&value, &args[1]);
+                if swapped {
+                    *value = args[2].clone();
+                }
+                Ok(Value::Boolean(swapped))
+            }
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+    }
+
+    // Other programs run alongside the script: spawnProcess starts one and
+    // gives a handle for the rest
+    fn register_process_functions(&mut self) {
+        // spawnProcess function - runs program with an array of string
+        // arguments. By default it has no stdin and shares the script's
+        // stdout and stderr; dict{"stdin": "pipe", ...} changes that for
+        // each stream, see process::stdio.
+        self.define_native_with_interpreter("spawnProcess", 1, 3, |interpreter, args| {
+            let Value::String(program) = &args[0] else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
+            };
+            let arguments = match args.get(1) {
+                None | Some(Value::Nil) => Vec::new(),
+                Some(Value::Array(arguments)) => arguments
+                    .iter()
+                    .map(|argument| match argument {
+                        Value::String(argument) => Some(argument.to_string()),
+                        _ => None,
+                    })
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1)))?,
+                Some(_) => return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1))),
+            };
+            let mut stdio = StdioOptions::default();
+            match args.get(2) {
+                None | Some(Value::Nil) => {}
+                Some(Value::Dictionary(options)) => {
+                    for (name, value) in options {
+                        let target = match name.as_str() {
+                            "stdin" => &mut stdio.stdin,
+                            "stdout" => &mut stdio.stdout,
+                            "stderr" => &mut stdio.stderr,
+                            _ => return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(2))),
+                        };
+                        *target = match value {
+                            Value::String(value) => process::stdio(value),
+                            _ => None,
+                        }
+                        .ok_or_else(|| InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(2)))?;
+                    }
+                }
+                Some(_) => return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(2))),
+            }
+            let process = Process::spawn(&interpreter.runtime, program, &arguments, stdio).map_err(|e| {
+                InterpreterError::runtime_error(RuntimeErrorKind::IoError(e.to_string()))
+            })?;
+            Ok(Value::Process(Arc::new(process)))
+        });
+        self.define_native("pid", 1, |args| Ok(Value::Number(process_arg(args)?.pid as f64)));
+        // stdin, stdout and stderr functions - a piped standard stream of
+        // the process, for write, readLine, lines and the other socket
+        // natives. Closing stdin ends the process's input.
+        for name in ["stdin", "stdout", "stderr"] {
+            self.define_native(name, 1, move |args| {
+                let process = process_arg(args)?;
+                let pipe = match name {
+                    "stdin" => &process.stdin,
+                    "stdout" => &process.stdout,
+                    _ => &process.stderr,
+                };
+                match pipe {
+                    Some(pipe) => Ok(Value::Pipe(pipe.clone())),
+                    None => Err(InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+                        0,
+                        format!("The process's {} is not piped", name),
+                    ))),
+                }
+            });
+        }
+        self.define_native("isRunning", 1, |args| Ok(Value::Boolean(process_arg(args)?.is_running())));
+        // kill function - sends a signal, a number or a name like "TERM"
+        // (the default) or "SIGKILL"; false when the process had already
+        // exited. Where there are no signals any of them ends the process.
+        self.define_native_optional("kill", 1, 2, |args| {
+            let process = process_arg(args)?;
+            let signal = match args.get(1) {
+                None | Some(Value::Nil) => process::signal_number("TERM"),
+                Some(Value::Number(n)) if n.fract() == 0.0 && *n > 0.0 => Some(*n as i32),
+                Some(Value::String(name)) => process::signal_number(&name.to_ascii_uppercase()),
+                Some(_) => None,
+            };
+            let signal = signal.ok_or_else(|| InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1)))?;
+            Ok(Value::Boolean(process.kill(signal)))
+        });
+        // wait function - promise of the exit code (128 plus the signal when
+        // a signal ended it), or nil when it still runs after timeout ms. For
+        // a wait group, the results of its tasks; see wait_group.
+        self.define_native_optional("wait", 1, 2, |args| {
+            let limit = timeout_arg(args, 1)?;
+            if let Value::WaitGroup(group) = &args[0] {
+                return Ok(Value::create_promise(Box::pin(wait_group(group.clone(), limit))));
+            }
+            let process = process_arg(args)?;
+            let future = async move {
+                let code = match limit {
+                    Some(limit) => tokio::time::timeout(limit, process.wait()).await.ok(),
+                    None => Some(process.wait().await),
+                };
+                Ok(code.map_or(Value::Nil, |code| Value::Number(code as f64)))
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+    }
+
+    fn register_network_functions(&mut self){
+        // listen function - server on port, bound to 127.0.0.1 unless another
+        // address such as "0.0.0.0" is given
+        self.define_native_optional("listen", 1, 2, |args| {
+            let port = port_arg(args, 0)?;
+            let host = host_arg(args, 1)?;
+            let future = async move {
+                let listener = TcpListener::bind((host.as_str(), port)).await.map_err(socket_error)?;
+                Ok(Value::Server(Arc::new(Listener::new(listener))))
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // connect function - TCP connection. The third argument is a timeout
+        // in ms, or dict{"timeout": ms, "proxy": url}; see connect_options
+        self.define_native_optional("connect", 2, 3, |args| {
+            let address = match &args[0] {
+                Value::String(address) => address.to_string(),
+                _ => return Err(InterpreterError::runtime_error(
+                    crate::error::RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let port = port_arg(args, 1)?;
+            let (limit, proxy) = connect_options(args, 2)?;
+            let future = async move {
+                let stream = with_timeout(limit, "Connect", proxy::connect(proxy.as_ref(), &address, port))
+                    .await
+                    .map_err(socket_error)?;
+                Ok(Value::Socket(Arc::new(Mutex::new(Connection::new(stream)))))
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        self.define_native_optional("connectTLS", 2, 3, |args| {
+            let address = match &args[0] {
+                Value::String(address) => address.to_string(),
+                _ => return Err(InterpreterError::runtime_error(
+                    crate::error::RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let port = port_arg(args, 1)?;
+            let (limit, proxy) = connect_options(args, 2)?;
+    
+            let future = async move {
+                // Connect to TCP first, then upgrade to TLS; the timeout covers both
+                let tls_stream = with_timeout(limit, "Connect", async {
+                    let stream = proxy::connect(proxy.as_ref(), &address, port).await?;
+                    connect_tls(&address, stream).await
+                })
+                .await
+                .map_err(socket_error)?;
+                
+                Ok(Value::TlsSocket(Arc::new(Mutex::new(Connection::new(tls_stream)))))
+            };
+    
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+    
+        // shutdown function - stops a server accepting, waits for the handlers
+        // serve or httpServe started to finish, at most timeout ms (default
+        // 30000, 0 waits as long as they take), then closes it. Resolves to
+        // false when handlers were still running at the timeout.
+        self.define_native_optional("shutdown", 1, 2, |args| {
+            let server = match &args[0] {
+                Value::Server(server) => server.clone(),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let limit = match args.get(1) {
+                None | Some(Value::Nil) => Some(Duration::from_secs(30)),
+                _ => timeout_arg(args, 1)?,
+            };
+            let future = async move { Ok(Value::Boolean(server.shutdown(limit).await)) };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        self.define_native("accept", 1, |args| {
+            let server = match &args[0] {
+                Value::Server(server) => server.clone(),
+                _ => return Err(InterpreterError::runtime_error(
+                    crate::error::RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let future = async move {
+                let (socket, _) = server.accept().await.map_err(socket_error)?;
+                Ok(Value::Socket(Arc::new(Mutex::new(Connection::new(socket)))))
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // serve function - accepts connections until the server is closed, handing
+        // each socket to handler on its own task so clients are served concurrently
+        self.define_native_with_interpreter("serve", 2, 2, |interpreter, args| {
+            let server = match &args[0] {
+                Value::Server(server) => server.clone(),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let handler = match &args[1] {
+                Value::Function(..) | Value::AsyncFunction(..) | Value::NativeFunction(_) => args[1].clone(),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(1),
+                )),
+            };
+            let environment = interpreter.environment();
+            let runtime = interpreter.runtime.clone();
+            let future = async move {
+                loop {
+                    let socket = match server.accept().await {
+                        Ok((socket, _)) => Value::Socket(Arc::new(Mutex::new(Connection::new(socket)))),
+                        Err(e) if e.kind() == std::io::ErrorKind::NotConnected => return Ok(Value::Nil),
+                        Err(_) => continue,
+                    };
+                    let (runtime, environment, handler) = (runtime.clone(), environment.clone(), handler.clone());
+                    let guard = server.track_handler();
+                    runtime.clone().spawn(async move {
+                        let _guard = guard;
+                        report_callback_error(Interpreter::call_detached(
+                            runtime,
+                            environment,
+                            handler,
+                            vec![socket],
+                        ));
+                    });
+                }
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        self.define_native("write", 2, |args| {
+            let socket = socket_arg(args, 0)?;
+            let message = match &args[1] {
+                Value::String(message) => {
+                    // Convert escape sequences to actual bytes
+                    message.replace("\\r\\n", "\r\n")
+                           .replace("\\n", "\n")
+                           .replace("\\r", "\r")
+                },
+                _ => return Err(InterpreterError::runtime_error(
+                    crate::error::RuntimeErrorKind::InvalidArgumentType(1),
+                )),
+            };
+
+            let future = async move {
+                let mut socket = socket.lock().await;
+                socket.write_all(message.as_bytes()).await.map_err(socket_error)?;
+                Ok(Value::Nil)
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        self.define_native("read", 1, |args| {
+            let socket = socket_arg(args, 0)?;
+            let future = async move {
+                let mut buffer = [0; 1024];
+                let mut socket = socket.lock().await;
+                let n = socket.read(&mut buffer).await.map_err(socket_error)?;
+                let message = String::from_utf8_lossy(&buffer[..n]).to_string();
+                Ok(Value::String(message.into()))
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // readBytes function - up to max bytes (default 4096) as an array of byte
+        // numbers, empty once the socket is closed
+        self.define_native_optional("readBytes", 1, 2, |args| {
+            let socket = socket_arg(args, 0)?;
+            let max = match args.get(1) {
+                None => 4096,
+                Some(Value::Number(n)) if *n >= 1.0 => *n as usize,
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(1),
+                )),
+            };
+            let future = async move {
+                let mut buffer = vec![0; max];
+                let n = socket.lock().await.read(&mut buffer).await.map_err(socket_error)?;
+                Ok(bytes_value(&buffer[..n]))
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // writeBytes function - writes a byte array (or string) as is
+        self.define_native("writeBytes", 2, |args| {
+            let socket = socket_arg(args, 0)?;
+            let data = bytes_arg(args, 1)?;
+            let future = async move {
+                socket.lock().await.write_all(&data).await.map_err(socket_error)?;
+                Ok(Value::Nil)
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // readExact function - exactly n bytes, rejecting if the socket closes first
+        self.define_native("readExact", 2, |args| {
+            let socket = socket_arg(args, 0)?;
+            let size = match args[1] {
+                Value::Number(n) if n >= 0.0 => n as usize,
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(1),
+                )),
+            };
+            let future = async move {
+                let data = socket.lock().await.read_exact(size).await.map_err(socket_error)?;
+                Ok(Value::String(String::from_utf8_lossy(&data).into()))
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // readUntil function - data before the next delimiter, the rest of the
+        // stream if it closes first, then nil
+        self.define_native("readUntil", 2, |args| {
+            let socket = socket_arg(args, 0)?;
+            let delimiter = match &args[1] {
+                Value::String(delimiter) if !delimiter.is_empty() => delimiter.clone(),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(1),
+                )),
+            };
+            let future = async move {
+                let data = socket.lock().await.read_until(delimiter.as_bytes()).await.map_err(socket_error)?;
+                Ok(data.map_or(Value::Nil, |data| {
+                    Value::String(String::from_utf8_lossy(&data).into())
+                }))
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // shutdownWrite function - tells the peer nothing more will be written
+        self.define_native("shutdownWrite", 1, |args| {
+            let socket = socket_arg(args, 0)?;
+            let future = async move {
+                socket.lock().await.shutdown_write().await.map_err(socket_error)?;
+                Ok(Value::Nil)
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // peerAddress function - "ip:port" of the other end of a socket
+        self.define_native_with_interpreter("peerAddress", 1, 1, |interpreter, args| {
+            let socket = socket_arg(args, 0)?;
+            let address = lock_socket(interpreter, &socket).tcp().and_then(|tcp| tcp.peer_addr()).map_err(socket_error)?;
+            Ok(Value::String(address.to_string().into()))
+        });
+        // localAddress function - "ip:port" a socket or server is bound to, which
+        // tells a server listening on port 0 the port it was given
+        self.define_native_with_interpreter("localAddress", 1, 1, |interpreter, args| {
+            if let Value::Server(server) = &args[0] {
+                let address = server.local_addr().map_err(socket_error)?;
+                return Ok(Value::String(address.to_string().into()));
+            }
+            let socket = socket_arg(args, 0)?;
+            let address = lock_socket(interpreter, &socket).tcp().and_then(|tcp| tcp.local_addr()).map_err(socket_error)?;
+            Ok(Value::String(address.to_string().into()))
+        });
+        // Timeouts are in ms, nil or 0 to wait forever; a timed out read or
+        // write rejects with an io error
+        self.define_native("setReadTimeout", 2, |args| {
+            let socket = socket_arg(args, 0)?;
+            let limit = timeout_arg(args, 1)?;
+            let future = async move {
+                socket.lock().await.read_timeout = limit;
+                Ok(Value::Nil)
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        self.define_native("setWriteTimeout", 2, |args| {
+            let socket = socket_arg(args, 0)?;
+            let limit = timeout_arg(args, 1)?;
+            let future = async move {
+                socket.lock().await.write_timeout = limit;
+                Ok(Value::Nil)
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        self.define_native("setNoDelay", 2, |args| {
+            let socket = socket_arg(args, 0)?;
+            let enabled = bool_arg(args, 1)?;
+            let future = async move {
+                socket.lock().await.tcp().and_then(|tcp| tcp.set_nodelay(enabled)).map_err(socket_error)?;
+                Ok(Value::Nil)
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        self.define_native("setKeepAlive", 2, |args| {
+            let socket = socket_arg(args, 0)?;
+            let enabled = bool_arg(args, 1)?;
+            let future = async move {
+                let socket = socket.lock().await;
+                let tcp = socket.tcp().map_err(socket_error)?;
+                socket2::SockRef::from(tcp).set_keepalive(enabled).map_err(socket_error)?;
+                Ok(Value::Nil)
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // sendMail function - sends mail through an SMTP server, see smtp::Mail for the dictionary keys
+        self.define_native("sendMail", 3, |args| {
+            let host = match &args[0] {
+                Value::String(host) => host.to_string(),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let port = port_arg(args, 1)?;
+            let mail = match &args[2] {
+                Value::Dictionary(dict) => smtp::Mail::from_dict(dict, port).map_err(|message| {
+                    InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, message))
+                })?,
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(2),
+                )),
+            };
+            let future = async move {
+                with_timeout(Some(Duration::from_secs(60)), "sendMail", smtp::send(&host, port, &mail))
+                    .await
+                    .map_err(socket_error)?;
+                Ok(Value::Nil)
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // httpGetJson and httpPostJson give promises of the decoded JSON the
+        // URL answers with (nil for an empty body). A status outside 2xx
+        // rejects with a dictionary of type "HttpError", message, status and
+        // body, the body decoded when it is JSON.
+        self.define_native("httpGetJson", 1, |args| {
+            let Value::String(url) = args[0].clone() else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
+            };
+            let future = async move {
+                let response = http::request("GET", &url, &[("Accept", "application/json")], None).await;
+                json_response(&url, response.map_err(socket_error)?)
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        self.define_native("httpPostJson", 2, |args| {
+            let Value::String(url) = args[0].clone() else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0)));
+            };
+            let body = json::stringify(&args[1], 0).map_err(|message| {
+                InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, message))
+            })?;
+            let future = async move {
+                let headers = [("Accept", "application/json"), ("Content-Type", "application/json")];
+                let response = http::request("POST", &url, &headers, Some(body.as_bytes())).await;
+                json_response(&url, response.map_err(socket_error)?)
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+        // setHttpPool function - how many idle connections httpGetJson and
+        // httpPostJson keep per host (0 closes each after its response) and
+        // for how many ms an unused one stays open (default 30000)
+        self.define_native_optional("setHttpPool", 1, 2, |args| {
+            let size = match args[0] {
+                Value::Number(n) if n >= 0.0 => n as usize,
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                )),
+            };
+            let idle_timeout = timeout_arg(args, 1)?.unwrap_or(Duration::from_secs(30));
+            http_pool::configure(size, idle_timeout);
+            Ok(Value::Nil)
+        });
+        // httpServe function - serves HTTP on port (and the optional bind address
+        // like listen), or on a server from listen, answering each request with
+        // handler(request). On a server it runs like serve: until shutdown,
+        // which waits for the requests being answered.
+        self.define_native_with_interpreter("httpServe", 2, 3, |interpreter, args| {
+            let server = match &args[0] {
+                Value::Server(server) => Some(server.clone()),
+                _ => None,
+            };
+            let port = if server.is_some() { 0 } else { port_arg(args, 0)? };
+            let host = host_arg(args, 2)?;
+            let handler = match &args[1] {
+                Value::Function(..) | Value::AsyncFunction(..) | Value::NativeFunction(_) => args[1].clone(),
+                _ => return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(1),
+                )),
+            };
+            let environment = interpreter.environment();
+            let runtime = interpreter.runtime.clone();
+            let future = async move {
+                let server = match server {
+                    Some(server) => server,
+                    None => {
+                        let listener = TcpListener::bind((host.as_str(), port)).await.map_err(socket_error)?;
+                        Arc::new(Listener::new(listener))
+                    }
+                };
+                loop {
+                    let socket = match server.accept().await {
+                        Ok((socket, _)) => socket,
+                        Err(e) if e.kind() == std::io::ErrorKind::NotConnected => return Ok(Value::Nil),
+                        Err(_) => continue,
+                    };
+                    let guard = server.track_handler();
+                    let connection = serve_http_connection(socket, runtime.clone(), environment.clone(), handler.clone());
+                    runtime.spawn(async move {
+                        let _guard = guard;
+                        connection.await
+                    });
+                }
+            };
+            Ok(Value::create_promise(Box::pin(future)))
+        });
+    }
+    // HTTP messages as text, for servers written on raw sockets. Parsed
+    // requests have the shape httpServe hands its handler, and responses the
+    // shape a handler returns.
+    fn register_http_message_functions(&mut self) {
+        self.define_native("parseHttpRequest", 1, |args| match &args[0] {
+            Value::String(text) => http::parse_request(text).map_err(|message| http_message_error("request", message)),
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+        self.define_native("parseHttpResponse", 1, |args| match &args[0] {
+            Value::String(text) => http::parse_response(text).map_err(|message| http_message_error("response", message)),
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+        self.define_native("parseUrlEncoded", 1, |args| match &args[0] {
+            Value::String(text) => Ok(Value::Dictionary(http::parse_urlencoded(text))),
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+        // parseMultipart function - the fields of a multipart/form-data body
+        // (a string or an array of bytes) given with its Content-Type. Text
+        // fields are strings; files are dictionaries of filename, contentType
+        // and data, the array of the file's bytes.
+        self.define_native("parseMultipart", 2, |args| {
+            let body = bytes_arg(args, 0)?;
+            let Value::String(content_type) = &args[1] else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1)));
+            };
+            let parts = http::parse_multipart(&body, content_type)
+                .map_err(|message| http_message_error("multipart body", message))?;
+            let mut fields = HashMap::new();
+            for part in parts {
+                let value = match part.filename {
+                    Some(filename) => Value::Dictionary(HashMap::from([
+                        ("filename".to_string(), Value::String(filename.into())),
+                        ("contentType".to_string(), part.content_type.map_or(Value::Nil, |s| Value::String(s.into()))),
+                        ("data".to_string(), bytes_value(&part.data)),
+                    ])),
+                    None => Value::String(String::from_utf8_lossy(&part.data).into()),
+                };
+                fields.insert(part.name, value);
+            }
+            Ok(Value::Dictionary(fields))
+        });
+        // buildHttpResponse function - the response text for a status,
+        // headers and body, with Content-Length set from the body
+        self.define_native("buildHttpResponse", 1, |args| match &args[0] {
+            response @ Value::Dictionary(_) => {
+                Ok(Value::String(String::from_utf8_lossy(&http::response_bytes(response)).into()))
+            }
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        });
+    }
+
+    // A router is a dictionary of routes, and like an array given to push()
+    // route() returns a new one: app = route(app, "GET", "/users/:id", getUser).
+    // dispatch() hands a request to the handler of the first route matching
+    // it, with the values of the pattern's :name segments in request["params"].
+    fn register_router_functions(&mut self) {
+        self.define_native("router", 0, |_args| {
+            Ok(Value::Dictionary(HashMap::from([("routes".to_string(), Value::Array(Vec::new()))])))
+        });
+        self.define_native("route", 4, |args| {
+            let mut routes = router_routes(&args[0])?.clone();
+            let Value::Dictionary(mut router) = args[0].clone() else { unreachable!() };
+            let (Value::String(method), Value::String(pattern)) = (&args[1], &args[2]) else {
+                let index = if matches!(args[1], Value::String(_)) { 2 } else { 1 };
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(index)));
+            };
+            let handler = callable_arg(args, 3)?;
+            routes.push(Value::Dictionary(HashMap::from([
+                ("method".to_string(), Value::String(method.to_uppercase().into())),
+                ("pattern".to_string(), Value::String(pattern.clone())),
+                ("handler".to_string(), handler),
+            ])));
+            router.insert("routes".to_string(), Value::Array(routes));
+            Ok(Value::Dictionary(router))
+        });
+        // dispatch function - what the route's handler returns; a 404 response
+        // when no route matches the path and a 405 when none takes the method
+        self.define_native_with_interpreter("dispatch", 2, 2, |interpreter, args| {
+            let routes = router_routes(&args[0])?;
+            let Value::Dictionary(request) = &args[1] else {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1)));
+            };
+            let text = |key: &str| match request.get(key) {
+                Some(Value::String(text)) => text.to_string(),
+                _ => String::new(),
+            };
+            let (method, path) = (text("method").to_uppercase(), text("path"));
+            let mut allowed = Vec::new();
+            for route in routes {
+                let Value::Dictionary(route) = route else { continue };
+                let (Some(Value::String(route_method)), Some(Value::String(pattern)), Some(handler)) =
+                    (route.get("method"), route.get("pattern"), route.get("handler"))
+                else {
+                    continue;
+                };
+                let Some(params) = http::match_route(pattern, &path) else { continue };
+                if &**route_method != "*" && **route_method != method {
+                    allowed.push(route_method.to_string());
+                    continue;
+                }
+                let mut request = request.clone();
+                request.insert("params".to_string(), Value::Dictionary(params));
+                return interpreter.call_value(handler.clone(), vec![Value::Dictionary(request)]);
+            }
+            let (status, headers) = if allowed.is_empty() {
+                (404, HashMap::new())
+            } else {
+                (405, HashMap::from([("Allow".to_string(), Value::String(allowed.join(", ").into()))]))
+            };
+            Ok(Value::Dictionary(HashMap::from([
+                ("status".to_string(), Value::Number(status as f64)),
+                ("headers".to_string(), Value::Dictionary(headers)),
+                ("body".to_string(), Value::String(http::reason(status).into())),
+            ])))
+        });
+    }
+
+    fn register_plugin_functions(&mut self) {
+        // loadPlugin(path) adds the natives of a shared library to the
+        // script's globals. A relative path is looked up next to the script,
+        // then wherever the system looks for libraries.
+        self.define_native_with_interpreter("loadPlugin", 1, 1, |interpreter, args| {
+            let Value::String(path) = &args[0] else {
+                return Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(0),
+                ));
+            };
+            let globals = globals(interpreter);
+            let mut globals = globals.lock().unwrap();
+            let path = beside_script(&globals, path);
+            plugin::load(&path, &mut globals).map_err(|message| {
+                InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, message))
+            })?;
+            Ok(Value::Nil)
+        });
+    }
+}
+
+// Answers a single request on an accepted `httpServe` connection
+async fn serve_http_connection(
+    socket: TcpStream,
+    runtime: tokio::runtime::Handle,
+    environment: Arc<std::sync::Mutex<Environment>>,
+    handler: Value,
+) {
+    let mut socket = tokio::io::BufReader::new(socket);
+    let response = match http::read_request(&mut socket).await {
+        Ok(Some(request)) => {
+            match Interpreter::call_detached(runtime, environment, handler, vec![request]) {
+                Ok(response) => http::response_bytes(&response),
+                Err(error) => {
+                    report_callback_error(Err(error));
+                    http::status_response(500)
+                }
+            }
+        }
+        Ok(None) | Err(http::RequestError::Io(_)) => return,
+        Err(http::RequestError::Status(status)) => http::status_response(status),
+    };
+    let socket = socket.get_mut();
+    let _ = socket.write_all(&response).await;
+    let _ = socket.shutdown().await;
+}
+
+enum SqlParams {
+    Positional(Vec<rusqlite::types::Value>),
+    Named(Vec<(String, rusqlite::types::Value)>),
+}
+
+// Runs a statement against an open database with args [db, sql, params?]
+fn with_database(
+    args: &[Value],
+    operation: impl FnOnce(&rusqlite::Connection, &str, SqlParams) -> rusqlite::Result<Value>,
+) -> InterpreterResult<Value> {
+    let db = match &args[0] {
+        Value::Database(db) => db,
+        _ => return Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(0),
+        )),
+    };
+    let sql = match &args[1] {
+        Value::String(sql) => sql,
+        _ => return Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(1),
+        )),
+    };
+    let params = match args.get(2) {
+        None | Some(Value::Nil) => SqlParams::Positional(Vec::new()),
+        Some(Value::Array(values)) => SqlParams::Positional(
+            values.iter().map(to_sql).collect::<Option<_>>().ok_or_else(|| {
+                InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(2))
+            })?,
+        ),
+        Some(Value::Dictionary(values)) => SqlParams::Named(
+            values
+                .iter()
+                .map(|(name, value)| Some((format!(":{}", name), to_sql(value)?)))
+                .collect::<Option<_>>()
+                .ok_or_else(|| {
+                    InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(2))
+                })?,
+        ),
+        Some(_) => return Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(2),
+        )),
+    };
+    match db.lock().unwrap().as_ref() {
+        Some(db) => operation(db, sql, params).map_err(sqlite_error),
+        None => Err(InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+            0,
+            "Database is closed".to_string(),
+        ))),
+    }
+}
+
+fn named_params(values: &[(String, rusqlite::types::Value)]) -> Vec<(&str, &dyn rusqlite::ToSql)> {
+    values.iter().map(|(name, value)| (name.as_str(), value as &dyn rusqlite::ToSql)).collect()
+}
+
+// Whole numbers are stored as integers so they work as row ids
+fn to_sql(value: &Value) -> Option<rusqlite::types::Value> {
+    use rusqlite::types::Value as Sql;
+    Some(match value {
+        Value::Nil => Sql::Null,
+        Value::Number(n) if n.fract() == 0.0 && n.abs() < 9e15 => Sql::Integer(*n as i64),
+        Value::Number(n) => Sql::Real(*n),
+        Value::String(s) => Sql::Text(s.to_string()),
+        Value::Boolean(b) => Sql::Integer(*b as i64),
+        _ => return None,
+    })
+}
+
+// Blobs come back as arrays of byte numbers
+fn from_sql(value: rusqlite::types::ValueRef) -> Value {
+    use rusqlite::types::ValueRef;
+    match value {
+        ValueRef::Null => Value::Nil,
+        ValueRef::Integer(n) => Value::Number(n as f64),
+        ValueRef::Real(n) => Value::Number(n),
+        ValueRef::Text(text) => Value::String(String::from_utf8_lossy(text).into()),
+        ValueRef::Blob(bytes) => bytes_value(bytes),
+    }
+}
+
+type PostgresParam = Box<dyn tokio_postgres::types::ToSql + Sync + Send>;
+
+// Client, sql and parameter values from args [client, sql, params?]
+fn postgres_args(args: &[Value]) -> InterpreterResult<(Arc<tokio_postgres::Client>, String, Vec<Value>)> {
+    let client = match &args[0] {
+        Value::Postgres(client) => client.lock().unwrap().clone().ok_or_else(|| {
+            InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+                0,
+                "Database is closed".to_string(),
+            ))
+        })?,
+        _ => return Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(0),
+        )),
+    };
+    let sql = match &args[1] {
+        Value::String(sql) => sql.to_string(),
+        _ => return Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(1),
+        )),
+    };
+    let params = match args.get(2) {
+        None | Some(Value::Nil) => Vec::new(),
+        Some(Value::Array(values)) => values.clone(),
+        Some(_) => return Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(2),
+        )),
+    };
+    Ok((client, sql.to_string(), params))
+}
+
+// PostgreSQL checks parameter types strictly, so numbers are converted to
+// whatever type the statement expects
+fn postgres_params(
+    values: &[Value],
+    types: &[tokio_postgres::types::Type],
+) -> InterpreterResult<Vec<PostgresParam>> {
+    use tokio_postgres::types::Type;
+    if values.len() != types.len() {
+        return Err(InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+            0,
+            format!("PostgreSQL: expected {} parameters, got {}", types.len(), values.len()),
+        )));
+    }
+    values
+        .iter()
+        .zip(types)
+        .map(|(value, ty)| {
+            let param: PostgresParam = match (value, ty) {
+                (Value::Nil, _) => match *ty {
+                    Type::BOOL => Box::new(None::<bool>),
+                    Type::INT2 => Box::new(None::<i16>),
+                    Type::INT4 => Box::new(None::<i32>),
+                    Type::INT8 => Box::new(None::<i64>),
+                    Type::FLOAT4 => Box::new(None::<f32>),
+                    Type::FLOAT8 => Box::new(None::<f64>),
+                    Type::BYTEA => Box::new(None::<Vec<u8>>),
+                    _ => Box::new(None::<String>),
+                },
+                (Value::Number(n), &Type::INT2) => Box::new(*n as i16),
+                (Value::Number(n), &Type::INT4) => Box::new(*n as i32),
+                (Value::Number(n), &Type::INT8) => Box::new(*n as i64),
+                (Value::Number(n), &Type::FLOAT4) => Box::new(*n as f32),
+                (Value::Number(n), &Type::FLOAT8) => Box::new(*n),
+                (Value::Boolean(b), &Type::BOOL) => Box::new(*b),
+                (Value::Array(_), &Type::BYTEA) => Box::new(bytes_arg(std::slice::from_ref(value), 0)?),
+                (Value::String(s), _) => Box::new(s.to_string()),
+                _ => {
+                    return Err(InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+                        0,
+                        format!("PostgreSQL: cannot pass {} as {}", value.get_type(), ty),
+                    )))
+                }
+            };
+            Ok(param)
+        })
+        .collect()
+}
+
+fn postgres_refs(params: &[PostgresParam]) -> Vec<&(dyn tokio_postgres::types::ToSql + Sync)> {
+    params.iter().map(|param| param.as_ref() as &(dyn tokio_postgres::types::ToSql + Sync)).collect()
+}
+
+fn from_postgres(row: &tokio_postgres::Row, index: usize) -> InterpreterResult<Value> {
+    use tokio_postgres::types::Type;
+    let column = &row.columns()[index];
+    let value = match *column.type_() {
+        Type::BOOL => row.try_get::<_, Option<bool>>(index).map(|v| v.map(Value::Boolean)),
+        Type::INT2 => row.try_get::<_, Option<i16>>(index).map(|v| v.map(|n| Value::Number(n as f64))),
+        Type::INT4 => row.try_get::<_, Option<i32>>(index).map(|v| v.map(|n| Value::Number(n as f64))),
+        Type::INT8 => row.try_get::<_, Option<i64>>(index).map(|v| v.map(|n| Value::Number(n as f64))),
+        Type::OID => row.try_get::<_, Option<u32>>(index).map(|v| v.map(|n| Value::Number(n as f64))),
+        Type::FLOAT4 => row.try_get::<_, Option<f32>>(index).map(|v| v.map(|n| Value::Number(n as f64))),
+        Type::FLOAT8 => row.try_get::<_, Option<f64>>(index).map(|v| v.map(Value::Number)),
+        Type::BYTEA => row.try_get::<_, Option<Vec<u8>>>(index).map(|v| v.map(|b| bytes_value(&b))),
+        _ => match row.try_get::<_, Option<String>>(index) {
+            Ok(v) => Ok(v.map(|s| Value::String(s.into()))),
+            Err(_) => {
+                return Err(InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+                    0,
+                    format!(
+                        "PostgreSQL: column '{}' has unsupported type {}, cast it to text",
+                        column.name(),
+                        column.type_()
+                    ),
+                )))
+            }
+        },
+    };
+    Ok(value.map_err(postgres_error)?.unwrap_or(Value::Nil))
+}
+
+fn postgres_error(e: tokio_postgres::Error) -> InterpreterError {
+    // Errors from the server only say "db error" unless the details are asked for
+    let message = match e.as_db_error() {
+        Some(db_error) => db_error.to_string(),
+        None => e.to_string(),
+    };
+    InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, format!("PostgreSQL: {}", message)))
+}
+
+fn sqlite_error(e: rusqlite::Error) -> InterpreterError {
+    InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, format!("SQLite: {}", e)))
+}
+
+fn zip_error(e: impl std::fmt::Display) -> InterpreterError {
+    InterpreterError::runtime_error(RuntimeErrorKind::IoError(e.to_string()))
+}
+
+// Adds a file, or a directory and everything below it, to a zip archive
+fn zip_add_path(
+    writer: &mut zip::ZipWriter<File>,
+    source: &std::path::Path,
+    name: &str,
+) -> InterpreterResult<()> {
+    let options = zip::write::SimpleFileOptions::default();
+    if source.is_dir() {
+        let name = name.trim_end_matches('/');
+        if !name.is_empty() {
+            writer.add_directory(name, options).map_err(zip_error)?;
+        }
+        for entry in std::fs::read_dir(source).map_err(zip_error)? {
+            let entry = entry.map_err(zip_error)?;
+            let child = entry.file_name().to_string_lossy().into_owned();
+            let child_name = if name.is_empty() { child } else { format!("{}/{}", name, child) };
+            zip_add_path(writer, &entry.path(), &child_name)?;
+        }
+    } else {
+        let mut file = File::open(source).map_err(zip_error)?;
+        writer.start_file(name, options).map_err(zip_error)?;
+        std::io::copy(&mut file, writer).map_err(zip_error)?;
+    }
+    Ok(())
+}
+
+// Passes a topic's values on to a subscriber's receiver until the topic is
+// closed or nothing holds the receiver any more
+async fn forward_topic(
+    mut subscription: tokio::sync::broadcast::Receiver<Value>,
+    sender: tokio::sync::mpsc::Sender<Value>,
+) {
+    use tokio::sync::broadcast::error::RecvError;
+    loop {
+        match subscription.recv().await {
+            Ok(value) => {
+                if sender.send(value).await.is_err() {
+                    break;
+                }
+            }
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+// Reads a socket line by line into a channel until the socket or the channel closes
+async fn send_lines(socket: Arc<Mutex<Connection>>, sender: tokio::sync::mpsc::Sender<Value>) {
+    loop {
+        let line = socket.lock().await.read_line().await;
+        let line = match line {
+            Ok(Some(line)) => String::from_utf8_lossy(&line).into_owned(),
+            Ok(None) | Err(_) => break,
+        };
+        if sender.send(Value::String(line.into())).await.is_err() {
+            break;
+        }
+    }
+}
+
+// Reading for jsonStream happens a chunk at a time; only the value being
+// read is held
+const JSON_CHUNK_SIZE: usize = 64 * 1024;
+
+async fn stream_json(socket: Arc<Mutex<Connection>>, sender: tokio::sync::mpsc::Sender<Value>) {
+    let mut splitter = json::JsonSplitter::new();
+    let mut chunk = vec![0; JSON_CHUNK_SIZE];
+    loop {
+        let read = socket.lock().await.read(&mut chunk).await;
+        let (items, ended) = json_stream_items(&mut splitter, read.map(|n| &chunk[..n]));
+        for item in items {
+            if sender.send(item).await.is_err() {
+                return;
+            }
+        }
+        if ended {
+            return;
+        }
+    }
+}
+
+// The same for a file, read on a blocking thread
+fn stream_json_file(file: Arc<std::sync::Mutex<Option<BufReader<File>>>>, sender: tokio::sync::mpsc::Sender<Value>) {
+    let mut splitter = json::JsonSplitter::new();
+    let mut chunk = vec![0; JSON_CHUNK_SIZE];
+    loop {
+        let read = match file.lock().unwrap().as_mut() {
+            Some(file) => file.read(&mut chunk),
+            None => Err(std::io::Error::other("File is closed")),
+        };
+        let (items, ended) = json_stream_items(&mut splitter, read.map(|n| &chunk[..n]));
+        for item in items {
+            if sender.blocking_send(item).is_err() {
+                return;
+            }
+        }
+        if ended {
+            return;
+        }
+    }
+}
+
+// What jsonStream sends for a chunk read, empty at the end of the text, and
+// whether that is the last of it
+fn json_stream_items(splitter: &mut json::JsonSplitter, chunk: std::io::Result<&[u8]>) -> (Vec<Value>, bool) {
+    let mut items = Vec::new();
+    let failure = match chunk {
+        Ok([]) => match splitter.finish() {
+            Ok(value) => {
+                items.extend(value);
+                return (items, true);
+            }
+            Err(message) => RuntimeErrorKind::RuntimeError(0, message),
+        },
+        Ok(chunk) => match splitter.feed(chunk, &mut items) {
+            Ok(()) => return (items, false),
+            Err(message) => RuntimeErrorKind::RuntimeError(0, message),
+        },
+        Err(e) => RuntimeErrorKind::IoError(e.to_string()),
+    };
+    let error = InterpreterError::runtime_error(failure);
+    items.push(Value::create_promise(Box::pin(async move { Err(error) })));
+    (items, true)
+}
+
+// Starts the promise at index, or a call of the async function there with
+// the arguments after it, in the background; the promise can be awaited
+// later or ignored
+fn spawn_task(interpreter: &mut Interpreter, args: &[Value], index: usize) -> InterpreterResult<Value> {
+    let promise = match &args[index] {
+        Value::Promise(promise) if args.len() == index + 1 => promise.clone(),
+        Value::AsyncFunction(..) => {
+            match interpreter.call_value(args[index].clone(), args[index + 1..].to_vec())? {
+                Value::Promise(promise) => promise,
+                value => return Ok(value),
+            }
+        }
+        _ => return Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(index),
+        )),
+    };
+    let task = promise.clone();
+    interpreter.runtime.spawn(async move {
+        let _ = task.settle().await;
+    });
+    Ok(Value::Promise(promise))
+}
+
+// Settles every task of a wait group, those added while it waits included,
+// with their results in the order they were added. The first to fail, or
+// running out of time, cancels the rest.
+async fn wait_group(group: Arc<std::sync::Mutex<Vec<Arc<Promise>>>>, limit: Option<Duration>) -> InterpreterResult<Value> {
+    let cancel_all = || {
+        for task in group.lock().unwrap().iter() {
+            task.cancel();
+        }
+    };
+    let settle_all = async {
+        let mut results = Vec::new();
+        loop {
+            let added: Vec<_> = group.lock().unwrap()[results.len()..].to_vec();
+            if added.is_empty() {
+                return Ok(Value::Array(results));
+            }
+            let start = results.len();
+            results.resize(start + added.len(), Value::Nil);
+            let mut tasks = JoinSet::new();
+            for (index, task) in added.into_iter().enumerate() {
+                tasks.spawn(async move { (start + index, task.settle().await) });
+            }
+            while let Some(joined) = tasks.join_next().await {
+                match joined.map_err(task_error)? {
+                    (index, Ok(value)) => results[index] = value,
+                    (_, Err(error)) => return Err(error),
+                }
+            }
+        }
+    };
+    let result = match limit {
+        Some(limit) => tokio::time::timeout(limit, settle_all).await.unwrap_or_else(|_| {
+            Err(InterpreterError::runtime_error(RuntimeErrorKind::Timeout(0, limit.as_secs_f64())))
+        }),
+        None => settle_all.await,
+    };
+    if result.is_err() {
+        cancel_all();
+    }
+    result
+}
+
+// A whole number for a counter
+fn counter_number(args: &[Value], index: usize) -> InterpreterResult<i64> {
+    match args[index] {
+        Value::Number(n) if n.fract() == 0.0 && n.abs() <= 2f64.powi(53) => Ok(n as i64),
+        _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(index))),
+    }
+}
+
+fn timer_args(args: &[Value]) -> InterpreterResult<(Value, Duration)> {
+    let callback = callback_arg(args, 0)?;
+    let period = match args[1] {
+        Value::Number(ms) => seconds_duration(ms / 1000.0),
+        _ => None,
+    };
+    match period {
+        Some(period) => Ok((callback, period)),
+        None => Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(1),
+        )),
+    }
+}
+
+fn process_arg(args: &[Value]) -> InterpreterResult<Arc<Process>> {
+    match &args[0] {
+        Value::Process(process) => Ok(process.clone()),
+        _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+    }
+}
+
+fn callback_arg(args: &[Value], index: usize) -> InterpreterResult<Value> {
+    match &args[index] {
+        Value::Function(..) | Value::AsyncFunction(..) | Value::NativeFunction(_) => Ok(args[index].clone()),
+        _ => Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(index),
+        )),
+    }
+}
+
+// The attempts, first delay in seconds and backoff factor retry is given,
+// each defaulting when left out
+fn retry_options(options: Option<&Value>) -> InterpreterResult<(u32, f64, f64)> {
+    let options = match options {
+        None | Some(Value::Nil) => return Ok((3, 0.1, 2.0)),
+        Some(Value::Dictionary(options)) => options,
+        Some(_) => return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1))),
+    };
+    let number = |key: &str, default: f64, min: f64| match options.get(key) {
+        None | Some(Value::Nil) => Ok(default),
+        Some(Value::Number(n)) if *n >= min && n.is_finite() => Ok(*n),
+        Some(_) => Err(InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+            0,
+            format!("retry option \"{}\" must be a finite number of at least {}", key, min),
+        ))),
+    };
+    Ok((number("attempts", 3.0, 1.0)? as u32, number("delay", 0.1, 0.0)?, number("backoff", 2.0, 1.0)?))
+}
+
+// Nothing awaits a timer callback, so its errors are reported like uncaught ones
+fn report_callback_error(result: InterpreterResult<Value>) {
+    if let Err(error) = result {
+        eprintln!("{}", error);
+    }
+}
+
+fn task_error(e: tokio::task::JoinError) -> InterpreterError {
+    InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, format!("Task failed: {}", e)))
+}
+
+// Runs an io operation on an open file handle
+fn with_open_file(
+    value: &Value,
+    operation: impl FnOnce(&mut BufReader<File>) -> std::io::Result<Value>,
+) -> InterpreterResult<Value> {
+    let file = match value {
+        Value::File(file) => file,
+        _ => return Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(0),
+        )),
+    };
+    let mut file = file.lock().unwrap();
+    match file.as_mut() {
+        Some(file) => operation(file)
+            .map_err(|e| InterpreterError::runtime_error(RuntimeErrorKind::IoError(e.to_string()))),
+        None => Err(InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+            0,
+            "File is closed".to_string(),
+        ))),
+    }
+}
+
+// The connection of a socket for a native that answers at once; only a read
+// or write another task has in progress on it is waited for
+fn lock_socket<'a>(interpreter: &Interpreter, socket: &'a Mutex<Connection>) -> tokio::sync::MutexGuard<'a, Connection> {
+    socket
+        .try_lock()
+        .unwrap_or_else(|_| tokio::task::block_in_place(|| interpreter.runtime.block_on(socket.lock())))
+}
+
+fn socket_arg(args: &[Value], index: usize) -> InterpreterResult<Arc<Mutex<Connection>>> {
+    match &args[index] {
+        Value::Socket(socket) | Value::TlsSocket(socket) | Value::Pipe(socket) => Ok(socket.clone()),
+        _ => Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(index),
+        )),
+    }
+}
+
+fn port_arg(args: &[Value], index: usize) -> InterpreterResult<u16> {
+    match args[index] {
+        Value::Number(n) if (0.0..=65535.0).contains(&n) && n.fract() == 0.0 => Ok(n as u16),
+        _ => Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(index),
+        )),
+    }
+}
+
+// Optional address to bind a server to, 127.0.0.1 by default
+fn host_arg(args: &[Value], index: usize) -> InterpreterResult<String> {
+    match args.get(index) {
+        None | Some(Value::Nil) => Ok("127.0.0.1".to_string()),
+        Some(Value::String(host)) => Ok(host.to_string()),
+        _ => Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(index),
+        )),
+    }
+}
+
+// Optional timeout in ms; nil, 0 or a missing argument means no timeout
+fn timeout_arg(args: &[Value], index: usize) -> InterpreterResult<Option<Duration>> {
+    match args.get(index) {
+        None | Some(Value::Nil) => Ok(None),
+        Some(Value::Number(ms)) if *ms == 0.0 => Ok(None),
+        Some(Value::Number(ms)) => seconds_duration(ms / 1000.0).map(Some).ok_or_else(|| {
+            InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(index))
+        }),
+        _ => Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(index),
+        )),
+    }
+}
+
+// A number of seconds as a Duration; None for NaN, negative numbers, and
+// waits too long for a timer to end, infinity among them
+fn seconds_duration(seconds: f64) -> Option<Duration> {
+    Duration::try_from_secs_f64(seconds)
+        .ok()
+        .filter(|duration| tokio::time::Instant::now().checked_add(*duration).is_some())
+}
+
+// The timeout and proxy of connect and connectTLS, from a timeout in ms or
+// dict{"timeout": ms, "proxy": url}. Without a proxy in the dictionary the one
+// in ALPHA_HTTP_PROXY is used; "proxy": false connects directly.
+fn connect_options(args: &[Value], index: usize) -> InterpreterResult<(Option<Duration>, Option<Proxy>)> {
+    let Some(Value::Dictionary(options)) = args.get(index) else {
+        return Ok((timeout_arg(args, index)?, Proxy::from_env().map_err(socket_error)?));
+    };
+    let timeout = match options.get("timeout") {
+        Some(timeout) => timeout_arg(std::slice::from_ref(timeout), 0).map_err(|_| {
+            InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(index))
+        })?,
+        None => None,
+    };
+    let proxy = match options.get("proxy") {
+        None | Some(Value::Nil) => Proxy::from_env(),
+        Some(Value::Boolean(false)) => Ok(None),
+        Some(Value::String(url)) => Proxy::parse(url).map(Some),
+        Some(_) => return Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(index),
+        )),
+    };
+    Ok((timeout, proxy.map_err(socket_error)?))
+}
+
+fn bool_arg(args: &[Value], index: usize) -> InterpreterResult<bool> {
+    match args[index] {
+        Value::Boolean(b) => Ok(b),
+        _ => Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(index),
+        )),
+    }
+}
+
+fn result_arg(args: &[Value], index: usize) -> InterpreterResult<Result<Value, Value>> {
+    args[index].as_result().ok_or_else(|| {
+        InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(index))
+    })
+}
+
+// Nil when the check passes; otherwise an AssertionFailed error with what
+// went wrong, after the message given for the assertion if any
+fn check(passed: bool, message: Option<&Value>, failure: impl FnOnce() -> String) -> InterpreterResult<Value> {
+    if passed {
+        return Ok(Value::Nil);
+    }
+    let failure = match message {
+        None | Some(Value::Nil) => failure(),
+        Some(Value::String(message)) => format!("{}: {}", message, failure()),
+        Some(message) => format!("{}: {}", shown(message), failure()),
+    };
+    Err(InterpreterError::runtime_error(RuntimeErrorKind::AssertionFailed(failure)))
+}
+
+// A value in an assertion's message, on one line with strings quoted so
+// "1" and 1 tell apart
+fn shown(value: &Value) -> String {
+    inspect::inspect(value, 6, usize::MAX)
+}
+
+fn callable_arg(args: &[Value], index: usize) -> InterpreterResult<Value> {
+    match &args[index] {
+        Value::Function(..) | Value::AsyncFunction(..) | Value::NativeFunction(_) => Ok(args[index].clone()),
+        _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(index))),
+    }
+}
+
+// The outermost environment of the running script
+fn globals(interpreter: &Interpreter) -> Arc<std::sync::Mutex<Environment>> {
+    let mut globals = interpreter.environment();
+    loop {
+        let enclosing = globals.lock().unwrap().get_enclosing();
+        match enclosing {
+            Some(enclosing) => globals = enclosing,
+            None => return globals,
+        }
+    }
+}
+
+// A relative path beside the script when there is such a file there, and
+// otherwise as it is, from the working directory
+fn beside_script(globals: &Environment, path: &str) -> PathBuf {
+    let beside_script = globals.base_path.join(path);
+    if beside_script.is_file() { beside_script } else { PathBuf::from(path) }
+}
+
+// A regex value, or a string compiled as a pattern without flags
+fn pattern_arg(args: &[Value], index: usize) -> InterpreterResult<Arc<pattern::Pattern>> {
+    match &args[index] {
+        Value::Regex(pattern) => Ok(Arc::clone(pattern)),
+        Value::String(source) => pattern::compile(source, "")
+            .map_err(|message| InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, message))),
+        _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(index))),
+    }
+}
+
+fn text_arg(args: &[Value], index: usize) -> InterpreterResult<&str> {
+    match &args[index] {
+        Value::String(text) => Ok(text),
+        _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(index))),
+    }
+}
+
+// What regexMatch gives for one match
+fn match_value(pattern: &pattern::Pattern, text: &str, captures: &regex::Captures) -> Value {
+    let group = |found: Option<regex::Match>| found.map_or(Value::Nil, |found| Value::String(found.as_str().into()));
+    let whole = captures.get(0).unwrap();
+    let named = pattern
+        .regex
+        .capture_names()
+        .flatten()
+        .map(|name| (name.to_string(), group(captures.name(name))))
+        .collect();
+    Value::Dictionary(HashMap::from([
+        ("match".to_string(), Value::String(whole.as_str().into())),
+        ("index".to_string(), Value::Number(pattern::char_index(text, whole.start()) as f64)),
+        ("groups".to_string(), Value::Array(captures.iter().skip(1).map(group).collect())),
+        ("named".to_string(), Value::Dictionary(named)),
+    ]))
+}
+
+// The routes of a router made by router()
+fn router_routes(router: &Value) -> InterpreterResult<&Vec<Value>> {
+    match router {
+        Value::Dictionary(router) => match router.get("routes") {
+            Some(Value::Array(routes)) => Ok(routes),
+            _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+        },
+        _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+    }
+}
+
+// The decoded body of a response to httpGetJson or httpPostJson
+fn json_response(url: &str, (status, _, body): (u16, HashMap<String, String>, Vec<u8>)) -> InterpreterResult<Value> {
+    let text = String::from_utf8_lossy(&body);
+    let decoded = if text.trim().is_empty() { Ok(Value::Nil) } else { json::parse(&text) };
+    if !(200..300).contains(&status) {
+        let error = HashMap::from([
+            ("type".to_string(), Value::String("HttpError".into())),
+            ("message".to_string(), Value::String(format!("{} answered with status {}", url, status).into())),
+            ("status".to_string(), Value::Number(status as f64)),
+            ("body".to_string(), decoded.unwrap_or_else(|_| Value::String(text.into()))),
+        ]);
+        return Err(InterpreterError::runtime_error(RuntimeErrorKind::Thrown(0, Value::Dictionary(error))));
+    }
+    decoded.map_err(|message| InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, message)))
+}
+
+fn http_message_error(kind: &str, message: String) -> InterpreterError {
+    InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, format!("Invalid HTTP {}: {}", kind, message)))
+}
+
+fn socket_error(e: std::io::Error) -> InterpreterError {
+    InterpreterError::runtime_error(RuntimeErrorKind::IoError(e.to_string()))
+}
+
+// The bytes of a UTF-8 character the end of the data cuts short, and how
+// many more it needs; (0, 0) when the data ends with a whole character or
+// with bytes that are not UTF-8 at all
+fn cut_character(data: &[u8]) -> (usize, usize) {
+    for cut in 1..=data.len().min(3) {
+        let byte = data[data.len() - cut];
+        if byte & 0xC0 == 0x80 {
+            continue;
+        }
+        let length = match byte {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => return (0, 0),
+        };
+        return if cut < length { (cut, length - cut) } else { (0, 0) };
+    }
+    (0, 0)
+}
+
+// Reads a string or an array of byte numbers as raw bytes
+fn bytes_arg(args: &[Value], index: usize) -> InterpreterResult<Vec<u8>> {
+    match &args[index] {
+        Value::String(s) => Ok(s.as_bytes().to_vec()),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| match value {
+                Value::Number(n) if (0.0..=255.0).contains(n) && n.fract() == 0.0 => Ok(*n as u8),
+                _ => Err(InterpreterError::runtime_error(
+                    RuntimeErrorKind::InvalidArgumentType(index),
+                )),
+            })
+            .collect(),
+        _ => Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(index),
+        )),
+    }
+}
+
+fn bytes_value(bytes: &[u8]) -> Value {
+    Value::Array(bytes.iter().map(|&b| Value::Number(b as f64)).collect())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok()).collect()
+}
+
+// Reads the optional `dict{"header": bool, "delimiter": ","}` argument of the csv natives
+fn csv_options(options: Option<&Value>) -> InterpreterResult<(bool, u8)> {
+    let options = match options {
+        None => return Ok((false, b',')),
+        Some(Value::Dictionary(options)) => options,
+        Some(_) => return Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(1),
+        )),
+    };
+    let header = match options.get("header") {
+        None => false,
+        Some(Value::Boolean(header)) => *header,
+        Some(_) => return Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(1),
+        )),
+    };
+    let delimiter = match options.get("delimiter") {
+        None => b',',
+        Some(Value::String(d)) if d.len() == 1 => d.as_bytes()[0],
+        Some(_) => return Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(1),
+        )),
+    };
+    Ok((header, delimiter))
+}
+
+fn csv_error(e: csv::Error) -> InterpreterError {
+    InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, format!("CSV error: {}", e)))
+}
+
+fn write_stdout(interpreter: &Interpreter, text: &str) -> InterpreterResult<Value> {
+    interpreter.io().write_stdout(text).map_err(|e| {
+        InterpreterError::runtime_error(RuntimeErrorKind::IoError(e.to_string()))
+    })?;
+    Ok(Value::Nil)
+}
+
+fn write_stderr(interpreter: &Interpreter, text: &str) -> InterpreterResult<Value> {
+    interpreter.io().write_stderr(text).map_err(|e| {
+        InterpreterError::runtime_error(RuntimeErrorKind::IoError(e.to_string()))
+    })?;
+    Ok(Value::Nil)
+}
+
+// The depth and width limits from the options of inspect and pprint
+fn inspect_options(args: &[Value]) -> InterpreterResult<(usize, usize)> {
+    let options = match args.get(1) {
+        None | Some(Value::Nil) => return Ok((6, 80)),
+        Some(Value::Dictionary(options)) => options,
+        Some(_) => return Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1))),
+    };
+    let limit = |name: &str, default: usize| match options.get(name) {
+        None => Ok(default),
+        Some(Value::Number(n)) if *n >= 0.0 => Ok(*n as usize),
+        Some(_) => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(1))),
+    };
+    Ok((limit("depth", 6)?, limit("width", 80)?))
+}
+
+// The text printed by the print natives: a single value as is, otherwise
+// a format string followed by its arguments. Instances among the arguments
+// print as the text their _str() gives.
+fn print_text(interpreter: &mut Interpreter, args: &[Value]) -> InterpreterResult<String> {
+    let args = args
+        .iter()
+        .map(|arg| match arg {
+            Value::Instance(..) => Ok(interpreter.str_method_text(arg)?.map_or_else(|| arg.clone(), |text| Value::String(text.into()))),
+            _ => Ok(arg.clone()),
+        })
+        .collect::<InterpreterResult<Vec<_>>>()?;
+    if args.len() == 1 {
+        Ok(format!("{}", args[0]))
+    } else {
+        format_args_string(&args)
+    }
+}
+
+// Formats `args[1..]` with the format string in `args[0]`
+fn format_args_string(args: &[Value]) -> InterpreterResult<String> {
+    match &args[0] {
+        Value::String(fmt) => format_values(fmt, &args[1..]),
+        _ => Err(InterpreterError::runtime_error(
+            RuntimeErrorKind::InvalidArgumentType(0),
+        )),
+    }
+}
+
+// printf-style formatting: `%[position$][flags][width][.precision]conversion`
+// with flags `-` (left align), `0` (zero pad) and `+` (always sign), and
+// conversions s, d, f, e, x, X, o, b. `%%` is a literal percent sign.
+// Precision goes up to 100 digits, as for toFixed, and width to 1000.
+const MAX_FORMAT_PRECISION: usize = 100;
+const MAX_FORMAT_WIDTH: usize = 1000;
+
+fn format_values(fmt: &str, args: &[Value]) -> InterpreterResult<String> {
+    let format_error = |message: String| {
+        InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(0, message))
+    };
+    let mut out = String::new();
+    let mut chars = fmt.chars().peekable();
+    let mut next_arg = 0;
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            out.push('%');
+            continue;
+        }
+        let mut spec = String::new();
+        let conversion = loop {
+            match chars.next() {
+                Some(c) if c.is_ascii_alphabetic() => break c,
+                Some(c) => spec.push(c),
+                None => return Err(format_error(format!("Unterminated placeholder '%{}'", spec))),
+            }
+        };
+        let spec = match spec.split_once('$') {
+            Some((position, rest)) => {
+                let position: usize = position
+                    .parse()
+                    .ok()
+                    .filter(|p| *p > 0)
+                    .ok_or_else(|| format_error(format!("Invalid placeholder position '{}'", position)))?;
+                next_arg = position - 1;
+                rest.to_string()
+            }
+            None => spec,
+        };
+        let flags: String = spec.chars().take_while(|c| "-+0".contains(*c)).collect();
+        let spec = &spec[flags.len()..];
+        let (width, precision) = match spec.split_once('.') {
+            Some((width, precision)) => (width, Some(precision)),
+            None => (spec, None),
+        };
+        let width: usize = if width.is_empty() {
+            0
+        } else {
+            width
+                .parse()
+                .ok()
+                .filter(|width| *width <= MAX_FORMAT_WIDTH)
+                .ok_or_else(|| format_error(format!("Invalid width '{}'", width)))?
+        };
+        let precision: Option<usize> = match precision {
+            Some(p) => Some(
+                p.parse()
+                    .ok()
+                    .filter(|p| *p <= MAX_FORMAT_PRECISION)
+                    .ok_or_else(|| format_error(format!("Invalid precision '{}'", p)))?,
+            ),
+            None => None,
+        };
+        let index = next_arg;
+        next_arg += 1;
+        let value = args
+            .get(index)
+            .ok_or_else(|| format_error(format!("Missing argument {} for format", index + 1)))?;
+        let number = || match value {
+            Value::Number(n) => Ok(*n),
+            _ => Err(InterpreterError::runtime_error(
+                RuntimeErrorKind::InvalidArgumentType(index + 1),
+            )),
+        };
+        let mut text = match conversion {
+            's' => {
+                let s = value.to_string();
+                match precision {
+                    Some(p) => s.chars().take(p).collect(),
+                    None => s,
+                }
+            }
+            'd' => format!("{}", number()?.trunc() as i64),
+            'f' => format!("{:.*}", precision.unwrap_or(6), number()?),
+            'e' => c_exponent(format!("{:.*e}", precision.unwrap_or(6), number()?)),
+            'x' => format!("{:x}", number()? as i64),
+            'X' => format!("{:X}", number()? as i64),
+            'o' => format!("{:o}", number()? as i64),
+            'b' => format!("{:b}", number()? as i64),
+            other => return Err(format_error(format!("Unknown format conversion '%{}'", other))),
+        };
+        let numeric = conversion != 's';
+        if numeric && flags.contains('+') && !text.starts_with('-') {
+            text.insert(0, '+');
+        }
+        let len = text.chars().count();
+        if len < width {
+            let padding = width - len;
+            if flags.contains('-') {
+                text.push_str(&" ".repeat(padding));
+            } else if numeric && flags.contains('0') {
+                let sign = if text.starts_with(['-', '+']) { 1 } else { 0 };
+                text.insert_str(sign, &"0".repeat(padding));
+            } else {
+                text.insert_str(0, &" ".repeat(padding));
+            }
+        }
+        out.push_str(&text);
+    }
+    Ok(out)
+}
+
+// Rust writes 1.5e3 where C's %e writes 1.5e+03: a signed exponent of at
+// least two digits
+fn c_exponent(text: String) -> String {
+    let Some((mantissa, exponent)) = text.split_once('e') else {
+        return text;
+    };
+    let (sign, digits) = match exponent.strip_prefix('-') {
+        Some(digits) => ('-', digits),
+        None => ('+', exponent),
+    };
+    format!("{}e{}{:0>2}", mantissa, sign, digits)
+}
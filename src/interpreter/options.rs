@@ -0,0 +1,68 @@
+// What scripts run by an interpreter are allowed to do. Everything is
+// allowed by default; the natives of a capability that is turned off stay
+// defined but fail when called, so a script learns why rather than finding
+// the name undefined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InterpreterOptions {
+    // Reading and writing files, archives and SQLite databases
+    pub filesystem: bool,
+    // Opening connections and listening, including mail and PostgreSQL
+    pub network: bool,
+    // Ending the process or setting its exit status
+    pub process_exit: bool,
+    // Starting other programs
+    pub subprocess: bool,
+    // Loading native plugins, which run with the interpreter's own rights
+    pub plugins: bool,
+    // How deeply function calls may nest; unlimited when None
+    pub max_call_depth: Option<usize>,
+    // Whether + and == fail on operands of different types instead of
+    // converting them or comparing them as unequal
+    pub strict: bool,
+    // Whether an imported module only runs when a name it declares is
+    // first used
+    pub lazy_imports: bool,
+}
+
+impl Default for InterpreterOptions {
+    fn default() -> Self {
+        InterpreterOptions {
+            filesystem: true,
+            network: true,
+            process_exit: true,
+            subprocess: true,
+            plugins: true,
+            max_call_depth: None,
+            strict: false,
+            lazy_imports: false,
+        }
+    }
+}
+
+impl InterpreterOptions {
+    // Nothing outside the interpreter itself is allowed
+    pub fn sandboxed() -> Self {
+        InterpreterOptions {
+            filesystem: false,
+            network: false,
+            process_exit: false,
+            subprocess: false,
+            plugins: false,
+            ..Self::default()
+        }
+    }
+}
+
+// The natives that give access to each capability. Natives working on
+// handles (files, sockets, connections) need no entry: without the native
+// that opens them there is nothing to pass them.
+pub(crate) const FILESYSTEM_NATIVES: &[&str] = &[
+    "readFile", "writeFile", "appendFile", "openFile", "zipCreate", "zipList", "zipExtract", "sqliteOpen",
+    "readFileAsync", "writeFileAsync", "listDirAsync", "glob", "loadMessages", "readFileBytes",
+];
+pub(crate) const NETWORK_NATIVES: &[&str] = &[
+    "listen", "connect", "connectTLS", "serve", "httpServe", "sendMail", "pgConnect", "httpGetJson", "httpPostJson",
+];
+pub(crate) const PROCESS_EXIT_NATIVES: &[&str] = &["exit", "exitCode"];
+pub(crate) const SUBPROCESS_NATIVES: &[&str] = &["spawnProcess"];
+pub(crate) const PLUGIN_NATIVES: &[&str] = &["loadPlugin"];
@@ -0,0 +1,68 @@
+use std::sync::{Arc, Mutex};
+
+use regex::{Regex, RegexBuilder};
+use rustc_hash::FxHashMap;
+
+// A compiled regular expression, from a /pattern/flags literal or regex(),
+// with the source and flags it was written with. The syntax is that of the
+// regex crate; the flags are
+//
+//   i   case insensitive
+//   m   ^ and $ match at the start and end of every line
+//   s   . matches newlines too
+//   x   whitespace and # comments in the pattern are ignored
+pub struct Pattern {
+    pub source: String,
+    pub flags: String,
+    pub regex: Regex,
+}
+
+pub const FLAGS: &str = "imsx";
+
+// Patterns compiled so far, by source and then flags, so a literal in a loop
+// or the same string passed to the regex natives again is compiled once.
+// Emptied when it fills rather than grow without end in a script building
+// patterns as it goes.
+static CACHE: Mutex<Option<FxHashMap<String, Vec<Arc<Pattern>>>>> = Mutex::new(None);
+
+const CACHE_SIZE: usize = 256;
+
+pub fn compile(source: &str, flags: &str) -> Result<Arc<Pattern>, String> {
+    let mut cache = CACHE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let cache = cache.get_or_insert_with(FxHashMap::default);
+    if let Some(pattern) = cache.get(source).and_then(|compiled| compiled.iter().find(|p| p.flags == flags)) {
+        return Ok(Arc::clone(pattern));
+    }
+    let mut builder = RegexBuilder::new(source);
+    for flag in flags.chars() {
+        match flag {
+            'i' => builder.case_insensitive(true),
+            'm' => builder.multi_line(true),
+            's' => builder.dot_matches_new_line(true),
+            'x' => builder.ignore_whitespace(true),
+            _ => return Err(format!("Unknown regex flag '{}'; the flags are {}", flag, FLAGS)),
+        };
+    }
+    let regex = builder.build().map_err(|e| match e {
+        regex::Error::Syntax(message) => format!("Invalid regex /{}/:\n{}", source, message),
+        e => format!("Invalid regex /{}/: {}", source, e),
+    })?;
+    let pattern = Arc::new(Pattern { source: source.to_string(), flags: flags.to_string(), regex });
+    if cache.len() >= CACHE_SIZE {
+        cache.clear();
+    }
+    cache.entry(source.to_string()).or_default().push(Arc::clone(&pattern));
+    Ok(pattern)
+}
+
+impl Pattern {
+    // As a literal would write it
+    pub fn literal(&self) -> String {
+        format!("/{}/{}", self.source, self.flags)
+    }
+}
+
+// Where a match starts, counted in characters as slice and len count them
+pub fn char_index(text: &str, byte: usize) -> usize {
+    text[..byte].chars().count()
+}
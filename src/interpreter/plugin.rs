@@ -0,0 +1,83 @@
+use std::path::Path;
+
+use super::enviroment::Environment;
+
+// Native plugins are shared libraries, usually a crate depending on alpha
+// and built as a cdylib, that add natives to the script loading them:
+//
+//     #[no_mangle]
+//     pub extern "C" fn alpha_plugin_abi_version() -> u32 {
+//         alpha::interpreter::plugin::ABI_VERSION
+//     }
+//
+//     #[no_mangle]
+//     pub extern "C" fn alpha_register_plugin(environment: &mut Environment) {
+//         environment.define_native("double", 1, |args| match args[0] {
+//             Value::Number(n) => Ok(Value::Number(n * 2.0)),
+//             _ => Err(InterpreterError::runtime_error(RuntimeErrorKind::InvalidArgumentType(0))),
+//         });
+//     }
+//
+// Environment is passed as a Rust type, so a plugin has to be built with
+// the same compiler and the same alpha as the interpreter loading it.
+// ABI_VERSION only catches plugins built against another alpha.
+pub const ABI_VERSION: u32 = 1;
+
+pub type AbiVersionFn = extern "C" fn() -> u32;
+pub type RegisterFn = extern "C" fn(&mut Environment);
+
+const ABI_VERSION_SYMBOL: &[u8] = b"alpha_plugin_abi_version\0";
+const REGISTER_SYMBOL: &[u8] = b"alpha_register_plugin\0";
+
+// Loads the library and lets it register its natives into the environment.
+// The library is never unloaded: the natives it registered run its code.
+#[cfg(unix)]
+pub fn load(path: &Path, environment: &mut Environment) -> Result<(), String> {
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+
+    let dl_error = || {
+        // dlerror describes the last failure of this thread, if any
+        let error = unsafe { libc::dlerror() };
+        if error.is_null() {
+            "unknown error".to_string()
+        } else {
+            unsafe { CStr::from_ptr(error) }.to_string_lossy().into_owned()
+        }
+    };
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| format!("Invalid plugin path: {}", path.display()))?;
+    let handle = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+    if handle.is_null() {
+        return Err(format!("Could not load plugin {}: {}", path.display(), dl_error()));
+    }
+    let symbol = |name: &[u8]| unsafe { libc::dlsym(handle, name.as_ptr().cast()) };
+    let version = symbol(ABI_VERSION_SYMBOL);
+    let register = symbol(REGISTER_SYMBOL);
+    if version.is_null() || register.is_null() {
+        unsafe { libc::dlclose(handle) };
+        return Err(format!(
+            "{} is not an alpha plugin: it does not export alpha_plugin_abi_version and alpha_register_plugin",
+            path.display()
+        ));
+    }
+    // The symbols have the types plugins are documented to export them with
+    let version = unsafe { std::mem::transmute::<*mut libc::c_void, AbiVersionFn>(version) }();
+    if version != ABI_VERSION {
+        unsafe { libc::dlclose(handle) };
+        return Err(format!(
+            "Plugin {} was built for plugin ABI {}, this interpreter uses {}",
+            path.display(),
+            version,
+            ABI_VERSION
+        ));
+    }
+    let register = unsafe { std::mem::transmute::<*mut libc::c_void, RegisterFn>(register) };
+    register(environment);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn load(path: &Path, _environment: &mut Environment) -> Result<(), String> {
+    Err(format!("Could not load plugin {}: plugins are only supported on Unix", path.display()))
+}
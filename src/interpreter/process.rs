@@ -0,0 +1,210 @@
+use std::{
+    io,
+    pin::Pin,
+    process::{ExitStatus, Stdio},
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+    process::{Child, ChildStderr, ChildStdin, ChildStdout, Command},
+    runtime::Handle,
+    sync::{mpsc, watch, Mutex},
+};
+
+use super::{native_functions, socket::{Connection, Stream}};
+
+// A program started by spawnProcess. A task waits for it to exit and passes
+// on the signals sent to it while it runs, so a pid is never signalled after
+// the process has been reaped and the number possibly given to another.
+pub struct Process {
+    pub pid: u32,
+    signals: mpsc::UnboundedSender<i32>,
+    // The exit code once the process has exited
+    exit: watch::Receiver<Option<i32>>,
+    // Its standard streams, those that are piped to the script
+    pub stdin: Option<Arc<Mutex<Connection>>>,
+    pub stdout: Option<Arc<Mutex<Connection>>>,
+    pub stderr: Option<Arc<Mutex<Connection>>>,
+}
+
+// Where each of a process's standard streams goes: stdin defaults to none,
+// stdout and stderr to the script's own
+pub struct StdioOptions {
+    pub stdin: Stdio,
+    pub stdout: Stdio,
+    pub stderr: Stdio,
+}
+
+impl Default for StdioOptions {
+    fn default() -> Self {
+        StdioOptions { stdin: Stdio::null(), stdout: Stdio::inherit(), stderr: Stdio::inherit() }
+    }
+}
+
+// "pipe" to read or write the stream from the script, "inherit" to share the
+// script's own, or "null" for none
+pub fn stdio(name: &str) -> Option<Stdio> {
+    match name {
+        "pipe" => Some(Stdio::piped()),
+        "inherit" => Some(Stdio::inherit()),
+        "null" => Some(Stdio::null()),
+        _ => None,
+    }
+}
+
+impl Process {
+    pub fn spawn(runtime: &Handle, program: &str, args: &[String], stdio: StdioOptions) -> io::Result<Process> {
+        let _entered = runtime.enter();
+        let mut child = Command::new(program)
+            .args(args)
+            .envs(native_functions::env_overrides())
+            .stdin(stdio.stdin)
+            .stdout(stdio.stdout)
+            .stderr(stdio.stderr)
+            .spawn()?;
+        let pid = child.id().unwrap_or_default();
+        let pipe = |pipe: Pipe| Arc::new(Mutex::new(Connection::new(pipe)));
+        let stdin = child.stdin.take().map(|stdin| pipe(Pipe::Stdin(stdin)));
+        let stdout = child.stdout.take().map(|stdout| pipe(Pipe::Stdout(stdout)));
+        let stderr = child.stderr.take().map(|stderr| pipe(Pipe::Stderr(stderr)));
+        let (signals, mut received) = mpsc::unbounded_channel();
+        let (exited, exit) = watch::channel(None);
+        runtime.spawn(async move {
+            loop {
+                tokio::select! {
+                    status = child.wait() => {
+                        let _ = exited.send(Some(status.map(exit_code).unwrap_or(-1)));
+                        return;
+                    }
+                    Some(signal) = received.recv() => send_signal(&mut child, pid, signal),
+                }
+            }
+        });
+        Ok(Process { pid, signals, exit, stdin, stdout, stderr })
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.exit.borrow().is_none()
+    }
+
+    // False when the process had already exited
+    pub fn kill(&self, signal: i32) -> bool {
+        self.is_running() && self.signals.send(signal).is_ok()
+    }
+
+    pub async fn wait(&self) -> i32 {
+        let mut exit = self.exit.clone();
+        let code = match exit.wait_for(Option::is_some).await {
+            Ok(code) => code.unwrap_or(-1),
+            Err(_) => -1,
+        };
+        code
+    }
+}
+
+// The exit code, or 128 plus the signal for a process a signal ended, as
+// shells report it
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return 128 + signal;
+    }
+    status.code().unwrap_or(-1)
+}
+
+#[cfg(unix)]
+fn send_signal(_child: &mut Child, pid: u32, signal: i32) {
+    // SAFETY: kill only sends a signal; the pid is the child's, not yet reaped
+    unsafe {
+        libc::kill(pid as libc::pid_t, signal);
+    }
+}
+
+// Without signals every one ends the process
+#[cfg(not(unix))]
+fn send_signal(child: &mut Child, _pid: u32, _signal: i32) {
+    let _ = child.start_kill();
+}
+
+// The number of a signal named like "TERM" or "SIGTERM"
+pub fn signal_number(name: &str) -> Option<i32> {
+    let name = name.strip_prefix("SIG").unwrap_or(name);
+    #[cfg(unix)]
+    let number = match name {
+        "HUP" => libc::SIGHUP,
+        "INT" => libc::SIGINT,
+        "QUIT" => libc::SIGQUIT,
+        "KILL" => libc::SIGKILL,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        "TERM" => libc::SIGTERM,
+        "STOP" => libc::SIGSTOP,
+        "CONT" => libc::SIGCONT,
+        _ => return None,
+    };
+    #[cfg(not(unix))]
+    let number = match name {
+        "HUP" => 1,
+        "INT" => 2,
+        "QUIT" => 3,
+        "KILL" => 9,
+        "TERM" => 15,
+        _ => return None,
+    };
+    Some(number)
+}
+
+// One of a child's standard streams, read or written through the socket
+// natives. Using one the wrong way round fails.
+enum Pipe {
+    Stdin(ChildStdin),
+    Stdout(ChildStdout),
+    Stderr(ChildStderr),
+}
+
+fn wrong_direction(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, format!("Cannot {} this stream", what))
+}
+
+impl AsyncRead for Pipe {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Pipe::Stdout(stdout) => Pin::new(stdout).poll_read(cx, buf),
+            Pipe::Stderr(stderr) => Pin::new(stderr).poll_read(cx, buf),
+            Pipe::Stdin(_) => Poll::Ready(Err(wrong_direction("read from"))),
+        }
+    }
+}
+
+impl AsyncWrite for Pipe {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Pipe::Stdin(stdin) => Pin::new(stdin).poll_write(cx, buf),
+            _ => Poll::Ready(Err(wrong_direction("write to"))),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Pipe::Stdin(stdin) => Pin::new(stdin).poll_flush(cx),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+
+    // Closing stdin tells the child its input has ended
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Pipe::Stdin(stdin) => Pin::new(stdin).poll_shutdown(cx),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+impl Stream for Pipe {
+    fn tcp(&self) -> Option<&TcpStream> {
+        None
+    }
+}
@@ -0,0 +1,117 @@
+use std::io;
+
+use super::io::ScriptIo;
+
+// Prompts for promptSelect, promptConfirm and promptPassword. At a terminal
+// a choice is made with the arrow keys; otherwise, input being piped in or
+// given by a host, everything is read a line at a time.
+
+// The index of the option chosen, None when the input ends or the choice is
+// cancelled with Escape or Ctrl+C
+pub fn select(io: &ScriptIo, title: &str, options: &[String]) -> io::Result<Option<usize>> {
+    io.write_stdout(&format!("{}\n", title))?;
+    if !io.is_interactive() {
+        return select_by_number(io, options);
+    }
+    // The cursor is hidden while the highlighted option shows where it is
+    io.write_stdout("\x1b[?25l")?;
+    let chosen = select_with_keys(io, options);
+    io.write_stdout("\x1b[?25h")?;
+    chosen
+}
+
+fn select_with_keys(io: &ScriptIo, options: &[String]) -> io::Result<Option<usize>> {
+    let mut chosen = 0;
+    loop {
+        for (i, option) in options.iter().enumerate() {
+            let line = if i == chosen { format!("\x1b[36m> {}\x1b[0m", option) } else { format!("  {}", option) };
+            io.write_stdout(&format!("\r\x1b[2K{}\n", line))?;
+        }
+        match io.read_key()?.as_deref() {
+            None | Some("Escape" | "Ctrl+C") => return Ok(None),
+            Some("Enter") => return Ok(Some(chosen)),
+            Some("Up" | "k") => chosen = (chosen + options.len() - 1) % options.len(),
+            Some("Down" | "j" | "Tab") => chosen = (chosen + 1) % options.len(),
+            Some("Home") => chosen = 0,
+            Some("End") => chosen = options.len() - 1,
+            Some(key) => {
+                if let Some(number) = key.parse::<usize>().ok().filter(|n| (1..=options.len()).contains(n)) {
+                    chosen = number - 1;
+                }
+            }
+        }
+        // Back up to the first option to draw them again
+        io.write_stdout(&format!("\x1b[{}A", options.len()))?;
+    }
+}
+
+// Options are numbered and the answer is a number or the option's text;
+// anything else asks again
+fn select_by_number(io: &ScriptIo, options: &[String]) -> io::Result<Option<usize>> {
+    for (i, option) in options.iter().enumerate() {
+        io.write_stdout(&format!("  {}) {}\n", i + 1, option))?;
+    }
+    loop {
+        io.write_stdout(&format!("Choose 1-{}: ", options.len()))?;
+        let Some(answer) = read_answer(io)? else {
+            return Ok(None);
+        };
+        let by_number = answer.parse::<usize>().ok().filter(|n| (1..=options.len()).contains(n)).map(|n| n - 1);
+        if let Some(chosen) = by_number.or_else(|| options.iter().position(|option| *option == answer)) {
+            return Ok(Some(chosen));
+        }
+    }
+}
+
+// True for y or yes and false for n or no, in any case. An empty answer
+// gives the default when there is one; it and anything else ask again
+// otherwise. None when the input ends.
+pub fn confirm(io: &ScriptIo, message: &str, default: Option<bool>) -> io::Result<Option<bool>> {
+    let choices = match default {
+        Some(true) => "[Y/n]",
+        Some(false) => "[y/N]",
+        None => "[y/n]",
+    };
+    loop {
+        io.write_stdout(&format!("{} {} ", message, choices))?;
+        let Some(answer) = read_answer(io)? else {
+            return Ok(None);
+        };
+        match answer.to_lowercase().as_str() {
+            "y" | "yes" => return Ok(Some(true)),
+            "n" | "no" => return Ok(Some(false)),
+            "" if default.is_some() => return Ok(default),
+            _ => {}
+        }
+    }
+}
+
+// The line typed, kept as typed apart from its line ending; None when the
+// input ends
+pub fn password(io: &ScriptIo, message: &str) -> io::Result<Option<String>> {
+    io.write_stdout(message)?;
+    let mut line = String::new();
+    let read = io.read_secret(&mut line)?;
+    // The Enter that ended it was not echoed either
+    if io.is_interactive() {
+        io.write_stdout("\n")?;
+    }
+    if read == 0 {
+        return Ok(None);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(Some(line))
+}
+
+fn read_answer(io: &ScriptIo) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if io.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
@@ -0,0 +1,175 @@
+use std::io;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use super::native_functions;
+
+// Proxy for connect, connectTLS and the HTTP client when a call names none
+pub const PROXY_VAR: &str = "ALPHA_HTTP_PROXY";
+
+// A proxy to reach hosts through, written as a URL:
+//
+//   http://[user:password@]host[:port]     tunnels with CONNECT (port 8080)
+//   socks5://[user:password@]host[:port]   SOCKS5, the proxy resolving names (port 1080)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Proxy {
+    kind: ProxyKind,
+    host: String,
+    port: u16,
+    credentials: Option<(String, String)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ProxyKind {
+    Http,
+    Socks5,
+}
+
+impl Proxy {
+    pub fn parse(url: &str) -> io::Result<Proxy> {
+        let invalid = || invalid_input(format!("Invalid proxy URL '{}'", url));
+        let (scheme, rest) = url.split_once("://").ok_or_else(invalid)?;
+        let (kind, default_port) = match scheme.to_ascii_lowercase().as_str() {
+            "http" => (ProxyKind::Http, 8080),
+            "socks5" | "socks5h" => (ProxyKind::Socks5, 1080),
+            _ => return Err(invalid_input(format!("Unsupported proxy scheme '{}'", scheme))),
+        };
+        let authority = rest.trim_end_matches('/');
+        let (credentials, address) = match authority.rsplit_once('@') {
+            Some((user_info, address)) => {
+                let (user, password) = user_info.split_once(':').unwrap_or((user_info, ""));
+                (Some((user.to_string(), password.to_string())), address)
+            }
+            None => (None, authority),
+        };
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+            None => (address, default_port),
+        };
+        if host.is_empty() || host.contains('/') {
+            return Err(invalid());
+        }
+        Ok(Proxy { kind, host: host.to_string(), port, credentials })
+    }
+
+    // The proxy ALPHA_HTTP_PROXY names, if it is set
+    pub fn from_env() -> io::Result<Option<Proxy>> {
+        match native_functions::env_var(PROXY_VAR) {
+            Some(url) if !url.trim().is_empty() => Proxy::parse(url.trim()).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    // A TCP stream to host:port that runs through the proxy
+    pub async fn connect(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+        match self.kind {
+            ProxyKind::Http => self.http_tunnel(&mut stream, host, port).await?,
+            ProxyKind::Socks5 => self.socks5_handshake(&mut stream, host, port).await?,
+        }
+        Ok(stream)
+    }
+
+    async fn http_tunnel(&self, stream: &mut TcpStream, host: &str, port: u16) -> io::Result<()> {
+        let target = if host.contains(':') { format!("[{}]:{}", host, port) } else { format!("{}:{}", host, port) };
+        let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", target, target);
+        if let Some((user, password)) = &self.credentials {
+            let token = STANDARD.encode(format!("{}:{}", user, password));
+            request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+
+        // Read the reply a byte at a time so nothing past its headers, which
+        // belongs to the tunnelled connection, is taken from the stream
+        let mut reply = Vec::new();
+        while !reply.ends_with(b"\r\n\r\n") {
+            if reply.len() > 8192 {
+                return Err(proxy_error("proxy reply is too long".to_string()));
+            }
+            let mut byte = [0];
+            if stream.read(&mut byte).await? == 0 {
+                return Err(proxy_error("proxy closed the connection".to_string()));
+            }
+            reply.push(byte[0]);
+        }
+        let reply = String::from_utf8_lossy(&reply);
+        let status = reply.lines().next().unwrap_or_default();
+        match status.split_whitespace().nth(1) {
+            Some(code) if code.starts_with('2') => Ok(()),
+            _ => Err(proxy_error(format!("proxy refused the tunnel: {}", status))),
+        }
+    }
+
+    async fn socks5_handshake(&self, stream: &mut TcpStream, host: &str, port: u16) -> io::Result<()> {
+        // Offer no authentication, and username/password when there are credentials
+        let methods: &[u8] = if self.credentials.is_some() { &[0x00, 0x02] } else { &[0x00] };
+        let mut greeting = vec![0x05, methods.len() as u8];
+        greeting.extend_from_slice(methods);
+        stream.write_all(&greeting).await?;
+        let mut choice = [0; 2];
+        stream.read_exact(&mut choice).await?;
+        match (choice, &self.credentials) {
+            ([0x05, 0x00], _) => {}
+            ([0x05, 0x02], Some((user, password))) => {
+                if user.len() > 255 || password.len() > 255 {
+                    return Err(invalid_input("Proxy credentials are too long".to_string()));
+                }
+                let mut login = vec![0x01, user.len() as u8];
+                login.extend_from_slice(user.as_bytes());
+                login.push(password.len() as u8);
+                login.extend_from_slice(password.as_bytes());
+                stream.write_all(&login).await?;
+                let mut status = [0; 2];
+                stream.read_exact(&mut status).await?;
+                if status[1] != 0x00 {
+                    return Err(proxy_error("proxy rejected the credentials".to_string()));
+                }
+            }
+            _ => return Err(proxy_error("proxy accepts none of the offered authentication methods".to_string())),
+        }
+
+        if host.len() > 255 {
+            return Err(invalid_input("Host name is too long".to_string()));
+        }
+        let mut request = vec![0x05, 0x01, 0x00, 0x03, host.len() as u8];
+        request.extend_from_slice(host.as_bytes());
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request).await?;
+        let mut reply = [0; 4];
+        stream.read_exact(&mut reply).await?;
+        if reply[1] != 0x00 {
+            return Err(proxy_error(format!("proxy could not connect (SOCKS reply {})", reply[1])));
+        }
+        // Skip the address the proxy bound, then the port
+        let address_len = match reply[3] {
+            0x01 => 4,
+            0x04 => 16,
+            0x03 => stream.read_u8().await? as usize,
+            _ => return Err(proxy_error("proxy sent an invalid reply".to_string())),
+        };
+        let mut bound = vec![0; address_len + 2];
+        stream.read_exact(&mut bound).await?;
+        Ok(())
+    }
+}
+
+// A TCP stream to host:port, through the proxy when there is one
+pub async fn connect(proxy: Option<&Proxy>, host: &str, port: u16) -> io::Result<TcpStream> {
+    match proxy {
+        Some(proxy) => proxy.connect(host, port).await,
+        None => TcpStream::connect((host, port)).await,
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn proxy_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionRefused, message)
+}
@@ -0,0 +1,79 @@
+use std::fs;
+use std::path::PathBuf;
+
+use sha2::{Digest, Sha256};
+
+use super::{http, native_functions};
+
+// Modules imported by URL are downloaded once into this directory and
+// loaded from there afterwards; delete a file to download it again
+pub fn cache_dir() -> PathBuf {
+    match native_functions::env_var("ALPHA_CACHE_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => dirs::cache_dir().unwrap_or_else(|| PathBuf::from(".")).join("alpha").join("remote"),
+    }
+}
+
+pub fn is_remote(import_path: &str) -> bool {
+    import_path.starts_with("https://") || import_path.starts_with("http://")
+}
+
+// The name a remote module is imported under: its file name without the
+// extension, as for local files
+pub fn module_name(url: &str) -> &str {
+    let path = url.split(['#', '?']).next().unwrap_or(url);
+    let file = path.rsplit('/').next().unwrap_or(path);
+    file.rsplit_once('.').map_or(file, |(stem, _)| stem)
+}
+
+// The local copy of a module imported by URL, downloading it if it is not
+// cached yet. A `#sha256=HEX` fragment pins the module's content: a
+// download, or a cached copy, with another hash is refused. Plain http://
+// can be tampered with on the way, so it is only accepted with a pin.
+pub fn fetch(url: &str) -> Result<PathBuf, String> {
+    let (location, integrity) = match url.split_once('#') {
+        Some((location, fragment)) => match fragment.strip_prefix("sha256=") {
+            Some(hash) => (location, Some(hash.to_lowercase())),
+            None => return Err(format!("unknown fragment #{} in {}, expected #sha256=HEX", fragment, url)),
+        },
+        None => (url, None),
+    };
+    if integrity.is_none() && !location.starts_with("https://") {
+        return Err(format!("{} is not https, pin its content with #sha256=HEX to import it", location));
+    }
+    let path = cache_dir().join(format!("{}.la", hex(&Sha256::digest(location.as_bytes()))));
+    let cached = fs::read(&path).ok();
+    let source = match cached {
+        Some(source) => source,
+        None => download(location)?,
+    };
+    if let Some(expected) = integrity {
+        let actual = hex(&Sha256::digest(&source));
+        if actual != expected {
+            return Err(format!("{} has sha256 {}, expected {}", location, actual, expected));
+        }
+    }
+    if !path.is_file() {
+        fs::create_dir_all(cache_dir())
+            .and_then(|_| fs::write(&path, &source))
+            .map_err(|e| format!("could not cache {}: {}", location, e))?;
+    }
+    Ok(path)
+}
+
+// Imports run outside async code, on the interpreter's runtime or with none
+fn download(url: &str) -> Result<Vec<u8>, String> {
+    let result = match tokio::runtime::Handle::try_current() {
+        Ok(handle) => tokio::task::block_in_place(|| handle.block_on(http::get(url))),
+        Err(_) => tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| e.to_string())?
+            .block_on(http::get(url)),
+    };
+    result.map_err(|e| format!("could not download {}: {}", url, e))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,238 @@
+use std::{collections::HashMap, io};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufStream},
+    net::TcpStream,
+};
+
+use super::{socket::connect_tls, value::Value};
+
+#[derive(PartialEq)]
+pub enum Security {
+    // TLS from the first byte, usually port 465
+    Tls,
+    // Plain connection upgraded with STARTTLS, usually port 587
+    StartTls,
+    None,
+}
+
+// A message for `sendMail`, read from the script's dictionary
+pub struct Mail {
+    from: String,
+    to: Vec<String>,
+    cc: Vec<String>,
+    bcc: Vec<String>,
+    subject: String,
+    body: String,
+    html: bool,
+    security: Security,
+    login: Option<(String, String)>,
+}
+
+impl Mail {
+    // Keys: from, to, cc, bcc (string or array), subject, body, html (bool),
+    // username, password and security ("tls", "starttls" or "none"; tls on
+    // port 465 and starttls elsewhere by default)
+    pub fn from_dict(dict: &HashMap<String, Value>, port: u16) -> Result<Mail, String> {
+        let text = |key: &str| match dict.get(key) {
+            None | Some(Value::Nil) => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s.to_string())),
+            Some(_) => Err(format!("sendMail: '{}' must be a string", key)),
+        };
+        let addresses = |key: &str| -> Result<Vec<String>, String> {
+            let list = match dict.get(key) {
+                None | Some(Value::Nil) => Vec::new(),
+                Some(Value::String(s)) => vec![s.to_string()],
+                Some(Value::Array(items)) => items
+                    .iter()
+                    .map(|item| match item {
+                        Value::String(s) => Ok(s.to_string()),
+                        _ => Err(format!("sendMail: '{}' must hold strings", key)),
+                    })
+                    .collect::<Result<_, _>>()?,
+                Some(_) => return Err(format!("sendMail: '{}' must be a string or array", key)),
+            };
+            for address in &list {
+                check_header(key, address)?;
+            }
+            Ok(list)
+        };
+
+        let from = text("from")?.ok_or("sendMail: 'from' is required")?;
+        check_header("from", &from)?;
+        let subject = text("subject")?.unwrap_or_default();
+        check_header("subject", &subject)?;
+        let to = addresses("to")?;
+        let cc = addresses("cc")?;
+        let bcc = addresses("bcc")?;
+        if to.is_empty() && cc.is_empty() && bcc.is_empty() {
+            return Err("sendMail: no recipients".to_string());
+        }
+        let security = match text("security")?.as_deref() {
+            None if port == 465 => Security::Tls,
+            None | Some("starttls") => Security::StartTls,
+            Some("tls") => Security::Tls,
+            Some("none") => Security::None,
+            Some(other) => return Err(format!("sendMail: unknown security '{}'", other)),
+        };
+        let login = match (text("username")?, text("password")?) {
+            (Some(username), Some(password)) => Some((username, password)),
+            (None, None) => None,
+            _ => return Err("sendMail: username and password go together".to_string()),
+        };
+        Ok(Mail {
+            from,
+            to,
+            cc,
+            bcc,
+            subject,
+            body: text("body")?.unwrap_or_default(),
+            html: matches!(dict.get("html"), Some(Value::Boolean(true))),
+            security,
+            login,
+        })
+    }
+
+    fn message(&self) -> String {
+        let mut message = format!("From: {}\r\n", self.from);
+        if !self.to.is_empty() {
+            message.push_str(&format!("To: {}\r\n", self.to.join(", ")));
+        }
+        if !self.cc.is_empty() {
+            message.push_str(&format!("Cc: {}\r\n", self.cc.join(", ")));
+        }
+        message.push_str(&format!("Subject: {}\r\n", encode_header(&self.subject)));
+        message.push_str("MIME-Version: 1.0\r\n");
+        let kind = if self.html { "html" } else { "plain" };
+        message.push_str(&format!("Content-Type: text/{}; charset=utf-8\r\n", kind));
+        message.push_str("Content-Transfer-Encoding: 8bit\r\n\r\n");
+        for line in self.body.lines() {
+            // A leading dot would otherwise be read as the end of the data
+            if line.starts_with('.') {
+                message.push('.');
+            }
+            message.push_str(line);
+            message.push_str("\r\n");
+        }
+        message
+    }
+}
+
+pub async fn send(host: &str, port: u16, mail: &Mail) -> io::Result<()> {
+    let stream = TcpStream::connect((host, port)).await?;
+    if mail.security == Security::Tls {
+        let stream = connect_tls(host, stream).await?;
+        let mut session = Session::new(stream);
+        session.expect(&[220]).await?;
+        session.hello().await?;
+        return session.deliver(mail).await;
+    }
+
+    let mut session = Session::new(stream);
+    session.expect(&[220]).await?;
+    let features = session.hello().await?;
+    if mail.security == Security::None {
+        return session.deliver(mail).await;
+    }
+    if !features.iter().any(|feature| feature.eq_ignore_ascii_case("STARTTLS")) {
+        return Err(smtp_error("server does not support STARTTLS"));
+    }
+    session.command("STARTTLS", &[220]).await?;
+    let stream = connect_tls(host, session.stream.into_inner()).await?;
+    let mut session = Session::new(stream);
+    session.hello().await?;
+    session.deliver(mail).await
+}
+
+struct Session<S> {
+    stream: BufStream<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Session<S> {
+    fn new(stream: S) -> Self {
+        Session { stream: BufStream::new(stream) }
+    }
+
+    // EHLO, giving back the extensions the server announced
+    async fn hello(&mut self) -> io::Result<Vec<String>> {
+        let name = hostname::get()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| "localhost".to_string());
+        let lines = self.command(&format!("EHLO {}", name), &[250]).await?;
+        Ok(lines.into_iter().skip(1).collect())
+    }
+
+    async fn deliver(&mut self, mail: &Mail) -> io::Result<()> {
+        if let Some((username, password)) = &mail.login {
+            let credentials = STANDARD.encode(format!("\0{}\0{}", username, password));
+            self.command(&format!("AUTH PLAIN {}", credentials), &[235]).await?;
+        }
+        self.command(&format!("MAIL FROM:<{}>", address(&mail.from)), &[250]).await?;
+        for recipient in mail.to.iter().chain(&mail.cc).chain(&mail.bcc) {
+            self.command(&format!("RCPT TO:<{}>", address(recipient)), &[250, 251]).await?;
+        }
+        self.command("DATA", &[354]).await?;
+        self.stream.write_all(mail.message().as_bytes()).await?;
+        self.command(".", &[250]).await?;
+        // The mail is accepted; a server hanging up early is not an error
+        let _ = self.command("QUIT", &[221]).await;
+        Ok(())
+    }
+
+    async fn command(&mut self, line: &str, expected: &[u16]) -> io::Result<Vec<String>> {
+        self.stream.write_all(line.as_bytes()).await?;
+        self.stream.write_all(b"\r\n").await?;
+        self.stream.flush().await?;
+        self.expect(expected).await
+    }
+
+    // Reads a possibly multi-line reply, failing unless its code is expected
+    async fn expect(&mut self, expected: &[u16]) -> io::Result<Vec<String>> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                return Err(smtp_error("connection closed by server"));
+            }
+            let line = line.trim_end();
+            let code = line.get(..3).and_then(|code| code.parse::<u16>().ok());
+            let code = code.ok_or_else(|| smtp_error(&format!("bad reply '{}'", line)))?;
+            lines.push(line.get(4..).unwrap_or("").to_string());
+            if line.as_bytes().get(3) != Some(&b'-') {
+                if !expected.contains(&code) {
+                    return Err(smtp_error(&format!("{} {}", code, lines.join(" "))));
+                }
+                return Ok(lines);
+            }
+        }
+    }
+}
+
+// The bare address from "Name <user@host>"
+fn address(mailbox: &str) -> &str {
+    match (mailbox.find('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+        _ => mailbox.trim(),
+    }
+}
+
+fn check_header(key: &str, value: &str) -> Result<(), String> {
+    if value.contains(['\r', '\n']) {
+        return Err(format!("sendMail: '{}' cannot contain line breaks", key));
+    }
+    Ok(())
+}
+
+// Non-ASCII header text goes out as an RFC 2047 encoded word
+fn encode_header(text: &str) -> String {
+    if text.is_ascii() {
+        text.to_string()
+    } else {
+        format!("=?utf-8?B?{}?=", STANDARD.encode(text))
+    }
+}
+
+fn smtp_error(message: &str) -> io::Error {
+    io::Error::other(format!("SMTP: {}", message))
+}
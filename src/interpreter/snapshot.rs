@@ -0,0 +1,208 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{InterpreterError, InterpreterResult, RuntimeErrorKind};
+use crate::parser::Expr;
+
+use super::enviroment::{Environment, Module};
+use super::value::Value;
+use super::Interpreter;
+
+// A snapshot starts with this, then the version of alpha that wrote it
+const MAGIC: &[u8] = b"ALS\0";
+
+// The globals of an environment and of the modules it imported. Values that
+// only mean something in the running process (natives, promises, files,
+// sockets, channels, locks, database connections, timers) are left out.
+#[derive(Serialize, Deserialize)]
+struct StoredEnvironment {
+    values: Vec<(String, StoredValue)>,
+    modules: Vec<StoredModule>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredModule {
+    name: String,
+    path: String,
+    environment: StoredEnvironment,
+}
+
+#[derive(Serialize, Deserialize)]
+enum StoredValue {
+    Number(f64),
+    String(String),
+    Boolean(bool),
+    Nil,
+    Array(Vec<StoredValue>),
+    Dictionary(Vec<(String, StoredValue)>),
+    Function(String, Vec<String>, Expr),
+    AsyncFunction(String, Vec<String>, Expr),
+    Class(String, Vec<(String, StoredValue)>),
+    // Fields and methods, without `this`
+    Instance(String, Vec<(String, StoredValue)>),
+}
+
+impl Interpreter {
+    // The global definitions of the running program and of the modules it
+    // imported, for `restore` to bring back later, in this process or
+    // another. Instances shared between several values come back as copies.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let stored = store_environment(&self.environment, &mut HashSet::new());
+        let mut bytes = MAGIC.to_vec();
+        let version = env!("CARGO_PKG_VERSION");
+        bytes.push(version.len() as u8);
+        bytes.extend_from_slice(version.as_bytes());
+        bytes.extend(rmp_serde::to_vec(&stored).expect("the snapshot serializes"));
+        bytes
+    }
+
+    // Defines everything a snapshot holds in this interpreter's globals,
+    // replacing definitions of the same names. Only snapshots written by
+    // this version of alpha are accepted.
+    pub fn restore(&mut self, bytes: &[u8]) -> InterpreterResult<()> {
+        let stored = decode(bytes).map_err(|error| {
+            InterpreterError::runtime_error(RuntimeErrorKind::RuntimeError(
+                0,
+                format!("Invalid snapshot: {}", error),
+            ))
+        })?;
+        restore_environment(&self.environment, stored);
+        Ok(())
+    }
+}
+
+fn decode(bytes: &[u8]) -> Result<StoredEnvironment, String> {
+    let rest = bytes.strip_prefix(MAGIC).ok_or("not a snapshot")?;
+    let length = *rest.first().ok_or("truncated snapshot")? as usize;
+    let version = rest.get(1..1 + length).ok_or("truncated snapshot")?;
+    if version != env!("CARGO_PKG_VERSION").as_bytes() {
+        return Err(format!("written by alpha {}", String::from_utf8_lossy(version)));
+    }
+    rmp_serde::from_slice(&rest[1 + length..]).map_err(|error| error.to_string())
+}
+
+// `seen` holds the instances being stored, so one holding itself is cut off
+fn store_environment(
+    environment: &Arc<Mutex<Environment>>,
+    seen: &mut HashSet<*const Mutex<Environment>>,
+) -> StoredEnvironment {
+    let (values, modules) = {
+        let environment = environment.lock().unwrap();
+        (environment.get_values(), environment.get_modules())
+    };
+    StoredEnvironment {
+        values: store_values(values, seen),
+        modules: modules
+            .into_iter()
+            .map(|module| StoredModule {
+                environment: store_environment(&module.environment, seen),
+                name: module.name,
+                path: module.path,
+            })
+            .collect(),
+    }
+}
+
+fn store_values(
+    values: impl IntoIterator<Item = (String, Value)>,
+    seen: &mut HashSet<*const Mutex<Environment>>,
+) -> Vec<(String, StoredValue)> {
+    let mut stored: Vec<_> = values
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, store_value(&value, seen)?)))
+        .collect();
+    stored.sort_by(|a, b| a.0.cmp(&b.0));
+    stored
+}
+
+fn store_value(value: &Value, seen: &mut HashSet<*const Mutex<Environment>>) -> Option<StoredValue> {
+    Some(match value {
+        Value::Number(n) => StoredValue::Number(*n),
+        Value::String(s) => StoredValue::String(s.to_string()),
+        Value::Boolean(b) => StoredValue::Boolean(*b),
+        Value::Nil => StoredValue::Nil,
+        Value::Array(elements) => {
+            StoredValue::Array(elements.iter().filter_map(|element| store_value(element, seen)).collect())
+        }
+        Value::Dictionary(entries) => StoredValue::Dictionary(store_values(entries.clone(), seen)),
+        Value::Function(name, params, body) => {
+            StoredValue::Function(name.clone(), params.to_vec(), Expr::clone(body))
+        }
+        Value::AsyncFunction(name, params, body) => {
+            StoredValue::AsyncFunction(name.clone(), params.to_vec(), Expr::clone(body))
+        }
+        Value::Class(name, methods) => StoredValue::Class(name.clone(), store_values(methods.clone(), seen)),
+        Value::Instance(name, environment) => {
+            if !seen.insert(Arc::as_ptr(environment)) {
+                return None;
+            }
+            let fields = environment.lock().unwrap().get_values();
+            let fields = fields.into_iter().filter(|(field, _)| field != "this");
+            let stored = StoredValue::Instance(name.clone(), store_values(fields, seen));
+            seen.remove(&Arc::as_ptr(environment));
+            stored
+        }
+        _ => return None,
+    })
+}
+
+fn restore_environment(environment: &Arc<Mutex<Environment>>, stored: StoredEnvironment) {
+    for (name, value) in stored.values {
+        let value = restore_value(value, environment);
+        environment.lock().unwrap().define(&name, value);
+    }
+    for module in stored.modules {
+        let module_environment = {
+            let environment = environment.lock().unwrap();
+            let mut module_environment = Environment::new(PathBuf::new());
+            module_environment.options = environment.options;
+            module_environment.io = Arc::clone(&environment.io);
+            module_environment.register_native_functions();
+            Arc::new(Mutex::new(module_environment))
+        };
+        restore_environment(&module_environment, module.environment);
+        environment.lock().unwrap().add_module(Module {
+            name: module.name,
+            environment: module_environment,
+            path: module.path,
+            deferred: None,
+        });
+    }
+}
+
+// Instances are scoped under the environment they are restored into, as
+// they were under the one that created them
+fn restore_value(value: StoredValue, globals: &Arc<Mutex<Environment>>) -> Value {
+    let restore_all = |values: Vec<(String, StoredValue)>| -> HashMap<String, Value> {
+        values.into_iter().map(|(name, value)| (name, restore_value(value, globals))).collect()
+    };
+    match value {
+        StoredValue::Number(n) => Value::Number(n),
+        StoredValue::String(s) => Value::String(s.into()),
+        StoredValue::Boolean(b) => Value::Boolean(b),
+        StoredValue::Nil => Value::Nil,
+        StoredValue::Array(elements) => {
+            Value::Array(elements.into_iter().map(|element| restore_value(element, globals)).collect())
+        }
+        StoredValue::Dictionary(entries) => Value::Dictionary(restore_all(entries)),
+        StoredValue::Function(name, params, body) => Value::Function(name, params.into(), Arc::new(body)),
+        StoredValue::AsyncFunction(name, params, body) => {
+            Value::AsyncFunction(name, params.into(), Arc::new(body))
+        }
+        StoredValue::Class(name, methods) => Value::Class(name, restore_all(methods)),
+        StoredValue::Instance(name, fields) => {
+            let environment = Environment::new_with_enclosing(Some(Arc::clone(globals)));
+            let instance = Value::Instance(name, Arc::clone(&environment));
+            let mut lock = environment.lock().unwrap();
+            for (field, value) in restore_all(fields) {
+                lock.define(&field, value);
+            }
+            lock.define("this", instance.clone());
+            drop(lock);
+            instance
+        }
+    }
+}
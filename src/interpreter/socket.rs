@@ -0,0 +1,292 @@
+use std::{future::Future, io, net::SocketAddr, sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Arc, Mutex}, time::Duration};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::Notify,
+    time::timeout,
+};
+use tokio_rustls::{client::TlsStream, TlsConnector};
+use rustls::{pki_types::ServerName, ClientConfig};
+
+// Streams a script socket can wrap. Those on a TCP socket give it, as that
+// is where the socket options are set; a child process's pipes have none.
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {
+    fn tcp(&self) -> Option<&TcpStream>;
+}
+
+impl Stream for TcpStream {
+    fn tcp(&self) -> Option<&TcpStream> {
+        Some(self)
+    }
+}
+
+impl Stream for TlsStream<TcpStream> {
+    fn tcp(&self) -> Option<&TcpStream> {
+        Some(self.get_ref().0)
+    }
+}
+
+// An open socket together with the timeouts set on it from script code.
+// Bytes read past the end of a line or delimiter wait in the buffer for the
+// next read. The stream is None once the socket has been closed.
+pub struct Connection {
+    stream: Option<Box<dyn Stream>>,
+    buffer: Vec<u8>,
+    write_shut: bool,
+    pub read_timeout: Option<Duration>,
+    pub write_timeout: Option<Duration>,
+}
+
+impl Connection {
+    pub fn new(stream: impl Stream + 'static) -> Self {
+        Connection {
+            stream: Some(Box::new(stream)),
+            buffer: Vec::new(),
+            write_shut: false,
+            read_timeout: None,
+            write_timeout: None,
+        }
+    }
+
+    pub fn tcp(&self) -> io::Result<&TcpStream> {
+        match &self.stream {
+            Some(stream) => stream.tcp().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::Unsupported, "Not a network socket")
+            }),
+            None => Err(closed_error("Socket")),
+        }
+    }
+
+    fn stream(&mut self) -> io::Result<&mut Box<dyn Stream>> {
+        self.stream.as_mut().ok_or_else(|| closed_error("Socket"))
+    }
+
+    pub async fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if !self.buffer.is_empty() {
+            let n = buffer.len().min(self.buffer.len());
+            buffer[..n].copy_from_slice(&self.buffer[..n]);
+            self.buffer.drain(..n);
+            return Ok(n);
+        }
+        with_timeout(self.read_timeout, "Read", self.stream()?.read(buffer)).await
+    }
+
+    // Exactly n bytes, failing if the stream ends first
+    pub async fn read_exact(&mut self, n: usize) -> io::Result<Vec<u8>> {
+        while self.buffer.len() < n {
+            if self.fill().await? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("Stream ended after {} of {} bytes", self.buffer.len(), n),
+                ));
+            }
+        }
+        Ok(self.buffer.drain(..n).collect())
+    }
+
+    // Bytes up to the delimiter, which is consumed but not returned. At the
+    // end of the stream whatever is left is returned, then None.
+    pub async fn read_until(&mut self, delimiter: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let mut searched = 0;
+        loop {
+            if let Some(pos) = find(&self.buffer[searched..], delimiter) {
+                let end = searched + pos;
+                let data = self.buffer[..end].to_vec();
+                self.buffer.drain(..end + delimiter.len());
+                return Ok(Some(data));
+            }
+            // The delimiter may start in the bytes already searched
+            searched = self.buffer.len().saturating_sub(delimiter.len() - 1);
+            if self.fill().await? == 0 {
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                }
+                return Ok(Some(std::mem::take(&mut self.buffer)));
+            }
+        }
+    }
+
+    // Next line without its \n or \r\n ending
+    pub async fn read_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut line = self.read_until(b"\n").await?;
+        if let Some(line) = &mut line {
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+        }
+        Ok(line)
+    }
+
+    async fn fill(&mut self) -> io::Result<usize> {
+        let mut chunk = [0; 4096];
+        let n = with_timeout(self.read_timeout, "Read", self.stream()?.read(&mut chunk)).await?;
+        self.buffer.extend_from_slice(&chunk[..n]);
+        Ok(n)
+    }
+
+    pub async fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        if self.write_shut {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "Socket is shut down for writing",
+            ));
+        }
+        with_timeout(self.write_timeout, "Write", self.stream()?.write_all(data)).await
+    }
+
+    // Sends end of stream to the peer; reading still works
+    pub async fn shutdown_write(&mut self) -> io::Result<()> {
+        if self.write_shut {
+            return Ok(());
+        }
+        with_timeout(self.write_timeout, "Shutdown", self.stream()?.shutdown()).await?;
+        self.write_shut = true;
+        Ok(())
+    }
+
+    // Closing twice is fine, any other use of a closed socket fails
+    pub async fn close(&mut self) {
+        if let Some(mut stream) = self.stream.take() {
+            if !self.write_shut {
+                let _ = with_timeout(self.write_timeout, "Shutdown", stream.shutdown()).await;
+            }
+        }
+        self.buffer.clear();
+    }
+}
+
+// A listening socket. Closing it wakes pending accepts, which let go of the
+// listener so the port is freed right away. Handlers `serve` runs for
+// accepted connections are counted so a shutdown can wait for them.
+pub struct Listener {
+    listener: Mutex<Option<Arc<TcpListener>>>,
+    closed: Notify,
+    stopped: AtomicBool,
+    handlers: AtomicUsize,
+    handlers_done: Notify,
+}
+
+// Held by a running connection handler, counting it until dropped
+pub struct HandlerGuard(Arc<Listener>);
+
+impl Drop for HandlerGuard {
+    fn drop(&mut self) {
+        if self.0.handlers.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.handlers_done.notify_waiters();
+        }
+    }
+}
+
+impl Listener {
+    pub fn new(listener: TcpListener) -> Self {
+        Listener {
+            listener: Mutex::new(Some(Arc::new(listener))),
+            closed: Notify::new(),
+            stopped: AtomicBool::new(false),
+            handlers: AtomicUsize::new(0),
+            handlers_done: Notify::new(),
+        }
+    }
+
+    pub fn track_handler(self: &Arc<Self>) -> HandlerGuard {
+        self.handlers.fetch_add(1, Ordering::SeqCst);
+        HandlerGuard(self.clone())
+    }
+
+    pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        // Registered before looking at the listener so a close in between is not missed
+        let closed = self.closed.notified();
+        tokio::pin!(closed);
+        closed.as_mut().enable();
+        if self.stopped.load(Ordering::SeqCst) {
+            return Err(closed_error("Server"));
+        }
+        let listener = self
+            .listener
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| closed_error("Server"))?;
+        tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = closed => Err(closed_error("Server")),
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match &*self.listener.lock().unwrap() {
+            Some(listener) => listener.local_addr(),
+            None => Err(closed_error("Server")),
+        }
+    }
+
+    pub fn close(&self) {
+        self.listener.lock().unwrap().take();
+        self.closed.notify_waiters();
+    }
+
+    // Stops accepting, waits up to the limit for the handlers still running,
+    // then closes the listener. False when the limit ran out first.
+    pub async fn shutdown(&self, limit: Option<Duration>) -> bool {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.closed.notify_waiters();
+        let drained = async {
+            loop {
+                let done = self.handlers_done.notified();
+                tokio::pin!(done);
+                done.as_mut().enable();
+                if self.handlers.load(Ordering::SeqCst) == 0 {
+                    return;
+                }
+                done.await;
+            }
+        };
+        let finished = match limit {
+            Some(limit) => timeout(limit, drained).await.is_ok(),
+            None => {
+                drained.await;
+                true
+            }
+        };
+        self.close();
+        finished
+    }
+}
+
+fn closed_error(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, format!("{} is closed", what))
+}
+
+// Upgrades a TCP connection to TLS, checking the server against the bundled roots
+pub async fn connect_tls(host: &str, stream: TcpStream) -> io::Result<TlsStream<TcpStream>> {
+    let config = ClientConfig::builder()
+        .with_root_certificates(rustls::RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.into(),
+        })
+        .with_no_client_auth();
+    let domain = ServerName::try_from(host.to_string())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Invalid domain name"))?;
+    TlsConnector::from(Arc::new(config)).connect(domain, stream).await
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+pub async fn with_timeout<T>(
+    limit: Option<Duration>,
+    operation: &str,
+    future: impl Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    match limit {
+        Some(limit) => match timeout(limit, future).await {
+            Ok(result) => result,
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("{} timed out", operation),
+            )),
+        },
+        None => future.await,
+    }
+}
@@ -0,0 +1,34 @@
+use std::path::{Path, PathBuf};
+
+// Modules built into the interpreter, imported as `import "std/NAME"`
+const MODULES: &[(&str, &str)] = &[
+    ("assert", include_str!("stdlib/assert.la")),
+    ("collections", include_str!("stdlib/collections.la")),
+    ("strings", include_str!("stdlib/strings.la")),
+];
+
+// Where built-in modules appear to live; no real directory is named like this
+const ROOT: &str = "<std>";
+
+// The path standing for a built-in module, if the import names one
+pub fn resolve(import_path: &str) -> Option<PathBuf> {
+    let name = import_path.strip_prefix("std/")?;
+    MODULES
+        .iter()
+        .any(|(module, _)| *module == name)
+        .then(|| Path::new(ROOT).join(format!("{}.la", name)))
+}
+
+// The source of a path `resolve` gave
+pub fn source(path: &Path) -> Option<&'static str> {
+    let name = path.strip_prefix(ROOT).ok()?.file_stem()?.to_str()?;
+    MODULES.iter().find(|(module, _)| *module == name).map(|(_, source)| *source)
+}
+
+// Reads a module, built-in or not
+pub fn read_module(path: &Path) -> std::io::Result<String> {
+    match source(path) {
+        Some(source) => Ok(source.to_string()),
+        None => std::fs::read_to_string(path),
+    }
+}
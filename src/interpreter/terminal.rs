@@ -0,0 +1,249 @@
+use std::io::{self, Read};
+
+// Single keypresses for readKey. A key is the character typed, or a name:
+// Enter, Tab, Backspace, Escape, Up, Down, Left, Right, Home, End, PageUp,
+// PageDown, Insert, Delete, F1 to F12, Ctrl+A to Ctrl+Z, and Alt+ followed
+// by the character. Keys the terminal sends an unknown sequence for are
+// Unknown.
+//
+// next gives the following byte of the input, None at its end. Asked for
+// the rest of an escape sequence it may also give None when nothing more
+// comes right away, which is how a lone Escape is told apart.
+pub fn read_key(next: &mut dyn FnMut(bool) -> io::Result<Option<u8>>) -> io::Result<Option<String>> {
+    let Some(byte) = next(false)? else {
+        return Ok(None);
+    };
+    let key = match byte {
+        b'\r' | b'\n' => "Enter".to_string(),
+        b'\t' => "Tab".to_string(),
+        0x7f | 0x08 => "Backspace".to_string(),
+        0x1b => escape(next)?,
+        0 => "Ctrl+Space".to_string(),
+        1..=26 => format!("Ctrl+{}", (b'A' + byte - 1) as char),
+        byte => character(byte, next)?,
+    };
+    Ok(Some(key))
+}
+
+fn escape(next: &mut dyn FnMut(bool) -> io::Result<Option<u8>>) -> io::Result<String> {
+    let key = match next(true)? {
+        None | Some(0x1b) => "Escape",
+        Some(b'[') => return control_sequence(next),
+        // F1 to F4, and the cursor keys of terminals in application mode
+        Some(b'O') => match next(true)? {
+            Some(byte) => final_key(byte),
+            None => "Unknown",
+        },
+        Some(byte) => return Ok(format!("Alt+{}", character(byte, next)?)),
+    };
+    Ok(key.to_string())
+}
+
+// ESC [ then parameters and a final byte, as in ESC [ A, ESC [ 3 ~ and
+// ESC [ 1 ; 5 C; modifiers are not reported
+fn control_sequence(next: &mut dyn FnMut(bool) -> io::Result<Option<u8>>) -> io::Result<String> {
+    let mut parameters = String::new();
+    loop {
+        let key = match next(true)? {
+            Some(byte @ (b'0'..=b'9' | b';')) => {
+                parameters.push(byte as char);
+                continue;
+            }
+            Some(b'~') => match parameters.split(';').next().unwrap_or("") {
+                "1" | "7" => "Home",
+                "2" => "Insert",
+                "3" => "Delete",
+                "4" | "8" => "End",
+                "5" => "PageUp",
+                "6" => "PageDown",
+                "11" => "F1",
+                "12" => "F2",
+                "13" => "F3",
+                "14" => "F4",
+                "15" => "F5",
+                "17" => "F6",
+                "18" => "F7",
+                "19" => "F8",
+                "20" => "F9",
+                "21" => "F10",
+                "23" => "F11",
+                "24" => "F12",
+                _ => "Unknown",
+            },
+            Some(byte) => final_key(byte),
+            None => "Unknown",
+        };
+        return Ok(key.to_string());
+    }
+}
+
+fn final_key(byte: u8) -> &'static str {
+    match byte {
+        b'A' => "Up",
+        b'B' => "Down",
+        b'C' => "Right",
+        b'D' => "Left",
+        b'H' => "Home",
+        b'F' => "End",
+        b'P' => "F1",
+        b'Q' => "F2",
+        b'R' => "F3",
+        b'S' => "F4",
+        _ => "Unknown",
+    }
+}
+
+// The character a UTF-8 sequence starting with first encodes
+fn character(first: u8, next: &mut dyn FnMut(bool) -> io::Result<Option<u8>>) -> io::Result<String> {
+    let length = match first {
+        0xf0..=0xf7 => 4,
+        0xe0..=0xef => 3,
+        0xc0..=0xdf => 2,
+        _ => 1,
+    };
+    let mut bytes = vec![first];
+    while bytes.len() < length {
+        match next(true)? {
+            Some(byte) => bytes.push(byte),
+            None => break,
+        }
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// Bytes of a reader for read_key, with no waiting to tell Escape apart:
+// input that is not a terminal has all its bytes already
+pub fn bytes(input: &mut dyn Read) -> impl FnMut(bool) -> io::Result<Option<u8>> + '_ {
+    move |_| {
+        let mut byte = [0];
+        loop {
+            match input.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+// The next key typed at the terminal, read with the terminal in raw mode so
+// it comes without waiting for Enter and is not echoed. None when stdin is
+// not a terminal.
+#[cfg(unix)]
+pub fn read_terminal_key() -> Option<io::Result<Option<String>>> {
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+        return None;
+    }
+    let read = || {
+        let _raw = TerminalMode::raw()?;
+        read_key(&mut terminal_byte)
+    };
+    Some(read())
+}
+
+#[cfg(not(unix))]
+pub fn read_terminal_key() -> Option<io::Result<Option<String>>> {
+    None
+}
+
+// The next line typed at the terminal, line ending included, without showing
+// it as it is typed. None when stdin is not a terminal.
+#[cfg(unix)]
+pub fn read_terminal_secret(line: &mut String) -> Option<io::Result<usize>> {
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+        return None;
+    }
+    let read = |line: &mut String| {
+        let _hidden = TerminalMode::without_echo()?;
+        std::io::stdin().read_line(line)
+    };
+    Some(read(line))
+}
+
+#[cfg(not(unix))]
+pub fn read_terminal_secret(_line: &mut String) -> Option<io::Result<usize>> {
+    None
+}
+
+// Whether both stdin and stdout are a terminal, so keys can be read one at a
+// time and the cursor moved over what was printed
+#[cfg(unix)]
+pub fn is_interactive() -> bool {
+    unsafe { libc::isatty(libc::STDIN_FILENO) == 1 && libc::isatty(libc::STDOUT_FILENO) == 1 }
+}
+
+#[cfg(not(unix))]
+pub fn is_interactive() -> bool {
+    false
+}
+
+// Restores the terminal's settings when dropped
+#[cfg(unix)]
+struct TerminalMode {
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl TerminalMode {
+    fn raw() -> io::Result<TerminalMode> {
+        TerminalMode::change(|settings| {
+            // Output is left alone so printed newlines still start a new line
+            settings.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+            settings.c_iflag &= !(libc::IXON | libc::ICRNL);
+            settings.c_cc[libc::VMIN] = 1;
+            settings.c_cc[libc::VTIME] = 0;
+        })
+    }
+
+    // Lines are still edited and ended with Enter as usual
+    fn without_echo() -> io::Result<TerminalMode> {
+        TerminalMode::change(|settings| settings.c_lflag &= !libc::ECHO)
+    }
+
+    fn change(change: impl FnOnce(&mut libc::termios)) -> io::Result<TerminalMode> {
+        let mut settings = unsafe { std::mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut settings) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let original = settings;
+        change(&mut settings);
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &settings) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(TerminalMode { original })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for TerminalMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+// A byte straight from the terminal, past the buffer of std's stdin. The
+// rest of an escape sequence arrives at once; waiting 50ms for it tells a
+// lone Escape apart.
+#[cfg(unix)]
+fn terminal_byte(continuation: bool) -> io::Result<Option<u8>> {
+    if continuation {
+        let mut poll = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+        if unsafe { libc::poll(&mut poll, 1, 50) } <= 0 {
+            return Ok(None);
+        }
+    }
+    let mut byte = 0u8;
+    loop {
+        match unsafe { libc::read(libc::STDIN_FILENO, (&mut byte as *mut u8).cast(), 1) } {
+            1 => return Ok(Some(byte)),
+            0 => return Ok(None),
+            _ => {
+                let error = io::Error::last_os_error();
+                if error.kind() != io::ErrorKind::Interrupted {
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
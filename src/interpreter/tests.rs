@@ -0,0 +1,581 @@
+use super::{value::Value, Interpreter};
+use crate::{error::InterpreterResult, parser::Parser, tokenizer::Tokenizer};
+
+// The value of a script's last statement, written without a trailing `;`
+// (which would make the last statement an empty one)
+fn run(source: &str) -> InterpreterResult<Value> {
+    let mut tokenizer = Tokenizer::new();
+    tokenizer.tokenize(source).unwrap();
+    assert!(tokenizer.errors.is_empty(), "syntax error in {}", source);
+    let program = Parser::new(tokenizer.into_tokens()).parse()?;
+    Interpreter::new().interpret(program)
+}
+
+// A server on a free port and a socket connected to it, as `server` and
+// `client`, followed by the rest of the script
+fn with_connection(rest: &str) -> String {
+    format!(
+        r#"var server = await listen(0);
+        var parts = regexSplit(":", localAddress(server));
+        var client = await connect("127.0.0.1", toNumber(parts[1]));
+        {}"#,
+        rest
+    )
+}
+
+#[test]
+fn close_closes_a_socket_without_being_awaited() {
+    let script = with_connection(
+        r#"close(client);
+        var failed = false;
+        try { await write(client, "after close"); } catch (e) { failed = true; }
+        failed"#,
+    );
+    assert_eq!(run(&script).unwrap(), Value::Boolean(true));
+}
+
+#[test]
+fn defer_closes_a_socket_when_the_function_returns() {
+    let script = with_connection(
+        r#"fun send(socket) {
+            defer close(socket);
+            await write(socket, "hello");
+            return "sent";
+        }
+        send(client);
+        var failed = false;
+        try { await write(client, "after return"); } catch (e) { failed = true; }
+        failed"#,
+    );
+    assert_eq!(run(&script).unwrap(), Value::Boolean(true));
+}
+
+#[test]
+fn deferred_expressions_see_variables_as_the_block_ends() {
+    let script = r#"var seen = [];
+        var i = 0;
+        while (i < 2) {
+            defer seen = push(seen, i);
+            i = i + 1;
+        }
+        seen"#;
+    let expected = Value::Array(vec![Value::Number(1.0), Value::Number(2.0)]);
+    assert_eq!(run(script).unwrap(), expected);
+}
+
+#[test]
+fn deferred_promises_are_awaited() {
+    let script = r#"var done = false;
+        async fun finish() { done = true; }
+        fun work() { defer finish(); }
+        work();
+        done"#;
+    assert_eq!(run(script).unwrap(), Value::Boolean(true));
+}
+
+#[test]
+fn read_chunk_keeps_characters_cut_by_the_chunk_size_whole() {
+    let text = "héllo wörld ✓ 𝄞!";
+    let path = std::env::temp_dir().join(format!("alpha-read-chunk-{}.txt", std::process::id()));
+    std::fs::write(&path, text).unwrap();
+    for size in 1..=4 {
+        let script = format!(
+            r#"var file = openFile("{}");
+            var text = "";
+            var chunk = readChunk(file, {});
+            while (chunk != nil) {{
+                text = text + chunk;
+                chunk = readChunk(file, {});
+            }}
+            close(file);
+            text"#,
+            path.display(),
+            size,
+            size
+        );
+        assert_eq!(run(&script).unwrap(), Value::String(text.into()), "chunks of {} bytes", size);
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn error_headers_name_the_line_the_snippet_shows() {
+    let script = "var a = 1;\nvar b = 2;\nvar c = toHex(\"x\");\nc";
+    let error = run(script).unwrap_err();
+    assert!(error.to_string().starts_with("[line 3] Error"), "{}", error);
+    let snippet = error.location().and_then(|location| location.snippet(script)).unwrap();
+    assert!(snippet.trim_start().starts_with("3 | var c = toHex"), "{}", snippet);
+}
+
+#[test]
+fn plain_http_imports_need_a_pinned_hash() {
+    let error = super::remote::fetch("http://127.0.0.1:1/module.la").unwrap_err();
+    assert!(error.contains("#sha256="), "{}", error);
+    // With a pin it is downloaded, and fails only because nothing listens
+    let pinned = format!("http://127.0.0.1:1/module.la#sha256={}", "0".repeat(64));
+    let error = super::remote::fetch(&pinned).unwrap_err();
+    assert!(error.starts_with("could not download"), "{}", error);
+}
+
+#[test]
+fn socket_addresses_are_strings_without_awaiting() {
+    let script = with_connection(
+        r#"var peer = peerAddress(client);
+        var local = localAddress(client);
+        [typeOf(peer), peer == localAddress(server), typeOf(local)]"#,
+    );
+    let string = Value::String("string".into());
+    assert_eq!(run(&script).unwrap(), Value::Array(vec![string.clone(), Value::Boolean(true), string]));
+}
+
+#[test]
+fn http_serve_on_a_server_stops_at_shutdown() {
+    let script = r#"var server = await listen(0);
+        var parts = regexSplit(":", localAddress(server));
+        var port = parts[1];
+        fun handle(request) { return dict{"status": 200, "body": "[1, 2]"}; }
+        var serving = spawn(httpServe(server, handle));
+        var answer = await httpGetJson("http://127.0.0.1:" + port + "/");
+        var stopped = await shutdown(server, 5000);
+        await serving;
+        [answer, stopped]"#;
+    let answer = Value::Array(vec![Value::Number(1.0), Value::Number(2.0)]);
+    assert_eq!(run(script).unwrap(), Value::Array(vec![answer, Value::Boolean(true)]));
+}
+
+#[test]
+fn class_constants_may_end_with_a_semicolon() {
+    let script = r#"class Limits {
+            const LOW = 1;
+            const HIGH = 10
+        }
+        [Limits.LOW, Limits.HIGH]"#;
+    assert_eq!(run(script).unwrap(), Value::Array(vec![Value::Number(1.0), Value::Number(10.0)]));
+}
+
+#[test]
+fn instances_have_members_with_or_without_a_constructor() {
+    let script = r#"class Plain {
+            const SCALE = 2;
+            fun scaled(n) { return n * SCALE; }
+        }
+        class Built {
+            const FIRST = 1;
+            fun _construct(a) { this.b = scaled(FIRST) + a; }
+            fun scaled(n) { return n * 10; }
+        }
+        var plain = Plain();
+        var built = Built(5);
+        [plain.scaled(3), built.b]"#;
+    assert_eq!(run(script).unwrap(), Value::Array(vec![Value::Number(6.0), Value::Number(15.0)]));
+}
+
+#[test]
+fn hmac_sha256_matches_rfc_4231() {
+    let block_key = "aa".repeat(131);
+    let key_25: String = (1..=25).map(|b| format!("{:02x}", b)).collect();
+    // Key and data in hex, and the MAC the RFC gives for them
+    let cases = [
+        ("0b".repeat(20), hex("Hi There"), "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"),
+        (hex("Jefe"), hex("what do ya want for nothing?"), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"),
+        ("aa".repeat(20), "dd".repeat(50), "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe"),
+        (key_25, "cd".repeat(50), "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b"),
+        (
+            block_key.clone(),
+            hex("Test Using Larger Than Block-Size Key - Hash Key First"),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+        ),
+        (
+            block_key,
+            hex("This is a test using a larger than block-size key and a larger than block-size data. The key needs to be hashed before being used by the HMAC algorithm."),
+            "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+        ),
+    ];
+    for (key, data, expected) in cases {
+        let script = format!(
+            r#"var mac = hmacSha256(fromHex("{key}"), fromHex("{data}"));
+            [mac, hmacSha256(fromHex("{key}"), fromHex("{data}"), "bytes") == fromHex(mac)]"#
+        );
+        let expected = Value::Array(vec![Value::String(expected.into()), Value::Boolean(true)]);
+        assert_eq!(run(&script).unwrap(), expected);
+    }
+}
+
+fn hex(text: &str) -> String {
+    text.bytes().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[test]
+fn to_hex_takes_only_whole_numbers() {
+    let expected = Value::Array(vec![Value::String("ff".into()), Value::String("-10".into())]);
+    assert_eq!(run("[toHex(255), toHex(-16)]").unwrap(), expected);
+    // The largest double below 2^64 is the largest number it takes
+    let largest = run("toHex(18446744073709549568)").unwrap();
+    assert_eq!(largest, Value::String("fffffffffffff800".into()));
+    for number in ["1.5", "-0.5", "18446744073709551616", "-18446744073709551616"] {
+        let error = run(&format!("toHex({})", number)).unwrap_err().without_location();
+        assert!(error.to_string().contains("Invalid argument type"), "{}: {}", number, error);
+    }
+}
+
+#[test]
+fn format_writes_exponents_like_c() {
+    let script = r#"[format("%e", 1234.5), format("%.2e", 0.00012), format("%e", 0), format("%.1e", -123456789)]"#;
+    let expected = ["1.234500e+03", "1.20e-04", "0.000000e+00", "-1.2e+08"];
+    let expected = Value::Array(expected.iter().map(|s| Value::String((*s).into())).collect());
+    assert_eq!(run(script).unwrap(), expected);
+}
+
+// Calls a native with values made here, for text the language's string
+// literals cannot hold, such as quotes
+fn call(name: &str, args: Vec<Value>) -> InterpreterResult<Value> {
+    let mut interpreter = Interpreter::new();
+    let native = interpreter.environment().lock().unwrap().get(name);
+    match native {
+        Some(Value::NativeFunction(native)) => native.call(&mut interpreter, &args),
+        _ => panic!("there is no native {}", name),
+    }
+}
+
+fn string(text: &str) -> Value {
+    Value::String(text.into())
+}
+
+fn strings(texts: &[&str]) -> Value {
+    Value::Array(texts.iter().map(|text| string(text)).collect())
+}
+
+fn dictionary(entries: Vec<(&str, Value)>) -> Value {
+    Value::Dictionary(
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+#[test]
+fn failed_assertions_show_the_values_compared() {
+    let error = call("assertEquals", vec![strings(&["a"]), strings(&["b"]), string("lists")]).unwrap_err();
+    assert!(
+        error.to_string().contains("lists: expected [\"b\"], got [\"a\"]"),
+        "{}",
+        error
+    );
+    assert!(call("assertEquals", vec![Value::Number(1.0), string("1")]).is_err());
+    assert_eq!(
+        call("assertEquals", vec![strings(&["a"]), strings(&["a"])]).unwrap(),
+        Value::Nil
+    );
+    let script = r#"import "std/assert";
+        assertEqual([1], [1]);
+        assertNotEqual(1, "1");
+        assertTrue(true);
+        var failed = false;
+        try { assertEqual(1, "1"); } catch (e) { failed = true; }
+        failed"#;
+    assert_eq!(run(script).unwrap(), Value::Boolean(true));
+}
+
+// Fails unless the script ends in an invalid argument error
+fn assert_invalid_argument(script: &str) {
+    let error = run(script).unwrap_err().without_location();
+    assert!(error.to_string().contains("Invalid argument type"), "{}: {}", script, error);
+}
+
+#[test]
+fn timeouts_too_long_to_wait_for_are_invalid() {
+    let huge = "1000000000000 * 1000000000000 * 1000000000000";
+    for timeout in ["Infinity", "NaN", "-1", huge] {
+        assert_invalid_argument(&with_connection(&format!("setReadTimeout(client, {})", timeout)));
+        assert_invalid_argument(&format!("await wait(spawnProcess(\"sleep\", [\"0.1\"]), {})", timeout));
+    }
+    // Long but finite waits are fine
+    let script = with_connection("await setReadTimeout(client, 1000000000000 * 1000000000); true");
+    assert_eq!(run(&script).unwrap(), Value::Boolean(true));
+}
+
+#[test]
+fn await_timeout_rejects_waits_too_long_for_a_timer() {
+    for seconds in ["Infinity", "NaN", "-1", "1000000000000 * 1000000000000"] {
+        assert_invalid_argument(&format!("await awaitTimeout(delay(0.01), {})", seconds));
+    }
+    let script = "await awaitTimeout(delay(0.01), 1000000000000 * 1000000)";
+    assert_eq!(run(script).unwrap(), Value::Nil);
+}
+
+#[test]
+fn timers_reject_periods_too_long_to_wait_for() {
+    for ms in ["Infinity", "NaN", "-1", "1000000000000 * 1000000000000 * 1000"] {
+        assert_invalid_argument(&format!("fun tick() {{}} setTimeout(tick, {})", ms));
+        assert_invalid_argument(&format!("fun tick() {{}} setInterval(tick, {})", ms));
+    }
+    assert_invalid_argument("fun tick() {} setInterval(tick, 0)");
+}
+
+#[test]
+fn every_rejects_periods_that_cannot_be_waited_for() {
+    for seconds in ["Infinity", "NaN", "0", "-1", "1000000000000 * 1000000000000"] {
+        assert_invalid_argument(&format!("fun tick() {{}} every({}, tick)", seconds));
+    }
+}
+
+#[test]
+fn interval_rejects_periods_that_cannot_be_waited_for() {
+    for seconds in ["Infinity", "NaN", "0", "-1", "1000000000000 * 1000000000000"] {
+        assert_invalid_argument(&format!("interval({})", seconds));
+    }
+}
+
+#[test]
+fn delay_settles_at_once_for_past_times_and_rejects_endless_ones() {
+    assert_eq!(run("await delay(-1)").unwrap(), Value::Nil);
+    for seconds in ["Infinity", "NaN", "1000000000000 * 1000000000000"] {
+        assert_invalid_argument(&format!("await delay({})", seconds));
+    }
+}
+
+#[test]
+fn retry_takes_only_finite_options() {
+    for option in ["delay", "backoff", "attempts"] {
+        let script = format!("fun work() {{ return 1; }} await retry(work, dict{{\"{}\": Infinity}})", option);
+        let error = run(&script).unwrap_err();
+        assert!(error.to_string().contains("finite number"), "{}: {}", option, error);
+    }
+}
+
+#[test]
+fn format_rejects_huge_widths_and_precisions() {
+    assert_eq!(run(r#"format("%.100f", 1)"#).unwrap(), Value::String(format!("1.{}", "0".repeat(100)).into()));
+    for spec in ["%.101f", "%.999999999f", "%1001d", "%99999999999d", "%99999999999999999999999d"] {
+        let error = run(&format!(r#"format("{}", 1)"#, spec)).unwrap_err();
+        assert!(error.to_string().contains("Invalid"), "{}: {}", spec, error);
+    }
+}
+
+#[test]
+fn csv_quotes_fields_that_need_it() {
+    let rows = Value::Array(vec![
+        strings(&["a,b", "say \"hi\"", "line\nbreak", "plain"]),
+        strings(&["", "x"]),
+    ]);
+    let text = call("csvStringify", vec![rows.clone()]).unwrap();
+    assert_eq!(text, string("\"a,b\",\"say \"\"hi\"\"\",\"line\nbreak\",plain\n,x\n"));
+    assert_eq!(call("csvParse", vec![text]).unwrap(), rows);
+    let options = dictionary(vec![("header", Value::Boolean(true)), ("delimiter", string(";"))]);
+    let parsed = call("csvParse", vec![string("name;note\nada;\"a;b\"\n"), options]).unwrap();
+    assert_eq!(
+        parsed,
+        Value::Array(vec![dictionary(vec![("name", string("ada")), ("note", string("a;b"))])])
+    );
+}
+
+#[test]
+fn format_pads_aligns_and_picks_arguments() {
+    let args = vec![
+        string("%5d|%-5s|%05.1f|%x|%X|%o|%b|%+d|%2$s|%%"),
+        Value::Number(42.0),
+        string("ab"),
+        Value::Number(2.71),
+    ];
+    let args = [args, vec![Value::Number(255.0); 4], vec![Value::Number(7.0)]].concat();
+    let expected = "   42|ab   |002.7|ff|FF|377|11111111|+7|ab|%";
+    assert_eq!(call("format", args).unwrap(), string(expected));
+    assert!(call("format", vec![string("%d")]).is_err());
+    assert!(call("format", vec![string("%q"), Value::Number(1.0)]).is_err());
+}
+
+#[test]
+fn http_messages_parse() {
+    let request =
+        super::http::parse_request("POST /path?q=1 HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhello!").unwrap();
+    let Value::Dictionary(request) = request else {
+        unreachable!()
+    };
+    assert_eq!(request["method"], string("POST"));
+    assert_eq!(request["path"], string("/path"));
+    assert_eq!(request["query"], string("q=1"));
+    assert_eq!(request["body"], string("hello"));
+    assert_eq!(
+        request["headers"],
+        dictionary(vec![("host", string("x")), ("content-length", string("5"))])
+    );
+    let response = "HTTP/1.1 404 Not Found\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nnop\r\n1\r\ne\r\n0\r\n\r\n";
+    let Value::Dictionary(response) = super::http::parse_response(response).unwrap() else {
+        unreachable!()
+    };
+    assert_eq!(response["status"], Value::Number(404.0));
+    assert_eq!(response["body"], string("nope"));
+    assert!(super::http::parse_request("GET / HTTP/1.1\r\n").is_err());
+}
+
+#[test]
+fn urlencoded_forms_decode() {
+    let form = super::http::parse_urlencoded("a=1+2&b=%C3%A9%26&c");
+    assert_eq!(
+        Value::Dictionary(form),
+        dictionary(vec![("a", string("1 2")), ("b", string("é&")), ("c", string(""))])
+    );
+}
+
+#[test]
+fn json_parses_and_stringifies() {
+    let value = super::json::parse("{\"a\": [1, 2.5, \"\\u00e9\\n\"], \"b\": null, \"c\": false}").unwrap();
+    let expected = dictionary(vec![
+        (
+            "a",
+            Value::Array(vec![Value::Number(1.0), Value::Number(2.5), string("é\n")]),
+        ),
+        ("b", Value::Nil),
+        ("c", Value::Boolean(false)),
+    ]);
+    assert_eq!(value, expected);
+    assert_eq!(
+        super::json::parse(&super::json::stringify(&value, 0).unwrap()).unwrap(),
+        value
+    );
+    assert_eq!(super::json::quote("say \"hi\"\n\t\\"), "\"say \\\"hi\\\"\\n\\t\\\\\"");
+    for invalid in ["", "[1,]", "{\"a\" 1}", "tru", "\"open", "1 2"] {
+        assert!(super::json::parse(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn glob_patterns_match_a_segment_at_a_time() {
+    let cases = [
+        ("*.la", "main.la", true),
+        ("*.la", "src/main.la", false),
+        ("src/**/*.la", "src/main.la", true),
+        ("src/**/*.la", "src/a/b/main.la", true),
+        ("?.la", "ab.la", false),
+        ("[a-c]x", "bx", true),
+        ("[!a-c]x", "bx", false),
+        ("*.{la,alpha}", "test.alpha", true),
+        ("*", ".hidden", false),
+        (".*", ".hidden", true),
+    ];
+    for (pattern, path, expected) in cases {
+        assert_eq!(
+            super::glob::matches(pattern, path),
+            expected,
+            "{} against {}",
+            pattern,
+            path
+        );
+    }
+}
+
+#[test]
+fn xml_parses_and_round_trips() {
+    let root =
+        super::xml::parse("<?xml version=\"1.0\"?>\n<a x='1'>hi<!-- note --><b/><![CDATA[<raw>]]>&amp;&#x41;</a>")
+            .unwrap();
+    let expected = dictionary(vec![
+        ("name", string("a")),
+        ("attributes", dictionary(vec![("x", string("1"))])),
+        (
+            "children",
+            Value::Array(vec![
+                string("hi"),
+                dictionary(vec![
+                    ("name", string("b")),
+                    ("attributes", dictionary(Vec::new())),
+                    ("children", Value::Array(Vec::new())),
+                ]),
+                // Text, CDATA and entities next to each other are one string
+                string("<raw>&A"),
+            ]),
+        ),
+    ]);
+    assert_eq!(root, expected);
+    let text = super::xml::stringify(&root, 0).unwrap();
+    assert_eq!(super::xml::parse(&text).unwrap(), root);
+    let error = super::xml::parse("<a>\n  <b></a>").unwrap_err();
+    assert!(error.contains("line 2"), "{}", error);
+}
+
+#[test]
+fn html_parses_loose_markup_and_selects_elements() {
+    let document =
+        super::html::parse("<ul><li class=item><a href=/x>One</a><li class='item last'>Two &amp; more</ul><p>after");
+    let items = super::html::select(&document, "li.item").unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(super::html::text(&items[1]), "Two & more");
+    let links = super::html::select(&document, "ul > li > a[href]").unwrap();
+    assert_eq!(super::html::text(&links[0]), "One");
+    assert_eq!(super::html::select(&document, "li.last").unwrap().len(), 1);
+    assert_eq!(super::html::select(&document, "p").unwrap().len(), 1);
+    assert!(super::html::select(&document, "li[").is_err());
+}
+
+#[test]
+fn json_stream_splits_values_across_chunks() {
+    let expected = vec![
+        Value::Number(1.0),
+        string("aé\"]"),
+        dictionary(vec![("k", Value::Array(vec![Value::Boolean(true), Value::Nil]))]),
+        Value::Number(-25.0),
+    ];
+    let texts = [
+        "[1, \"aé\\\"]\", {\"k\": [true, null]}, -2.5e1]",
+        "1\n\"aé\\\"]\"\n{\"k\":[true,null]}\n-2.5e1",
+    ];
+    for text in texts {
+        for size in [1, 2, 3, 7] {
+            let mut splitter = super::json::JsonSplitter::new();
+            let mut values = Vec::new();
+            for chunk in text.as_bytes().chunks(size) {
+                splitter.feed(chunk, &mut values).unwrap();
+            }
+            values.extend(splitter.finish().unwrap());
+            assert_eq!(values, expected, "{} in chunks of {}", text, size);
+        }
+    }
+    let mut splitter = super::json::JsonSplitter::new();
+    splitter.feed(b"[1, 2", &mut Vec::new()).unwrap();
+    assert!(splitter.finish().is_err());
+    assert!(super::json::JsonSplitter::new()
+        .feed(b"[1 2]", &mut Vec::new())
+        .is_err());
+}
+
+#[test]
+fn marshal_round_trips_data() {
+    let value = dictionary(vec![
+        (
+            "numbers",
+            Value::Array(vec![
+                Value::Number(0.0),
+                Value::Number(-0.0),
+                Value::Number(-7.0),
+                Value::Number(2.5),
+                Value::Number(9_007_199_254_740_992.0),
+                Value::Number(1e300),
+            ]),
+        ),
+        ("text", string("héllo ✓")),
+        (
+            "flags",
+            Value::Array(vec![Value::Boolean(true), Value::Boolean(false), Value::Nil]),
+        ),
+        (
+            "nested",
+            dictionary(vec![
+                ("empty", Value::Array(Vec::new())),
+                ("none", dictionary(Vec::new())),
+            ]),
+        ),
+    ]);
+    let bytes = super::marshal::marshal(&value).unwrap();
+    let back = super::marshal::unmarshal(&bytes).unwrap();
+    assert_eq!(back, value);
+    let Value::Dictionary(back) = back else { unreachable!() };
+    let Value::Array(numbers) = &back["numbers"] else {
+        unreachable!()
+    };
+    assert!(matches!(numbers[1], Value::Number(n) if n == 0.0 && n.is_sign_negative()));
+    // Whole numbers take the one byte of a small integer
+    assert_eq!(super::marshal::marshal(&Value::Number(5.0)).unwrap(), vec![5]);
+    assert_eq!(super::marshal::marshal(&Value::Number(0.5)).unwrap().len(), 9);
+    assert!(super::marshal::unmarshal(&[0xc1]).is_err());
+}
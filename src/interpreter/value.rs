@@ -1,46 +1,337 @@
-use std::{collections::HashMap, fmt::{self, Debug}, future::Future, pin::Pin, sync::{Arc, Mutex}};
-use tokio::{net::{TcpListener, TcpSocket, TcpStream}, task::JoinHandle};
-use crate::{error::{InterpreterError, InterpreterResult}, parser::Expr};
+use std::{any::Any, cell::RefCell, collections::HashMap, fmt::{self, Debug}, fs::File, future::Future, io::BufReader, pin::Pin, sync::{atomic::{AtomicBool, AtomicI64, Ordering}, Arc, Mutex}};
+use tokio::{sync::{broadcast, mpsc, Mutex as AsyncMutex, Notify, OnceCell, Semaphore}, task::AbortHandle};
+use crate::{error::{InterpreterError, RuntimeErrorKind}, parser::Expr};
 
-use super::{enviroment::Environment, native::NativeFunction, Interpreter};
+use super::{enviroment::Environment, native::NativeFunction, pattern::Pattern, process::Process, socket::{Connection, Listener}};
 
 #[derive(Clone)]
 pub enum Value {
     Number(f64),
-    String(String),
+    String(Arc<str>),
     Boolean(bool),
     NativeFunction(NativeFunction),
-    Promise(Arc<Mutex<PromiseState>>),
-    Function(String, Vec<String>, Box<Expr>),
-    AsyncFunction(String, Vec<String>, Box<Expr>),
+    Promise(Arc<Promise>),
+    Function(String, Arc<[String]>, Arc<Expr>),
+    AsyncFunction(String, Arc<[String]>, Arc<Expr>),
     Class(String, HashMap<String, Value>),
     Instance(String, Arc<Mutex<Environment>>),
     Array(Vec<Value>),
     Dictionary(HashMap<String, Value>),
-    Socket(Arc<Mutex<TcpStream>>),
-    TlsSocket(Arc<Mutex<tokio_rustls::client::TlsStream<TcpStream>>>),
-    Server(Arc<Mutex<TcpListener>>),
+    Socket(Arc<AsyncMutex<Connection>>),
+    TlsSocket(Arc<AsyncMutex<Connection>>),
+    // Standard stream of a spawned process, used like a socket
+    Pipe(Arc<AsyncMutex<Connection>>),
+    Server(Arc<Listener>),
+    // None once the file has been closed
+    File(Arc<Mutex<Option<BufReader<File>>>>),
+    // The sender is dropped, closing the channel, when the script closes it
+    Sender(Arc<Mutex<Option<mpsc::Sender<Value>>>>),
+    Receiver(Arc<AsyncMutex<mpsc::Receiver<Value>>>),
+    // Broadcast topic; closing drops the sender, ending every subscription
+    Topic(Arc<Mutex<Option<broadcast::Sender<Value>>>>),
+    // Semaphore and its total permit count; mutex() is a semaphore of one
+    Semaphore(Arc<Semaphore>, usize),
+    // SQLite connection, None once closed
+    Database(Arc<Mutex<Option<rusqlite::Connection>>>),
+    // PostgreSQL client, None once closed
+    Postgres(Arc<Mutex<Option<Arc<tokio_postgres::Client>>>>),
+    // Pending setTimeout/setInterval callback
+    Timer(Arc<AbortHandle>),
+    // Text appended to in place by stringBuilder's append
+    StringBuilder(Arc<Mutex<String>>),
+    // Program started by spawnProcess
+    Process(Arc<Process>),
+    // Promises started by add, for wait to settle together
+    WaitGroup(Arc<Mutex<Vec<Arc<Promise>>>>),
+    // Whole number tasks change with atomic operations, from atomicCounter
+    Counter(Arc<AtomicI64>),
+    // Value tasks share, read and replaced whole, from sharedCell
+    Cell(Arc<Mutex<Value>>),
+    // Compiled regular expression, from a /pattern/flags literal or regex()
+    Regex(Arc<Pattern>),
+    // Object of the embedding program, handed to scripts and back to its
+    // natives as it is; see Value::from_extern
+    Extern(Arc<dyn Any + Send + Sync>),
     Nil,
 }
 
 
 
-pub enum PromiseState {
-    Pending(Pin<Box<dyn Future<Output = Result<Value, InterpreterError>>>>),
-    Fulfilled(Value),
-    Rejected(InterpreterError),
+pub type PromiseFuture = Pin<Box<dyn Future<Output = Result<Value, InterpreterError>> + Send>>;
+
+// The future runs once, the first time the promise is awaited, and every
+// await after that gets the same settled result
+pub struct Promise {
+    future: Mutex<Option<PromiseFuture>>,
+    result: OnceCell<Result<Value, InterpreterError>>,
+    cancellation: Arc<Cancellation>,
+}
+
+// Set when a promise is cancelled. The awaits of an async function's body
+// running in the promise's future watch it too, so cancelling stops the
+// function at the await it is waiting in.
+#[derive(Default)]
+struct Cancellation {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl Cancellation {
+    async fn wait(&self) {
+        let notified = self.notify.notified();
+        tokio::pin!(notified);
+        // Register before checking the flag so a cancel in between is not missed
+        notified.as_mut().enable();
+        if self.cancelled.load(Ordering::SeqCst) {
+            return;
+        }
+        notified.await
+    }
+}
+
+thread_local! {
+    // The cancellation of the promise whose future this thread is running
+    static RUNNING: RefCell<Option<Arc<Cancellation>>> = const { RefCell::new(None) };
+}
+
+impl Promise {
+    pub async fn settle(&self) -> Result<Value, InterpreterError> {
+        self.result
+            .get_or_init(|| async {
+                if self.cancellation.cancelled.load(Ordering::SeqCst) {
+                    return Err(cancelled_error());
+                }
+                let future = self.future.lock().unwrap().take();
+                match future {
+                    Some(mut future) => {
+                        let running = std::future::poll_fn(|cx| {
+                            let outer = RUNNING.with(|running| running.replace(Some(Arc::clone(&self.cancellation))));
+                            let poll = future.as_mut().poll(cx);
+                            RUNNING.with(|running| *running.borrow_mut() = outer);
+                            poll
+                        });
+                        tokio::select! {
+                            result = running => result,
+                            _ = self.cancellation.wait() => Err(cancelled_error()),
+                        }
+                    }
+                    // An earlier await was cancelled while running the future
+                    None => Err(InterpreterError::runtime_error(
+                        RuntimeErrorKind::PromiseRejected(0),
+                    )),
+                }
+            })
+            .await
+            .clone()
+    }
+
+    // Settles the promise for an `await` in the script. Awaited in the body
+    // of an async function whose promise is cancelled meanwhile, it is
+    // cancelled too and rejects.
+    pub fn awaited(&self) -> impl Future<Output = Result<Value, InterpreterError>> + '_ {
+        let outer = RUNNING.with(|running| running.borrow().clone());
+        async move {
+            match outer {
+                Some(outer) => tokio::select! {
+                    result = self.settle() => result,
+                    _ = outer.wait() => {
+                        self.cancel();
+                        Err(cancelled_error())
+                    }
+                },
+                None => self.settle().await,
+            }
+        }
+    }
+
+    // Drops the future, stopping whatever it was waiting on; awaiting the
+    // promise afterwards rejects. Returns false if it had already settled.
+    pub fn cancel(&self) -> bool {
+        if self.result.initialized() {
+            return false;
+        }
+        self.cancellation.cancelled.store(true, Ordering::SeqCst);
+        self.cancellation.notify.notify_waiters();
+        self.future.lock().unwrap().take();
+        // Not awaited yet: settle right away so nothing waits on it
+        let _ = self.result.set(Err(cancelled_error()));
+        true
+    }
+}
+
+fn cancelled_error() -> InterpreterError {
+    InterpreterError::runtime_error(RuntimeErrorKind::PromiseCancelled(0))
 }
 
 impl Value {
-    pub fn create_promise(future: Pin<Box<dyn Future<Output = Result<Value, InterpreterError>>>>) -> Value {
-        Value::Promise(Arc::new(Mutex::new(PromiseState::Pending(future))))
+    pub fn create_promise(future: PromiseFuture) -> Value {
+        Value::Promise(Arc::new(Promise {
+            future: Mutex::new(Some(future)),
+            result: OnceCell::new(),
+            cancellation: Arc::default(),
+        }))
+    }
+
+    // Results are dictionaries, dict{"ok": true, "value": v} or
+    // dict{"ok": false, "error": e}, so a failure can be returned as a value
+    // and checked without try/catch
+    pub fn ok(value: Value) -> Value {
+        Value::Dictionary(HashMap::from([
+            ("ok".to_string(), Value::Boolean(true)),
+            ("value".to_string(), value),
+        ]))
+    }
+
+    pub fn err(error: Value) -> Value {
+        Value::Dictionary(HashMap::from([
+            ("ok".to_string(), Value::Boolean(false)),
+            ("error".to_string(), error),
+        ]))
+    }
+
+    // Hands a Rust object to scripts, which can pass it around and back to
+    // natives but not look inside it
+    pub fn from_extern<T: Any + Send + Sync>(object: T) -> Value {
+        Value::Extern(Arc::new(object))
+    }
+
+    // The object from_extern wrapped, if this is one of type T
+    pub fn as_extern<T: Any + Send + Sync>(&self) -> Option<&T> {
+        match self {
+            Value::Extern(object) => object.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    // The same as a shared pointer, for keeping it after the call
+    pub fn extern_arc<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        match self {
+            Value::Extern(object) => Arc::clone(object).downcast().ok(),
+            _ => None,
+        }
+    }
+
+    // The value or error of a result, None for anything else
+    pub fn as_result(&self) -> Option<Result<Value, Value>> {
+        let Value::Dictionary(entries) = self else {
+            return None;
+        };
+        match entries.get("ok")? {
+            Value::Boolean(true) => Some(Ok(entries.get("value").cloned().unwrap_or(Value::Nil))),
+            Value::Boolean(false) => Some(Err(entries.get("error").cloned().unwrap_or(Value::Nil))),
+            _ => None,
+        }
+    }
+}
+
+// Structural equality: arrays and dictionaries by their elements, instances
+// of the same class by their fields, numbers as == compares them except that
+// NaN equals NaN, anything else as ==. An instance reached again while it is
+// being compared, through a field pointing back at it, counts as equal there
+// instead of being compared forever.
+pub fn deep_equals(a: &Value, b: &Value) -> bool {
+    deep_equals_visiting(a, b, &mut Vec::new())
+}
+
+fn deep_equals_visiting(a: &Value, b: &Value, visiting: &mut Vec<(usize, usize)>) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            a == b || (a - b).abs() < f64::EPSILON || (a.is_nan() && b.is_nan())
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| deep_equals_visiting(a, b, visiting))
+        }
+        (Value::Dictionary(a), Value::Dictionary(b)) => {
+            a.len() == b.len()
+                && a.iter().all(|(key, a)| b.get(key).is_some_and(|b| deep_equals_visiting(a, b, visiting)))
+        }
+        (Value::Instance(a_class, a_env), Value::Instance(b_class, b_env)) => {
+            if a_class != b_class {
+                return false;
+            }
+            let pair = (Arc::as_ptr(a_env) as usize, Arc::as_ptr(b_env) as usize);
+            if Arc::ptr_eq(a_env, b_env) || visiting.contains(&pair) {
+                return true;
+            }
+            // One lock at a time: the two may share fields
+            let fields = |environment: &Arc<Mutex<Environment>>| {
+                let mut fields = environment.lock().unwrap().get_values();
+                fields.remove("this");
+                fields
+            };
+            let (a_fields, b_fields) = (fields(a_env), fields(b_env));
+            visiting.push(pair);
+            let equal = a_fields.len() == b_fields.len()
+                && a_fields.iter().all(|(name, a)| {
+                    b_fields.get(name).is_some_and(|b| deep_equals_visiting(a, b, visiting))
+                });
+            visiting.pop();
+            equal
+        }
+        (a, b) => a == b,
+    }
+}
+
+// A copy sharing nothing that can change with the value: arrays and
+// dictionaries are copied with their elements, instances get fields of their
+// own. An instance reached twice is copied once, so the copy keeps the shape
+// of the original, cycles included. Functions and handles such as files and
+// sockets are shared.
+pub fn deep_copy(value: &Value) -> Value {
+    deep_copy_with(value, &mut Vec::new())
+}
+
+fn deep_copy_with(value: &Value, copies: &mut Vec<(usize, Value)>) -> Value {
+    match value {
+        Value::Array(elements) => Value::Array(elements.iter().map(|element| deep_copy_with(element, copies)).collect()),
+        Value::Dictionary(entries) => Value::Dictionary(
+            entries.iter().map(|(key, value)| (key.clone(), deep_copy_with(value, copies))).collect(),
+        ),
+        Value::Instance(class, environment) => {
+            let original = Arc::as_ptr(environment) as usize;
+            if let Some((_, copy)) = copies.iter().find(|(instance, _)| *instance == original) {
+                return copy.clone();
+            }
+            let (enclosing, fields) = {
+                let environment = environment.lock().unwrap();
+                (environment.get_enclosing(), environment.get_values())
+            };
+            let fields_copy = Environment::new_with_enclosing(enclosing);
+            let copy = Value::Instance(class.clone(), Arc::clone(&fields_copy));
+            copies.push((original, copy.clone()));
+            for (name, field) in fields {
+                let field = deep_copy_with(&field, copies);
+                fields_copy.lock().unwrap().define(&name, field);
+            }
+            copy
+        }
+        value => value.clone(),
+    }
+}
+
+// What typeOf can return
+pub const TYPE_NAMES: &[&str] = &[
+    "number", "string", "boolean", "nil", "function", "async function", "native function", "class",
+    "instance", "array", "dictionary", "socket", "tls socket", "server", "file", "sender", "receiver",
+    "semaphore", "timer", "database", "postgres", "promise", "string builder", "topic", "process", "pipe",
+    "wait group", "counter", "cell", "regex", "extern",
+];
+
+// Numbers as scripts write them: NaN and Infinity by those names
+pub fn format_number(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else {
+        n.to_string()
     }
 }
 
 impl Debug for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Value::Number(n) => write!(f, "{}", n),
+            Value::Number(n) => write!(f, "{}", format_number(*n)),
             Value::String(s) => write!(f, "\"{}\"", s),
             Value::Boolean(b) => write!(f, "{}", b),
             Value::Nil => write!(f, "nil"),
@@ -71,7 +362,23 @@ impl Debug for Value {
             },
             Value::Socket(_) => write!(f, "<socket>"),
             Value::TlsSocket(_) => write!(f, "<tls socket>"),
+            Value::Pipe(_) => write!(f, "<pipe>"),
             Value::Server(_) => write!(f, "<server>"),
+            Value::File(_) => write!(f, "<file>"),
+            Value::Sender(_) => write!(f, "<sender>"),
+            Value::Receiver(_) => write!(f, "<receiver>"),
+            Value::Topic(_) => write!(f, "<topic>"),
+            Value::Semaphore(..) => write!(f, "<semaphore>"),
+            Value::Timer(_) => write!(f, "<timer>"),
+            Value::StringBuilder(_) => write!(f, "<string builder>"),
+            Value::Process(process) => write!(f, "<process {}>", process.pid),
+            Value::WaitGroup(_) => write!(f, "<wait group>"),
+            Value::Counter(counter) => write!(f, "<counter {}>", counter.load(Ordering::SeqCst)),
+            Value::Cell(_) => write!(f, "<cell>"),
+            Value::Regex(pattern) => write!(f, "{}", pattern.literal()),
+            Value::Extern(_) => write!(f, "<extern>"),
+            Value::Database(_) => write!(f, "<database>"),
+            Value::Postgres(_) => write!(f, "<postgres>"),
             Value::Promise(_) => write!(f, "<promise>"),
         }
     }
@@ -86,28 +393,39 @@ impl PartialEq for Value{
             (Value::Nil, Value::Nil) => true,
             (Value::Function(a, _, _), Value::Function(b, _, _)) => a == b,
             (Value::Class(a, _), Value::Class(b, _)) => a == b,
-            (Value::Instance(a, a_en), Value::Instance(b, b_en)) => {
-                if a != b {
-                    return false;
-                }
-                let a_en = a_en.lock().unwrap();
-                let b_en = b_en.lock().unwrap();
-                a_en.get_values() == b_en.get_values()
-            },
+            (Value::Instance(..), Value::Instance(..)) => deep_equals(self, other),
             (Value::Array(a), Value::Array(b)) => a == b,
             (Value::Dictionary(a), Value::Dictionary(b)) => a == b,
             (Value::Socket(a), Value::Socket(b)) => Arc::ptr_eq(a, b),
+            (Value::TlsSocket(a), Value::TlsSocket(b)) => Arc::ptr_eq(a, b),
+            (Value::Pipe(a), Value::Pipe(b)) => Arc::ptr_eq(a, b),
             (Value::Server(a), Value::Server(b)) => Arc::ptr_eq(a, b),
+            (Value::File(a), Value::File(b)) => Arc::ptr_eq(a, b),
+            (Value::Database(a), Value::Database(b)) => Arc::ptr_eq(a, b),
+            (Value::Postgres(a), Value::Postgres(b)) => Arc::ptr_eq(a, b),
+            (Value::Sender(a), Value::Sender(b)) => Arc::ptr_eq(a, b),
+            (Value::Receiver(a), Value::Receiver(b)) => Arc::ptr_eq(a, b),
+            (Value::Topic(a), Value::Topic(b)) => Arc::ptr_eq(a, b),
+            (Value::Semaphore(a, _), Value::Semaphore(b, _)) => Arc::ptr_eq(a, b),
+            (Value::Timer(a), Value::Timer(b)) => Arc::ptr_eq(a, b),
+            (Value::StringBuilder(a), Value::StringBuilder(b)) => Arc::ptr_eq(a, b),
+            (Value::Process(a), Value::Process(b)) => Arc::ptr_eq(a, b),
+            (Value::WaitGroup(a), Value::WaitGroup(b)) => Arc::ptr_eq(a, b),
+            (Value::Counter(a), Value::Counter(b)) => Arc::ptr_eq(a, b),
+            (Value::Cell(a), Value::Cell(b)) => Arc::ptr_eq(a, b),
+            (Value::Regex(a), Value::Regex(b)) => a.source == b.source && a.flags == b.flags,
+            (Value::Extern(a), Value::Extern(b)) => Arc::ptr_eq(a, b),
             _ => false,
         }
     }
 }
 
 impl Value {
+    #[allow(clippy::inherent_to_string_shadow_display)]
     pub fn to_string(&self) -> String {
         match self {
-            Value::Number(n) => n.to_string(),
-            Value::String(s) => s.clone(),
+            Value::Number(n) => format_number(*n),
+            Value::String(s) => s.to_string(),
             Value::Boolean(b) => b.to_string(),
             Value::Nil => "nil".to_string(),
             Value::Function(name, _, _) => name.clone(),
@@ -133,7 +451,7 @@ impl Value {
                     if i > 0 {
                         s.push_str(", ");
                     }
-                    s.push_str(&k);
+                    s.push_str(k);
                     s.push_str(": ");
                     s.push_str(&v.to_string());
                 }
@@ -142,12 +460,29 @@ impl Value {
             }
             Value::Socket(_) => "socket".to_string(),
             Value::TlsSocket(_) => "tls socket".to_string(),
+            Value::Pipe(_) => "pipe".to_string(),
             Value::Server(_) => "server".to_string(),
+            Value::File(_) => "file".to_string(),
+            Value::Sender(_) => "sender".to_string(),
+            Value::Receiver(_) => "receiver".to_string(),
+            Value::Topic(_) => "topic".to_string(),
+            Value::Semaphore(..) => "semaphore".to_string(),
+            Value::Timer(_) => "timer".to_string(),
+            Value::StringBuilder(_) => "string builder".to_string(),
+            Value::Process(_) => "process".to_string(),
+            Value::WaitGroup(_) => "wait group".to_string(),
+            Value::Counter(_) => "counter".to_string(),
+            Value::Cell(_) => "cell".to_string(),
+            Value::Regex(pattern) => pattern.literal(),
+            Value::Extern(_) => "extern".to_string(),
+            Value::Database(_) => "database".to_string(),
+            Value::Postgres(_) => "postgres".to_string(),
             Value::AsyncFunction(name, _,_) => name.clone(),
             Value::Promise(_) => "promise".to_string(),
         }
     }
 
+    // One of TYPE_NAMES
     pub fn get_type(&self) -> String {
         match self {
             Value::Number(_) => "number".to_string(),
@@ -163,7 +498,23 @@ impl Value {
             Value::Dictionary(_) => "dictionary".to_string(),
             Value::Socket(_) => "socket".to_string(),
             Value::TlsSocket(_) => "tls socket".to_string(),
+            Value::Pipe(_) => "pipe".to_string(),
             Value::Server(_) => "server".to_string(),
+            Value::File(_) => "file".to_string(),
+            Value::Sender(_) => "sender".to_string(),
+            Value::Receiver(_) => "receiver".to_string(),
+            Value::Topic(_) => "topic".to_string(),
+            Value::Semaphore(..) => "semaphore".to_string(),
+            Value::Timer(_) => "timer".to_string(),
+            Value::StringBuilder(_) => "string builder".to_string(),
+            Value::Process(_) => "process".to_string(),
+            Value::WaitGroup(_) => "wait group".to_string(),
+            Value::Counter(_) => "counter".to_string(),
+            Value::Cell(_) => "cell".to_string(),
+            Value::Regex(_) => "regex".to_string(),
+            Value::Extern(_) => "extern".to_string(),
+            Value::Database(_) => "database".to_string(),
+            Value::Postgres(_) => "postgres".to_string(),
             Value::Promise(_) => "promise".to_string(),
         }
     }
@@ -173,7 +524,7 @@ impl Value {
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Value::Number(n) => write!(f, "{}", n),
+            Value::Number(n) => write!(f, "{}", format_number(*n)),
             Value::String(s) => write!(f, "{}", s),
             Value::Boolean(b) => write!(f, "{}", b),
             Value::Nil => write!(f, "nil"),
@@ -181,7 +532,7 @@ impl fmt::Display for Value {
             Value::AsyncFunction(name,_, _) => write!(f, "<async fn {}>", name),
             Value::NativeFunction(nf) => write!(f, "<native fn {}>", nf.name),
             Value::Class(name, _) => write!(f, "<class {}>", name),
-            Value::Instance(name, values) => write!(f, "<instance {} {:#?}>", name, values),
+            Value::Instance(name, _) => write!(f, "<instance {}>", name),
             Value::Array(arr) => {
                 write!(f, "[")?;
                 for (i, v) in arr.iter().enumerate() {
@@ -204,8 +555,37 @@ impl fmt::Display for Value {
             }
             Value::Socket(_) => write!(f, "socket"),
             Value::TlsSocket(_) => write!(f, "tls socket"),
+            Value::Pipe(_) => write!(f, "pipe"),
             Value::Server(_) => write!(f, "server"),
+            Value::File(_) => write!(f, "file"),
+            Value::Sender(_) => write!(f, "sender"),
+            Value::Receiver(_) => write!(f, "receiver"),
+            Value::Topic(_) => write!(f, "topic"),
+            Value::Semaphore(..) => write!(f, "semaphore"),
+            Value::Timer(_) => write!(f, "timer"),
+            Value::StringBuilder(_) => write!(f, "string builder"),
+            Value::Process(_) => write!(f, "process"),
+            Value::WaitGroup(_) => write!(f, "wait group"),
+            Value::Counter(_) => write!(f, "counter"),
+            Value::Cell(_) => write!(f, "cell"),
+            Value::Regex(pattern) => write!(f, "{}", pattern.literal()),
+            Value::Extern(_) => write!(f, "extern"),
+            Value::Database(_) => write!(f, "database"),
+            Value::Postgres(_) => write!(f, "postgres"),
             Value::Promise(_) => write!(f, "promise"),
         }
     }
-}
\ No newline at end of file
+}
+// Hosts move values, results and whole interpreters across threads and
+// tokio tasks; this stops compiling if a change breaks that
+const _: () = {
+    fn assert_send_sync<T: Send + Sync>() {}
+    fn assert_send<T: Send>() {}
+    #[allow(dead_code)]
+    fn assertions() {
+        assert_send_sync::<Value>();
+        assert_send_sync::<super::native::NativeFunction>();
+        assert_send_sync::<crate::error::InterpreterResult<Value>>();
+        assert_send::<super::Interpreter>();
+    }
+};
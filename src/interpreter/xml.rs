@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+
+use super::value::Value;
+
+// Parses an XML document into its root element. Elements are dictionaries
+//
+//   dict{"name": "item", "attributes": dict{"id": "1"}, "children": [...]}
+//
+// whose children are elements and strings of text, entities and CDATA
+// sections decoded. Text that is only whitespace between tags, comments,
+// processing instructions and the doctype are left out. Errors name the line
+// and column the text stops making sense at.
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = XmlParser { chars: text.chars().collect(), position: 0, line: 1, column: 1 };
+    parser.skip_misc()?;
+    let root = match parser.peek() {
+        Some('<') => parser.element()?,
+        Some(c) => return Err(parser.error(&format!("unexpected '{}' before the root element", c))),
+        None => return Err(parser.error("expected an element but the text ended")),
+    };
+    parser.skip_misc()?;
+    match parser.peek() {
+        None => Ok(root),
+        Some(c) => Err(parser.error(&format!("unexpected '{}' after the root element", c))),
+    }
+}
+
+// The element as XML text, on one line when indent is 0 and otherwise with
+// each child element on a line of its own, indented by that many spaces per
+// level. Attributes are sorted so the same tree always gives the same text.
+pub fn stringify(element: &Value, indent: usize) -> Result<String, String> {
+    let mut out = String::new();
+    write_element(&mut out, element, indent, 0)?;
+    Ok(out)
+}
+
+fn write_element(out: &mut String, element: &Value, indent: usize, depth: usize) -> Result<(), String> {
+    let Value::Dictionary(fields) = element else {
+        return Err(format!("a {} is not an XML element", element.get_type()));
+    };
+    let name = match fields.get("name") {
+        Some(Value::String(name)) if is_name(name) => name,
+        Some(Value::String(name)) => return Err(format!("'{}' is not an XML name", name)),
+        _ => return Err("an XML element needs a string \"name\"".to_string()),
+    };
+    out.push('<');
+    out.push_str(name);
+    match fields.get("attributes") {
+        None | Some(Value::Nil) => {}
+        Some(Value::Dictionary(attributes)) => {
+            let mut attributes: Vec<_> = attributes.iter().collect();
+            attributes.sort_by(|a, b| a.0.cmp(b.0));
+            for (key, value) in attributes {
+                if !is_name(key) {
+                    return Err(format!("'{}' is not an XML name", key));
+                }
+                out.push_str(&format!(" {}=\"{}\"", key, escape(&value.to_string(), true)));
+            }
+        }
+        Some(other) => return Err(format!("the attributes of <{}> are a {}, not a dictionary", name, other.get_type())),
+    }
+    let children = match fields.get("children") {
+        None | Some(Value::Nil) => &[][..],
+        Some(Value::Array(children)) => &children[..],
+        Some(other) => return Err(format!("the children of <{}> are a {}, not an array", name, other.get_type())),
+    };
+    if children.is_empty() {
+        out.push_str("/>");
+        return Ok(());
+    }
+    out.push('>');
+    // Indenting would change the text of elements that have some
+    let indented = indent > 0 && children.iter().all(|child| matches!(child, Value::Dictionary(_)));
+    for child in children {
+        if indented {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent * (depth + 1)));
+        }
+        match child {
+            Value::Dictionary(_) => write_element(out, child, indent, depth + 1)?,
+            Value::String(text) => out.push_str(&escape(text, false)),
+            Value::Number(_) | Value::Boolean(_) => out.push_str(&child.to_string()),
+            other => return Err(format!("a {} is not an XML element or text", other.get_type())),
+        }
+    }
+    if indented {
+        out.push('\n');
+        out.push_str(&" ".repeat(indent * depth));
+    }
+    out.push_str(&format!("</{}>", name));
+    Ok(())
+}
+
+fn escape(text: &str, attribute: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' if attribute => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_' || c == ':')
+        && chars.all(is_name_char)
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | ':' | '-' | '.')
+}
+
+struct XmlParser {
+    chars: Vec<char>,
+    position: usize,
+    line: usize,
+    column: usize,
+}
+
+impl XmlParser {
+    fn error(&self, message: &str) -> String {
+        format!("Invalid XML at line {} column {}: {}", self.line, self.column, message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        text.chars().enumerate().all(|(i, c)| self.chars.get(self.position + i) == Some(&c))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        if !self.starts_with(expected) {
+            return match self.peek() {
+                Some(c) => Err(self.error(&format!("expected '{}' but found '{}'", expected, c))),
+                None => Err(self.error(&format!("expected '{}' but the text ended", expected))),
+            };
+        }
+        for _ in expected.chars() {
+            self.next();
+        }
+        Ok(())
+    }
+
+    // Everything up to the terminator, which is consumed but not returned
+    fn until(&mut self, terminator: &str, what: &str) -> Result<String, String> {
+        let mut text = String::new();
+        while !self.starts_with(terminator) {
+            text.push(self.next().ok_or_else(|| self.error(&format!("unterminated {}", what)))?);
+        }
+        self.expect(terminator)?;
+        Ok(text)
+    }
+
+    // Whitespace, comments, processing instructions and doctypes around the root
+    fn skip_misc(&mut self) -> Result<(), String> {
+        loop {
+            self.skip_whitespace();
+            if self.starts_with("<?") {
+                self.until("?>", "processing instruction")?;
+            } else if self.starts_with("<!--") {
+                self.until("-->", "comment")?;
+            } else if self.starts_with("<!DOCTYPE") {
+                self.doctype()?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    // Skipped whole, including an internal subset in brackets
+    fn doctype(&mut self) -> Result<(), String> {
+        let mut depth = 0;
+        loop {
+            match self.next() {
+                Some('[') => depth += 1,
+                Some(']') => depth -= 1,
+                Some('>') if depth == 0 => return Ok(()),
+                Some(_) => {}
+                None => return Err(self.error("unterminated doctype")),
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let mut name = String::new();
+        while let Some(c) = self.peek().filter(|&c| is_name_char(c)) {
+            name.push(c);
+            self.next();
+        }
+        if !is_name(&name) {
+            return Err(self.error("expected a name"));
+        }
+        Ok(name)
+    }
+
+    fn element(&mut self) -> Result<Value, String> {
+        self.expect("<")?;
+        let name = self.name()?;
+        let mut attributes = HashMap::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('/') => {
+                    self.expect("/>")?;
+                    return Ok(element(name, attributes, Vec::new()));
+                }
+                Some('>') => {
+                    self.next();
+                    break;
+                }
+                Some(_) => {
+                    let key = self.name()?;
+                    self.skip_whitespace();
+                    self.expect("=")?;
+                    self.skip_whitespace();
+                    let value = self.attribute_value()?;
+                    if attributes.insert(key.clone(), Value::String(value.into())).is_some() {
+                        return Err(self.error(&format!("duplicate attribute '{}'", key)));
+                    }
+                }
+                None => return Err(self.error(&format!("unterminated <{}> tag", name))),
+            }
+        }
+        let children = self.content(&name)?;
+        Ok(element(name, attributes, children))
+    }
+
+    fn attribute_value(&mut self) -> Result<String, String> {
+        let quote = match self.next() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => return Err(self.error("expected a quoted attribute value")),
+        };
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                Some(c) if c == quote => return Ok(value),
+                Some('&') => value.push_str(&self.entity()?),
+                Some('<') => return Err(self.error("'<' in attribute value")),
+                Some(c) => value.push(c),
+                None => return Err(self.error("unterminated attribute value")),
+            }
+        }
+    }
+
+    // Children up to and including the closing tag
+    fn content(&mut self, name: &str) -> Result<Vec<Value>, String> {
+        let mut children = Vec::new();
+        let mut text = String::new();
+        loop {
+            if self.starts_with("</") {
+                push_text(&mut children, &mut text);
+                self.expect("</")?;
+                let closing = self.name()?;
+                if closing != name {
+                    return Err(self.error(&format!("</{}> closes <{}>", closing, name)));
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+                return Ok(children);
+            } else if self.starts_with("<!--") {
+                self.until("-->", "comment")?;
+            } else if self.starts_with("<![CDATA[") {
+                self.expect("<![CDATA[")?;
+                text.push_str(&self.until("]]>", "CDATA section")?);
+            } else if self.starts_with("<?") {
+                self.until("?>", "processing instruction")?;
+            } else if self.starts_with("<") {
+                push_text(&mut children, &mut text);
+                children.push(self.element()?);
+            } else {
+                match self.next() {
+                    Some('&') => text.push_str(&self.entity()?),
+                    Some(c) => text.push(c),
+                    None => return Err(self.error(&format!("<{}> is never closed", name))),
+                }
+            }
+        }
+    }
+
+    // The text of an entity reference, after its '&'
+    fn entity(&mut self) -> Result<String, String> {
+        let reference = self.until(";", "entity reference")?;
+        let decoded = match reference.as_str() {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match reference.strip_prefix("#x").or_else(|| reference.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                None => reference.strip_prefix('#').and_then(|decimal| decimal.parse().ok()).and_then(char::from_u32),
+            },
+        };
+        decoded
+            .map(String::from)
+            .ok_or_else(|| self.error(&format!("unknown entity &{};", reference)))
+    }
+}
+
+fn element(name: String, attributes: HashMap<String, Value>, children: Vec<Value>) -> Value {
+    Value::Dictionary(HashMap::from([
+        ("name".to_string(), Value::String(name.into())),
+        ("attributes".to_string(), Value::Dictionary(attributes)),
+        ("children".to_string(), Value::Array(children)),
+    ]))
+}
+
+// Adds the text gathered so far as a child unless it is only whitespace
+fn push_text(children: &mut Vec<Value>, text: &mut String) {
+    if !text.trim().is_empty() {
+        children.push(Value::String(text.as_str().into()));
+    }
+    text.clear();
+}
@@ -0,0 +1,6 @@
+// The language as a library, for programs that embed the interpreter. The
+// `alpha` executable is built on top of it.
+pub mod error;
+pub mod interpreter;
+pub mod parser;
+pub mod tokenizer;
@@ -1,59 +1,272 @@
-use std::env;
 use std::fs;
-use std::io::{self, Write};
+use std::io::Write as _;
 use std::path::Path;
 use std::path::PathBuf;
-pub mod tokenizer;
-use tokenizer::Tokenizer;
-pub mod parser;
-use parser::Parser;
-pub mod error;
-pub mod interpreter;
-
-#[tokio::main]
-async fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 1 {
-        writeln!(io::stderr(), "Usage: {} alpha <filename>", args[0]).unwrap();
-        return;
-    }
-    let filename = &args[1];
-    let file_path = PathBuf::from(filename);
-    let base_dir = file_path.parent()
-        .unwrap_or_else(|| Path::new(""))
-        .to_path_buf();
-
-    let file_contents = fs::read_to_string(filename).unwrap_or_else(|_| {
-        writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
-        String::new()
-    });
-    if !file_contents.is_empty() {
-        let mut tokenizer = Tokenizer::new();
-        let input = file_contents.as_str();
-        tokenizer.tokenize(input).unwrap();
-        if tokenizer.errors.iter().count() > 0 {
-            std::process::exit(65);
-        }
-        let mut parser = Parser::new(tokenizer.get_tokens());
-        let exprs = parser.parse();
-        match exprs {
-            Ok(exprs) => {
-                let mut interpreter = interpreter::Interpreter::new_with_base_path(base_dir);
-                match interpreter.interpret(exprs) {
-                    Ok(_) => {}
+use clap::{error::ErrorKind, CommandFactory, Parser as _, ValueEnum as _};
+use alpha::{error, interpreter, parser, tokenizer};
+use tokenizer::Token;
+use parser::Expr;
+pub mod cli;
+use cli::{BuildArgs, Cli, Command, RunArgs};
+use error::{InterpreterError, RuntimeErrorKind};
+use interpreter::{native_functions::exit_code, options::InterpreterOptions, value::Value};
+
+fn main() {
+    // An executable built with --self-contained runs its script, every
+    // argument going to the script
+    if let Some(source) = cli::build::embedded_script() {
+        interpreter::native_functions::set_script_args(std::env::args().skip(1).collect());
+        run(parse(&source, "-"), &source, "-", PathBuf::new(), InterpreterOptions::default());
+    }
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(error) => {
+            let _ = error.print();
+            std::process::exit(match error.kind() {
+                ErrorKind::DisplayHelp | ErrorKind::DisplayVersion => 0,
+                _ => cli::EXIT_USAGE,
+            });
+        }
+    };
+    match cli.command {
+        Some(Command::Run(args)) => run_command(args),
+        Some(Command::Repl) => cli::repl::start(),
+        Some(Command::Lint(args)) => {
+            cli::set_diagnostics(args.diagnostics);
+            cli::lint::start(&args.files)
+        }
+        Some(Command::Fmt(args)) => cli::fmt::start(&args.files, args.check),
+        Some(Command::Bench(args)) => cli::bench::start(&args.paths),
+        Some(Command::Test(args)) => cli::test::start(&args.paths),
+        Some(Command::Install(args)) => cli::install::start(&args.sources),
+        Some(Command::Bundle(args)) => cli::bundle::start(&args.script, args.output.as_deref()),
+        Some(Command::Compile(args)) => cli::compile::start(&args.script, args.output.as_deref()),
+        Some(Command::Build(args)) => build_command(args),
+        None => run_command(cli.run),
+    }
+}
+
+fn run_command(mut args: RunArgs) {
+    cli::set_diagnostics(args.diagnostics);
+    // In a project, `alpha run` alone runs its entry point from the project root
+    if args.eval.is_none() && args.args.is_empty() {
+        if let Some((dir, manifest)) = cli::manifest::find() {
+            if let Err(error) = std::env::set_current_dir(&dir) {
+                eprintln!("Failed to enter {}: {}", dir.display(), error);
+                std::process::exit(cli::EXIT_NO_INPUT);
+            }
+            args.args.push(manifest.package.entry.to_string_lossy().into_owned());
+            args.args.extend(manifest.run.args);
+            if args.trace.is_none() {
+                args.trace = manifest.run.trace.and_then(|trace| trace.flag());
+            }
+            if args.sandbox.is_none() {
+                args.sandbox = manifest.run.sandbox.and_then(|sandbox| sandbox.flag());
+            }
+            args.strict |= manifest.run.strict;
+        }
+    }
+    let (source, compiled, name, base_dir, script_args) = match (args.eval, args.args.split_first()) {
+        // Imports are resolved from the working directory
+        (Some(source), _) => (source, None, "-e".to_string(), PathBuf::new(), args.args.as_slice()),
+        (None, Some((filename, _))) if args.watch => {
+            let mut run_args = Vec::new();
+            if args.check {
+                run_args.push("--check".to_string());
+            }
+            if let Some(function) = &args.trace {
+                run_args.push(format!("--trace={}", function));
+            }
+            if args.strict {
+                run_args.push("--strict".to_string());
+            }
+            if args.lazy_imports {
+                run_args.push("--lazy-imports".to_string());
+            }
+            if args.diagnostics == cli::DiagnosticsFormat::Json {
+                run_args.push("--diagnostics=json".to_string());
+            }
+            if let Some(denied) = &args.sandbox {
+                let denied: Vec<_> = denied
+                    .iter()
+                    .filter_map(|capability| capability.to_possible_value())
+                    .map(|value| value.get_name().to_string())
+                    .collect();
+                run_args.push(format!("--sandbox={}", denied.join(",")));
+            }
+            if args.tokenize {
+                run_args.push("--tokenize".to_string());
+            }
+            if args.parse {
+                run_args.push("--parse".to_string());
+            }
+            run_args.push("--".to_string());
+            run_args.extend(args.args.iter().cloned());
+            cli::watch::start(Path::new(filename), run_args);
+        }
+        (None, Some((filename, script_args))) => {
+            let bytes = fs::read(filename).unwrap_or_else(|error| {
+                eprintln!("Failed to read file {}: {}", filename, error);
+                std::process::exit(cli::EXIT_NO_INPUT);
+            });
+            let (source, compiled) = if cli::compile::is_compiled(&bytes) {
+                match cli::compile::decode(&bytes) {
+                    Ok(exprs) => (String::new(), Some(exprs)),
                     Err(error) => {
-                        eprintln!("{}", error);
-                        std::process::exit(70);
+                        eprintln!("{}: {}", filename, error);
+                        std::process::exit(cli::EXIT_DATA_ERROR);
                     }
                 }
-                interpreter.runtime.shutdown_background();
+            } else {
+                let source = String::from_utf8(bytes).unwrap_or_else(|error| {
+                    eprintln!("Failed to read file {}: {}", filename, error);
+                    std::process::exit(cli::EXIT_NO_INPUT);
+                });
+                (source, None)
+            };
+            let base_dir = Path::new(filename)
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .to_path_buf();
+            (source, compiled, filename.clone(), base_dir, script_args)
+        }
+        (None, None) => {
+            let _ = Cli::command()
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    "a script FILE or -e PROGRAM is required outside a project with an alpha.toml (`alpha repl` starts an interactive session)",
+                )
+                .print();
+            std::process::exit(cli::EXIT_USAGE);
+        }
+    };
+    interpreter::native_functions::set_script_args(script_args.to_vec());
+    if compiled.is_some() && (args.check || args.tokenize) {
+        let _ = Cli::command()
+            .error(ErrorKind::ArgumentConflict, "--check and --tokenize need the script's source, not a compiled file")
+            .print();
+        std::process::exit(cli::EXIT_USAGE);
+    }
+    if args.check {
+        cli::check::start(&source, &name, &base_dir);
+    } else if args.tokenize {
+        print_lines(tokenize(&source, &name).into_iter().map(|token| match token.literal {
+            Some(literal) => format!("{:>4} {:<14} {} {}", token.line, token.token_type.to_string(), token.lexeme, literal),
+            None => format!("{:>4} {:<14} {}", token.line, token.token_type.to_string(), token.lexeme),
+        }));
+    } else if args.parse {
+        let exprs = compiled.unwrap_or_else(|| parse(&source, &name));
+        print_lines(exprs.into_iter().map(|(expr, line)| format!("{:>4} {}", line, expr.to_rpn())));
+    } else {
+        if let Some(function) = args.trace {
+            interpreter::enable_trace(Some(function).filter(|name| !name.is_empty()));
+        }
+        if args.debug_on_error {
+            interpreter::enable_post_mortem();
+        }
+        let mut options = cli::sandbox_options(args.sandbox.as_deref());
+        options.strict = args.strict;
+        options.lazy_imports = args.lazy_imports;
+        let exprs = compiled.unwrap_or_else(|| parse(&source, &name));
+        if args.strict {
+            strict_check(&exprs, &name, &base_dir);
+        }
+        run(exprs, &source, &name, base_dir, options);
+    }
+}
+
+// Writes the lines to standard output. A reader that stops early, as
+// `| head` does, closes the pipe, which ends the output without an error.
+fn print_lines(mut lines: impl Iterator<Item = String>) {
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    match lines.try_for_each(|line| writeln!(out, "{}", line)).and_then(|_| out.flush()) {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::BrokenPipe => std::process::exit(0),
+        Err(error) => {
+            eprintln!("Failed to write output: {}", error);
+            std::process::exit(cli::EXIT_FAILURE);
+        }
+    }
+}
+
+fn build_command(args: BuildArgs) {
+    let script = match args.script {
+        Some(script) => script,
+        None => match cli::manifest::find() {
+            Some((dir, manifest)) => dir.join(manifest.package.entry),
+            None => {
+                let _ = Cli::command()
+                    .error(
+                        ErrorKind::MissingRequiredArgument,
+                        "a script FILE is required outside a project with an alpha.toml",
+                    )
+                    .print();
+                std::process::exit(cli::EXIT_USAGE);
+            }
+        },
+    };
+    cli::build::start(&script, args.output.as_deref(), args.self_contained);
+}
+
+// Exits with status 65 if the source does not tokenize
+fn tokenize(source: &str, name: &str) -> Vec<Token> {
+    let mut tokenizer = cli::tokenizer();
+    tokenizer.tokenize(source).unwrap();
+    if !tokenizer.errors.is_empty() {
+        cli::report_tokenizer_errors(&tokenizer, name);
+        std::process::exit(cli::EXIT_DATA_ERROR);
+    }
+    tokenizer.into_tokens()
+}
+
+// Exits with status 65 if the source does not parse
+fn parse(source: &str, name: &str) -> Vec<(Expr, usize)> {
+    match cli::parse(tokenize(source, name)) {
+        Ok(exprs) => exprs,
+        Err(error) => {
+            cli::report_error(&error, source, name);
+            std::process::exit(cli::EXIT_DATA_ERROR);
+        }
+    }
+}
+
+// Exits with status 65 after printing what --strict finds, if anything
+fn strict_check(exprs: &[(Expr, usize)], name: &str, base_dir: &Path) {
+    let statements: Vec<Expr> = exprs.iter().map(|(expr, _)| expr.clone()).collect();
+    let diagnostics = cli::lint::strict_diagnostics(&statements, base_dir);
+    for diagnostic in &diagnostics {
+        match cli::diagnostics() {
+            cli::DiagnosticsFormat::Human => {
+                eprintln!("{}:{}:{}: {}", name, diagnostic.line, diagnostic.column, diagnostic.message)
             }
-            Err(error) => {
-                eprintln!("{}", error);
-                std::process::exit(65);
+            cli::DiagnosticsFormat::Json => eprintln!("{}", diagnostic.record("error", name)),
+        }
+    }
+    if !diagnostics.is_empty() {
+        std::process::exit(cli::EXIT_DATA_ERROR);
+    }
+}
+
+// Interprets a program, then exits with its status
+fn run(exprs: Vec<(Expr, usize)>, source: &str, name: &str, base_dir: PathBuf, options: InterpreterOptions) -> ! {
+    let mut interpreter = interpreter::Interpreter::builder().base_path(base_dir).options(options).build();
+    match interpreter.interpret(exprs) {
+        // Background tasks still running end with the process
+        // rather than being torn down while they run script code
+        Ok(_) => std::process::exit(exit_code()),
+        // A top-level `return` ends the script, a number it returns
+        // becoming the exit status
+        Err(InterpreterError::RuntimeError(RuntimeErrorKind::Return(value))) => match value {
+            Value::Number(code) => std::process::exit(code as i32),
+            _ => std::process::exit(exit_code()),
+        },
+        Err(error) => {
+            cli::report_error(&error, source, name);
+            // Only kept under --debug-on-error
+            if let Some(failure) = interpreter.take_failure() {
+                cli::repl::post_mortem(&mut interpreter, failure);
             }
+            std::process::exit(cli::EXIT_SOFTWARE);
         }
-    } else {
-        println!("Eof  null");
     }
-}
\ No newline at end of file
+}
@@ -1,16 +1,23 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
 
 use crate::{
     error::{InterpreterError, InterpreterResult},
     tokenizer::{Token, TokenType},
 };
-#[derive(Debug, Clone, PartialEq)]
+pub mod printer;
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TryCatch {
     pub try_block: Box<Expr>,
     pub catch_param: String,  // The error parameter name
     pub catch_block: Box<Expr>
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Expr {
     Binary(Box<Expr>, Token, Box<Expr>),
     Logical(Box<Expr>, Token, Box<Expr>),
@@ -24,19 +31,32 @@ pub enum Expr {
     Assign(Token, Box<Expr>),               // For variable assignment 
     Let(Token, Box<Expr>),                  // For variable declaration
     Block(Vec<Expr>),                       // For block of expressions
-    Function(Token, Vec<Token>, Box<Expr>), // Function declaration
-    AsyncFunction(Token, Vec<Token>, Box<Expr>), // Function declaration
+    Function(Token, Vec<Token>, Arc<Expr>), // Function declaration
+    AsyncFunction(Token, Vec<Token>, Arc<Expr>), // Function declaration
     Class(Token, Vec<Expr>),                // Class declaration
     Call(Option<Box<Expr>>, Box<Expr>, Vec<Expr>),      // Function call (owner, func, args)
     Await(Box<Expr>), // Async function call (owner, func, args
     If(Box<Expr>, Box<Expr>, Box<Expr>),
     While(Box<Expr>, Box<Expr>),
     For(Box<Expr>, Box<Expr>, Box<Expr>, Box<Expr>),
-    Import(Box<Expr>),
+    ForAwait(Token, Box<Expr>, Box<Expr>), // for await (name in iterable) body
+    // The name a data file is imported as, if any
+    Import(Box<Expr>, Option<Token>),
+    // Passes on a module's names to whoever imports this one: all of them
+    // for `export *`, or each listed name under the name it is exported as
+    Export(Box<Expr>, Option<Vec<(Token, Token)>>),
     Return(Token, Box<Expr>),
+    // Raises the value; a catch gets it unchanged
+    Throw(Token, Box<Expr>),
+    // Runs the expression when the enclosing block ends, however it ends.
+    // It is evaluated then, not when the defer is reached, so it sees the
+    // variables it names as they are at the end of the block.
+    Defer(Token, Arc<Expr>),
     // Break(Token),
     Get(Box<Expr>, Box<Expr>),
-    Set(Token, Box<Expr>, Box<Expr>),
+    // Stores the value under the key of the target, itself a variable or
+    // another Get: `a[1][2] = value` is Set(Get(a, 1), 2, value)
+    Set(Box<Expr>, Box<Expr>, Box<Expr>),
     TryCatch(TryCatch),
     // This(Token),
     // Super(Token, Token),
@@ -52,13 +72,13 @@ impl Expr {
                 format!("(group {})", expr.to_rpn())
             }
             Expr::Literal(_, literal) => {
-                format!("{}", literal)
+                literal.to_string()
             }
             Expr::Unary(operator, expr) => {
                 format!("({} {})", operator.lexeme, expr.to_rpn())
             }
             Expr::Nil => {
-                format!("nil nil")
+                "nil nil".to_string()
             }
             Expr::Variable(token) => {
                 format!("var {}", token.lexeme)
@@ -106,12 +126,32 @@ impl Expr {
             Expr::For(initializer, condition, increment, body) => {
                 format!("for {} {} {} {}", initializer.to_rpn(), condition.to_rpn(), increment.to_rpn(), body.to_rpn())
             }
+            Expr::ForAwait(name, iterable, body) => {
+                format!("for-await {} {} {}", name.lexeme, iterable.to_rpn(), body.to_rpn())
+            }
             Expr::Return(token, expr) => {
                 format!("return {} {}", token.lexeme, expr.to_rpn())
             }
-            Expr::Import(module) => {
-                format!("import {}", module.to_rpn())
-            }
+            Expr::Throw(token, expr) => {
+                format!("throw {} {}", token.lexeme, expr.to_rpn())
+            }
+            Expr::Defer(token, expr) => {
+                format!("defer {} {}", token.lexeme, expr.to_rpn())
+            }
+            Expr::Import(module, alias) => match alias {
+                Some(alias) => format!("import {} as {}", module.to_rpn(), alias.lexeme),
+                None => format!("import {}", module.to_rpn()),
+            },
+            Expr::Export(module, names) => match names {
+                Some(names) => {
+                    let names: Vec<String> = names
+                        .iter()
+                        .map(|(name, exported)| format!("{} as {}", name.lexeme, exported.lexeme))
+                        .collect();
+                    format!("export {{ {} }} from {}", names.join(", "), module.to_rpn())
+                }
+                None => format!("export * from {}", module.to_rpn()),
+            },
             Expr::Class(token, methods) => {
                 let mut rpn = String::new();
                 for method in methods {
@@ -127,7 +167,7 @@ impl Expr {
                 format!("get {} {}", object.to_rpn(), name.to_rpn())
             }
             Expr::Set(object, name, value) => {
-                format!("set {:?} {} {}", object, name.to_rpn(), value.to_rpn())
+                format!("set {} {} {}", object.to_rpn(), name.to_rpn(), value.to_rpn())
             }
             Expr::Array(elements) => {
                 let mut rpn = String::new();
@@ -138,10 +178,41 @@ impl Expr {
                 format!("array {}", rpn)
             }
             _ => {
-                format!("unknown")
+                "unknown".to_string()
             }
         }
     }
+
+    // The first token of the expression, which is where it starts in the source
+    pub fn first_token(&self) -> Option<&Token> {
+        match self {
+            Expr::Literal(token, _)
+            | Expr::Unary(token, _)
+            | Expr::Variable(token)
+            | Expr::Assign(token, _)
+            | Expr::Let(token, _)
+            | Expr::Function(token, ..)
+            | Expr::AsyncFunction(token, ..)
+            | Expr::Class(token, _)
+            | Expr::Return(token, _)
+            | Expr::Throw(token, _)
+            | Expr::Defer(token, _)
+            | Expr::ForAwait(token, ..) => Some(token),
+            Expr::Binary(left, ..) | Expr::Logical(left, ..) => left.first_token(),
+            Expr::Grouping(expr) | Expr::Await(expr) | Expr::Import(expr, _) | Expr::Export(expr, _) => {
+                expr.first_token()
+            }
+            Expr::Call(Some(owner), ..) => owner.first_token(),
+            Expr::Call(None, callee, _) => callee.first_token(),
+            Expr::Get(object, _) | Expr::Set(object, ..) => object.first_token(),
+            Expr::If(condition, ..) | Expr::While(condition, _) => condition.first_token(),
+            Expr::For(initializer, condition, ..) => initializer.first_token().or_else(|| condition.first_token()),
+            Expr::Block(statements) | Expr::Array(statements) => statements.iter().find_map(Expr::first_token),
+            Expr::Dictionary(entries) => entries.iter().find_map(|(key, _)| key.first_token()),
+            Expr::TryCatch(try_catch) => try_catch.try_block.first_token(),
+            Expr::Nil => None,
+        }
+    }
 }
 
 pub struct Parser {
@@ -158,13 +229,20 @@ impl Parser {
         let mut statements = Vec::new();
 
         while !self.is_at_end() {
+            // Errors in the statement are reported at the line it starts on
+            let line = self.peek().line;
             let stmt = self.expression()?;
-            statements.push((stmt, self.peek().line));
+            statements.push((stmt, line));
         }
 
         Ok(statements)
     }
 
+    // Where parsing stopped, which is where a parse error is shown
+    pub fn current_token(&self) -> Token {
+        self.peek().clone()
+    }
+
     fn match_token(&mut self, token_type: TokenType) -> bool {
         if self.check(token_type) {
             self.advance();
@@ -178,19 +256,19 @@ impl Parser {
         self.comparison()
     }
 
-    fn peek(&self) -> Token {
-        self.tokens[self.current].clone()
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current]
     }
 
-    fn previous(&self) -> Token {
-        self.tokens[self.current - 1].clone()
+    fn previous(&self) -> &Token {
+        &self.tokens[self.current - 1]
     }
 
     fn is_at_end(&self) -> bool {
         self.peek().token_type == TokenType::Eof
     }
 
-    fn advance(&mut self) -> Token {
+    fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
             self.current += 1;
         }
@@ -199,15 +277,17 @@ impl Parser {
 
     fn comparison(&mut self) -> InterpreterResult<Expr> {
         let mut expr = self.logical()?;
-        while self.match_tokens(vec![
+        while self.match_tokens(&[
             TokenType::Greater,
             TokenType::GreaterEqual,
             TokenType::Less,
             TokenType::LessEqual,
             TokenType::BandEqual,
             TokenType::EqualEqual,
+            TokenType::StrictEqual,
+            TokenType::StrictNotEqual,
         ]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let right = self.logical()?;
             expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
         }
@@ -217,11 +297,11 @@ impl Parser {
 
     fn logical(&mut self) -> InterpreterResult<Expr> {
         let mut expr = self.term()?;
-        while self.match_tokens(vec![
+        while self.match_tokens(&[
             TokenType::Or,
             TokenType::And,
         ]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let right = self.term()?;
             expr = Expr::Logical(Box::new(expr), operator, Box::new(right));
         }
@@ -231,8 +311,8 @@ impl Parser {
 
     fn term(&mut self) -> InterpreterResult<Expr> {
         let mut expr = self.factor()?;
-        while self.match_tokens(vec![TokenType::Minus, TokenType::Plus]) {
-            let operator = self.previous();
+        while self.match_tokens(&[TokenType::Minus, TokenType::Plus]) {
+            let operator = self.previous().clone();
             let right = self.factor()?;
             expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
         }
@@ -241,8 +321,8 @@ impl Parser {
 
     fn factor(&mut self) -> InterpreterResult<Expr> {
         let mut expr = self.unary()?;
-        while self.match_tokens(vec![TokenType::Slash, TokenType::Star, TokenType::Modulo]) {
-            let operator = self.previous();
+        while self.match_tokens(&[TokenType::Slash, TokenType::Star, TokenType::Modulo, TokenType::Div]) {
+            let operator = self.previous().clone();
             let right = self.unary()?;
             expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
         }
@@ -250,8 +330,8 @@ impl Parser {
     }
 
     fn unary(&mut self) -> InterpreterResult<Expr> {
-        if self.match_tokens(vec![TokenType::Bang, TokenType::Minus]) {
-            let operator = self.previous();
+        if self.match_tokens(&[TokenType::Bang, TokenType::Minus]) {
+            let operator = self.previous().clone();
             let right = self.unary()?;
             return Ok(Expr::Unary(operator, Box::new(right)));
         }
@@ -260,86 +340,100 @@ impl Parser {
 
     fn primary(&mut self) -> InterpreterResult<Expr> {
 
-        if self.match_tokens(vec![TokenType::Try]) {
+        if self.match_tokens(&[TokenType::Try]) {
             match self.try_statement() {
                 Ok(expr) => return Ok(expr),
                 Err(e) => return Err(e),
             }
         }
-        if self.match_tokens(vec![TokenType::LeftBrace]) {
+        if self.match_tokens(&[TokenType::LeftBrace]) {
             match self.block() {
                 Ok(expr) => return Ok(expr),
                 Err(e) => return Err(e),
             }
         }
-        if self.match_tokens(vec![TokenType::Class]) {
+        if self.match_tokens(&[TokenType::Class]) {
             match self.class_declaration() {
                 Ok(expr) => return Ok(expr),
                 Err(e) => return Err(e),
             }
         }
-        if self.match_tokens(vec![TokenType::Fun]) {
+        if self.match_tokens(&[TokenType::Fun]) {
             match self.function_declaration() {
                 Ok(expr) => return Ok(expr),
                 Err(e) => return Err(e),
             }
         }
-        if self.match_tokens(vec![TokenType::Return]) {
+        if self.match_tokens(&[TokenType::Return]) {
             match self.return_statement() {
                 Ok(expr) => return Ok(expr),
                 Err(e) => return Err(e),
             }
         }
-        if self.match_tokens(vec![TokenType::Var]) {
+        if self.match_tokens(&[TokenType::Throw]) {
+            let keyword = self.previous().clone();
+            let value = self.expression()?;
+            return Ok(Expr::Throw(keyword, Box::new(value)));
+        }
+        if self.match_tokens(&[TokenType::Defer]) {
+            let keyword = self.previous().clone();
+            let deferred = self.expression()?;
+            return Ok(Expr::Defer(keyword, Arc::new(deferred)));
+        }
+        if self.match_tokens(&[TokenType::Var]) {
             match self.var_declaration() {
                 Ok(expr) => return Ok(expr),
                 Err(e) => return Err(e),
             }
         }
-        if self.match_tokens(vec![TokenType::Import]) {
+        if self.match_tokens(&[TokenType::Import]) {
             match self.import_statement() {
                 Ok(expr) => return Ok(expr),
                 Err(e) => return Err(e),
             }
         }
-        if self.match_tokens(vec![TokenType::If]) {
+        if self.at_export() {
+            self.advance();
+            return self.export_statement();
+        }
+        if self.match_tokens(&[TokenType::If]) {
             match self.if_statement() {
                 Ok(expr) => return Ok(expr),
                 Err(e) => return Err(e),
             }
         }
-        if self.match_tokens(vec![TokenType::While]) {
+        if self.match_tokens(&[TokenType::While]) {
             match self.while_statement() {
                 Ok(expr) => return Ok(expr),
                 Err(e) => return Err(e),
             }
         }
-        if self.match_tokens(vec![TokenType::For]) {
+        if self.match_tokens(&[TokenType::For]) {
 
             match self.for_statement() {
                 Ok(expr) => return Ok(expr),
                 Err(e) => return Err(e),
             }
         }
-        if self.match_tokens(vec![TokenType::New]) {
+        if self.match_tokens(&[TokenType::New]) {
             match self.class_instantiation() {
                 Ok(expr) => return Ok(expr),
                 Err(e) => return Err(e),
             }
         }
-        if self.match_tokens(vec![TokenType::Async]){
+        if self.match_tokens(&[TokenType::Async]){
             match self.async_function_declaration() {
                 Ok(expr) => return Ok(expr),
                 Err(e) => return Err(e),
             }
         }
-        if self.match_tokens(vec![TokenType::Await]){
+        if self.match_tokens(&[TokenType::Await]){
             match self.await_statement() {
                 Ok(expr) => return Ok(expr),
                 Err(e) => return Err(e),
             }
         }
-        if self.match_tokens(vec![TokenType::IDENTIfIER]) {
+        if self.match_tokens(&[TokenType::IDENTIfIER]) {
             if self.check(TokenType::LeftBracket){
                 match self.array_dictionary_access() {
                     Ok(expr) => return Ok(expr),
@@ -363,7 +457,7 @@ impl Parser {
             }
             return self.variable();
         }
-        if self.match_tokens(vec![TokenType::LeftParen]) {
+        if self.match_tokens(&[TokenType::LeftParen]) {
             match self.expression() {
                 Ok(expr) => {
                     self.consume(TokenType::RightParen)?;
@@ -372,52 +466,51 @@ impl Parser {
                 Err(e) => return Err(e),
             }
         }
-        if self.match_tokens(vec![TokenType::False]) {
-            return Ok(Expr::Literal(self.previous(), "false".to_string()));
+        if self.match_tokens(&[TokenType::False]) {
+            return Ok(Expr::Literal(self.previous().clone(), "false".to_string()));
         }
-        if self.match_tokens(vec![TokenType::True]) {
-            return Ok(Expr::Literal(self.previous(), "true".to_string()));
+        if self.match_tokens(&[TokenType::True]) {
+            return Ok(Expr::Literal(self.previous().clone(), "true".to_string()));
         }
-        if self.match_tokens(vec![TokenType::Nil]) {
-            return Ok(Expr::Literal(self.previous(), "nil".to_string()));
+        if self.match_tokens(&[TokenType::Nil]) {
+            return Ok(Expr::Literal(self.previous().clone(), "nil".to_string()));
         }
-        if self.match_tokens(vec![TokenType::Number, TokenType::STRING]) {
-            match self.previous().literal {
-                Some(literal) => return Ok(Expr::Literal(self.previous(), literal)),
-                None => return Ok(Expr::Literal(self.previous(), "null".to_string())),
-            }
+        if self.match_tokens(&[TokenType::Number, TokenType::STRING, TokenType::Regex]) {
+            let token = self.previous().clone();
+            let literal = token.literal.clone().unwrap_or_else(|| "null".to_string());
+            return Ok(Expr::Literal(token, literal));
         }
-        if self.match_tokens(vec![TokenType::LeftBracket]) {
+        if self.match_tokens(&[TokenType::LeftBracket]) {
             match self.array() {
                 Ok(expr) => return Ok(expr),
                 Err(e) => return Err(e),
             }
         }
-        if self.match_tokens(vec![TokenType::Dict]) {
+        if self.match_tokens(&[TokenType::Dict]) {
             match self.dictionary() {
                 Ok(expr) => return Ok(expr),
                 Err(e) => return Err(e),  // If it looks like a call but isn't valid, return error
             } 
         }
-        if self.match_tokens(vec![TokenType::Semicolon]) {
+        if self.match_tokens(&[TokenType::Semicolon]) {
             return Ok(Expr::Nil);
         }
         Err(InterpreterError::parser_error(
-            crate::error::ParserErrorKind::ExpectExpression(self.peek().lexeme,self.peek().line),
+            crate::error::ParserErrorKind::ExpectExpression(self.peek().lexeme.clone(),self.peek().line),
         ))
     }
 
     fn consume(&mut self, token_type: TokenType) -> InterpreterResult<Token> {
-        if self.check(token_type.clone()) {
-            return Ok(self.advance());
+        if self.check(token_type) {
+            return Ok(self.advance().clone());
         }
         Err(InterpreterError::parser_error(
-            crate::error::ParserErrorKind::ExpectExpression(self.previous().lexeme, self.peek().line),
+            crate::error::ParserErrorKind::ExpectExpression(self.previous().lexeme.clone(), self.peek().line),
         ))
     }
 
-    fn match_tokens(&mut self, types: Vec<TokenType>) -> bool {
-        for token_type in types {
+    fn match_tokens(&mut self, types: &[TokenType]) -> bool {
+        for &token_type in types {
             if self.check(token_type) {
                 self.advance();
                 return true;
@@ -434,7 +527,7 @@ impl Parser {
     }
 
     fn variable(&mut self) -> InterpreterResult<Expr> {
-        let name = self.previous();
+        let name = self.previous().clone();
         Ok(Expr::Variable(name))
     }
     fn array(&mut self) -> InterpreterResult<Expr>{
@@ -442,7 +535,7 @@ impl Parser {
         if !self.check(TokenType::RightBracket) {
             loop {
                 elements.push(self.expression()?);
-                if !self.match_tokens(vec![TokenType::Comma]) {
+                if !self.match_tokens(&[TokenType::Comma]) {
                     break;
                 }
             }
@@ -459,7 +552,7 @@ impl Parser {
                 self.consume(TokenType::Colon)?;
                 let value = self.expression()?;
                 elements.push((key, value));
-                if !self.match_tokens(vec![TokenType::Comma]) {
+                if !self.match_tokens(&[TokenType::Comma]) {
                     break;
                 }
             }
@@ -468,15 +561,15 @@ impl Parser {
         Ok(Expr::Dictionary(elements))
     }
     fn array_dictionary_access(&mut self) -> InterpreterResult<Expr>{
-        let name: Token = self.previous();
+        let name: Token = self.previous().clone();
         self.consume(TokenType::LeftBracket)?;
         let index = self.expression()?;
         self.consume(TokenType::RightBracket)?;
-        if self.match_tokens(vec![TokenType::Equal]){
-            let new_value = self.expression()?;
-            return Ok(Expr::Set(name, Box::new(index), Box::new(new_value)));
+        let expr = self.indexes(Expr::Variable(name), index)?;
+        if matches!(expr, Expr::Get(..)) && self.match_tokens(&[TokenType::Dot]) {
+            return self.member_access(expr);
         }
-        Ok(Expr::Get(Box::new(Expr::Variable(name)), Box::new(index)))
+        Ok(expr)
     }
 
     fn try_statement(&mut self) -> InterpreterResult<Expr> {
@@ -489,9 +582,9 @@ impl Parser {
         // Parse catch parameter
         self.consume(TokenType::LeftParen)?;
         let error_param = match self.peek().token_type {
-            TokenType::IDENTIfIER => self.advance().lexeme,
+            TokenType::IDENTIfIER => self.advance().lexeme.clone(),
             _ => return Err(InterpreterError::parser_error(
-                crate::error::ParserErrorKind::ExpectExpression(self.previous().lexeme, self.peek().line),
+                crate::error::ParserErrorKind::ExpectExpression(self.previous().lexeme.clone(), self.peek().line),
             ))
         };
         self.consume(TokenType::RightParen)?;
@@ -506,8 +599,8 @@ impl Parser {
     }
 
     fn assignment(&mut self) -> InterpreterResult<Expr> {
-        let name = self.previous();
-        if self.match_tokens(vec![TokenType::Equal]) {
+        let name = self.previous().clone();
+        if self.match_tokens(&[TokenType::Equal]) {
             let value = self.expression()?;
             return Ok(Expr::Assign(name, Box::new(value)));
         }
@@ -517,13 +610,16 @@ impl Parser {
     }
 
     fn instance_or_get_or_set(&mut self) -> InterpreterResult<Expr>{
-        let name = self.previous();
-        if self.match_tokens(vec![TokenType::Dot]) {
+        let name = self.previous().clone();
+        if self.match_tokens(&[TokenType::Dot]) {
+            if self.check(TokenType::IDENTIfIER) {
+                return self.member_access(Expr::Variable(name));
+            }
             let var = self.expression()?;
-            if self.match_tokens(vec![TokenType::Equal]){
+            if self.match_tokens(&[TokenType::Equal]){
                 let new_value = self.expression()?;
-                return Ok(Expr::Set(name, Box::new(var), Box::new(new_value)));
-            }else if self.match_tokens(vec![TokenType::LeftParen]) {
+                return Ok(Expr::Set(Box::new(Expr::Variable(name)), Box::new(var), Box::new(new_value)));
+            }else if self.match_tokens(&[TokenType::LeftParen]) {
                 let fun_name = var.clone();
                 let arguments = self.arguments()?;
                 self.consume(TokenType::RightParen)?;
@@ -538,6 +634,46 @@ impl Parser {
     }
     
 
+    // `a.b`, `a.b(arguments)` and `a.b = value`, chained as in `a.b.c()`
+    // and `a.b[0].c = value`. The member ends at its name, arguments or
+    // indexes, so `a.b + 1` adds to a.b.
+    fn member_access(&mut self, object: Expr) -> InterpreterResult<Expr> {
+        let mut expr = object;
+        loop {
+            let member = Expr::Variable(self.consume(TokenType::IDENTIfIER)?);
+            if self.match_tokens(&[TokenType::LeftParen]) {
+                let arguments = self.arguments()?;
+                self.consume(TokenType::RightParen)?;
+                let call = Expr::Call(None, Box::new(member), arguments);
+                expr = Expr::Get(Box::new(expr), Box::new(call));
+            } else {
+                expr = self.indexes(expr, member)?;
+                if let Expr::Set(..) = expr {
+                    return Ok(expr);
+                }
+            }
+            if !self.match_tokens(&[TokenType::Dot]) {
+                return Ok(expr);
+            }
+        }
+    }
+
+    // The `[index]` parts following object[key], then the assignment to the
+    // last of them if there is one
+    fn indexes(&mut self, object: Expr, key: Expr) -> InterpreterResult<Expr> {
+        let (mut object, mut key) = (object, key);
+        while self.match_tokens(&[TokenType::LeftBracket]) {
+            object = Expr::Get(Box::new(object), Box::new(key));
+            key = self.expression()?;
+            self.consume(TokenType::RightBracket)?;
+        }
+        if self.match_tokens(&[TokenType::Equal]) {
+            let value = self.expression()?;
+            return Ok(Expr::Set(Box::new(object), Box::new(key), Box::new(value)));
+        }
+        Ok(Expr::Get(Box::new(object), Box::new(key)))
+    }
+
     fn var_declaration(&mut self) -> InterpreterResult<Expr> {
         let name = self.consume(TokenType::IDENTIfIER)?;
 
@@ -551,12 +687,12 @@ impl Parser {
     }
 
     fn call(&mut self) -> InterpreterResult<Expr> {
-        let mut expr: Expr = Expr::Variable(self.previous());
+        let mut expr: Expr = Expr::Variable(self.previous().clone());
         // Now handle the arguments if there are parentheses
-        if self.match_tokens(vec![TokenType::Dot]){
+        if self.match_tokens(&[TokenType::Dot]){
             let fun_name = self.consume(TokenType::IDENTIfIER)?;
             let fun = Expr::Variable(fun_name);
-            while self.match_tokens(vec![TokenType::LeftParen]) {
+            if self.match_tokens(&[TokenType::LeftParen]) {
                 let arguments = self.arguments()?;
                 self.consume(TokenType::RightParen)?;
                 expr = Expr::Call(Some(Box::new(expr)),Box::new(fun), arguments);
@@ -564,7 +700,7 @@ impl Parser {
                 return Ok(expr);
             }
         }
-        while self.match_tokens(vec![TokenType::LeftParen]) {
+        while self.match_tokens(&[TokenType::LeftParen]) {
             let arguments = self.arguments()?;
             self.consume(TokenType::RightParen)?;
             expr = Expr::Call(None,Box::new(expr), arguments);
@@ -573,7 +709,7 @@ impl Parser {
             Ok(expr)
         } else {
             Err(InterpreterError::parser_error(
-                crate::error::ParserErrorKind::ExpectExpression(self.previous().lexeme, self.peek().line),
+                crate::error::ParserErrorKind::ExpectExpression(self.previous().lexeme.clone(), self.peek().line),
             ))
         }
     }
@@ -605,7 +741,7 @@ impl Parser {
         self.consume(TokenType::LeftBrace)?;
         let body = self.block()?;
 
-        Ok(Expr::AsyncFunction(name, parameters, Box::new(body)))
+        Ok(Expr::AsyncFunction(name, parameters, Arc::new(body)))
     }
 
     fn function_declaration(&mut self) -> InterpreterResult<Expr> {
@@ -630,7 +766,7 @@ impl Parser {
         self.consume(TokenType::LeftBrace)?;
         let body = self.block()?;
 
-        Ok(Expr::Function(name, parameters, Box::new(body)))
+        Ok(Expr::Function(name, parameters, Arc::new(body)))
     }
     fn block(&mut self) -> InterpreterResult<Expr> {
         let mut statements = Vec::new();
@@ -667,6 +803,9 @@ impl Parser {
         Ok(Expr::While(Box::new(condition), Box::new(body)))
     }
     fn for_statement(&mut self) -> InterpreterResult<Expr> {
+        if self.match_token(TokenType::Await) {
+            return self.for_await_statement();
+        }
         self.consume(TokenType::LeftParen)?;
         let initializer = if self.match_token(TokenType::Semicolon) {
             Expr::Nil
@@ -683,7 +822,8 @@ impl Parser {
                 token_type: TokenType::True, 
                 lexeme: "true".to_string(), 
                 literal: None, 
-                line: self.peek().line}, "true".to_string())
+                line: self.peek().line,
+                column: self.peek().column}, "true".to_string())
         } else {
             self.expression()?
         };
@@ -697,20 +837,100 @@ impl Parser {
         let body = self.expression()?;
         Ok(Expr::For(Box::new(initializer),Box::new(condition),Box::new(increment), Box::new(body)))
     }
+    fn for_await_statement(&mut self) -> InterpreterResult<Expr> {
+        self.consume(TokenType::LeftParen)?;
+        self.match_token(TokenType::Var);
+        let name = self.consume(TokenType::IDENTIfIER)?;
+        self.consume(TokenType::In)?;
+        let iterable = self.expression()?;
+        self.consume(TokenType::RightParen)?;
+        let body = self.expression()?;
+        Ok(Expr::ForAwait(name, Box::new(iterable), Box::new(body)))
+    }
     fn import_statement(&mut self) -> InterpreterResult<Expr> {
         self.consume(TokenType::STRING)?;
-        match self.previous().literal {
-            Some(literal) => Ok(Expr::Import(Box::new(Expr::Literal(self.previous(), literal)))),
+        let path = self.previous().clone();
+        match path.literal.clone() {
+            Some(literal) => {
+                // `as` is only a keyword here, so it stays usable as a name
+                let alias = if self.check(TokenType::IDENTIfIER) && self.peek().lexeme == "as" {
+                    self.advance();
+                    Some(self.consume(TokenType::IDENTIfIER)?)
+                } else {
+                    None
+                };
+                Ok(Expr::Import(Box::new(Expr::Literal(path, literal)), alias))
+            }
             None => Err(InterpreterError::parser_error(
                 crate::error::ParserErrorKind::InvalidImport(self.peek().line),
             ))
         }
     }
+    // `export` is only a keyword before `* from` or a list of names, so it
+    // stays usable as a name
+    fn at_export(&self) -> bool {
+        let ahead = |distance: usize| self.tokens.get(self.current + distance);
+        self.check(TokenType::IDENTIfIER)
+            && self.peek().lexeme == "export"
+            && match ahead(1).map(|token| &token.token_type) {
+                Some(TokenType::Star) => {
+                    ahead(2).is_some_and(|token| token.lexeme == "from")
+                        && ahead(3).is_some_and(|token| token.token_type == TokenType::STRING)
+                }
+                Some(TokenType::LeftBrace) => true,
+                _ => false,
+            }
+    }
+    fn export_statement(&mut self) -> InterpreterResult<Expr> {
+        let names = if self.match_token(TokenType::Star) {
+            None
+        } else {
+            self.consume(TokenType::LeftBrace)?;
+            let mut names = Vec::new();
+            while !self.check(TokenType::RightBrace) {
+                let name = self.consume(TokenType::IDENTIfIER)?;
+                let exported = if self.check(TokenType::IDENTIfIER) && self.peek().lexeme == "as" {
+                    self.advance();
+                    self.consume(TokenType::IDENTIfIER)?
+                } else {
+                    name.clone()
+                };
+                names.push((name, exported));
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
+            self.consume(TokenType::RightBrace)?;
+            Some(names)
+        };
+        if !(self.check(TokenType::IDENTIfIER) && self.peek().lexeme == "from") {
+            return Err(InterpreterError::parser_error(
+                crate::error::ParserErrorKind::InvalidImport(self.peek().line),
+            ));
+        }
+        self.advance();
+        let path = self.consume(TokenType::STRING)?;
+        match path.literal.clone() {
+            Some(literal) => Ok(Expr::Export(Box::new(Expr::Literal(path, literal)), names)),
+            None => Err(InterpreterError::parser_error(
+                crate::error::ParserErrorKind::InvalidImport(path.line),
+            )),
+        }
+    }
     fn class_declaration(&mut self) -> InterpreterResult<Expr> {
         let name = self.consume(TokenType::IDENTIfIER)?;
         self.consume(TokenType::LeftBrace)?;
         let mut methods = Vec::new();
         while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            // `const` is only a keyword here, so it stays usable as a name
+            if self.check(TokenType::IDENTIfIER) && self.peek().lexeme == "const" {
+                self.advance();
+                let constant = self.consume(TokenType::IDENTIfIER)?;
+                self.consume(TokenType::Equal)?;
+                methods.push(Expr::Let(constant, Box::new(self.expression()?)));
+                self.match_token(TokenType::Semicolon);
+                continue;
+            }
             methods.push(self.expression()?);
         }
         self.consume(TokenType::RightBrace)?;
@@ -730,7 +950,7 @@ impl Parser {
         if !self.check(TokenType::RightParen) {
             loop {
                 args.push(self.expression()?);
-                if !self.match_tokens(vec![TokenType::Comma]) {
+                if !self.match_tokens(&[TokenType::Comma]) {
                     break;
                 }
             }
@@ -738,7 +958,7 @@ impl Parser {
         Ok(args)
     }
     fn return_statement(&mut self) -> InterpreterResult<Expr> {
-        let keyword = self.previous();
+        let keyword = self.previous().clone();
         let value = if !self.check(TokenType::Semicolon) {
             self.expression()?
         } else {
@@ -0,0 +1,282 @@
+use super::Expr;
+
+const INDENT: &str = "    ";
+
+// Prints a parsed program back to alpha source. The output follows the
+// parser's grammar exactly (a bare `;` is its own Expr::Nil statement,
+// groupings are kept as written), so parsing it again gives the same tree.
+pub fn print_program(statements: &[Expr]) -> String {
+    let mut out = String::new();
+    write_statements(&mut out, statements, 0);
+    out
+}
+
+impl Expr {
+    pub fn to_source(&self) -> String {
+        let mut out = String::new();
+        write_expr(&mut out, self, 0);
+        out
+    }
+}
+
+fn push_indent(out: &mut String, indent: usize) {
+    for _ in 0..indent {
+        out.push_str(INDENT);
+    }
+}
+
+fn write_statements(out: &mut String, statements: &[Expr], indent: usize) {
+    let mut after_statement = false;
+    for statement in statements {
+        if *statement == Expr::Nil {
+            // Keep the semicolon on the line of the statement it terminates
+            if after_statement {
+                out.pop();
+            } else {
+                push_indent(out, indent);
+            }
+            out.push_str(";\n");
+            after_statement = false;
+            continue;
+        }
+        push_indent(out, indent);
+        write_expr(out, statement, indent);
+        out.push('\n');
+        after_statement = true;
+    }
+}
+
+fn write_list(out: &mut String, exprs: &[Expr], indent: usize) {
+    for (i, expr) in exprs.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_expr(out, expr, indent);
+    }
+}
+
+fn write_expr(out: &mut String, expr: &Expr, indent: usize) {
+    match expr {
+        Expr::Binary(left, operator, right) | Expr::Logical(left, operator, right) => {
+            write_expr(out, left, indent);
+            out.push(' ');
+            out.push_str(&operator.lexeme);
+            out.push(' ');
+            write_expr(out, right, indent);
+        }
+        Expr::Grouping(expr) => {
+            out.push('(');
+            write_expr(out, expr, indent);
+            out.push(')');
+        }
+        Expr::Literal(token, _) => out.push_str(&token.lexeme),
+        Expr::Array(elements) => {
+            out.push('[');
+            write_list(out, elements, indent);
+            out.push(']');
+        }
+        Expr::Dictionary(elements) => {
+            out.push_str("dict{");
+            for (i, (key, value)) in elements.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_expr(out, key, indent);
+                out.push_str(": ");
+                write_expr(out, value, indent);
+            }
+            out.push('}');
+        }
+        Expr::Unary(operator, expr) => {
+            out.push_str(&operator.lexeme);
+            write_expr(out, expr, indent);
+        }
+        Expr::Nil => out.push(';'),
+        Expr::Variable(token) => out.push_str(&token.lexeme),
+        Expr::Assign(token, value) => {
+            out.push_str(&token.lexeme);
+            out.push_str(" = ");
+            write_expr(out, value, indent);
+        }
+        Expr::Let(token, initializer) => {
+            out.push_str("var ");
+            out.push_str(&token.lexeme);
+            if **initializer != Expr::Nil {
+                out.push_str(" = ");
+                write_expr(out, initializer, indent);
+            }
+        }
+        Expr::Block(statements) => {
+            out.push_str("{\n");
+            write_statements(out, statements, indent + 1);
+            push_indent(out, indent);
+            out.push('}');
+        }
+        Expr::Function(name, params, body) => {
+            out.push_str("fun ");
+            write_function(out, &name.lexeme, params, body, indent);
+        }
+        Expr::AsyncFunction(name, params, body) => {
+            out.push_str("async fun ");
+            write_function(out, &name.lexeme, params, body, indent);
+        }
+        Expr::Class(name, methods) => {
+            out.push_str("class ");
+            out.push_str(&name.lexeme);
+            out.push_str(" {\n");
+            for member in methods {
+                match member {
+                    Expr::Let(name, value) => {
+                        push_indent(out, indent + 1);
+                        out.push_str("const ");
+                        out.push_str(&name.lexeme);
+                        out.push_str(" = ");
+                        write_expr(out, value, indent + 1);
+                        out.push('\n');
+                    }
+                    member => write_statements(out, std::slice::from_ref(member), indent + 1),
+                }
+            }
+            push_indent(out, indent);
+            out.push('}');
+        }
+        Expr::Call(owner, callee, arguments) => {
+            if let Some(owner) = owner {
+                write_expr(out, owner, indent);
+                out.push('.');
+            }
+            write_expr(out, callee, indent);
+            out.push('(');
+            write_list(out, arguments, indent);
+            out.push(')');
+        }
+        Expr::Await(expr) => {
+            out.push_str("await ");
+            write_expr(out, expr, indent);
+        }
+        Expr::If(condition, then_branch, else_branch) => {
+            out.push_str("if (");
+            write_expr(out, condition, indent);
+            out.push_str(") ");
+            write_expr(out, then_branch, indent);
+            if **else_branch != Expr::Nil {
+                out.push_str(" else ");
+                write_expr(out, else_branch, indent);
+            }
+        }
+        Expr::While(condition, body) => {
+            out.push_str("while (");
+            write_expr(out, condition, indent);
+            out.push_str(") ");
+            write_expr(out, body, indent);
+        }
+        Expr::For(initializer, condition, increment, body) => {
+            out.push_str("for (");
+            if **initializer != Expr::Nil {
+                write_expr(out, initializer, indent);
+            }
+            out.push_str("; ");
+            write_expr(out, condition, indent);
+            out.push_str("; ");
+            if **increment != Expr::Nil {
+                write_expr(out, increment, indent);
+            }
+            out.push_str(") ");
+            write_expr(out, body, indent);
+        }
+        Expr::ForAwait(name, iterable, body) => {
+            out.push_str("for await (");
+            out.push_str(&name.lexeme);
+            out.push_str(" in ");
+            write_expr(out, iterable, indent);
+            out.push_str(") ");
+            write_expr(out, body, indent);
+        }
+        Expr::Import(path, alias) => {
+            out.push_str("import ");
+            write_expr(out, path, indent);
+            if let Some(alias) = alias {
+                out.push_str(" as ");
+                out.push_str(&alias.lexeme);
+            }
+        }
+        Expr::Export(path, names) => {
+            out.push_str("export ");
+            match names {
+                Some(names) => {
+                    let names: Vec<String> = names
+                        .iter()
+                        .map(|(name, exported)| {
+                            if name.lexeme == exported.lexeme {
+                                name.lexeme.clone()
+                            } else {
+                                format!("{} as {}", name.lexeme, exported.lexeme)
+                            }
+                        })
+                        .collect();
+                    out.push_str(&format!("{{ {} }}", names.join(", ")));
+                }
+                None => out.push('*'),
+            }
+            out.push_str(" from ");
+            write_expr(out, path, indent);
+        }
+        Expr::Throw(_, value) => {
+            out.push_str("throw ");
+            write_expr(out, value, indent);
+        }
+        Expr::Defer(_, deferred) => {
+            out.push_str("defer ");
+            write_expr(out, deferred, indent);
+        }
+        Expr::Return(_, value) => {
+            out.push_str("return");
+            if **value != Expr::Nil {
+                out.push(' ');
+                write_expr(out, value, indent);
+            }
+        }
+        Expr::Get(object, name) => {
+            // `a.b` and `a[b]` parse to the same tree; the index form is the
+            // only one that cannot swallow the tokens that follow it
+            write_expr(out, object, indent);
+            out.push('[');
+            write_expr(out, name, indent);
+            out.push(']');
+        }
+        Expr::Set(object, name, value) => {
+            write_expr(out, object, indent);
+            out.push('[');
+            write_expr(out, name, indent);
+            out.push_str("] = ");
+            write_expr(out, value, indent);
+        }
+        Expr::TryCatch(try_catch) => {
+            out.push_str("try ");
+            write_expr(out, &try_catch.try_block, indent);
+            out.push_str(" catch (");
+            out.push_str(&try_catch.catch_param);
+            out.push_str(") ");
+            write_expr(out, &try_catch.catch_block, indent);
+        }
+    }
+}
+
+fn write_function(
+    out: &mut String,
+    name: &str,
+    params: &[crate::tokenizer::Token],
+    body: &Expr,
+    indent: usize,
+) {
+    out.push_str(name);
+    out.push('(');
+    for (i, param) in params.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        out.push_str(&param.lexeme);
+    }
+    out.push_str(") ");
+    write_expr(out, body, indent);
+}
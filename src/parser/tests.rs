@@ -0,0 +1,343 @@
+use std::sync::Arc;
+
+use proptest::collection::vec;
+use proptest::option;
+use proptest::prelude::*;
+
+use super::{printer::print_program, Expr, Parser, TryCatch};
+use crate::tokenizer::{Token, TokenType, Tokenizer};
+
+const KEYWORDS: &[&str] = &[
+    "and", "class", "new", "else", "false", "for", "fun", "dict", "div", "if", "nil", "or", "return",
+    "throw", "defer", "try", "catch", "super", "true", "var", "while", "import", "async", "await", "in",
+];
+
+#[derive(Clone, Debug)]
+enum Statement {
+    // Followed by `;` when printed
+    Simple(Expr),
+    // Ends with a block, no terminator
+    Compound(Expr),
+}
+
+fn token(source: &str) -> Token {
+    let mut tokenizer = Tokenizer::new();
+    tokenizer.tokenize(source).unwrap();
+    tokenizer.tokens[0].clone()
+}
+
+// An operator as it is tokenized after a value; alone, `/` would start a regex
+fn operator_token(source: &str) -> Token {
+    let mut tokenizer = Tokenizer::new();
+    tokenizer.tokenize(&format!("0 {}", source)).unwrap();
+    tokenizer.tokens[1].clone()
+}
+
+fn literal_token(source: &str) -> Expr {
+    let token = token(source);
+    let literal = token.literal.clone().unwrap_or_else(|| source.to_string());
+    Expr::Literal(token, literal)
+}
+
+fn parse(source: &str) -> Result<Vec<Expr>, String> {
+    let mut tokenizer = Tokenizer::new();
+    tokenizer.tokenize(source).map_err(|e| e.to_string())?;
+    if !tokenizer.errors.is_empty() {
+        return Err(format!("tokenizer errors in:\n{}", source));
+    }
+    let statements = Parser::new(tokenizer.into_tokens())
+        .parse()
+        .map_err(|e| format!("{}\n{}", e, source))?;
+    Ok(statements.into_iter().map(|(expr, _)| expr).collect())
+}
+
+// Tokens remember their position, which depends on layout rather than structure
+fn without_lines(exprs: &[Expr]) -> String {
+    let mut out = format!("{:?}", exprs);
+    for field in ["line: ", "column: "] {
+        let debug = std::mem::take(&mut out);
+        let mut rest = debug.as_str();
+        while let Some(pos) = rest.find(field) {
+            out.push_str(&rest[..pos + field.len()]);
+            rest = rest[pos + field.len()..].trim_start_matches(|c: char| c.is_ascii_digit());
+        }
+        out.push_str(rest);
+    }
+    out
+}
+
+fn binary_precedence(operator: &Token) -> u8 {
+    match operator.token_type {
+        TokenType::Plus | TokenType::Minus => 3,
+        TokenType::Star | TokenType::Slash | TokenType::Modulo | TokenType::Div => 4,
+        _ => 1,
+    }
+}
+
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Binary(_, operator, _) => binary_precedence(operator),
+        Expr::Logical(..) => 2,
+        Expr::Unary(..) => 5,
+        _ => 6,
+    }
+}
+
+fn at_least(expr: Expr, min: u8) -> Box<Expr> {
+    let expr = canonical(expr);
+    if precedence(&expr) < min {
+        Box::new(Expr::Grouping(Box::new(expr)))
+    } else {
+        Box::new(expr)
+    }
+}
+
+// Adds the groupings the parser would need to produce this shape
+fn canonical(expr: Expr) -> Expr {
+    match expr {
+        Expr::Binary(left, operator, right) => {
+            let level = binary_precedence(&operator);
+            Expr::Binary(at_least(*left, level), operator, at_least(*right, level + 1))
+        }
+        Expr::Logical(left, operator, right) => {
+            Expr::Logical(at_least(*left, 2), operator, at_least(*right, 3))
+        }
+        Expr::Unary(operator, expr) => Expr::Unary(operator, at_least(*expr, 5)),
+        Expr::Await(expr) => Expr::Await(at_least(*expr, 6)),
+        Expr::Grouping(expr) => Expr::Grouping(Box::new(canonical(*expr))),
+        Expr::Array(elements) => Expr::Array(elements.into_iter().map(canonical).collect()),
+        Expr::Dictionary(elements) => Expr::Dictionary(
+            elements
+                .into_iter()
+                .map(|(key, value)| (canonical(key), canonical(value)))
+                .collect(),
+        ),
+        Expr::Call(owner, callee, arguments) => {
+            Expr::Call(owner, callee, arguments.into_iter().map(canonical).collect())
+        }
+        Expr::Get(object, index) => Expr::Get(Box::new(canonical(*object)), Box::new(canonical(*index))),
+        other => other,
+    }
+}
+
+fn identifier() -> impl Strategy<Value = Token> + Clone {
+    "[a-z][a-z0-9_]{0,5}"
+        .prop_filter("keywords are not identifiers", |name| {
+            !KEYWORDS.contains(&name.as_str())
+        })
+        .prop_map(|name| token(&name))
+}
+
+fn literal() -> impl Strategy<Value = Expr> {
+    prop_oneof![
+        (0u32..10_000, option::of(0u32..1_000)).prop_map(|(int, fraction)| match fraction {
+            Some(fraction) => format!("{}.{}", int, fraction),
+            None => int.to_string(),
+        }),
+        "[a-z ]{0,8}".prop_map(|s| format!("\"{}\"", s)),
+        ("[a-z]{1,5}", "i?").prop_map(|(pattern, flags)| format!("/{}/{}", pattern, flags)),
+        Just("true".to_string()),
+        Just("false".to_string()),
+        Just("nil".to_string()),
+    ]
+    .prop_map(|source| literal_token(&source))
+}
+
+fn binary_operator() -> impl Strategy<Value = Token> {
+    prop::sample::select(vec!["+", "-", "*", "/", "%", "div", ">", ">=", "<", "<=", "==", "!=", "===", "!=="])
+        .prop_map(operator_token)
+}
+
+fn expression() -> impl Strategy<Value = Expr> + Clone {
+    let leaf = prop_oneof![literal(), identifier().prop_map(Expr::Variable)];
+    leaf.prop_recursive(4, 32, 3, |inner| {
+        prop_oneof![
+            inner.clone().prop_map(|expr| Expr::Grouping(Box::new(expr))),
+            (inner.clone(), binary_operator(), inner.clone()).prop_map(|(left, operator, right)| {
+                Expr::Binary(Box::new(left), operator, Box::new(right))
+            }),
+            (inner.clone(), prop::sample::select(vec!["and", "or"]), inner.clone()).prop_map(
+                |(left, operator, right)| {
+                    Expr::Logical(Box::new(left), token(operator), Box::new(right))
+                }
+            ),
+            (prop::sample::select(vec!["-", "!"]), inner.clone())
+                .prop_map(|(operator, expr)| Expr::Unary(token(operator), Box::new(expr))),
+            vec(inner.clone(), 0..3).prop_map(Expr::Array),
+            vec((inner.clone(), inner.clone()), 0..3).prop_map(Expr::Dictionary),
+            (identifier(), vec(inner.clone(), 0..3)).prop_map(|(name, arguments)| {
+                Expr::Call(None, Box::new(Expr::Variable(name)), arguments)
+            }),
+            (identifier(), vec(inner.clone(), 1..3)).prop_map(|(name, indexes)| {
+                indexes.into_iter().fold(Expr::Variable(name), |object, index| {
+                    Expr::Get(Box::new(object), Box::new(index))
+                })
+            }),
+            inner.prop_map(|expr| Expr::Await(Box::new(expr))),
+        ]
+    })
+    .prop_map(canonical)
+}
+
+fn flatten(statements: Vec<Statement>) -> Vec<Expr> {
+    statements
+        .into_iter()
+        .flat_map(|statement| match statement {
+            Statement::Simple(expr) => vec![expr, Expr::Nil],
+            Statement::Compound(expr) => vec![expr],
+        })
+        .collect()
+}
+
+fn simple_statement() -> impl Strategy<Value = Statement> {
+    prop_oneof![
+        expression(),
+        (identifier(), option::of(expression())).prop_map(|(name, value)| {
+            Expr::Let(name, Box::new(value.unwrap_or(Expr::Nil)))
+        }),
+        (identifier(), expression())
+            .prop_map(|(name, value)| Expr::Assign(name, Box::new(value))),
+        (identifier(), vec(expression(), 0..2), expression(), expression()).prop_map(
+            |(name, outer, index, value)| {
+                let target = outer.into_iter().fold(Expr::Variable(name), |object, index| {
+                    Expr::Get(Box::new(object), Box::new(index))
+                });
+                Expr::Set(Box::new(target), Box::new(index), Box::new(value))
+            }
+        ),
+        option::of(expression()).prop_map(|value| {
+            Expr::Return(token("return"), Box::new(value.unwrap_or(Expr::Nil)))
+        }),
+        expression().prop_map(|value| Expr::Throw(token("throw"), Box::new(value))),
+        expression().prop_map(|value| Expr::Defer(token("defer"), Arc::new(value))),
+        "[a-z]{1,6}".prop_map(|path| {
+            Expr::Import(Box::new(literal_token(&format!("\"{}.la\"", path))), None)
+        }),
+        ("[a-z]{1,6}", identifier()).prop_map(|(path, alias)| {
+            Expr::Import(Box::new(literal_token(&format!("\"{}.json\"", path))), Some(alias))
+        }),
+        "[a-z]{1,6}".prop_map(|path| {
+            Expr::Export(Box::new(literal_token(&format!("\"{}.la\"", path))), None)
+        }),
+        ("[a-z]{1,6}", vec((identifier(), option::of(identifier())), 0..3)).prop_map(|(path, names)| {
+            let names = names
+                .into_iter()
+                .map(|(name, exported)| (name.clone(), exported.unwrap_or(name)))
+                .collect();
+            Expr::Export(Box::new(literal_token(&format!("\"{}.la\"", path))), Some(names))
+        }),
+    ]
+    .prop_map(Statement::Simple)
+}
+
+fn statement() -> impl Strategy<Value = Statement> {
+    simple_statement().prop_recursive(3, 24, 3, |inner| {
+        let body = vec(inner, 0..3).prop_map(|statements| Expr::Block(flatten(statements)));
+        let function = (identifier(), vec(identifier(), 0..3), body.clone());
+        prop_oneof![
+            body.clone(),
+            (expression(), body.clone(), option::of(body.clone())).prop_map(
+                |(condition, then_branch, else_branch)| {
+                    Expr::If(
+                        Box::new(condition),
+                        Box::new(then_branch),
+                        Box::new(else_branch.unwrap_or(Expr::Nil)),
+                    )
+                }
+            ),
+            (expression(), body.clone())
+                .prop_map(|(condition, body)| Expr::While(Box::new(condition), Box::new(body))),
+            (
+                option::of((identifier(), expression())),
+                expression(),
+                option::of((identifier(), expression())),
+                body.clone(),
+            )
+                .prop_map(|(initializer, condition, increment, body)| {
+                    Expr::For(
+                        Box::new(initializer.map_or(Expr::Nil, |(name, value)| {
+                            Expr::Let(name, Box::new(value))
+                        })),
+                        Box::new(condition),
+                        Box::new(increment.map_or(Expr::Nil, |(name, value)| {
+                            Expr::Assign(name, Box::new(value))
+                        })),
+                        Box::new(body),
+                    )
+                }),
+            (identifier(), expression(), body.clone()).prop_map(|(name, iterable, body)| {
+                Expr::ForAwait(name, Box::new(iterable), Box::new(body))
+            }),
+            (function.clone(), any::<bool>()).prop_map(|((name, params, body), is_async)| {
+                if is_async {
+                    Expr::AsyncFunction(name, params, Arc::new(body))
+                } else {
+                    Expr::Function(name, params, Arc::new(body))
+                }
+            }),
+            (identifier(), vec(class_member(function), 0..3))
+                .prop_map(|(name, members)| Expr::Class(name, members)),
+            (body.clone(), identifier(), body).prop_map(|(try_block, param, catch_block)| {
+                Expr::TryCatch(TryCatch {
+                    try_block: Box::new(try_block),
+                    catch_param: param.lexeme,
+                    catch_block: Box::new(catch_block),
+                })
+            }),
+        ]
+        .prop_map(Statement::Compound)
+    })
+}
+
+// A method, or a constant, which the class body holds as a declaration
+fn class_member(
+    function: impl Strategy<Value = (Token, Vec<Token>, Expr)>,
+) -> impl Strategy<Value = Expr> {
+    prop_oneof![
+        function.prop_map(|(name, params, body)| Expr::Function(name, params, Arc::new(body))),
+        (identifier(), expression()).prop_map(|(name, value)| Expr::Let(name, Box::new(value))),
+    ]
+}
+
+fn program() -> impl Strategy<Value = Vec<Expr>> {
+    // A statement starting with `-` right after a block would continue it as
+    // a subtraction, which the grammar cannot tell apart
+    vec(statement(), 0..6).prop_map(flatten).prop_filter(
+        "statement starting with a minus after a block",
+        |program| {
+            !print_program(program)
+                .lines()
+                .any(|line| line.trim_start().starts_with('-'))
+        },
+    )
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn printed_program_parses_back_to_the_same_tree(program in program()) {
+        let source = print_program(&program);
+        let parsed = parse(&source).map_err(TestCaseError::fail)?;
+        prop_assert_eq!(without_lines(&parsed), without_lines(&program), "source:\n{}", source);
+    }
+
+    #[test]
+    fn printing_is_idempotent(program in program()) {
+        let source = print_program(&program);
+        let parsed = parse(&source).map_err(TestCaseError::fail)?;
+        prop_assert_eq!(print_program(&parsed), source);
+    }
+}
+
+#[test]
+fn examples_round_trip() {
+    for entry in std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/example")).unwrap() {
+        let path = entry.unwrap().path();
+        let source = std::fs::read_to_string(&path).unwrap();
+        let program = parse(&source).unwrap();
+        let printed = print_program(&program);
+        let reparsed = parse(&printed).unwrap();
+        assert_eq!(without_lines(&reparsed), without_lines(&program), "{}", path.display());
+    }
+}
@@ -1,8 +1,10 @@
 use std::error::Error;
 
-use crate::error::{InterpreterError, InterpreterResult};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq)]
+use crate::{error::{InterpreterError, InterpreterResult, SourceLocation}, interpreter::pattern};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum TokenType {
     LeftParen,
     RightParen,
@@ -20,10 +22,15 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    // `div`, integer division
+    Div,
     Bang,
     BandEqual,
     Equal,
     EqualEqual,
+    // === and !==, which never convert
+    StrictEqual,
+    StrictNotEqual,
     Greater,
     GreaterEqual,
     Less,
@@ -31,6 +38,8 @@ pub enum TokenType {
     IDENTIfIER,
     STRING,
     Number,
+    // /pattern/flags; the literal is the pattern
+    Regex,
     And,
     Class,
     New,
@@ -42,6 +51,8 @@ pub enum TokenType {
     Nil,
     Or,
     Return,
+    Throw,
+    Defer,
     Super,
     True,
     Try,
@@ -51,7 +62,8 @@ pub enum TokenType {
     Eof,
     Import,
     Async,
-    Await
+    Await,
+    In
 }
 
 impl std::fmt::Display for TokenType {
@@ -60,12 +72,13 @@ impl std::fmt::Display for TokenType {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub literal: Option<String>,
     pub line: usize,
+    pub column: usize,
 }
 
 pub struct Tokenizer {
@@ -73,7 +86,24 @@ pub struct Tokenizer {
     pub tokens: Vec<Token>,
     pub line: usize,
     pub errors: Vec<Box<dyn Error>>,
+    // Whether errors are printed to stderr as they are found, rather than
+    // only collected
+    pub print_errors: bool,
+    // How many `//` comments were skipped, which printing the tokens or the
+    // tree back as source would lose
+    pub comments: usize,
+    line_start: usize,
+    // For each brace still open, whether it opened a dict literal, after
+    // which a `/` divides rather than starting a regex
+    braces: Vec<bool>,
+    closed_dict: bool,
+}
+impl Default for Tokenizer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
+
 impl Tokenizer {
     pub fn new() -> Self {
         Tokenizer {
@@ -81,10 +111,19 @@ impl Tokenizer {
             tokens: Vec::new(),
             line: 1,
             errors: Vec::new(),
+            print_errors: true,
+            comments: 0,
+            line_start: 0,
+            braces: Vec::new(),
+            closed_dict: false,
         }
     }
     pub fn get_tokens(&self) -> Vec<Token> {
-        return self.tokens.clone();
+        self.tokens.clone()
+    }
+    // The tokens without copying them, for handing them to the parser
+    pub fn into_tokens(self) -> Vec<Token> {
+        self.tokens
     }
     pub fn tokenize(&mut self, input: &str) -> InterpreterResult<()> {
         let chars: Vec<char> = input.chars().collect();
@@ -96,92 +135,123 @@ impl Tokenizer {
                     lexeme: "(".to_string(),
                     literal: None,
                     line: self.line,
+                    column: self.column(self.current),
                 }),
                 ')' => self.add_token(Token {
                     token_type: TokenType::RightParen,
                     lexeme: ")".to_string(),
                     literal: None,
                     line: self.line,
+                    column: self.column(self.current),
                 }),
                 '[' => self.add_token(Token {
                     token_type: TokenType::LeftBracket,
                     lexeme: "[".to_string(),
                     literal: None,
                     line: self.line,
+                    column: self.column(self.current),
                 }),
                 ']' => self.add_token(Token {
                     token_type: TokenType::RightBracket,
                     lexeme: "]".to_string(),
                     literal: None,
                     line: self.line,
+                    column: self.column(self.current),
                 }),
-                '{' => self.add_token(Token {
-                    token_type: TokenType::LeftBrace,
-                    lexeme: "{".to_string(),
-                    literal: None,
-                    line: self.line,
-                }),
-                '}' => self.add_token(Token {
-                    token_type: TokenType::RightBrace,
-                    lexeme: "}".to_string(),
-                    literal: None,
-                    line: self.line,
-                }),
+                '{' => {
+                    let dict = self.tokens.last().is_some_and(|token| token.token_type == TokenType::Dict);
+                    self.braces.push(dict);
+                    self.add_token(Token {
+                        token_type: TokenType::LeftBrace,
+                        lexeme: "{".to_string(),
+                        literal: None,
+                        line: self.line,
+                        column: self.column(self.current),
+                    })
+                }
+                '}' => {
+                    self.closed_dict = self.braces.pop().unwrap_or(false);
+                    self.add_token(Token {
+                        token_type: TokenType::RightBrace,
+                        lexeme: "}".to_string(),
+                        literal: None,
+                        line: self.line,
+                        column: self.column(self.current),
+                    })
+                }
                 ':' => self.add_token(Token {
                     token_type: TokenType::Colon,
                     lexeme: ":".to_string(),
                     literal: None,
                     line: self.line,
+                    column: self.column(self.current),
                 }),
                 ',' => self.add_token(Token {
                     token_type: TokenType::Comma,
                     lexeme: ",".to_string(),
                     literal: None,
                     line: self.line,
+                    column: self.column(self.current),
                 }),
                 '.' => self.add_token(Token {
                     token_type: TokenType::Dot,
                     lexeme: ".".to_string(),
                     literal: None,
                     line: self.line,
+                    column: self.column(self.current),
                 }),
                 '-' => self.add_token(Token {
                     token_type: TokenType::Minus,
                     lexeme: "-".to_string(),
                     literal: None,
                     line: self.line,
+                    column: self.column(self.current),
                 }),
                 '%' => self.add_token(Token {
                     token_type: TokenType::Modulo,
                     lexeme: "%".to_string(),
                     literal: None,
                     line: self.line,
+                    column: self.column(self.current),
                 }),
                 '+' => self.add_token(Token {
                     token_type: TokenType::Plus,
                     lexeme: "+".to_string(),
                     literal: None,
                     line: self.line,
+                    column: self.column(self.current),
                 }),
                 ';' => self.add_token(Token {
                     token_type: TokenType::Semicolon,
                     lexeme: ";".to_string(),
                     literal: None,
                     line: self.line,
+                    column: self.column(self.current),
                 }),
                 '*' => self.add_token(Token {
                     token_type: TokenType::Star,
                     lexeme: "*".to_string(),
                     literal: None,
                     line: self.line,
+                    column: self.column(self.current),
                 }),
                 '!' => {
-                    if self.peek_next(&chars) == '=' {
+                    if self.peek_next(&chars) == '=' && chars.get(self.current + 2) == Some(&'=') {
+                        self.add_token(Token {
+                            token_type: TokenType::StrictNotEqual,
+                            lexeme: "!==".to_string(),
+                            literal: None,
+                            line: self.line,
+                            column: self.column(self.current),
+                        });
+                        self.current += 2;
+                    } else if self.peek_next(&chars) == '=' {
                         self.add_token(Token {
                             token_type: TokenType::BandEqual,
                             lexeme: "!=".to_string(),
                             literal: None,
                             line: self.line,
+                            column: self.column(self.current),
                         });
                         self.current += 1;
                     } else {
@@ -190,16 +260,27 @@ impl Tokenizer {
                             lexeme: "!".to_string(),
                             literal: None,
                             line: self.line,
+                            column: self.column(self.current),
                         });
                     }
                 }
                 '=' => {
-                    if self.peek_next(&chars) == '=' {
+                    if self.peek_next(&chars) == '=' && chars.get(self.current + 2) == Some(&'=') {
+                        self.add_token(Token {
+                            token_type: TokenType::StrictEqual,
+                            lexeme: "===".to_string(),
+                            literal: None,
+                            line: self.line,
+                            column: self.column(self.current),
+                        });
+                        self.current += 2;
+                    } else if self.peek_next(&chars) == '=' {
                         self.add_token(Token {
                             token_type: TokenType::EqualEqual,
                             lexeme: "==".to_string(),
                             literal: None,
                             line: self.line,
+                            column: self.column(self.current),
                         });
                         self.current += 1;
                     } else {
@@ -208,6 +289,7 @@ impl Tokenizer {
                             lexeme: "=".to_string(),
                             literal: None,
                             line: self.line,
+                            column: self.column(self.current),
                         });
                     }
                 }
@@ -218,6 +300,7 @@ impl Tokenizer {
                             lexeme: "<=".to_string(),
                             literal: None,
                             line: self.line,
+                            column: self.column(self.current),
                         });
                         self.current += 1;
                     } else {
@@ -226,6 +309,7 @@ impl Tokenizer {
                             lexeme: "<".to_string(),
                             literal: None,
                             line: self.line,
+                            column: self.column(self.current),
                         });
                     }
                 }
@@ -236,6 +320,7 @@ impl Tokenizer {
                             lexeme: ">=".to_string(),
                             literal: None,
                             line: self.line,
+                            column: self.column(self.current),
                         });
                         self.current += 1;
                     } else {
@@ -244,39 +329,47 @@ impl Tokenizer {
                             lexeme: ">".to_string(),
                             literal: None,
                             line: self.line,
+                            column: self.column(self.current),
                         });
                     }
                 }
                 '/' => {
                     if self.peek_next(&chars) == '/' {
+                        self.comments += 1;
                         while self.current < chars.len() && chars[self.current] != '\n' {
                             self.current += 1;
                         }
                         self.line += 1;
+                        self.line_start = self.current + 1;
+                    } else if self.regex_allowed() {
+                        let column = self.column(self.current);
+                        if let Err(e) = self.regex(&chars) {
+                            self.add_error(e, column, "/");
+                        }
                     } else {
                         self.add_token(Token {
                             token_type: TokenType::Slash,
                             lexeme: "/".to_string(),
                             literal: None,
                             line: self.line,
+                            column: self.column(self.current),
                         });
                     }
                 }
                 x if ['\n'].contains(&x) => {
                     self.line += 1;
+                    self.line_start = self.current + 1;
                 }
                 x if [' ', '\t', '\r'].contains(&x) => {
                     // Handle whitespace
                 }
                 '"' => {
-                    match self.string(&chars) {
-                        Ok(_) => (),
-                        Err(e) => {
-                            self.add_error(Box::new(e));
-                        }
+                    let column = self.column(self.current);
+                    if let Err(e) = self.string(&chars) {
+                        self.add_error(e, column, "\"");
                     }
                 }
-                x if x.is_digit(10) => {
+                x if x.is_ascii_digit() => {
                     self.number(&chars);
                 }
                 x if x.is_alphabetic() || ['_'].contains(&x) => {
@@ -284,7 +377,7 @@ impl Tokenizer {
                 }
                 _ => {
                     let err = InterpreterError::tokenizer_error(crate::error::TokenizerErrorKind::UnexpectedCharacter(c, self.line));
-                    self.add_error(Box::new(err));
+                    self.add_error(err, self.column(self.current), &c.to_string());
                 }
             }
             self.current += 1;
@@ -295,19 +388,28 @@ impl Tokenizer {
             lexeme: "".to_string(),
             literal: None,
             line: self.line,
+            column: self.column(self.current),
         });
         Ok(())
     }
 
-    fn add_error(&mut self, error: Box<dyn Error>) {
-        eprintln!("{}", error);
-        self.errors.push(error);
+    fn add_error(&mut self, error: InterpreterError, column: usize, lexeme: &str) {
+        if self.print_errors {
+            eprintln!("{}", error);
+        }
+        let location = SourceLocation { file: None, line: self.line, column, lexeme: lexeme.to_string() };
+        self.errors.push(Box::new(error.located_at(location)));
     }
     fn add_token(&mut self, token: Token) {
         self.tokens.push(token);
     }
+
+    // Columns count characters from 1, like lines
+    fn column(&self, position: usize) -> usize {
+        position + 1 - self.line_start
+    }
     
-    fn peek_next(&self, chars: &Vec<char>) -> char {
+    fn peek_next(&self, chars: &[char]) -> char {
         if self.current + 1 >= chars.len() {
             '\0'
         } else {
@@ -315,7 +417,7 @@ impl Tokenizer {
         }
     }
 
-    fn string(&mut self, chars: &Vec<char>) -> InterpreterResult<()> {
+    fn string(&mut self, chars: &[char]) -> InterpreterResult<()> {
 
         self.current += 1;
         let start = self.current;
@@ -333,25 +435,91 @@ impl Tokenizer {
             lexeme: format!("\"{}\"", value.clone()),
             literal: Some(value),
             line: self.line,
+            column: self.column(start - 1),
+        });
+        Ok(())
+    }
+
+    // A `/` starts a regex where a value could start, and divides after one
+    fn regex_allowed(&self) -> bool {
+        let Some(previous) = self.tokens.last() else {
+            return true;
+        };
+        match previous.token_type {
+            TokenType::IDENTIfIER
+            | TokenType::STRING
+            | TokenType::Number
+            | TokenType::Regex
+            | TokenType::RightParen
+            | TokenType::RightBracket
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Nil
+            | TokenType::Super => false,
+            // The end of a dict literal is a value, the end of a block is not
+            TokenType::RightBrace => !self.closed_dict,
+            _ => true,
+        }
+    }
+
+    // Up to the closing `/`, which a `\` escapes and which does not end the
+    // pattern inside [...]. The pattern is compiled here, so a bad one is a
+    // syntax error and running the literal finds it already compiled.
+    fn regex(&mut self, chars: &[char]) -> InterpreterResult<()> {
+        let start = self.current;
+        let mut class = false;
+        self.current += 1;
+        loop {
+            match chars.get(self.current) {
+                None | Some('\n') => {
+                    return Err(InterpreterError::tokenizer_error(
+                        crate::error::TokenizerErrorKind::UnterminatedRegex(self.line),
+                    ));
+                }
+                Some('\\') => self.current += 1,
+                Some('[') => class = true,
+                Some(']') => class = false,
+                Some('/') if !class => break,
+                Some(_) => {}
+            }
+            self.current += 1;
+        }
+        let pattern = chars[start + 1..self.current].iter().collect::<String>();
+        let mut flags = String::new();
+        while let Some(&c) = chars.get(self.current + 1).filter(|c| c.is_alphanumeric()) {
+            flags.push(c);
+            self.current += 1;
+        }
+        if let Err(message) = pattern::compile(&pattern, &flags) {
+            return Err(InterpreterError::tokenizer_error(
+                crate::error::TokenizerErrorKind::InvalidRegex(message, self.line),
+            ));
+        }
+        self.tokens.push(Token {
+            token_type: TokenType::Regex,
+            lexeme: chars[start..=self.current].iter().collect(),
+            literal: Some(pattern),
+            line: self.line,
+            column: self.column(start),
         });
         Ok(())
     }
 
-    fn number(&mut self, chars: &Vec<char>) {
+    fn number(&mut self, chars: &[char]) {
         let start = self.current;
         let mut fractional_start = self.current;
         let mut fractional_length = 0;
-        while self.current < chars.len() && chars[self.current].is_digit(10) {
+        while self.current < chars.len() && chars[self.current].is_ascii_digit() {
             self.current += 1;
             fractional_start += 1;
         }
         if self.current < chars.len()
             && chars[self.current] == '.'
             && self.current + 1 < chars.len()
-            && chars[self.current + 1].is_digit(10)
+            && chars[self.current + 1].is_ascii_digit()
         {
             self.current += 1;
-            while self.current < chars.len() && chars[self.current].is_digit(10) {
+            while self.current < chars.len() && chars[self.current].is_ascii_digit() {
                 self.current += 1;
                 fractional_length += 1;
             }
@@ -368,7 +536,7 @@ impl Tokenizer {
             right.push('0');
         }
         let mut value = left.clone();
-        value.push_str(".");
+        value.push('.');
         value.push_str(&right);
         let lexeme = chars[start..self.current].iter().collect::<String>();
         self.tokens.push(Token {
@@ -376,11 +544,12 @@ impl Tokenizer {
             lexeme,
             literal: Some(value),
             line: self.line,
+            column: self.column(start),
         });
         self.current -= 1;
     }
 
-    fn identifier(&mut self, chars: &Vec<char>) {
+    fn identifier(&mut self, chars: &[char]) {
         let start = self.current;
         while self.current < chars.len()
             && (chars[self.current].is_alphanumeric() || ['_', '-'].contains(&chars[self.current]))
@@ -397,10 +566,13 @@ impl Tokenizer {
             "for" => TokenType::For,
             "fun" => TokenType::Fun,
             "dict" => TokenType::Dict,
+            "div" => TokenType::Div,
             "if" => TokenType::If,
             "nil" => TokenType::Nil,
             "or" => TokenType::Or,
             "return" => TokenType::Return,
+            "throw" => TokenType::Throw,
+            "defer" => TokenType::Defer,
             "try" => TokenType::Try,
             "catch" => TokenType::Catch,
             "super" => TokenType::Super,
@@ -410,6 +582,7 @@ impl Tokenizer {
             "import" => TokenType::Import,
             "async" => TokenType::Async,
             "await" => TokenType::Await,
+            "in" => TokenType::In,
             _ => TokenType::IDENTIfIER,
         };
         self.tokens.push(Token {
@@ -417,6 +590,7 @@ impl Tokenizer {
             lexeme: value.clone(),
             literal: None,
             line: self.line,
+            column: self.column(start),
         });
         self.current -= 1;
     }